@@ -0,0 +1,17 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/pbn.proto");
+        // Use the vendored compiler unless the environment names one
+        if std::env::var_os("PROTOC").is_none() {
+            let protoc = protoc_bin_vendored::protoc_bin_path()
+                .expect("protoc is vendored for this platform");
+            // SAFETY: the build script is single-threaded
+            unsafe { std::env::set_var("PROTOC", protoc) };
+        }
+        tonic_build::configure()
+            .build_client(false)
+            .compile_protos(&["proto/pbn.proto"], &["proto"])
+            .expect("proto/pbn.proto compiles");
+    }
+}
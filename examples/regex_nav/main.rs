@@ -0,0 +1,146 @@
+//! An interactive synthesizer for regular expressions
+//!
+//! Expressions are partial regular expressions, valid expressions agree with
+//! positive and negative example strings, and steps fill the leftmost hole.
+//! Steps are provided by an [`OracleProvider`], so every provided step can
+//! still reach a valid regular expression within the depth bound.
+//!
+//! Run with example strings (prefixed by `+` for positive examples and `-`
+//! for negative ones), and choose a step at each prompt:
+//!
+//! ```text
+//! cargo run --example regex_nav -- +a +ab +abb -b -ba
+//! ```
+//!
+//! When standard input is not interactive, the shortest navigation to a valid
+//! regular expression is found by automated search instead.
+
+mod nav;
+mod regex;
+
+use nav::{Examples, Fill, Forms};
+use regex::Re;
+
+use pbn::budget::{PausableTimer, TickBudget};
+use pbn::oracle::OracleProvider;
+use pbn::render::{Render, Target};
+use pbn::{Controller, Decider};
+
+use std::io::{BufRead, IsTerminal, Write};
+use std::time::Duration;
+
+/// The maximum number of steps to a valid regular expression
+const DEPTH: usize = 5;
+
+/// A decider that asks the user to choose steps on the terminal
+///
+/// The `pbn` crate itself has no terminal decider (deciders are part of the
+/// user interface), so this example defines a minimal one.
+struct Prompt {
+    target: Target,
+}
+
+impl Decider<Fill> for Prompt {
+    fn decide(&mut self, e: &Re, steps: &[Fill]) -> Option<usize> {
+        println!("\n{}", e.render_to(self.target));
+        for (i, step) in steps.iter().enumerate() {
+            println!("  {}. {}", i + 1, step.render_to(self.target));
+        }
+        loop {
+            print!("> ");
+            std::io::stdout().flush().ok()?;
+            let mut line = String::new();
+            if std::io::stdin().lock().read_line(&mut line).ok()? == 0 {
+                return None;
+            }
+            match line.trim() {
+                "q" => return None,
+                choice => match choice.parse::<usize>() {
+                    Ok(i) if 1 <= i && i <= steps.len() => return Some(i - 1),
+                    _ => println!(
+                        "enter a number from 1 to {} (or q)",
+                        steps.len()
+                    ),
+                },
+            }
+        }
+    }
+}
+
+fn main() {
+    let mut examples = Examples {
+        positive: vec![],
+        negative: vec![],
+    };
+    for arg in std::env::args().skip(1) {
+        match arg.split_at_checked(1) {
+            Some(("+", s)) => examples.positive.push(s.chars().collect()),
+            Some(("-", s)) => examples.negative.push(s.chars().collect()),
+            _ => {
+                eprintln!("examples must start with + or - (got {:?})", arg);
+                std::process::exit(2);
+            }
+        }
+    }
+    if examples.positive.is_empty() && examples.negative.is_empty() {
+        examples.positive =
+            ["a", "ab", "abb"].map(|s| s.chars().collect()).into();
+        examples.negative = ["", "b", "ba"].map(|s| s.chars().collect()).into();
+    }
+
+    let provider =
+        OracleProvider::new(Forms::new(&examples), examples.clone(), DEPTH)
+            .with_canon(|e: &Re| e.clone());
+    let mut controller = Controller::new(
+        PausableTimer::new(Duration::from_secs(60)),
+        provider,
+        examples,
+        Re::Hole,
+        true,
+    )
+    .with_timer_pausing(true);
+
+    let mut decider = Prompt {
+        target: if std::io::stdout().is_terminal() {
+            Target::Ansi
+        } else {
+            Target::Plain
+        },
+    };
+
+    if !std::io::stdin().is_terminal() {
+        let mut search = controller
+            .solve_deepening(|e: &Re| e.clone(), TickBudget::new(1_000_000));
+        println!("searching: {:?}", search.run());
+        search.commit();
+    }
+
+    while !controller.valid() {
+        let steps = match controller.provide() {
+            Ok(steps) => steps,
+            Err(err) => {
+                eprintln!("could not provide steps: {}", err);
+                std::process::exit(1);
+            }
+        };
+        if steps.is_empty() {
+            println!(
+                "no regular expression within {} steps agrees with the examples",
+                DEPTH
+            );
+            return;
+        }
+        let Some(i) = decider.decide(controller.working_expression(), &steps)
+        else {
+            return;
+        };
+        if let Err(err) = controller.decide(steps.into_iter().nth(i).unwrap()) {
+            eprintln!("could not decide on the step: {}", err);
+            std::process::exit(1);
+        }
+    }
+    println!("\nsolution: {}", controller.render(decider.target));
+    if let Some(derivation) = controller.derivation() {
+        print!("{}", derivation.render_to(decider.target));
+    }
+}
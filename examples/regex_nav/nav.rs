@@ -0,0 +1,92 @@
+//! Programming by Navigation for regular expressions
+//!
+//! Steps fill the leftmost hole of a partial regular expression with one
+//! syntactic form, and an expression is valid if it is complete and agrees
+//! with every positive and negative example.
+
+use crate::regex::Re;
+
+use pbn::render::{Render, Rendering, Style};
+use pbn::{Step, StepEnumerator, ValidityChecker};
+
+/// A step that fills the leftmost hole with a syntactic form (whose own
+/// subexpressions are holes)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fill(pub Re);
+
+impl Step for Fill {
+    type Exp = Re;
+
+    fn apply(&self, e: &Re) -> Option<Re> {
+        e.fill_first_hole(&self.0)
+    }
+}
+
+impl Render for Fill {
+    fn render(&self, out: &mut Rendering) {
+        out.push(Style::Comment, "fill the first hole with ");
+        self.0.render(out);
+    }
+}
+
+/// The syntactic forms that can fill a hole
+pub struct Forms {
+    alphabet: Vec<char>,
+}
+
+impl Forms {
+    /// Creates the forms over the characters that appear in `examples`
+    pub fn new(examples: &Examples) -> Self {
+        let mut alphabet: Vec<char> = examples
+            .positive
+            .iter()
+            .chain(&examples.negative)
+            .flatten()
+            .copied()
+            .collect();
+        alphabet.sort();
+        alphabet.dedup();
+        Self { alphabet }
+    }
+}
+
+impl StepEnumerator for Forms {
+    type Step = Fill;
+
+    fn enumerate(&self, e: &Re) -> Vec<Fill> {
+        if e.is_complete() {
+            return vec![];
+        }
+        let hole = || Box::new(Re::Hole);
+        self.alphabet
+            .iter()
+            .map(|c| Re::Char(*c))
+            .chain([
+                Re::Any,
+                Re::Concat(hole(), hole()),
+                Re::Alt(hole(), hole()),
+                Re::Star(hole()),
+            ])
+            .map(Fill)
+            .collect()
+    }
+}
+
+/// Positive and negative example strings
+#[derive(Debug, Clone)]
+pub struct Examples {
+    /// Strings that a valid regular expression must match
+    pub positive: Vec<Vec<char>>,
+    /// Strings that a valid regular expression must not match
+    pub negative: Vec<Vec<char>>,
+}
+
+impl ValidityChecker for Examples {
+    type Exp = Re;
+
+    fn check(&self, e: &Re) -> bool {
+        e.is_complete()
+            && self.positive.iter().all(|s| e.matches(s))
+            && !self.negative.iter().any(|s| e.matches(s))
+    }
+}
@@ -0,0 +1,151 @@
+//! Partial regular expressions over a small alphabet
+
+use pbn::render::{Render, Rendering, Style};
+
+use std::collections::BTreeSet;
+
+/// A regular expression that may contain holes
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Re {
+    /// A part of the regular expression that has not been written yet
+    Hole,
+    /// A literal character
+    Char(char),
+    /// Any single character
+    Any,
+    /// A regular expression followed by another
+    Concat(Box<Re>, Box<Re>),
+    /// Either of two regular expressions
+    Alt(Box<Re>, Box<Re>),
+    /// Zero or more repetitions of a regular expression
+    Star(Box<Re>),
+}
+
+impl Re {
+    /// Returns whether or not the regular expression has no holes
+    pub fn is_complete(&self) -> bool {
+        match self {
+            Re::Hole => false,
+            Re::Char(_) | Re::Any => true,
+            Re::Concat(a, b) | Re::Alt(a, b) => {
+                a.is_complete() && b.is_complete()
+            }
+            Re::Star(a) => a.is_complete(),
+        }
+    }
+
+    /// Replaces the leftmost hole with `fill`, returning `None` if there are
+    /// no holes
+    pub fn fill_first_hole(&self, fill: &Re) -> Option<Re> {
+        match self {
+            Re::Hole => Some(fill.clone()),
+            Re::Char(_) | Re::Any => None,
+            Re::Concat(a, b) => match a.fill_first_hole(fill) {
+                Some(a) => Some(Re::Concat(Box::new(a), b.clone())),
+                None => Some(Re::Concat(
+                    a.clone(),
+                    Box::new(b.fill_first_hole(fill)?),
+                )),
+            },
+            Re::Alt(a, b) => match a.fill_first_hole(fill) {
+                Some(a) => Some(Re::Alt(Box::new(a), b.clone())),
+                None => {
+                    Some(Re::Alt(a.clone(), Box::new(b.fill_first_hole(fill)?)))
+                }
+            },
+            Re::Star(a) => Some(Re::Star(Box::new(a.fill_first_hole(fill)?))),
+        }
+    }
+
+    /// Returns whether or not the (complete) regular expression matches all
+    /// of `s`
+    pub fn matches(&self, s: &[char]) -> bool {
+        self.ends(s, 0).contains(&s.len())
+    }
+
+    /// Returns the positions at which a match of the regular expression
+    /// starting at `start` in `s` can end
+    fn ends(&self, s: &[char], start: usize) -> BTreeSet<usize> {
+        match self {
+            Re::Hole => BTreeSet::new(),
+            Re::Char(c) => (s.get(start) == Some(c))
+                .then_some(start + 1)
+                .into_iter()
+                .collect(),
+            Re::Any => {
+                (start < s.len()).then_some(start + 1).into_iter().collect()
+            }
+            Re::Concat(a, b) => a
+                .ends(s, start)
+                .into_iter()
+                .flat_map(|mid| b.ends(s, mid))
+                .collect(),
+            Re::Alt(a, b) => {
+                let mut ends = a.ends(s, start);
+                ends.extend(b.ends(s, start));
+                ends
+            }
+            Re::Star(a) => {
+                let mut ends = BTreeSet::from([start]);
+                let mut frontier = vec![start];
+                while let Some(pos) = frontier.pop() {
+                    for end in a.ends(s, pos) {
+                        if ends.insert(end) {
+                            frontier.push(end);
+                        }
+                    }
+                }
+                ends
+            }
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Re::Alt(..) => 0,
+            Re::Concat(..) => 1,
+            Re::Star(_) => 2,
+            Re::Hole | Re::Char(_) | Re::Any => 3,
+        }
+    }
+
+    fn render_at(&self, out: &mut Rendering, precedence: u8) {
+        let parenthesize = self.precedence() < precedence;
+        if parenthesize {
+            out.push(Style::Keyword, "(");
+        }
+        match self {
+            Re::Hole => {
+                out.push(Style::Hole, "?");
+            }
+            Re::Char(c) => {
+                out.push(Style::Literal, c.encode_utf8(&mut [0; 4]));
+            }
+            Re::Any => {
+                out.push(Style::Keyword, ".");
+            }
+            Re::Concat(a, b) => {
+                a.render_at(out, 1);
+                b.render_at(out, 2);
+            }
+            Re::Alt(a, b) => {
+                a.render_at(out, 0);
+                out.push(Style::Keyword, "|");
+                b.render_at(out, 1);
+            }
+            Re::Star(a) => {
+                a.render_at(out, 3);
+                out.push(Style::Keyword, "*");
+            }
+        }
+        if parenthesize {
+            out.push(Style::Keyword, ")");
+        }
+    }
+}
+
+impl Render for Re {
+    fn render(&self, out: &mut Rendering) {
+        self.render_at(out, 0);
+    }
+}
@@ -0,0 +1,243 @@
+//! Derive macros for the [`pbn`](https://docs.rs/pbn) crate
+//!
+//! These macros are re-exported by `pbn` when its `derive` feature is enabled.
+
+use proc_macro::{Delimiter, Spacing, TokenStream, TokenTree};
+
+/// Derives `pbn::Step` for an enum whose variants each map to an apply
+/// function
+///
+/// The enum must carry a `#[step(exp = Type)]` attribute giving the notion of
+/// expressions, and every variant must carry a `#[step(apply = path)]`
+/// attribute naming a function that takes a reference to the expression
+/// followed by references to the fields of the variant (in order) and returns
+/// an `Option` of the resulting expression.
+#[proc_macro_derive(Step, attributes(step))]
+pub fn derive_step(input: TokenStream) -> TokenStream {
+    match expand(input) {
+        Ok(ts) => ts,
+        Err(msg) => format!("compile_error!({:?});", msg).parse().unwrap(),
+    }
+}
+
+enum Fields {
+    Unit,
+    Tuple(usize),
+    Named(Vec<String>),
+}
+
+struct Variant {
+    name: String,
+    fields: Fields,
+    apply: Option<String>,
+}
+
+fn expand(input: TokenStream) -> Result<TokenStream, String> {
+    let tokens: Vec<TokenTree> = input.into_iter().collect();
+    let mut i = 0;
+    let mut exp = None;
+
+    while let Some((attr, next)) = attribute(&tokens, i) {
+        if let Some(ts) = step_arg(&attr, "exp")? {
+            exp = Some(ts);
+        }
+        i = next;
+    }
+    i = skip_visibility(&tokens, i);
+
+    match tokens.get(i) {
+        Some(TokenTree::Ident(id)) if id.to_string() == "enum" => (),
+        _ => return Err("#[derive(Step)] only supports enums".to_owned()),
+    }
+    let name = match tokens.get(i + 1) {
+        Some(TokenTree::Ident(id)) => id.to_string(),
+        _ => return Err("expected enum name".to_owned()),
+    };
+    let body = match tokens.get(i + 2) {
+        Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Brace => {
+            g.stream()
+        }
+        Some(TokenTree::Punct(p)) if p.as_char() == '<' => {
+            return Err("#[derive(Step)] does not support generics".to_owned());
+        }
+        _ => return Err("expected enum body".to_owned()),
+    };
+    let exp = exp.ok_or("missing #[step(exp = ...)] attribute on enum")?;
+
+    let mut arms = String::new();
+    for chunk in split_commas(body.into_iter().collect()) {
+        let v = variant(&chunk)?;
+        let apply = v.apply.ok_or(format!(
+            "missing #[step(apply = ...)] attribute on variant {}",
+            v.name
+        ))?;
+        let (pattern, args) = match v.fields {
+            Fields::Unit => (String::new(), vec![]),
+            Fields::Tuple(n) => {
+                let args: Vec<String> =
+                    (0..n).map(|k| format!("__pbn_f{}", k)).collect();
+                (format!("({})", args.join(", ")), args)
+            }
+            Fields::Named(names) => {
+                (format!("{{ {} }}", names.join(", ")), names)
+            }
+        };
+        let mut call_args = vec!["__pbn_e".to_owned()];
+        call_args.extend(args);
+        arms.push_str(&format!(
+            "{}::{}{} => {}({}),\n",
+            name,
+            v.name,
+            pattern,
+            apply,
+            call_args.join(", ")
+        ));
+    }
+
+    format!(
+        "impl ::pbn::Step for {name} {{
+            type Exp = {exp};
+
+            #[allow(unused_variables)]
+            fn apply(&self, __pbn_e: &Self::Exp) -> ::core::option::Option<Self::Exp> {{
+                match self {{
+                    {arms}
+                }}
+            }}
+        }}"
+    )
+    .parse()
+    .map_err(|e| format!("{:?}", e))
+}
+
+/// Returns the bracketed contents of the outer attribute at index `i` (if
+/// any) and the index after it
+fn attribute(tokens: &[TokenTree], i: usize) -> Option<(TokenStream, usize)> {
+    match (tokens.get(i), tokens.get(i + 1)) {
+        (Some(TokenTree::Punct(p)), Some(TokenTree::Group(g)))
+            if p.as_char() == '#' && g.delimiter() == Delimiter::Bracket =>
+        {
+            Some((g.stream(), i + 2))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the value of `key` in an attribute of the form
+/// `step(key = value, ...)` (if the attribute is a `step` attribute with that
+/// key)
+fn step_arg(attr: &TokenStream, key: &str) -> Result<Option<String>, String> {
+    let tokens: Vec<TokenTree> = attr.clone().into_iter().collect();
+    match (tokens.first(), tokens.get(1)) {
+        (Some(TokenTree::Ident(id)), Some(TokenTree::Group(g)))
+            if id.to_string() == "step"
+                && g.delimiter() == Delimiter::Parenthesis =>
+        {
+            for arg in split_commas(g.stream().into_iter().collect()) {
+                match (arg.first(), arg.get(1)) {
+                    (Some(TokenTree::Ident(k)), Some(TokenTree::Punct(eq)))
+                        if eq.as_char() == '=' =>
+                    {
+                        if k.to_string() == key {
+                            let value: TokenStream =
+                                arg[2..].iter().cloned().collect();
+                            return Ok(Some(value.to_string()));
+                        }
+                    }
+                    _ => return Err("malformed #[step(...)] attribute".into()),
+                }
+            }
+            Ok(None)
+        }
+        _ => Ok(None),
+    }
+}
+
+fn skip_visibility(tokens: &[TokenTree], i: usize) -> usize {
+    match tokens.get(i) {
+        Some(TokenTree::Ident(id)) if id.to_string() == "pub" => {
+            match tokens.get(i + 1) {
+                Some(TokenTree::Group(g))
+                    if g.delimiter() == Delimiter::Parenthesis =>
+                {
+                    i + 2
+                }
+                _ => i + 1,
+            }
+        }
+        _ => i,
+    }
+}
+
+/// Splits tokens on commas that are not nested inside angle brackets
+fn split_commas(tokens: Vec<TokenTree>) -> Vec<Vec<TokenTree>> {
+    let mut chunks = vec![];
+    let mut current = vec![];
+    let mut depth = 0usize;
+    let mut prev_joint_dash = false;
+    for tt in tokens {
+        let mut joint_dash = false;
+        if let TokenTree::Punct(p) = &tt {
+            match p.as_char() {
+                ',' if depth == 0 => {
+                    chunks.push(std::mem::take(&mut current));
+                    continue;
+                }
+                '<' => depth += 1,
+                '>' if !prev_joint_dash => depth = depth.saturating_sub(1),
+                '-' => joint_dash = p.spacing() == Spacing::Joint,
+                _ => (),
+            }
+        }
+        prev_joint_dash = joint_dash;
+        current.push(tt);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks.retain(|c| !c.is_empty());
+    chunks
+}
+
+fn variant(tokens: &[TokenTree]) -> Result<Variant, String> {
+    let mut i = 0;
+    let mut apply = None;
+    while let Some((attr, next)) = attribute(tokens, i) {
+        if let Some(path) = step_arg(&attr, "apply")? {
+            apply = Some(path);
+        }
+        i = next;
+    }
+    let name = match tokens.get(i) {
+        Some(TokenTree::Ident(id)) => id.to_string(),
+        _ => return Err("expected variant name".to_owned()),
+    };
+    let fields = match tokens.get(i + 1) {
+        Some(TokenTree::Group(g))
+            if g.delimiter() == Delimiter::Parenthesis =>
+        {
+            Fields::Tuple(split_commas(g.stream().into_iter().collect()).len())
+        }
+        Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Brace => {
+            let mut names = vec![];
+            for field in split_commas(g.stream().into_iter().collect()) {
+                let mut j = 0;
+                while let Some((_, next)) = attribute(&field, j) {
+                    j = next;
+                }
+                j = skip_visibility(&field, j);
+                match field.get(j) {
+                    Some(TokenTree::Ident(id)) => names.push(id.to_string()),
+                    _ => return Err("expected field name".to_owned()),
+                }
+            }
+            Fields::Named(names)
+        }
+        _ => Fields::Unit,
+    };
+    Ok(Variant {
+        name,
+        fields,
+        apply,
+    })
+}
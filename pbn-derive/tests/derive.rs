@@ -0,0 +1,86 @@
+use pbn::Step;
+
+type Exp = Vec<u8>;
+
+fn push(e: &Exp, x: &u8) -> Option<Exp> {
+    let mut e = e.clone();
+    e.push(*x);
+    Some(e)
+}
+
+fn pop(e: &Exp) -> Option<Exp> {
+    let mut e = e.clone();
+    e.pop()?;
+    Some(e)
+}
+
+fn set(e: &Exp, i: &usize, x: &u8) -> Option<Exp> {
+    let mut e = e.clone();
+    *e.get_mut(*i)? = *x;
+    Some(e)
+}
+
+fn swap(e: &Exp, a: &usize, b: &usize) -> Option<Exp> {
+    let mut e = e.clone();
+    if *a >= e.len() || *b >= e.len() {
+        return None;
+    }
+    e.swap(*a, *b);
+    Some(e)
+}
+
+fn replace(e: &Exp, f: &Exp) -> Option<Exp> {
+    (!e.is_empty()).then(|| f.clone())
+}
+
+#[derive(Debug, Clone, Step)]
+#[step(exp = Vec<u8>)]
+pub enum Edit {
+    #[step(apply = push)]
+    Push(u8),
+    #[step(apply = pop)]
+    Pop,
+    #[step(apply = set)]
+    Set { i: usize, x: u8 },
+    #[step(apply = self::swap)]
+    Swap(usize, usize),
+    /// A field named like the generated binding for the expression
+    #[step(apply = replace)]
+    Replace { e: Exp },
+}
+
+#[test]
+fn unit_variants_receive_only_the_expression() {
+    assert_eq!(Edit::Pop.apply(&vec![1, 2]), Some(vec![1]));
+    assert_eq!(Edit::Pop.apply(&vec![]), None);
+}
+
+#[test]
+fn tuple_variants_receive_fields_in_order() {
+    assert_eq!(Edit::Push(3).apply(&vec![1]), Some(vec![1, 3]));
+    assert_eq!(Edit::Swap(0, 2).apply(&vec![1, 2, 3]), Some(vec![3, 2, 1]));
+    assert_eq!(Edit::Swap(0, 3).apply(&vec![1, 2, 3]), None);
+}
+
+#[test]
+fn named_variants_receive_fields_in_order() {
+    assert_eq!(
+        Edit::Set { i: 1, x: 7 }.apply(&vec![0, 0]),
+        Some(vec![0, 7])
+    );
+    assert_eq!(Edit::Set { i: 2, x: 7 }.apply(&vec![0, 0]), None);
+}
+
+#[test]
+fn fields_do_not_shadow_the_expression() {
+    let step = Edit::Replace { e: vec![9] };
+    assert_eq!(step.apply(&vec![1, 2]), Some(vec![9]));
+    assert_eq!(step.apply(&vec![]), None);
+}
+
+#[test]
+fn derived_steps_work_with_default_methods() {
+    let steps = [Edit::Pop, Edit::Push(1)];
+    assert_eq!(Edit::apply_all(&steps, &vec![]), vec![None, Some(vec![1])]);
+    assert!(!Edit::Pop.applicable(&vec![]));
+}
@@ -0,0 +1,218 @@
+//! A benchmark harness for step providers
+//!
+//! The harness runs a set of [`Problem`]s with an automated [`Decider`] and
+//! collects, for each problem, whether it was solved, the wall time, the
+//! number of calls to [`StepProvider::provide`](crate::StepProvider::provide),
+//! and the branching factor (number of provided steps) at every decision
+//! point. The results can be emitted as CSV or JSON.
+
+use crate::{Decider, PbnError, Problem, Step, Timer, ValidityChecker, json};
+
+use std::time::{Duration, Instant};
+
+/// The ways a benchmark run can end
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// A valid expression was reached
+    Solved,
+    /// The provider provided no steps for an invalid expression
+    Stuck,
+    /// The provider was cut off by the timer
+    CutOff,
+    /// The decider chose to stop
+    Abandoned,
+    /// The maximum number of decisions was reached
+    StepLimit,
+    /// The provider failed (or provided an inapplicable step)
+    Failed,
+}
+
+impl Outcome {
+    fn name(&self) -> &'static str {
+        match self {
+            Outcome::Solved => "solved",
+            Outcome::Stuck => "stuck",
+            Outcome::CutOff => "cutoff",
+            Outcome::Abandoned => "abandoned",
+            Outcome::StepLimit => "step_limit",
+            Outcome::Failed => "failed",
+        }
+    }
+}
+
+/// The result of running a single problem
+#[derive(Debug, Clone)]
+pub struct RunResult {
+    /// The name of the problem
+    pub name: String,
+    /// How the run ended
+    pub outcome: Outcome,
+    /// The total wall time of the run
+    pub wall_time: Duration,
+    /// The number of calls to the provider
+    pub provide_calls: usize,
+    /// The number of steps provided at each decision point
+    pub branching: Vec<usize>,
+}
+
+impl RunResult {
+    /// Returns the mean branching factor of the run (or zero if there were no
+    /// decision points)
+    pub fn mean_branching(&self) -> f64 {
+        if self.branching.is_empty() {
+            return 0.0;
+        }
+        self.branching.iter().sum::<usize>() as f64
+            / self.branching.len() as f64
+    }
+
+    /// Returns the maximum branching factor of the run
+    pub fn max_branching(&self) -> usize {
+        self.branching.iter().copied().max().unwrap_or(0)
+    }
+}
+
+/// The results of running a set of problems
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// The results of the individual runs (in order)
+    pub results: Vec<RunResult>,
+}
+
+impl Report {
+    /// Returns the fraction of problems that were solved
+    pub fn solve_rate(&self) -> f64 {
+        if self.results.is_empty() {
+            return 0.0;
+        }
+        self.results
+            .iter()
+            .filter(|r| r.outcome == Outcome::Solved)
+            .count() as f64
+            / self.results.len() as f64
+    }
+
+    /// Returns the results as CSV (with a header row)
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from(
+            "name,outcome,wall_time_ms,provide_calls,decisions,\
+             mean_branching,max_branching\n",
+        );
+        for r in &self.results {
+            out.push_str(&format!(
+                "{},{},{:.3},{},{},{:.3},{}\n",
+                csv_field(&r.name),
+                r.outcome.name(),
+                r.wall_time.as_secs_f64() * 1000.0,
+                r.provide_calls,
+                r.branching.len(),
+                r.mean_branching(),
+                r.max_branching(),
+            ));
+        }
+        out
+    }
+
+    /// Returns the results as a JSON array of objects
+    pub fn to_json(&self) -> String {
+        let runs: Vec<String> = self
+            .results
+            .iter()
+            .map(|r| {
+                let branching: Vec<String> =
+                    r.branching.iter().map(|b| b.to_string()).collect();
+                format!(
+                    "{{\"name\":{},\"outcome\":\"{}\",\"wall_time_ms\":{:.3},\
+                     \"provide_calls\":{},\"branching\":[{}]}}",
+                    json::string(&r.name),
+                    r.outcome.name(),
+                    r.wall_time.as_secs_f64() * 1000.0,
+                    r.provide_calls,
+                    branching.join(","),
+                )
+            })
+            .collect();
+        format!("[{}]", runs.join(","))
+    }
+}
+
+/// Runs a single problem with an automated decider for at most
+/// `max_decisions` decisions
+pub fn run_one<T, S, C>(
+    problem: &Problem<T, S, C>,
+    timer: T,
+    decider: &mut impl Decider<S>,
+    max_decisions: usize,
+) -> RunResult
+where
+    T: Timer + 'static,
+    S: Step + 'static,
+    C: ValidityChecker<Exp = S::Exp> + Clone + 'static,
+{
+    let start = Instant::now();
+    let mut controller = problem.controller(timer, false);
+    let mut provide_calls = 0;
+    let mut branching = vec![];
+    let outcome = loop {
+        if controller.valid() {
+            break Outcome::Solved;
+        }
+        if branching.len() >= max_decisions {
+            break Outcome::StepLimit;
+        }
+        provide_calls += 1;
+        let mut steps = match controller.provide() {
+            Ok(steps) => steps,
+            Err(PbnError::Cutoff(_)) => break Outcome::CutOff,
+            Err(_) => break Outcome::Failed,
+        };
+        if steps.is_empty() {
+            break Outcome::Stuck;
+        }
+        branching.push(steps.len());
+        match decider.decide(controller.working_expression(), &steps) {
+            Some(i) => {
+                if controller.decide(steps.swap_remove(i)).is_err() {
+                    break Outcome::Failed;
+                }
+            }
+            None => break Outcome::Abandoned,
+        }
+    };
+    RunResult {
+        name: problem.name().to_owned(),
+        outcome,
+        wall_time: start.elapsed(),
+        provide_calls,
+        branching,
+    }
+}
+
+/// Runs a set of problems with an automated decider (see [`run_one`]), using
+/// a fresh timer for each problem
+pub fn run<'a, T, S, C>(
+    problems: impl IntoIterator<Item = &'a Problem<T, S, C>>,
+    mut timer: impl FnMut() -> T,
+    decider: &mut impl Decider<S>,
+    max_decisions: usize,
+) -> Report
+where
+    T: Timer + 'static,
+    S: Step + 'static,
+    C: ValidityChecker<Exp = S::Exp> + Clone + 'static,
+{
+    Report {
+        results: problems
+            .into_iter()
+            .map(|p| run_one(p, timer(), decider, max_decisions))
+            .collect(),
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
@@ -0,0 +1,253 @@
+//! Splittable budgets for fair sharing among sub-providers
+//!
+//! A [`Budget`] is a [`Timer`] whose remaining budget can be split into fair
+//! shares. Consumption of a share also counts against the budget it was split
+//! from, and shares are intended to be consumed in order: each share may use
+//! whatever earlier shares left unused, but must leave a fair share for every
+//! later one.
+
+#[cfg(feature = "std")]
+use crate::sync::Mutex;
+use crate::{
+    BestEffortCompoundProvider, InvalidArgument, Step, StepProvider, Timer,
+};
+
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+/// A timer whose remaining budget can be split into fair shares
+pub trait Budget: Timer + Sized {
+    /// Splits the remaining budget into `n` (positive) shares to be consumed
+    /// in order
+    ///
+    /// # Panics
+    ///
+    /// Implementations may panic if `n` is zero (see [`Self::try_split`])
+    fn split(&self, n: usize) -> Vec<Self>;
+
+    /// Splits the remaining budget into `n` shares (see [`Self::split`]), or
+    /// fails if `n` is zero
+    fn try_split(&self, n: usize) -> Result<Vec<Self>, InvalidArgument> {
+        InvalidArgument::check(
+            n > 0,
+            "cannot split a budget into zero shares",
+            || self.split(n),
+        )
+    }
+}
+
+/// The early cutoff reason for running out of budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfBudget;
+
+impl core::fmt::Display for OutOfBudget {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "out of budget")
+    }
+}
+
+impl core::error::Error for OutOfBudget {}
+
+/// A budget measured in a number of ticks
+#[derive(Debug, Clone)]
+pub struct TickBudget {
+    counter: Arc<AtomicUsize>,
+    reserve: usize,
+}
+
+impl TickBudget {
+    /// Creates a new budget of `ticks` ticks
+    pub fn new(ticks: usize) -> Self {
+        Self {
+            counter: Arc::new(AtomicUsize::new(ticks)),
+            reserve: 0,
+        }
+    }
+
+    /// Returns the number of ticks remaining in this budget
+    pub fn remaining(&self) -> usize {
+        self.counter
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.reserve)
+    }
+}
+
+impl Timer for TickBudget {
+    type EarlyCutoff = OutOfBudget;
+
+    fn tick(&self) -> Result<(), Self::EarlyCutoff> {
+        self.counter
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |c| {
+                if c > self.reserve { Some(c - 1) } else { None }
+            })
+            .map(|_| ())
+            .map_err(|_| OutOfBudget)
+    }
+}
+
+impl Budget for TickBudget {
+    fn split(&self, n: usize) -> Vec<Self> {
+        assert!(n > 0, "cannot split a budget into zero shares");
+        let available = self.remaining();
+        let share = available / n;
+        (0..n)
+            .map(|i| Self {
+                counter: self.counter.clone(),
+                reserve: if i + 1 == n {
+                    self.reserve
+                } else {
+                    self.reserve + available - (i + 1) * share
+                },
+            })
+            .collect()
+    }
+}
+
+/// A budget measured in wall-clock time
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct TimeBudget {
+    deadline: Instant,
+}
+
+#[cfg(feature = "std")]
+impl TimeBudget {
+    /// Creates a new budget that lasts for `duration` from now
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            deadline: Instant::now() + duration,
+        }
+    }
+
+    /// Returns the amount of time remaining in this budget
+    pub fn remaining(&self) -> Duration {
+        self.deadline.saturating_duration_since(Instant::now())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Timer for TimeBudget {
+    type EarlyCutoff = OutOfBudget;
+
+    fn tick(&self) -> Result<(), Self::EarlyCutoff> {
+        if Instant::now() < self.deadline {
+            Ok(())
+        } else {
+            Err(OutOfBudget)
+        }
+    }
+}
+
+/// A budget measured in wall-clock time that is only consumed while the timer
+/// is running (see [`Timer::pause`])
+///
+/// Clones share the same budget, so a clone can be kept to pause and resume
+/// a timer owned by a [`Controller`](crate::Controller).
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct PausableTimer {
+    limit: Duration,
+    state: Arc<Mutex<PausableState>>,
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug)]
+struct PausableState {
+    /// The time consumed before the timer was last resumed
+    used: Duration,
+    /// When the timer was last resumed (or `None` if it is paused)
+    running_since: Option<Instant>,
+}
+
+#[cfg(feature = "std")]
+impl PausableState {
+    fn elapsed(&self) -> Duration {
+        self.used + self.running_since.map_or(Duration::ZERO, |t| t.elapsed())
+    }
+}
+
+#[cfg(feature = "std")]
+impl PausableTimer {
+    /// Creates a new (running) timer with a budget of `limit`
+    pub fn new(limit: Duration) -> Self {
+        Self {
+            limit,
+            state: Arc::new(Mutex::new(PausableState {
+                used: Duration::ZERO,
+                running_since: Some(Instant::now()),
+            })),
+        }
+    }
+
+    /// Returns the amount of budget consumed so far
+    pub fn elapsed(&self) -> Duration {
+        self.state.lock().elapsed()
+    }
+
+    /// Returns the amount of budget remaining
+    pub fn remaining(&self) -> Duration {
+        self.limit.saturating_sub(self.elapsed())
+    }
+
+    /// Returns whether or not the timer is paused
+    pub fn is_paused(&self) -> bool {
+        self.state.lock().running_since.is_none()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Timer for PausableTimer {
+    type EarlyCutoff = OutOfBudget;
+
+    fn tick(&self) -> Result<(), Self::EarlyCutoff> {
+        if self.elapsed() < self.limit {
+            Ok(())
+        } else {
+            Err(OutOfBudget)
+        }
+    }
+
+    fn pause(&self) {
+        let mut state = self.state.lock();
+        if let Some(t) = state.running_since.take() {
+            state.used += t.elapsed();
+        }
+    }
+
+    fn resume(&self) {
+        let mut state = self.state.lock();
+        if state.running_since.is_none() {
+            state.running_since = Some(Instant::now());
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Budget for TimeBudget {
+    fn split(&self, n: usize) -> Vec<Self> {
+        assert!(n > 0, "cannot split a budget into zero shares");
+        let now = Instant::now();
+        let share = self.remaining() / n as u32;
+        (0..n)
+            .map(|i| Self {
+                deadline: if i + 1 == n {
+                    self.deadline
+                } else {
+                    now + share * (i as u32 + 1)
+                },
+            })
+            .collect()
+    }
+}
+
+impl<T: Budget + 'static, S: Step> BestEffortCompoundProvider<T, S> {
+    /// Creates a new [`BestEffortCompoundProvider`] in which each
+    /// sub-provider receives a fair share of the budget that remains when it
+    /// starts
+    pub fn fair(providers: Vec<Box<dyn StepProvider<T, Step = S>>>) -> Self {
+        let n = providers.len();
+        Self::new(providers, move |t: &T, i| t.split(n - i).swap_remove(0))
+    }
+}
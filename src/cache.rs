@@ -0,0 +1,125 @@
+//! Memoization of validity checking
+//!
+//! Validity checking (e.g., running tests or an SMT solver) is often the
+//! bottleneck of a session, and the same expressions recur across undo, redo,
+//! and automated search. A [`CachedChecker`] remembers the validity of every
+//! expression it has checked; its cache can be invalidated through a
+//! [`CacheInvalidator`] (e.g., when the test suite changes).
+
+use crate::{ScoredValidityChecker, ValidityChecker, sync::Mutex};
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+
+type Cache<E> = Arc<Mutex<HashMap<E, bool>>>;
+
+/// A validity checker wrapper that memoizes validity results
+pub struct CachedChecker<C: ValidityChecker> {
+    inner: C,
+    cache: Cache<C::Exp>,
+    capacity: Option<usize>,
+}
+
+impl<C> CachedChecker<C>
+where
+    C: ValidityChecker,
+    C::Exp: Hash + Eq + Clone,
+{
+    /// Creates a new (empty) cache for a validity checker
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+            capacity: None,
+        }
+    }
+
+    /// Bounds the number of cached results (the cache is cleared whenever it
+    /// would exceed `capacity`)
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// Returns a handle that can invalidate cached results (e.g., after the
+    /// checker is moved into a [`Controller`](crate::Controller))
+    pub fn invalidator(&self) -> CacheInvalidator<C::Exp> {
+        CacheInvalidator {
+            cache: self.cache.clone(),
+        }
+    }
+
+    /// Returns the number of cached results
+    pub fn len(&self) -> usize {
+        self.cache.lock().len()
+    }
+
+    /// Returns whether or not no results are cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<C> ValidityChecker for CachedChecker<C>
+where
+    C: ValidityChecker,
+    C::Exp: Hash + Eq + Clone,
+{
+    type Exp = C::Exp;
+
+    fn check(&self, e: &Self::Exp) -> bool {
+        if let Some(&valid) = self.cache.lock().get(e) {
+            return valid;
+        }
+        let valid = self.inner.check(e);
+        let mut cache = self.cache.lock();
+        if self.capacity.is_some_and(|c| cache.len() >= c) {
+            cache.clear();
+        }
+        cache.insert(e.clone(), valid);
+        valid
+    }
+}
+
+/// Scores are not memoized.
+impl<C> ScoredValidityChecker for CachedChecker<C>
+where
+    C: ScoredValidityChecker,
+    C::Exp: Hash + Eq + Clone,
+{
+    fn score(&self, e: &Self::Exp) -> f64 {
+        self.inner.score(e)
+    }
+}
+
+/// A handle for invalidating the results cached by a [`CachedChecker`]
+pub struct CacheInvalidator<E> {
+    cache: Cache<E>,
+}
+
+impl<E> Clone for CacheInvalidator<E> {
+    fn clone(&self) -> Self {
+        Self {
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl<E: Hash + Eq> CacheInvalidator<E> {
+    /// Forgets the cached result for an expression
+    pub fn invalidate(&self, e: &E) {
+        self.cache.lock().remove(e);
+    }
+
+    /// Forgets the cached results for all expressions that satisfy a
+    /// predicate
+    pub fn invalidate_if(&self, mut f: impl FnMut(&E, bool) -> bool) {
+        self.cache.lock().retain(|e, valid| !f(e, *valid));
+    }
+
+    /// Forgets all cached results
+    pub fn clear(&self) {
+        self.cache.lock().clear();
+    }
+}
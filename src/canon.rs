@@ -0,0 +1,314 @@
+//! Canonicalization of expressions for deduplication during search
+//!
+//! Many expressions are equivalent for the purposes of navigation (e.g.,
+//! alpha-equivalent terms, or terms equal up to normalization). An
+//! [`ExpCanon`] maps each expression to a canonical key so that automated
+//! searches (see [`Anytime::with_canon`](crate::solve::Anytime::with_canon))
+//! and enumerators (see
+//! [`OracleProvider::with_canon`](crate::oracle::OracleProvider::with_canon))
+//! can avoid re-exploring equivalent expressions.
+//!
+//! By default, the set of visited expressions is exact, which can take too
+//! much memory for very large searches. A [`VisitedBackend`] trades exactness
+//! for bounded memory (see, e.g.,
+//! [`Anytime::with_canon_backend`](crate::solve::Anytime::with_canon_backend)).
+
+use crate::InvalidArgument;
+use crate::rng::Rng;
+
+use alloc::{
+    collections::{BTreeMap, btree_map::Entry},
+    vec,
+    vec::Vec,
+};
+use core::hash::{Hash, Hasher};
+
+/// A canonicalization of expressions
+///
+/// Expressions with equal keys must be interchangeable for navigation: the
+/// same steps must lead to expressions with equal keys, and validity must
+/// agree.
+pub trait ExpCanon<E> {
+    /// The type of canonical keys
+    type Key: Ord;
+
+    /// Returns the canonical key of an expression
+    fn canonical_key(&self, e: &E) -> Self::Key;
+}
+
+impl<E, K: Ord, F: Fn(&E) -> K> ExpCanon<E> for F {
+    type Key = K;
+
+    fn canonical_key(&self, e: &E) -> K {
+        self(e)
+    }
+}
+
+/// A set of visited expressions, each with the least depth at which it was
+/// visited
+pub(crate) trait Visited<E> {
+    /// Records a visit to `e` at `depth` and returns whether or not `e` was
+    /// not already visited at the same or a lesser depth
+    fn visit(&mut self, e: &E, depth: usize) -> bool;
+
+    /// Forgets all visits
+    fn clear(&mut self);
+}
+
+/// A set of visited expressions, identified by their canonical keys
+pub(crate) struct CanonVisited<X: ExpCanon<E>, E> {
+    canon: X,
+    seen: BTreeMap<X::Key, usize>,
+}
+
+impl<X: ExpCanon<E>, E> CanonVisited<X, E> {
+    pub(crate) fn new(canon: X) -> Self {
+        Self {
+            canon,
+            seen: BTreeMap::new(),
+        }
+    }
+}
+
+impl<X: ExpCanon<E>, E> Visited<E> for CanonVisited<X, E> {
+    fn visit(&mut self, e: &E, depth: usize) -> bool {
+        match self.seen.entry(self.canon.canonical_key(e)) {
+            Entry::Vacant(v) => {
+                v.insert(depth);
+                true
+            }
+            Entry::Occupied(mut o) => {
+                if *o.get() <= depth {
+                    return false;
+                }
+                o.insert(depth);
+                true
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.seen.clear();
+    }
+}
+
+/// The ways to store the set of visited expressions of a search
+///
+/// Searches panic when configured with an invalid backend (see
+/// [`Self::validate`]).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VisitedBackend {
+    /// Remember every visited expression exactly (the default)
+    Exact,
+    /// Remember at most `capacity` (positive) of the most recently visited
+    /// expressions, forgetting the least recently visited ones
+    ///
+    /// Forgotten expressions may be explored again.
+    Lru {
+        /// The maximum number of remembered expressions
+        capacity: usize,
+    },
+    /// Remember visited expressions in a Bloom filter sized for `capacity`
+    /// (positive) expressions with a false-positive rate of about
+    /// `false_positive_rate` (in `(0, 1)`)
+    ///
+    /// A false positive skips an expression that was never explored, so the
+    /// search may miss results. The filter does not record depths: an
+    /// expression is never explored again, even if it is later reached in
+    /// fewer decisions. The false-positive rate grows once more than
+    /// `capacity` expressions are visited.
+    Bloom {
+        /// The expected number of visited expressions
+        capacity: usize,
+        /// The target false-positive rate
+        false_positive_rate: f64,
+    },
+}
+
+impl VisitedBackend {
+    /// Checks that the parameters of the backend are in range
+    pub fn validate(&self) -> Result<(), InvalidArgument> {
+        match *self {
+            VisitedBackend::Exact => Ok(()),
+            VisitedBackend::Lru { capacity } => InvalidArgument::check(
+                capacity > 0,
+                "capacity must be positive",
+                || (),
+            ),
+            VisitedBackend::Bloom {
+                capacity,
+                false_positive_rate,
+            } => {
+                if capacity == 0 {
+                    return Err(InvalidArgument("capacity must be positive"));
+                }
+                InvalidArgument::check(
+                    0.0 < false_positive_rate && false_positive_rate < 1.0,
+                    "false_positive_rate must be in (0, 1)",
+                    || (),
+                )
+            }
+        }
+    }
+}
+
+/// A set of visited expressions stored with any [`VisitedBackend`]
+pub(crate) enum BackendVisited<X: ExpCanon<E>, E> {
+    Exact(CanonVisited<X, E>),
+    Lru(LruVisited<X, E>),
+    Bloom(BloomVisited<X>),
+}
+
+impl<X, E> BackendVisited<X, E>
+where
+    X: ExpCanon<E>,
+    X::Key: Clone + Hash,
+{
+    /// Creates an empty set, or fails if the parameters of `backend` are out
+    /// of range
+    pub(crate) fn try_new(
+        canon: X,
+        backend: VisitedBackend,
+    ) -> Result<Self, InvalidArgument> {
+        backend.validate()?;
+        Ok(match backend {
+            VisitedBackend::Exact => Self::Exact(CanonVisited::new(canon)),
+            VisitedBackend::Lru { capacity } => Self::Lru(LruVisited {
+                canon,
+                capacity,
+                seen: BTreeMap::new(),
+                recency: BTreeMap::new(),
+                clock: 0,
+            }),
+            VisitedBackend::Bloom {
+                capacity,
+                false_positive_rate,
+            } => {
+                // The optimal number of hashes is log2(1 / rate), with
+                // (number of hashes) * log2(e) bits per expression
+                let mut hashes = 1;
+                let mut rate = 0.5;
+                while rate > false_positive_rate && hashes < 64 {
+                    rate *= 0.5;
+                    hashes += 1;
+                }
+                let bits = (capacity as f64
+                    * hashes as f64
+                    * core::f64::consts::LOG2_E)
+                    as usize;
+                let words = bits.div_ceil(64).max(1);
+                Self::Bloom(BloomVisited {
+                    canon,
+                    hashes,
+                    words: vec![0; words],
+                })
+            }
+        })
+    }
+}
+
+impl<X, E> Visited<E> for BackendVisited<X, E>
+where
+    X: ExpCanon<E>,
+    X::Key: Clone + Hash,
+{
+    fn visit(&mut self, e: &E, depth: usize) -> bool {
+        match self {
+            Self::Exact(v) => v.visit(e, depth),
+            Self::Lru(v) => v.visit(e, depth),
+            Self::Bloom(v) => v.visit(e),
+        }
+    }
+
+    fn clear(&mut self) {
+        match self {
+            Self::Exact(v) => v.clear(),
+            Self::Lru(v) => {
+                v.seen.clear();
+                v.recency.clear();
+            }
+            Self::Bloom(v) => v.words.fill(0),
+        }
+    }
+}
+
+/// A bounded set of the most recently visited expressions
+pub(crate) struct LruVisited<X: ExpCanon<E>, E> {
+    canon: X,
+    capacity: usize,
+    /// The least depth and the time of the latest visit of each key
+    seen: BTreeMap<X::Key, (usize, u64)>,
+    /// The key of each time of a latest visit
+    recency: BTreeMap<u64, X::Key>,
+    clock: u64,
+}
+
+impl<X: ExpCanon<E>, E> LruVisited<X, E>
+where
+    X::Key: Clone,
+{
+    fn visit(&mut self, e: &E, depth: usize) -> bool {
+        let key = self.canon.canonical_key(e);
+        self.clock += 1;
+        let (fresh, least) = match self.seen.get(&key) {
+            Some(&(d, time)) => {
+                self.recency.remove(&time);
+                (d > depth, d.min(depth))
+            }
+            None => (true, depth),
+        };
+        self.recency.insert(self.clock, key.clone());
+        self.seen.insert(key, (least, self.clock));
+        if self.seen.len() > self.capacity
+            && let Some((_, oldest)) = self.recency.pop_first()
+        {
+            self.seen.remove(&oldest);
+        }
+        fresh
+    }
+}
+
+/// A Bloom filter of visited expressions
+pub(crate) struct BloomVisited<X> {
+    canon: X,
+    hashes: u32,
+    words: Vec<u64>,
+}
+
+impl<X> BloomVisited<X> {
+    fn visit<E>(&mut self, e: &E) -> bool
+    where
+        X: ExpCanon<E>,
+        X::Key: Hash,
+    {
+        let mut hasher = Fnv(0xcbf29ce484222325);
+        self.canon.canonical_key(e).hash(&mut hasher);
+        let mut rng = Rng::new(hasher.finish());
+        let h1 = rng.next_u64();
+        let h2 = rng.next_u64() | 1;
+        let bits = self.words.len() as u64 * 64;
+        let mut fresh = false;
+        for i in 0..self.hashes as u64 {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) % bits;
+            let (word, mask) = ((bit / 64) as usize, 1 << (bit % 64));
+            fresh |= self.words[word] & mask == 0;
+            self.words[word] |= mask;
+        }
+        fresh
+    }
+}
+
+/// The FNV-1a hash function
+struct Fnv(u64);
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = (self.0 ^ *b as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! Derivations of expressions, for auditing how they were obtained
+//!
+//! A [`Derivation`] is a sequence of steps from a start expression, along with
+//! the intermediate expressions it passes through. Derivations can be
+//! extracted from sessions (see [`Controller::derivation`], which includes
+//! the results of automated navigation committed with
+//! [`Anytime::commit`](crate::solve::Anytime::commit)) and from other search
+//! results, and rendered for a human with user-supplied printers.
+//!
+//! Intermediate expressions can be large, so a derivation can be compressed
+//! to keep only its start and end (see [`Derivation::compress`]); they are
+//! recomputed on demand.
+
+use crate::{
+    Controller, DescribedStep, Step, StepProvider, Timer, ValidityChecker,
+    history::HistoryStore,
+    render::{Render, Rendering, Target},
+};
+
+use alloc::{format, string::String, vec::Vec};
+
+/// A sequence of steps from a start expression, with intermediate expressions
+#[derive(Debug, Clone)]
+pub struct Derivation<S: Step> {
+    start: S::Exp,
+    steps: Vec<S>,
+    /// The result of each step (all `None` except the last if compressed)
+    results: Vec<Option<S::Exp>>,
+}
+
+impl<S: Step> Derivation<S> {
+    /// Creates a new derivation by applying `steps` in order from `start` (or
+    /// returns `None` if some step does not apply)
+    pub fn new(start: S::Exp, steps: Vec<S>) -> Option<Self> {
+        let mut results = Vec::with_capacity(steps.len());
+        let mut e = start.clone();
+        for step in &steps {
+            e = step.apply(&e)?;
+            results.push(Some(e.clone()));
+        }
+        Some(Self {
+            start,
+            steps,
+            results,
+        })
+    }
+
+    /// Returns the start expression
+    pub fn start(&self) -> &S::Exp {
+        &self.start
+    }
+
+    /// Returns the derived expression
+    pub fn result(&self) -> &S::Exp {
+        match self.results.last() {
+            Some(e) => e.as_ref().expect("last result is never compressed"),
+            None => &self.start,
+        }
+    }
+
+    /// Returns the steps of the derivation
+    pub fn steps(&self) -> &[S] {
+        &self.steps
+    }
+
+    /// Returns the number of steps of the derivation
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Returns whether or not the derivation has no steps
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Discards the intermediate expressions (keeping the start and result)
+    pub fn compress(&mut self) {
+        if let Some((_, intermediate)) = self.results.split_last_mut() {
+            intermediate.fill(None);
+        }
+    }
+
+    /// Returns whether or not the intermediate expressions were discarded
+    pub fn is_compressed(&self) -> bool {
+        self.results.iter().rev().skip(1).any(Option::is_none)
+    }
+
+    /// Recomputes any discarded intermediate expressions
+    pub fn expand(&mut self) {
+        let mut e = self.start.clone();
+        for (step, result) in self.steps.iter().zip(&mut self.results) {
+            let next = match result {
+                Some(next) => next.clone(),
+                None => step.apply(&e).expect("derivation steps apply"),
+            };
+            *result = Some(next.clone());
+            e = next;
+        }
+    }
+
+    /// Returns each step with the expression it results in (recomputing
+    /// discarded intermediate expressions)
+    pub fn links(&self) -> Vec<(&S, S::Exp)> {
+        let mut e = self.start.clone();
+        self.steps
+            .iter()
+            .zip(&self.results)
+            .map(|(step, result)| {
+                e = match result {
+                    Some(next) => next.clone(),
+                    None => step.apply(&e).expect("derivation steps apply"),
+                };
+                (step, e.clone())
+            })
+            .collect()
+    }
+
+    /// Renders the derivation as text, one line per expression and step,
+    /// using the given printers
+    pub fn render(
+        &self,
+        step: impl Fn(&S) -> String,
+        exp: impl Fn(&S::Exp) -> String,
+    ) -> String {
+        let mut out = format!("{}\n", exp(&self.start));
+        for (i, (s, e)) in self.links().into_iter().enumerate() {
+            out.push_str(&format!("  {}. {}\n{}\n", i + 1, step(s), exp(&e)));
+        }
+        out
+    }
+
+    /// Returns the derivation as a graph in the Graphviz DOT language (see
+    /// [`render::to_dot`](crate::render::to_dot))
+    pub fn to_dot(&self) -> String
+    where
+        S: Render,
+        S::Exp: Render,
+    {
+        let links = self.links();
+        crate::render::to_dot(
+            &self.start,
+            links.iter().map(|(s, e)| (Some(*s), e)),
+        )
+    }
+
+    /// Renders the derivation like [`Self::render`], using the [`Render`]
+    /// implementations of the steps and expressions
+    pub fn render_to(&self, target: Target) -> String
+    where
+        S: Render,
+        S::Exp: Render,
+    {
+        let mut out = Rendering::new();
+        out.render(&self.start).text("\n");
+        for (i, (s, e)) in self.links().into_iter().enumerate() {
+            out.text(&format!("  {}. ", i + 1))
+                .render(s)
+                .text("\n")
+                .render(&e)
+                .text("\n");
+        }
+        out.to_target(target)
+    }
+}
+
+impl<S> core::fmt::Display for Derivation<S>
+where
+    S: DescribedStep,
+    S::Exp: core::fmt::Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.render(S::description, |e| format!("{:?}", e)))
+    }
+}
+
+impl<T, S, P, C, H> Controller<T, S, P, C, H>
+where
+    T: Timer,
+    S: Step + Clone,
+    P: StepProvider<T, Step = S> + ?Sized,
+    C: ValidityChecker<Exp = S::Exp> + ?Sized,
+    H: HistoryStore<S::Exp> + ?Sized,
+{
+    /// Returns the derivation of the working expression from the start of
+    /// the session (or `None` if the working expression was edited
+    /// externally)
+    pub fn derivation(&self) -> Option<Derivation<S>> {
+        let branch = self.branch()?;
+        Derivation::new(branch.base, branch.steps)
+    }
+}
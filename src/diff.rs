@@ -0,0 +1,101 @@
+//! Differences between successive provided step sets
+//!
+//! After a decision, most of the previously provided steps are usually
+//! provided again. A [`StepDiff`] separates the steps that appeared or
+//! disappeared since the previous call to the provider, which user interfaces
+//! can use to animate the choices (or to explain how a decision changed them).
+
+use std::collections::HashSet;
+use std::hash::Hash;
+
+/// The difference between two provided step sets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepDiff<S> {
+    /// The steps that were not previously provided (in their new order)
+    pub added: Vec<S>,
+    /// The steps that are no longer provided (in their old order)
+    pub removed: Vec<S>,
+    /// The number of steps that are still provided
+    pub kept: usize,
+}
+
+impl<S> StepDiff<S> {
+    /// Returns whether or not the step sets are the same
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Returns the difference from `prev` to `next`, identifying steps by `key`
+pub fn diff_steps_by<S: Clone, K: Eq + Hash>(
+    prev: &[S],
+    next: &[S],
+    key: impl Fn(&S) -> K,
+) -> StepDiff<S> {
+    let old: HashSet<K> = prev.iter().map(&key).collect();
+    let new: HashSet<K> = next.iter().map(&key).collect();
+    let added: Vec<S> = next
+        .iter()
+        .filter(|s| !old.contains(&key(s)))
+        .cloned()
+        .collect();
+    StepDiff {
+        kept: next.len() - added.len(),
+        added,
+        removed: prev
+            .iter()
+            .filter(|s| !new.contains(&key(s)))
+            .cloned()
+            .collect(),
+    }
+}
+
+/// Returns the difference from `prev` to `next`
+pub fn diff_steps<S: Clone + Eq + Hash>(prev: &[S], next: &[S]) -> StepDiff<S> {
+    diff_steps_by(prev, next, Clone::clone)
+}
+
+/// A tracker of successive provided step sets
+pub struct StepDiffer<S, F> {
+    key: F,
+    last: Vec<S>,
+}
+
+impl<S: Clone + Eq + Hash> StepDiffer<S, fn(&S) -> S> {
+    /// Creates a new [`StepDiffer`] that identifies steps by equality
+    pub fn new() -> Self {
+        Self::with_key(Clone::clone)
+    }
+}
+
+impl<S: Clone + Eq + Hash> Default for StepDiffer<S, fn(&S) -> S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Clone, F> StepDiffer<S, F> {
+    /// Creates a new [`StepDiffer`] that identifies steps by `key`
+    pub fn with_key<K: Eq + Hash>(key: F) -> Self
+    where
+        F: Fn(&S) -> K,
+    {
+        Self { key, last: vec![] }
+    }
+
+    /// Returns the difference from the previous step set (initially empty)
+    /// to `steps`, which becomes the previous step set
+    pub fn update<K: Eq + Hash>(&mut self, steps: &[S]) -> StepDiff<S>
+    where
+        F: Fn(&S) -> K,
+    {
+        let diff = diff_steps_by(&self.last, steps, &self.key);
+        self.last = steps.to_vec();
+        diff
+    }
+
+    /// Forgets the previous step set (e.g., after undoing a decision)
+    pub fn reset(&mut self) {
+        self.last.clear();
+    }
+}
@@ -0,0 +1,185 @@
+//! Differential testing between two step providers
+//!
+//! The [`compare`] function drives two providers over the same random
+//! decision sequences and reports the first expression at which their
+//! provided step sets differ (modulo a user-supplied equivalence on steps).
+//! This is useful for validating an optimized provider against a reference
+//! implementation such as an [`OracleProvider`](crate::oracle::OracleProvider).
+
+use crate::{Step, StepProvider, Timer, rng::Rng, shrink};
+
+use alloc::{vec, vec::Vec};
+
+/// Configuration for differential testing
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The number of random decision sequences to try
+    pub trials: usize,
+    /// The maximum length of each decision sequence
+    pub max_depth: usize,
+    /// The seed for choosing random decisions
+    pub seed: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            trials: 100,
+            max_depth: 10,
+            seed: 0,
+        }
+    }
+}
+
+/// A difference between the step sets provided by two providers
+pub struct Mismatch<S: Step> {
+    /// The (minimized) sequence of steps from the start expression that
+    /// reproduces the difference
+    pub trace: Vec<S>,
+    /// The expression obtained by applying the trace to the start expression
+    pub exp: S::Exp,
+    /// The steps provided only by the left provider
+    pub only_left: Vec<S>,
+    /// The steps provided only by the right provider
+    pub only_right: Vec<S>,
+}
+
+/// Drives two providers over random decision sequences from `start` and
+/// returns the first difference found between their provided step sets
+/// (where `eq` defines when two steps are equivalent)
+///
+/// Decisions are chosen among the steps provided by the left provider. The
+/// trace of the returned mismatch is minimized (see [`shrink`]) while the
+/// remaining steps are still provided and the difference is still exhibited.
+pub fn compare<T, S, L, R>(
+    timer: &T,
+    left: &mut L,
+    right: &mut R,
+    start: &S::Exp,
+    eq: impl Fn(&S, &S) -> bool,
+    config: &Config,
+) -> Result<Option<Mismatch<S>>, T::EarlyCutoff>
+where
+    T: Timer,
+    S: Step + Clone,
+    L: StepProvider<T, Step = S>,
+    R: StepProvider<T, Step = S>,
+{
+    let mut rng = Rng::new(config.seed);
+    for _ in 0..config.trials {
+        let mut trace = vec![];
+        let mut e = start.clone();
+        left.on_session_start(&e);
+        right.on_session_start(&e);
+        for _ in 0..=config.max_depth {
+            let mut l = left.provide(timer, &e)?;
+            let r = right.provide(timer, &e)?;
+            let (only_left, only_right) = difference(&l, &r, &eq);
+            if !only_left.is_empty() || !only_right.is_empty() {
+                let mismatch = Mismatch {
+                    trace,
+                    exp: e,
+                    only_left,
+                    only_right,
+                };
+                return minimize(timer, left, right, start, mismatch, &eq)
+                    .map(Some);
+            }
+            if l.is_empty() || trace.len() == config.max_depth {
+                break;
+            }
+            let step = l.swap_remove(rng.below(l.len()));
+            e = match step.apply(&e) {
+                Some(e2) => e2,
+                None => break,
+            };
+            left.on_decide(&step, &e);
+            right.on_decide(&step, &e);
+            trace.push(step);
+        }
+    }
+    Ok(None)
+}
+
+/// Replays a trace from `start`, returning the first difference between the
+/// providers along it (if every step of the trace is provided and there is
+/// one)
+fn replay<T, S, L, R>(
+    timer: &T,
+    left: &mut L,
+    right: &mut R,
+    start: &S::Exp,
+    trace: &[S],
+    eq: &impl Fn(&S, &S) -> bool,
+) -> Result<Option<Mismatch<S>>, T::EarlyCutoff>
+where
+    T: Timer,
+    S: Step + Clone,
+    L: StepProvider<T, Step = S>,
+    R: StepProvider<T, Step = S>,
+{
+    let mut e = start.clone();
+    left.on_session_start(&e);
+    right.on_session_start(&e);
+    for i in 0..=trace.len() {
+        let l = left.provide(timer, &e)?;
+        let r = right.provide(timer, &e)?;
+        let (only_left, only_right) = difference(&l, &r, eq);
+        if !only_left.is_empty() || !only_right.is_empty() {
+            return Ok(Some(Mismatch {
+                trace: trace[..i].to_vec(),
+                exp: e,
+                only_left,
+                only_right,
+            }));
+        }
+        if i == trace.len() || !l.iter().any(|a| eq(a, &trace[i])) {
+            break;
+        }
+        e = match trace[i].apply(&e) {
+            Some(e2) => e2,
+            None => return Ok(None),
+        };
+        left.on_decide(&trace[i], &e);
+        right.on_decide(&trace[i], &e);
+    }
+    Ok(None)
+}
+
+fn minimize<T, S, L, R>(
+    timer: &T,
+    left: &mut L,
+    right: &mut R,
+    start: &S::Exp,
+    mismatch: Mismatch<S>,
+    eq: &impl Fn(&S, &S) -> bool,
+) -> Result<Mismatch<S>, T::EarlyCutoff>
+where
+    T: Timer,
+    S: Step + Clone,
+    L: StepProvider<T, Step = S>,
+    R: StepProvider<T, Step = S>,
+{
+    let trace = shrink::try_shrink(mismatch.trace.clone(), |t| {
+        Ok(replay(timer, left, right, start, t, eq)?.is_some())
+    })?;
+    Ok(replay(timer, left, right, start, &trace, eq)?.unwrap_or(mismatch))
+}
+
+fn difference<S: Clone>(
+    l: &[S],
+    r: &[S],
+    eq: &impl Fn(&S, &S) -> bool,
+) -> (Vec<S>, Vec<S>) {
+    let only_left = l
+        .iter()
+        .filter(|a| !r.iter().any(|b| eq(a, b)))
+        .cloned()
+        .collect();
+    let only_right = r
+        .iter()
+        .filter(|b| !l.iter().any(|a| eq(a, b)))
+        .cloned()
+        .collect();
+    (only_left, only_right)
+}
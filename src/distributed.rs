@@ -0,0 +1,722 @@
+//! Automated search distributed across independent workers
+//!
+//! A [`DistributedNavigator`] coordinates a search for valid expressions
+//! whose frontier is partitioned into [`Batch`]es of [`Task`]s and processed
+//! by [`Worker`]s, which may run in other processes or on other machines. The
+//! coordinator and workers communicate only through a user-supplied
+//! [`Transport`], and batches and their results can be serialized to JSON
+//! with codecs for expressions and steps (see [`ExpCodec`] and
+//! [`StepCodec`]). The coordinator merges the frontiers returned by workers
+//! into a single visited set of canonical expressions (see [`ExpCanon`]) and
+//! collects the valid expressions they find.
+//!
+//! [`LocalTransport`] runs the workers in the current process, which is
+//! useful for testing a deployment before replacing it with a network
+//! transport.
+
+use crate::{
+    InvalidArgument, ProviderError, Step, StepProvider, Timer, ValidityChecker,
+    canon::{BackendVisited, CanonVisited, ExpCanon, Visited, VisitedBackend},
+    json::Value,
+    trace::{ExpCodec, StepCodec},
+};
+
+use std::collections::VecDeque;
+
+/// An expression to explore, with the steps that reach it from the start
+/// expression of the search
+#[derive(Debug, Clone)]
+pub struct Task<S: Step> {
+    /// The expression
+    pub exp: S::Exp,
+    /// The steps that reach the expression
+    pub path: Vec<S>,
+}
+
+/// A share of the frontier of a search, sent to a worker
+#[derive(Clone)]
+pub struct Batch<S: Step> {
+    /// The expressions to explore
+    pub tasks: Vec<Task<S>>,
+    /// The number of steps needed to reach the best valid expression known
+    /// to the coordinator (if any), which expressions must improve on
+    pub bound: Option<usize>,
+}
+
+/// The result of processing a [`Batch`]
+#[derive(Clone)]
+pub struct BatchResult<S: Step> {
+    /// The valid expressions found
+    pub solutions: Vec<Task<S>>,
+    /// The expressions of the batch that were not explored
+    pub pending: Vec<Task<S>>,
+    /// The newly discovered expressions that remain to be explored
+    pub frontier: Vec<Task<S>>,
+    /// The number of expressions explored
+    pub explored: usize,
+}
+
+fn field(key: &str, value: Value) -> (String, Value) {
+    (key.to_owned(), value)
+}
+
+fn tasks_to_json<S: Step>(
+    tasks: &[Task<S>],
+    exp_codec: &impl ExpCodec<S>,
+    step_codec: &impl StepCodec<S>,
+) -> Value {
+    Value::Array(
+        tasks
+            .iter()
+            .map(|t| t.to_json(exp_codec, step_codec))
+            .collect(),
+    )
+}
+
+fn tasks_from_json<S: Step>(
+    v: &Value,
+    exp_codec: &impl ExpCodec<S>,
+    step_codec: &impl StepCodec<S>,
+) -> Option<Vec<Task<S>>> {
+    v.as_array()?
+        .iter()
+        .map(|t| Task::from_json(t, exp_codec, step_codec))
+        .collect()
+}
+
+impl<S: Step> Task<S> {
+    /// Returns the task as a JSON object
+    pub fn to_json(
+        &self,
+        exp_codec: &impl ExpCodec<S>,
+        step_codec: &impl StepCodec<S>,
+    ) -> Value {
+        let path = self.path.iter().map(|s| step_codec.encode(s)).collect();
+        Value::Object(vec![
+            field("exp", exp_codec.encode(&self.exp)),
+            field("path", Value::Array(path)),
+        ])
+    }
+
+    /// Reads a task from a JSON object (as written by [`Self::to_json`])
+    pub fn from_json(
+        v: &Value,
+        exp_codec: &impl ExpCodec<S>,
+        step_codec: &impl StepCodec<S>,
+    ) -> Option<Self> {
+        Some(Self {
+            exp: exp_codec.decode(v.get("exp")?)?,
+            path: v
+                .get("path")?
+                .as_array()?
+                .iter()
+                .map(|s| step_codec.decode(s))
+                .collect::<Option<_>>()?,
+        })
+    }
+}
+
+impl<S: Step> Batch<S> {
+    /// Returns the batch as a JSON object
+    pub fn to_json(
+        &self,
+        exp_codec: &impl ExpCodec<S>,
+        step_codec: &impl StepCodec<S>,
+    ) -> Value {
+        Value::Object(vec![
+            field("tasks", tasks_to_json(&self.tasks, exp_codec, step_codec)),
+            field(
+                "bound",
+                self.bound.map_or(Value::Null, |b| Value::Number(b as f64)),
+            ),
+        ])
+    }
+
+    /// Reads a batch from a JSON object (as written by [`Self::to_json`])
+    pub fn from_json(
+        v: &Value,
+        exp_codec: &impl ExpCodec<S>,
+        step_codec: &impl StepCodec<S>,
+    ) -> Option<Self> {
+        Some(Self {
+            tasks: tasks_from_json(v.get("tasks")?, exp_codec, step_codec)?,
+            bound: match v.get("bound")? {
+                Value::Null => None,
+                b => Some(b.as_u64()? as usize),
+            },
+        })
+    }
+}
+
+impl<S: Step> BatchResult<S> {
+    /// Returns the result as a JSON object
+    pub fn to_json(
+        &self,
+        exp_codec: &impl ExpCodec<S>,
+        step_codec: &impl StepCodec<S>,
+    ) -> Value {
+        Value::Object(vec![
+            field(
+                "solutions",
+                tasks_to_json(&self.solutions, exp_codec, step_codec),
+            ),
+            field(
+                "pending",
+                tasks_to_json(&self.pending, exp_codec, step_codec),
+            ),
+            field(
+                "frontier",
+                tasks_to_json(&self.frontier, exp_codec, step_codec),
+            ),
+            field("explored", Value::Number(self.explored as f64)),
+        ])
+    }
+
+    /// Reads a result from a JSON object (as written by [`Self::to_json`])
+    pub fn from_json(
+        v: &Value,
+        exp_codec: &impl ExpCodec<S>,
+        step_codec: &impl StepCodec<S>,
+    ) -> Option<Self> {
+        Some(Self {
+            solutions: tasks_from_json(
+                v.get("solutions")?,
+                exp_codec,
+                step_codec,
+            )?,
+            pending: tasks_from_json(v.get("pending")?, exp_codec, step_codec)?,
+            frontier: tasks_from_json(
+                v.get("frontier")?,
+                exp_codec,
+                step_codec,
+            )?,
+            explored: v.get("explored")?.as_u64()? as usize,
+        })
+    }
+}
+
+/// A processor of batches, with its own step provider and validity checker
+pub struct Worker<T: Timer, S: Step> {
+    provider: Box<dyn StepProvider<T, Step = S>>,
+    checker: Box<dyn ValidityChecker<Exp = S::Exp>>,
+    max_explored: usize,
+}
+
+impl<T: Timer, S: Step + Clone> Worker<T, S> {
+    /// Creates a new [`Worker`] that explores at most `max_explored`
+    /// (positive) expressions per batch, returning the rest of its frontier
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_explored` is zero (see [`Self::try_new`])
+    #[track_caller]
+    pub fn new(
+        provider: impl StepProvider<T, Step = S> + 'static,
+        checker: impl ValidityChecker<Exp = S::Exp> + 'static,
+        max_explored: usize,
+    ) -> Self {
+        InvalidArgument::unwrap(Self::try_new(provider, checker, max_explored))
+    }
+
+    /// Creates a new [`Worker`] (see [`Self::new`]), or fails if
+    /// `max_explored` is zero
+    pub fn try_new(
+        provider: impl StepProvider<T, Step = S> + 'static,
+        checker: impl ValidityChecker<Exp = S::Exp> + 'static,
+        max_explored: usize,
+    ) -> Result<Self, InvalidArgument> {
+        InvalidArgument::check(
+            max_explored > 0,
+            "max_explored must be positive",
+            || Self {
+                provider: Box::new(provider),
+                checker: Box::new(checker),
+                max_explored,
+            },
+        )
+    }
+
+    /// Explores the expressions of a batch depth-first until the batch is
+    /// exhausted, the exploration limit is reached, or `timer` is cut off
+    pub fn process(&mut self, timer: &T, batch: Batch<S>) -> BatchResult<S> {
+        let mut bound = batch.bound;
+        // Each task is paired with whether or not it came from the batch
+        let mut stack: Vec<_> =
+            batch.tasks.into_iter().rev().map(|t| (t, true)).collect();
+        let mut result = BatchResult {
+            solutions: vec![],
+            pending: vec![],
+            frontier: vec![],
+            explored: 0,
+        };
+        let mut current: Option<S::Exp> = None;
+        while let Some((task, given)) = stack.pop() {
+            if result.explored >= self.max_explored || timer.tick().is_err() {
+                stack.push((task, given));
+                break;
+            }
+            let depth = task.path.len();
+            if bound.is_some_and(|b| depth >= b) {
+                continue;
+            }
+            if self.checker.check(&task.exp) {
+                result.explored += 1;
+                bound = Some(depth);
+                result.solutions.push(task);
+                continue;
+            }
+            if bound.is_some_and(|b| depth + 1 >= b) {
+                result.explored += 1;
+                continue;
+            }
+            // The provider starts a session at the first expression it is
+            // asked about, and later expressions are jumps within it
+            match &current {
+                None => self.provider.on_session_start(&task.exp),
+                Some(prev) => self.provider.on_external_edit(prev, &task.exp),
+            }
+            current = Some(task.exp.clone());
+            let steps = match self.provider.try_provide(timer, &task.exp) {
+                Ok(steps) => steps,
+                Err(ProviderError::Cutoff(_)) => {
+                    stack.push((task, given));
+                    break;
+                }
+                Err(ProviderError::Failure(_)) => {
+                    result.explored += 1;
+                    continue;
+                }
+            };
+            result.explored += 1;
+            let results = S::apply_all(&steps, &task.exp);
+            for (step, exp) in steps.into_iter().zip(results).rev() {
+                if let Some(exp) = exp {
+                    let mut path = task.path.clone();
+                    path.push(step);
+                    stack.push((Task { exp, path }, false));
+                }
+            }
+        }
+        for (task, given) in stack.into_iter().rev() {
+            if given {
+                result.pending.push(task);
+            } else {
+                result.frontier.push(task);
+            }
+        }
+        result
+    }
+}
+
+/// A means of sending batches to workers and receiving their results
+pub trait Transport<S: Step> {
+    /// Sends a batch to be processed by the worker at index `worker`
+    fn send(&mut self, worker: usize, batch: Batch<S>);
+
+    /// Waits for the result of a batch that was sent (and returns it with
+    /// the index of its worker), or returns `None` if no result will arrive
+    /// (e.g., because the connection to the workers was lost)
+    fn receive(&mut self) -> Option<(usize, BatchResult<S>)>;
+}
+
+/// A transport that processes batches immediately, in the current process
+///
+/// Batches sent to a worker index that is out of range are dropped, so a
+/// [`DistributedNavigator`] with more workers than the transport ends with
+/// [`DistributedStatus::Disconnected`].
+pub struct LocalTransport<T: Timer, S: Step> {
+    workers: Vec<Worker<T, S>>,
+    timer: T,
+    results: VecDeque<(usize, BatchResult<S>)>,
+}
+
+impl<T: Timer, S: Step> LocalTransport<T, S> {
+    /// Creates a new [`LocalTransport`] for `workers` (at least one), each
+    /// of which processes its batches with `timer`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `workers` is empty (see [`Self::try_new`])
+    #[track_caller]
+    pub fn new(workers: Vec<Worker<T, S>>, timer: T) -> Self {
+        InvalidArgument::unwrap(Self::try_new(workers, timer))
+    }
+
+    /// Creates a new [`LocalTransport`] (see [`Self::new`]), or fails if
+    /// `workers` is empty
+    pub fn try_new(
+        workers: Vec<Worker<T, S>>,
+        timer: T,
+    ) -> Result<Self, InvalidArgument> {
+        InvalidArgument::check(
+            !workers.is_empty(),
+            "at least one worker is required",
+            || Self {
+                workers,
+                timer,
+                results: VecDeque::new(),
+            },
+        )
+    }
+
+    /// Returns the number of workers
+    pub fn workers(&self) -> usize {
+        self.workers.len()
+    }
+}
+
+impl<T: Timer, S: Step + Clone> Transport<S> for LocalTransport<T, S> {
+    fn send(&mut self, worker: usize, batch: Batch<S>) {
+        if let Some(w) = self.workers.get_mut(worker) {
+            let result = w.process(&self.timer, batch);
+            self.results.push_back((worker, result));
+        }
+    }
+
+    fn receive(&mut self) -> Option<(usize, BatchResult<S>)> {
+        self.results.pop_front()
+    }
+}
+
+/// The ways a run of a [`DistributedNavigator`] can end
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DistributedStatus {
+    /// Every expression that could improve on the best valid expression was
+    /// explored (so the best result is optimal)
+    Exhausted,
+    /// The budget of the coordinator was cut off (the search can be
+    /// continued by running it again)
+    OutOfBudget,
+    /// The transport stopped delivering results, so the expressions sent to
+    /// the workers with outstanding batches were lost
+    Disconnected,
+}
+
+/// The coordinator of a search distributed across workers
+pub struct DistributedNavigator<S: Step> {
+    frontier: VecDeque<Task<S>>,
+    visited: Box<dyn Visited<S::Exp>>,
+    solutions: Vec<Task<S>>,
+    workers: usize,
+    batch_size: usize,
+    explored: usize,
+}
+
+impl<S: Step + Clone> DistributedNavigator<S> {
+    /// Creates a new [`DistributedNavigator`] that searches from `start`
+    /// with `workers` (positive) workers, sending batches of at most
+    /// `batch_size` (positive) expressions and identifying expressions that
+    /// are equivalent according to `canon`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `workers` or `batch_size` is zero (see [`Self::try_new`])
+    #[track_caller]
+    pub fn new<X>(
+        start: S::Exp,
+        canon: X,
+        workers: usize,
+        batch_size: usize,
+    ) -> Self
+    where
+        X: ExpCanon<S::Exp> + 'static,
+        S::Exp: 'static,
+    {
+        InvalidArgument::unwrap(Self::try_new(
+            start, canon, workers, batch_size,
+        ))
+    }
+
+    /// Creates a new [`DistributedNavigator`] (see [`Self::new`]), or fails
+    /// if `workers` or `batch_size` is zero
+    pub fn try_new<X>(
+        start: S::Exp,
+        canon: X,
+        workers: usize,
+        batch_size: usize,
+    ) -> Result<Self, InvalidArgument>
+    where
+        X: ExpCanon<S::Exp> + 'static,
+        S::Exp: 'static,
+    {
+        if workers == 0 {
+            return Err(InvalidArgument("at least one worker is required"));
+        }
+        if batch_size == 0 {
+            return Err(InvalidArgument("batch_size must be positive"));
+        }
+        let mut visited: Box<dyn Visited<S::Exp>> =
+            Box::new(CanonVisited::new(canon));
+        visited.visit(&start, 0);
+        Ok(Self {
+            frontier: VecDeque::from([Task {
+                exp: start,
+                path: vec![],
+            }]),
+            visited,
+            solutions: vec![],
+            workers,
+            batch_size,
+            explored: 0,
+        })
+    }
+
+    /// Identifies equivalent expressions according to `canon` (instead of the
+    /// one given to [`Self::new`]), storing the visited expressions with
+    /// `backend` (e.g., to bound the memory of very large searches)
+    ///
+    /// Expressions visited before this call are forgotten, except for those
+    /// still in the frontier.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the parameters of `backend` are out of range (see
+    /// [`Self::try_with_canon_backend`])
+    #[track_caller]
+    pub fn with_canon_backend<X>(
+        self,
+        canon: X,
+        backend: VisitedBackend,
+    ) -> Self
+    where
+        X: ExpCanon<S::Exp> + 'static,
+        X::Key: Clone + std::hash::Hash,
+        S::Exp: 'static,
+    {
+        InvalidArgument::unwrap(self.try_with_canon_backend(canon, backend))
+    }
+
+    /// Like [`Self::with_canon_backend`], but fails if the parameters of
+    /// `backend` are out of range
+    pub fn try_with_canon_backend<X>(
+        mut self,
+        canon: X,
+        backend: VisitedBackend,
+    ) -> Result<Self, InvalidArgument>
+    where
+        X: ExpCanon<S::Exp> + 'static,
+        X::Key: Clone + std::hash::Hash,
+        S::Exp: 'static,
+    {
+        self.visited = Box::new(BackendVisited::try_new(canon, backend)?);
+        for task in &self.frontier {
+            self.visited.visit(&task.exp, task.path.len());
+        }
+        Ok(self)
+    }
+
+    /// Returns the valid expressions found so far, in the order they were
+    /// found
+    pub fn solutions(&self) -> &[Task<S>] {
+        &self.solutions
+    }
+
+    /// Returns the valid expression reachable in the fewest steps found so
+    /// far (if any)
+    pub fn best(&self) -> Option<&Task<S>> {
+        self.solutions.iter().min_by_key(|t| t.path.len())
+    }
+
+    /// Returns the expressions that remain to be explored
+    pub fn frontier(&self) -> &VecDeque<Task<S>> {
+        &self.frontier
+    }
+
+    /// Returns the number of expressions explored by the workers so far
+    pub fn explored(&self) -> usize {
+        self.explored
+    }
+
+    fn bound(&self) -> Option<usize> {
+        self.best().map(|t| t.path.len())
+    }
+
+    /// Sends a batch to a worker (if there is any work)
+    fn dispatch(
+        &mut self,
+        worker: usize,
+        transport: &mut impl Transport<S>,
+    ) -> bool {
+        let n = self.batch_size.min(self.frontier.len());
+        if n == 0 {
+            return false;
+        }
+        let tasks = self.frontier.drain(..n).collect();
+        transport.send(
+            worker,
+            Batch {
+                tasks,
+                bound: self.bound(),
+            },
+        );
+        true
+    }
+
+    /// Merges the result of a batch into the search
+    fn merge(&mut self, result: BatchResult<S>) {
+        self.explored += result.explored;
+        self.solutions.extend(result.solutions);
+        let bound = self.bound();
+        let improves = |t: &Task<S>| bound.is_none_or(|b| t.path.len() < b);
+        // Pending tasks were already visited when they were discovered
+        self.frontier
+            .extend(result.pending.into_iter().filter(|t| improves(t)));
+        for task in result.frontier {
+            if improves(&task) && self.visited.visit(&task.exp, task.path.len())
+            {
+                self.frontier.push_back(task);
+            }
+        }
+    }
+
+    /// Runs the search until the frontier is exhausted or `budget` (ticked
+    /// once per received result) is cut off
+    pub fn run(
+        &mut self,
+        transport: &mut impl Transport<S>,
+        budget: &impl Timer,
+    ) -> DistributedStatus {
+        let mut busy = 0;
+        let mut idle: Vec<usize> = (0..self.workers).rev().collect();
+        let mut cut_off = false;
+        loop {
+            while !cut_off && let Some(&worker) = idle.last() {
+                if !self.dispatch(worker, transport) {
+                    break;
+                }
+                idle.pop();
+                busy += 1;
+            }
+            if busy == 0 {
+                return if cut_off {
+                    DistributedStatus::OutOfBudget
+                } else {
+                    DistributedStatus::Exhausted
+                };
+            }
+            let Some((worker, result)) = transport.receive() else {
+                return DistributedStatus::Disconnected;
+            };
+            busy -= 1;
+            idle.push(worker);
+            self.merge(result);
+            cut_off = cut_off || budget.tick().is_err();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::TickBudget;
+    use crate::testing::{AllEqual, Fill, Fills, Slots, budget, empty};
+
+    use std::sync::{Arc, Mutex};
+
+    /// Records the lifecycle hooks it receives before providing fills
+    struct Hooks {
+        fills: Fills,
+        log: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl StepProvider<TickBudget> for Hooks {
+        type Step = Fill;
+
+        fn provide(
+            &mut self,
+            timer: &TickBudget,
+            e: &Slots,
+        ) -> Result<Vec<Fill>, <TickBudget as Timer>::EarlyCutoff> {
+            self.fills.provide(timer, e)
+        }
+
+        fn on_session_start(&mut self, _: &Slots) {
+            self.log.lock().unwrap().push("start");
+        }
+
+        fn on_external_edit(&mut self, _: &Slots, _: &Slots) {
+            self.log.lock().unwrap().push("edit");
+        }
+    }
+
+    fn worker(max_explored: usize) -> Worker<TickBudget, Fill> {
+        Worker::new(Fills { digits: 2 }, AllEqual(1), max_explored)
+    }
+
+    fn navigator(workers: usize) -> DistributedNavigator<Fill> {
+        DistributedNavigator::new(empty(), |e: &Slots| *e, workers, 4)
+    }
+
+    #[test]
+    fn local_transports_need_a_worker() {
+        assert!(
+            LocalTransport::<TickBudget, Fill>::try_new(vec![], budget())
+                .is_err()
+        );
+        let transport = LocalTransport::new(vec![worker(2)], budget());
+        assert_eq!(transport.workers(), 1);
+    }
+
+    #[test]
+    fn workers_start_one_session_per_batch() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let mut w = Worker::new(
+            Hooks {
+                fills: Fills { digits: 1 },
+                log: log.clone(),
+            },
+            AllEqual(0),
+            1_000,
+        );
+        let task = |exp| Task { exp, path: vec![] };
+        let result = w.process(
+            &budget(),
+            Batch {
+                tasks: vec![task(empty()), task([Some(0), None, None])],
+                bound: None,
+            },
+        );
+        assert!(!result.solutions.is_empty());
+        let log = log.lock().unwrap();
+        assert!(log.len() > 2);
+        assert_eq!(log[0], "start");
+        assert!(log[1..].iter().all(|&h| h == "edit"));
+    }
+
+    #[test]
+    fn local_transports_find_solutions() {
+        let mut nav = navigator(2);
+        let mut transport =
+            LocalTransport::new(vec![worker(3), worker(3)], budget());
+        assert_eq!(
+            nav.run(&mut transport, &budget()),
+            DistributedStatus::Exhausted
+        );
+        assert_eq!(nav.best().unwrap().exp, [Some(1); 3]);
+    }
+
+    #[test]
+    fn out_of_range_backends_are_rejected() {
+        let lru = |capacity| VisitedBackend::Lru { capacity };
+        let canon = |e: &Slots| *e;
+        assert!(navigator(1).try_with_canon_backend(canon, lru(0)).is_err());
+        let mut nav =
+            navigator(1).try_with_canon_backend(canon, lru(16)).unwrap();
+        let mut transport = LocalTransport::new(vec![worker(3)], budget());
+        assert_eq!(
+            nav.run(&mut transport, &budget()),
+            DistributedStatus::Exhausted
+        );
+        assert!(nav.best().is_some());
+    }
+
+    #[test]
+    fn missing_workers_disconnect() {
+        let mut nav = navigator(2);
+        let mut transport = LocalTransport::new(vec![worker(3)], budget());
+        assert_eq!(
+            nav.run(&mut transport, &budget()),
+            DistributedStatus::Disconnected
+        );
+    }
+}
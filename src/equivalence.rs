@@ -0,0 +1,166 @@
+//! Collapsing equivalent steps into classes
+//!
+//! Providers sometimes produce many steps that differ only in irrelevant ways
+//! (e.g., the fresh name of a variable they introduce), which overwhelms both
+//! programmers and automated deciders. Steps that implement
+//! [`EquivalentSteps`] (or any key function) can be collapsed into
+//! [`StepClass`]es, each shown as one representative with a count and
+//! expanded on demand (see [`Controller::provide_collapsed`]). The
+//! [`Collapsed`] decider wrapper lets an existing decider choose among class
+//! representatives only.
+
+use crate::{
+    Controller, Decider, PbnError, Step, StepProvider, Timer, ValidityChecker,
+    history::HistoryStore,
+};
+
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+
+/// Steps that can be equivalent to one another
+pub trait EquivalentSteps: Step {
+    /// The notion of equivalence classes
+    type Key: Ord;
+
+    /// Returns the equivalence class of the step (steps with equal keys are
+    /// interchangeable)
+    fn equivalence_key(&self) -> Self::Key;
+}
+
+/// A nonempty class of equivalent steps
+#[derive(Debug, Clone)]
+pub struct StepClass<S> {
+    members: Vec<S>,
+}
+
+impl<S> StepClass<S> {
+    /// Returns the representative of the class (its first member)
+    pub fn representative(&self) -> &S {
+        &self.members[0]
+    }
+
+    /// Returns the number of steps in the class
+    pub fn count(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Returns the steps in the class (in provided order)
+    pub fn members(&self) -> &[S] {
+        &self.members
+    }
+
+    /// Returns the representative of the class, discarding the other steps
+    pub fn into_representative(self) -> S {
+        self.members
+            .into_iter()
+            .next()
+            .expect("classes are nonempty")
+    }
+
+    /// Returns the steps in the class (in provided order)
+    pub fn expand(self) -> Vec<S> {
+        self.members
+    }
+}
+
+/// Returns the index of the class of each step, numbering classes by first
+/// appearance
+fn classify<S, K: Ord>(steps: &[S], key: impl Fn(&S) -> K) -> Vec<usize> {
+    let mut classes = BTreeMap::new();
+    steps
+        .iter()
+        .map(|step| {
+            let n = classes.len();
+            *classes.entry(key(step)).or_insert(n)
+        })
+        .collect()
+}
+
+/// Collapses steps with equal keys into classes (ordered by first appearance)
+pub fn collapse_by<S, K: Ord>(
+    steps: Vec<S>,
+    key: impl Fn(&S) -> K,
+) -> Vec<StepClass<S>> {
+    let indices = classify(&steps, key);
+    let mut classes: Vec<StepClass<S>> = vec![];
+    for (step, c) in steps.into_iter().zip(indices) {
+        match classes.get_mut(c) {
+            Some(class) => class.members.push(step),
+            None => classes.push(StepClass {
+                members: vec![step],
+            }),
+        }
+    }
+    classes
+}
+
+/// Collapses equivalent steps into classes (ordered by first appearance)
+pub fn collapse<S: EquivalentSteps>(steps: Vec<S>) -> Vec<StepClass<S>> {
+    collapse_by(steps, S::equivalence_key)
+}
+
+/// A decider that decides among the representatives of classes of
+/// equivalent steps using another decider
+pub struct Collapsed<D> {
+    inner: D,
+}
+
+impl<D> Collapsed<D> {
+    /// Creates a new [`Collapsed`] decider wrapping `inner`
+    pub fn new(inner: D) -> Self {
+        Self { inner }
+    }
+
+    /// Returns the wrapped decider
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+}
+
+impl<S, D> Decider<S> for Collapsed<D>
+where
+    S: EquivalentSteps + Clone,
+    D: Decider<S>,
+{
+    fn decide(&mut self, e: &S::Exp, steps: &[S]) -> Option<usize> {
+        let classes = classify(steps, S::equivalence_key);
+        let mut firsts = vec![];
+        for (i, &c) in classes.iter().enumerate() {
+            if c == firsts.len() {
+                firsts.push(i);
+            }
+        }
+        let representatives: Vec<S> =
+            firsts.iter().map(|&i| steps[i].clone()).collect();
+        let choice = self.inner.decide(e, &representatives)?;
+        firsts.get(choice).copied()
+    }
+}
+
+impl<T, S, P, C, H> Controller<T, S, P, C, H>
+where
+    T: Timer,
+    S: Step,
+    P: StepProvider<T, Step = S> + ?Sized,
+    C: ValidityChecker<Exp = S::Exp> + ?Sized,
+    H: HistoryStore<S::Exp> + ?Sized,
+{
+    /// Provide a list of possible next steps, collapsing steps with equal
+    /// keys into classes (see [`Self::provide`])
+    pub fn provide_collapsed_by<K: Ord>(
+        &mut self,
+        key: impl Fn(&S) -> K,
+    ) -> Result<Vec<StepClass<S>>, PbnError<T::EarlyCutoff>> {
+        Ok(collapse_by(self.provide()?, key))
+    }
+
+    /// Provide a list of possible next steps, collapsing equivalent steps
+    /// into classes (see [`Self::provide`])
+    pub fn provide_collapsed(
+        &mut self,
+    ) -> Result<Vec<StepClass<S>>, PbnError<T::EarlyCutoff>>
+    where
+        S: EquivalentSteps,
+    {
+        self.provide_collapsed_by(S::equivalence_key)
+    }
+}
@@ -0,0 +1,211 @@
+//! Type-erased steps and providers
+//!
+//! [`BoxStep`] and [`BoxProvider`] let step providers with different step
+//! types (over the same notion of expressions) coexist in one combinator or
+//! [`Controller`](crate::Controller), dispatching [`Step::apply`]
+//! dynamically.
+
+use crate::{
+    Annotation, Capabilities, Context, Feedback, ProgressReporter,
+    ProviderError, Step, StepProvider, Timer,
+};
+
+use core::any::Any;
+
+use alloc::{boxed::Box, string::String, vec::Vec};
+
+trait ErasedStep<E> {
+    fn apply(&self, e: &E) -> Option<E>;
+    fn applicable(&self, e: &E) -> bool;
+    fn why_inapplicable(&self, e: &E) -> Option<String>;
+    fn source(&self) -> Option<&str>;
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<S: Step + 'static> ErasedStep<S::Exp> for S {
+    fn apply(&self, e: &S::Exp) -> Option<S::Exp> {
+        Step::apply(self, e)
+    }
+
+    fn applicable(&self, e: &S::Exp) -> bool {
+        Step::applicable(self, e)
+    }
+
+    fn why_inapplicable(&self, e: &S::Exp) -> Option<String> {
+        Step::why_inapplicable(self, e)
+    }
+
+    fn source(&self) -> Option<&str> {
+        Step::source(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A type-erased step over expressions of type `E`
+pub struct BoxStep<E> {
+    inner: Box<dyn ErasedStep<E>>,
+}
+
+impl<E> BoxStep<E> {
+    /// Erases the type of a step
+    pub fn new<S: Step<Exp = E> + 'static>(step: S) -> Self {
+        Self {
+            inner: Box::new(step),
+        }
+    }
+
+    /// Returns a reference to the underlying step if it has type `S`
+    pub fn downcast_ref<S: Step<Exp = E> + 'static>(&self) -> Option<&S> {
+        self.inner.as_any().downcast_ref()
+    }
+}
+
+impl<E: Clone> Step for BoxStep<E> {
+    type Exp = E;
+
+    fn apply(&self, e: &Self::Exp) -> Option<Self::Exp> {
+        self.inner.apply(e)
+    }
+
+    fn applicable(&self, e: &Self::Exp) -> bool {
+        self.inner.applicable(e)
+    }
+
+    fn why_inapplicable(&self, e: &Self::Exp) -> Option<String> {
+        self.inner.why_inapplicable(e)
+    }
+
+    fn source(&self) -> Option<&str> {
+        self.inner.source()
+    }
+}
+
+/// A step provider whose steps are type-erased into [`BoxStep`]s
+///
+/// When a step that did not come from the inner provider is decided on, the
+/// inner provider cannot be told which step it was, so it is instead notified
+/// via [`StepProvider::on_session_start`] that navigation continues from the
+/// resulting expression.
+pub struct BoxProvider<P> {
+    inner: P,
+}
+
+impl<P> BoxProvider<P> {
+    /// Creates a new [`BoxProvider`]
+    pub fn new(inner: P) -> Self {
+        Self { inner }
+    }
+}
+
+impl<T, P> StepProvider<T> for BoxProvider<P>
+where
+    T: Timer,
+    P: StepProvider<T>,
+    P::Step: 'static,
+{
+    type Step = BoxStep<<P::Step as Step>::Exp>;
+
+    fn provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        Ok(self
+            .inner
+            .provide(timer, e)?
+            .into_iter()
+            .map(BoxStep::new)
+            .collect())
+    }
+
+    fn provide_partial(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> (Vec<Self::Step>, Option<T::EarlyCutoff>) {
+        let (steps, cutoff) = self.inner.provide_partial(timer, e);
+        (steps.into_iter().map(BoxStep::new).collect(), cutoff)
+    }
+
+    fn try_provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, ProviderError<T::EarlyCutoff>> {
+        Ok(self
+            .inner
+            .try_provide(timer, e)?
+            .into_iter()
+            .map(BoxStep::new)
+            .collect())
+    }
+
+    fn provide_annotated(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+        annotations: &[Annotation],
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        Ok(self
+            .inner
+            .provide_annotated(timer, e, annotations)?
+            .into_iter()
+            .map(BoxStep::new)
+            .collect())
+    }
+
+    fn capabilities(&mut self) -> Capabilities {
+        self.inner.capabilities().forwarded()
+    }
+
+    fn on_session_start(&mut self, start: &<Self::Step as Step>::Exp) {
+        self.inner.on_session_start(start);
+    }
+
+    fn on_decide(&mut self, step: &Self::Step, e: &<Self::Step as Step>::Exp) {
+        match step.downcast_ref::<P::Step>() {
+            Some(step) => self.inner.on_decide(step, e),
+            None => self.inner.on_session_start(e),
+        }
+    }
+
+    fn on_undo(&mut self, e: &<Self::Step as Step>::Exp) {
+        self.inner.on_undo(e);
+    }
+
+    fn on_external_edit(
+        &mut self,
+        prev: &<Self::Step as Step>::Exp,
+        e: &<Self::Step as Step>::Exp,
+    ) {
+        self.inner.on_external_edit(prev, e);
+    }
+
+    fn on_refine(
+        &mut self,
+        valid: &dyn Fn(&<Self::Step as Step>::Exp) -> bool,
+    ) {
+        self.inner.on_refine(valid);
+    }
+
+    fn on_feedback(&mut self, step: &Self::Step, feedback: Feedback) {
+        if let Some(step) = step.downcast_ref::<P::Step>() {
+            self.inner.on_feedback(step, feedback);
+        }
+    }
+
+    fn set_progress(&mut self, reporter: ProgressReporter) {
+        self.inner.set_progress(reporter);
+    }
+
+    fn set_context(&mut self, context: Context) {
+        self.inner.set_context(context);
+    }
+
+    fn warmup(&mut self, timer: &T) -> Result<(), T::EarlyCutoff> {
+        self.inner.warmup(timer)
+    }
+}
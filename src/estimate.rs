@@ -0,0 +1,121 @@
+//! Search-space size estimation
+//!
+//! The [`estimate`] function samples random navigation paths under a provider
+//! to estimate (in the style of Knuth's tree-size estimator) the number of
+//! navigation paths from a start expression and the number of those paths
+//! that end at valid expressions. Because paths are counted rather than
+//! distinct expressions, expressions reachable in several ways are counted
+//! several times.
+
+use crate::{Step, StepProvider, Timer, ValidityChecker, rng::Rng};
+
+use alloc::vec::Vec;
+
+/// Configuration for search-space size estimation
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The number of random paths to sample
+    pub samples: usize,
+    /// The maximum length of each sampled path
+    pub max_depth: usize,
+    /// The seed for choosing random paths
+    pub seed: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            samples: 1000,
+            max_depth: 20,
+            seed: 0,
+        }
+    }
+}
+
+/// The mean and (sample) variance of an estimated quantity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Statistic {
+    /// The mean of the samples
+    pub mean: f64,
+    /// The unbiased sample variance of the samples
+    pub variance: f64,
+}
+
+impl Statistic {
+    fn of(xs: &[f64]) -> Self {
+        let n = xs.len() as f64;
+        if xs.is_empty() {
+            return Self {
+                mean: 0.0,
+                variance: 0.0,
+            };
+        }
+        let mean = xs.iter().sum::<f64>() / n;
+        let variance = if xs.len() < 2 {
+            0.0
+        } else {
+            xs.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / (n - 1.0)
+        };
+        Self { mean, variance }
+    }
+}
+
+/// An estimate of the size of a search space
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    /// The estimated number of reachable expressions (counted by path)
+    pub expressions: Statistic,
+    /// The estimated number of reachable valid expressions (counted by path)
+    pub solutions: Statistic,
+}
+
+/// Estimates the size of the search space from `start` under a provider
+/// (bounded by the maximum depth of the configuration)
+pub fn estimate<T, P>(
+    timer: &T,
+    provider: &mut P,
+    checker: &impl ValidityChecker<Exp = <P::Step as Step>::Exp>,
+    start: &<P::Step as Step>::Exp,
+    config: &Config,
+) -> Result<Estimate, T::EarlyCutoff>
+where
+    T: Timer,
+    P: StepProvider<T>,
+{
+    let mut rng = Rng::new(config.seed);
+    let mut expressions = Vec::with_capacity(config.samples);
+    let mut solutions = Vec::with_capacity(config.samples);
+    for _ in 0..config.samples {
+        let mut e = start.clone();
+        provider.on_session_start(&e);
+        let mut weight = 1.0;
+        let mut exp_count = 0.0;
+        let mut sol_count = 0.0;
+        for depth in 0..=config.max_depth {
+            exp_count += weight;
+            if checker.check(&e) {
+                sol_count += weight;
+            }
+            if depth == config.max_depth {
+                break;
+            }
+            let mut steps = provider.provide(timer, &e)?;
+            if steps.is_empty() {
+                break;
+            }
+            weight *= steps.len() as f64;
+            let step = steps.swap_remove(rng.below(steps.len()));
+            e = match step.apply(&e) {
+                Some(e2) => e2,
+                None => break,
+            };
+            provider.on_decide(&step, &e);
+        }
+        expressions.push(exp_count);
+        solutions.push(sol_count);
+    }
+    Ok(Estimate {
+        expressions: Statistic::of(&expressions),
+        solutions: Statistic::of(&solutions),
+    })
+}
@@ -0,0 +1,219 @@
+//! Simple preference learning from decider feedback
+//!
+//! Deciders can give [`Feedback`] on provided steps with
+//! [`Controller::give_feedback`](crate::Controller::give_feedback), which is
+//! passed to the provider's [`StepProvider::on_feedback`] hook. The
+//! [`FeedbackRanked`] provider uses this feedback to re-rank the steps of
+//! another provider: steps are grouped into kinds by a key function, liked
+//! kinds are moved toward the front, disliked kinds toward the back, and
+//! kinds marked [`Feedback::Never`] are no longer provided. The learned
+//! [`Preferences`] last for the lifetime of the provider (across session
+//! restarts and external edits) and can be carried over to new providers.
+
+use crate::{
+    Annotation, Capabilities, Context, Feedback, ProgressReporter,
+    ProviderError, Step, StepProvider, Timer,
+};
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    vec::Vec,
+};
+
+/// Preferences for kinds of steps, learned from feedback
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preferences<K: Ord> {
+    scores: BTreeMap<K, i64>,
+    never: BTreeSet<K>,
+}
+
+impl<K: Ord> Default for Preferences<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord> Preferences<K> {
+    /// Creates new, empty preferences
+    pub fn new() -> Self {
+        Self {
+            scores: BTreeMap::new(),
+            never: BTreeSet::new(),
+        }
+    }
+
+    /// Records feedback on a kind of step
+    pub fn record(&mut self, kind: K, feedback: Feedback) {
+        match feedback {
+            Feedback::Liked => *self.scores.entry(kind).or_default() += 1,
+            Feedback::Disliked => *self.scores.entry(kind).or_default() -= 1,
+            Feedback::Never => {
+                self.never.insert(kind);
+            }
+        }
+    }
+
+    /// Returns the score of a kind of step (the number of times it was liked
+    /// minus the number of times it was disliked)
+    pub fn score(&self, kind: &K) -> i64 {
+        self.scores.get(kind).copied().unwrap_or(0)
+    }
+
+    /// Returns whether or not a kind of step should never be provided
+    pub fn is_blocked(&self, kind: &K) -> bool {
+        self.never.contains(kind)
+    }
+
+    /// Forgets all feedback
+    pub fn clear(&mut self) {
+        self.scores.clear();
+        self.never.clear();
+    }
+}
+
+/// A provider that re-ranks (and filters) the steps provided by another
+/// provider according to the feedback given on their kinds
+pub struct FeedbackRanked<P, F, K: Ord> {
+    inner: P,
+    kind: F,
+    preferences: Preferences<K>,
+}
+
+impl<P, F, K: Ord> FeedbackRanked<P, F, K> {
+    /// Creates a new [`FeedbackRanked`] provider that groups steps into kinds
+    /// by `kind`
+    pub fn new(inner: P, kind: F) -> Self {
+        Self {
+            inner,
+            kind,
+            preferences: Preferences::new(),
+        }
+    }
+
+    /// Starts from previously learned preferences
+    pub fn with_preferences(mut self, preferences: Preferences<K>) -> Self {
+        self.preferences = preferences;
+        self
+    }
+
+    /// Returns the learned preferences
+    pub fn preferences(&self) -> &Preferences<K> {
+        &self.preferences
+    }
+
+    /// Returns the inner provider and drops self
+    pub fn into_inner(self) -> P {
+        self.inner
+    }
+
+    fn rank<S>(&self, steps: Vec<S>) -> Vec<S>
+    where
+        F: Fn(&S) -> K,
+    {
+        let mut ranked: Vec<(i64, S)> = steps
+            .into_iter()
+            .filter_map(|step| {
+                let kind = (self.kind)(&step);
+                (!self.preferences.is_blocked(&kind))
+                    .then(|| (-self.preferences.score(&kind), step))
+            })
+            .collect();
+        ranked.sort_by_key(|(score, _)| *score);
+        ranked.into_iter().map(|(_, step)| step).collect()
+    }
+}
+
+impl<T, P, F, K> StepProvider<T> for FeedbackRanked<P, F, K>
+where
+    T: Timer,
+    P: StepProvider<T>,
+    F: Fn(&P::Step) -> K,
+    K: Ord,
+{
+    type Step = P::Step;
+
+    fn provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        let steps = self.inner.provide(timer, e)?;
+        Ok(self.rank(steps))
+    }
+
+    fn provide_partial(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> (Vec<Self::Step>, Option<T::EarlyCutoff>) {
+        let (steps, cutoff) = self.inner.provide_partial(timer, e);
+        (self.rank(steps), cutoff)
+    }
+
+    fn try_provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, ProviderError<T::EarlyCutoff>> {
+        let steps = self.inner.try_provide(timer, e)?;
+        Ok(self.rank(steps))
+    }
+
+    fn provide_annotated(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+        annotations: &[Annotation],
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        let steps = self.inner.provide_annotated(timer, e, annotations)?;
+        Ok(self.rank(steps))
+    }
+
+    fn capabilities(&mut self) -> Capabilities {
+        self.inner.capabilities().forwarded()
+    }
+
+    fn on_session_start(&mut self, start: &<Self::Step as Step>::Exp) {
+        self.inner.on_session_start(start);
+    }
+
+    fn on_decide(&mut self, step: &Self::Step, e: &<Self::Step as Step>::Exp) {
+        self.inner.on_decide(step, e);
+    }
+
+    fn on_undo(&mut self, e: &<Self::Step as Step>::Exp) {
+        self.inner.on_undo(e);
+    }
+
+    fn on_external_edit(
+        &mut self,
+        prev: &<Self::Step as Step>::Exp,
+        e: &<Self::Step as Step>::Exp,
+    ) {
+        self.inner.on_external_edit(prev, e);
+    }
+
+    fn on_refine(
+        &mut self,
+        valid: &dyn Fn(&<Self::Step as Step>::Exp) -> bool,
+    ) {
+        self.inner.on_refine(valid);
+    }
+
+    fn on_feedback(&mut self, step: &Self::Step, feedback: Feedback) {
+        self.preferences.record((self.kind)(step), feedback);
+        self.inner.on_feedback(step, feedback);
+    }
+
+    fn set_progress(&mut self, reporter: ProgressReporter) {
+        self.inner.set_progress(reporter);
+    }
+
+    fn set_context(&mut self, context: Context) {
+        self.inner.set_context(context);
+    }
+
+    fn warmup(&mut self, timer: &T) -> Result<(), T::EarlyCutoff> {
+        self.inner.warmup(timer)
+    }
+}
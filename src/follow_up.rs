@@ -0,0 +1,81 @@
+//! Suggested follow-ups to decided steps
+//!
+//! Some steps naturally come in sequences (e.g., introducing a variable and
+//! then using it). Steps that implement [`FollowUpStep`] suggest the steps
+//! that should come after them, and [`Controller::provide_with_follow_ups`]
+//! surfaces the suggested steps that are also provided as a "recommended
+//! next" list. Because recommendations are drawn from the provided steps,
+//! multi-step flows keep every individual step a legitimate provided step.
+
+use crate::{
+    Controller, Decision, PbnError, Step, StepProvider, Timer, ValidityChecker,
+    history::HistoryStore,
+};
+
+use alloc::vec::Vec;
+
+/// Steps that can suggest follow-up steps
+pub trait FollowUpStep: Step + Sized {
+    /// Returns the steps suggested after this step (in order of preference),
+    /// given the expression `e` that it resulted in
+    fn follow_ups(&self, e: &Self::Exp) -> Vec<Self>;
+}
+
+/// Provided steps, split into those recommended as follow-ups and the rest
+/// (see [`Controller::provide_with_follow_ups`])
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FollowUps<S> {
+    /// The provided steps suggested as follow-ups to the most recent
+    /// decision (in order of preference)
+    pub recommended: Vec<S>,
+    /// The other provided steps (in provided order)
+    pub others: Vec<S>,
+}
+
+impl<S> FollowUps<S> {
+    /// Returns all provided steps, recommended ones first
+    pub fn into_steps(mut self) -> Vec<S> {
+        self.recommended.append(&mut self.others);
+        self.recommended
+    }
+}
+
+impl<T, S, P, C, H> Controller<T, S, P, C, H>
+where
+    T: Timer,
+    S: FollowUpStep + PartialEq,
+    P: StepProvider<T, Step = S> + ?Sized,
+    C: ValidityChecker<Exp = S::Exp> + ?Sized,
+    H: HistoryStore<S::Exp> + ?Sized,
+{
+    /// Returns the follow-ups suggested by the most recently decided step
+    /// (which need not be provided), or nothing if the working expression
+    /// was not reached by a decision
+    pub fn follow_ups(&self) -> Vec<S> {
+        match self.decisions.last().and_then(Decision::step) {
+            Some(step) => step.follow_ups(&self.state),
+            None => Vec::new(),
+        }
+    }
+
+    /// Ask the synthesizer to provide a list of possible next steps (see
+    /// [`Self::provide`]), recommending those that are suggested as
+    /// follow-ups to the most recent decision
+    ///
+    /// Suggested follow-ups that are not provided are not recommended.
+    pub fn provide_with_follow_ups(
+        &mut self,
+    ) -> Result<FollowUps<S>, PbnError<T::EarlyCutoff>> {
+        let mut others = self.provide()?;
+        let mut recommended = Vec::new();
+        for suggestion in self.follow_ups() {
+            if let Some(i) = others.iter().position(|s| *s == suggestion) {
+                recommended.push(others.remove(i));
+            }
+        }
+        Ok(FollowUps {
+            recommended,
+            others,
+        })
+    }
+}
@@ -0,0 +1,80 @@
+//! Scratch copies of sessions for speculative exploration
+//!
+//! User interfaces sometimes let the programmer "preview" a path of decisions
+//! before committing to it. [`Controller::fork`] creates an independent copy
+//! of a session, with its own provider and checker, in which decisions can be
+//! explored and then discarded without affecting the original session (its
+//! working expression, history, timeline, or provider state).
+
+use crate::{
+    Controller, Step, StepProvider, Timer, ValidityChecker,
+    history::HistoryStore,
+};
+
+use alloc::boxed::Box;
+
+impl<T, S, P, C, H> Controller<T, S, P, C, H>
+where
+    T: Timer,
+    S: Step + Clone,
+    P: StepProvider<T, Step = S> + ?Sized,
+    C: ValidityChecker<Exp = S::Exp> + ?Sized,
+    H: HistoryStore<S::Exp> + ?Sized,
+{
+    /// Creates a scratch copy of the session that uses `timer`, `provider`,
+    /// and `checker` (e.g., fresh ones created by a factory)
+    ///
+    /// The fork starts at the working expression of this session, with the
+    /// same timeline, annotations, context, and configuration. It does not save
+    /// history (see [`Self::with_history`]) and has no scorer, resource
+    /// checker, or telemetry sink; these can be attached to it separately.
+    /// The provider is notified of a new session starting at the working
+    /// expression (see [`StepProvider::on_session_start`]).
+    pub fn fork(
+        &self,
+        timer: T,
+        provider: Box<P>,
+        checker: Box<C>,
+    ) -> Controller<T, S, P, C, H> {
+        let mut fork = Self::from_boxed(
+            timer,
+            provider,
+            checker,
+            self.state.clone(),
+            None,
+        );
+        fork.start = self.start.clone();
+        fork.decisions = self.decisions.clone();
+        fork.annotations = self.annotations.clone();
+        fork.resync = self.resync;
+        fork.auto_advance = self.auto_advance;
+        if !self.context.is_empty() {
+            fork.set_context(self.context.clone());
+        }
+        fork.with_timer_pausing(self.pause_timer)
+            .with_capabilities(self.supported)
+    }
+
+    /// Creates a scratch copy of the session using clones of its timer,
+    /// provider, and checkers (see [`Self::fork`])
+    ///
+    /// Unlike [`Self::fork`], all refinements of the notion of validity (see
+    /// [`Self::refine_checker`]) are kept.
+    pub fn fork_cloned(&self) -> Controller<T, S, P, C, H>
+    where
+        T: Clone,
+        P: Clone,
+        C: Clone,
+    {
+        let mut fork = self.fork(
+            self.timer.clone(),
+            self.provider.clone(),
+            self.checkers[0].clone(),
+        );
+        if self.checkers.len() > 1 {
+            fork.checkers = self.checkers.clone();
+            fork.notify_refine();
+        }
+        fork
+    }
+}
@@ -0,0 +1,244 @@
+//! Fuzzing entry points for step providers
+//!
+//! The functions in this module interpret raw fuzzer input (e.g., the `&[u8]`
+//! given to a `cargo fuzz` target) as a sequence of decisions among the
+//! provided steps and check that the provider upholds its basic invariants
+//! along the way. With the `arbitrary` feature, [`Decisions`] can also be
+//! part of a structured fuzz input (see [`navigate_with`]):
+//!
+//! ```ignore
+//! fuzz_target!(|d: pbn::fuzz::Decisions| {
+//!     let mut provider = MyProvider::new();
+//!     let timer = TickBudget::new(1_000);
+//!     pbn::fuzz::navigate_with(&timer, &mut provider, &start(), d, 16)
+//!         .unwrap();
+//! });
+//! ```
+
+use crate::{Step, StepProvider, Timer};
+
+use alloc::vec::Vec;
+
+/// A source of decisions driven by raw fuzzer input
+#[derive(Debug, Clone)]
+pub struct Decisions<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Decisions<'a> {
+    /// Creates a new source of decisions from raw fuzzer input
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    /// Returns whether or not the input has been exhausted
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Returns a decision in `0..n`, consuming just enough bytes of input to
+    /// cover the range (or `None` if the range is empty or the input has been
+    /// exhausted)
+    pub fn choose(&mut self, n: usize) -> Option<usize> {
+        if n == 0 || self.data.is_empty() {
+            return None;
+        }
+        let mut x: usize = 0;
+        let mut range: usize = 1;
+        while range < n {
+            let Some((b, rest)) = self.data.split_first() else {
+                break;
+            };
+            x = x.wrapping_shl(8) | *b as usize;
+            range = range.saturating_mul(256);
+            self.data = rest;
+        }
+        Some(x % n)
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Decisions<'a> {
+    fn arbitrary(
+        u: &mut arbitrary::Unstructured<'a>,
+    ) -> arbitrary::Result<Self> {
+        let len = u.arbitrary_len::<u8>()?;
+        Ok(Self::new(u.bytes(len)?))
+    }
+
+    fn arbitrary_take_rest(
+        u: arbitrary::Unstructured<'a>,
+    ) -> arbitrary::Result<Self> {
+        Ok(Self::new(u.take_rest()))
+    }
+
+    fn size_hint(_depth: usize) -> (usize, Option<usize>) {
+        (0, None)
+    }
+}
+
+/// A violation of a provider invariant found while fuzzing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// A provided step did not apply to the expression it was provided for
+    Inapplicable {
+        /// The number of decisions made before the violation
+        depth: usize,
+        /// The index of the offending step among the provided steps
+        index: usize,
+    },
+    /// The provider reported a cutoff even though the timer did not
+    SpuriousCutoff {
+        /// The number of decisions made before the violation
+        depth: usize,
+    },
+}
+
+impl core::fmt::Display for Violation {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Violation::Inapplicable { depth, index } => write!(
+                f,
+                "provided step {} does not apply (after {} decisions)",
+                index, depth
+            ),
+            Violation::SpuriousCutoff { depth } => write!(
+                f,
+                "provider cut off without timer cutoff (after {} decisions)",
+                depth
+            ),
+        }
+    }
+}
+
+impl core::error::Error for Violation {}
+
+/// Navigates from `start` using decisions driven by `data` (for at most
+/// `max_depth` decisions), checking that every provided step applies and that
+/// the provider only reports cutoffs that the timer also reports
+///
+/// Navigation stops early when the input is exhausted, when no steps are
+/// provided, or when the timer cuts off the provider. Returns the number of
+/// decisions that were made.
+pub fn navigate<T, P>(
+    timer: &T,
+    provider: &mut P,
+    start: &<P::Step as Step>::Exp,
+    data: &[u8],
+    max_depth: usize,
+) -> Result<usize, Violation>
+where
+    T: Timer,
+    P: StepProvider<T>,
+{
+    navigate_with(timer, provider, start, Decisions::new(data), max_depth)
+}
+
+/// Like [`navigate`], but with decisions from an existing source (e.g., one
+/// that is part of a larger fuzz input)
+pub fn navigate_with<T, P>(
+    timer: &T,
+    provider: &mut P,
+    start: &<P::Step as Step>::Exp,
+    mut decisions: Decisions<'_>,
+    max_depth: usize,
+) -> Result<usize, Violation>
+where
+    T: Timer,
+    P: StepProvider<T>,
+{
+    let mut e = start.clone();
+    provider.on_session_start(&e);
+    for depth in 0..max_depth {
+        let mut steps = match provider.provide(timer, &e) {
+            Ok(steps) => steps,
+            Err(_) if timer.tick().is_err() => return Ok(depth),
+            Err(_) => return Err(Violation::SpuriousCutoff { depth }),
+        };
+        let mut results = Vec::with_capacity(steps.len());
+        for (index, step) in steps.iter().enumerate() {
+            match step.apply(&e) {
+                Some(e2) => results.push(e2),
+                None => return Err(Violation::Inapplicable { depth, index }),
+            }
+        }
+        if steps.is_empty() {
+            return Ok(depth);
+        }
+        let Some(i) = decisions.choose(steps.len()) else {
+            return Ok(depth);
+        };
+        let step = steps.swap_remove(i);
+        e = results.swap_remove(i);
+        provider.on_decide(&step, &e);
+    }
+    Ok(max_depth)
+}
+
+/// Like [`navigate`], but panics on violations (suitable for use directly in
+/// a fuzz target)
+///
+/// # Panics
+///
+/// Panics if a provider invariant is violated
+pub fn check<T, P>(
+    timer: &T,
+    provider: &mut P,
+    start: &<P::Step as Step>::Exp,
+    data: &[u8],
+    max_depth: usize,
+) where
+    T: Timer,
+    P: StepProvider<T>,
+{
+    if let Err(v) = navigate(timer, provider, start, data, max_depth) {
+        panic!("{}", v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Fills, budget, empty};
+
+    #[test]
+    fn empty_ranges_have_no_decision() {
+        let mut d = Decisions::new(&[7, 8]);
+        assert_eq!(d.choose(0), None);
+        assert_eq!(d.choose(3), Some(1));
+        assert_eq!(d.choose(300), Some(8));
+        assert!(d.is_empty());
+        assert_eq!(d.choose(3), None);
+    }
+
+    #[test]
+    fn navigation_stops_when_the_input_is_exhausted() {
+        let mut fills = Fills { digits: 2 };
+        assert_eq!(
+            navigate(&budget(), &mut fills, &empty(), &[1, 2], 8),
+            Ok(2)
+        );
+        assert_eq!(
+            navigate(&budget(), &mut fills, &empty(), &[0; 9], 8),
+            Ok(3)
+        );
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn arbitrary_decisions_take_the_rest_of_the_input() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        let d = Decisions::arbitrary_take_rest(Unstructured::new(&[1, 2]));
+        assert_eq!(
+            navigate_with(
+                &budget(),
+                &mut Fills { digits: 2 },
+                &empty(),
+                d.unwrap(),
+                8
+            ),
+            Ok(2)
+        );
+    }
+}
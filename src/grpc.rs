@@ -0,0 +1,468 @@
+//! A gRPC service that hosts navigation sessions
+//!
+//! A [`NavigationService`] implements the `pbn.v1.Navigation` service defined
+//! in `proto/pbn.proto` with [tonic], so that step providers can be hosted
+//! centrally and navigated from any language with gRPC bindings. Expressions
+//! and steps cross the wire as opaque bytes (see [`Codec::encode_bytes`]), and
+//! provided steps are streamed in pages. Each session is a
+//! [`SharedController`], so a long-running provider only holds up requests
+//! for its own session.
+//!
+//! To host the service (e.g., in a tokio runtime):
+//!
+//! ```ignore
+//! let service = NavigationService::new(
+//!     |start| SendController::new_send(timer(), provider(), checker(), start, true),
+//!     MyExpCodec,
+//!     MyStepCodec,
+//! );
+//! tonic::transport::Server::builder()
+//!     .add_service(service.into_server())
+//!     .serve(addr)
+//!     .await?;
+//! ```
+
+// tonic handlers return `Status` (which is large) by value
+#![allow(clippy::result_large_err)]
+
+use crate::{
+    PbnError, SendController, Step, Timer,
+    session::SessionId,
+    shared::SharedController,
+    trace::{Codec, ExpCodec, StepCodec},
+};
+
+use proto::navigation_server::{Navigation, NavigationServer};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use tonic::{Request, Response, Status};
+
+/// The messages and service traits generated from `proto/pbn.proto`
+#[allow(missing_docs, clippy::all)]
+pub mod proto {
+    tonic::include_proto!("pbn.v1");
+}
+
+type Factory<T, S> =
+    dyn Fn(<S as Step>::Exp) -> SendController<T, S> + Send + Sync;
+
+struct Sessions<T: Timer, S: Step> {
+    controllers: HashMap<SessionId, SharedController<T, S>>,
+    next_id: u64,
+}
+
+/// A gRPC service that creates and navigates sessions, each with its own
+/// [`SendController`]
+pub struct NavigationService<T: Timer, S: Step> {
+    sessions: Arc<Mutex<Sessions<T, S>>>,
+    factory: Arc<Factory<T, S>>,
+    exp_codec: Arc<dyn Codec<S::Exp> + Send + Sync>,
+    step_codec: Arc<dyn Codec<S> + Send + Sync>,
+}
+
+impl<T, S> NavigationService<T, S>
+where
+    T: Timer + Send + 'static,
+    S: Step + Send + 'static,
+    S::Exp: Send + Sync,
+{
+    /// Creates a new [`NavigationService`] that starts sessions with
+    /// controllers created by `factory` (from the decoded start expression)
+    /// and encodes expressions and steps with `exp_codec` and `step_codec`
+    pub fn new(
+        factory: impl Fn(S::Exp) -> SendController<T, S> + Send + Sync + 'static,
+        exp_codec: impl ExpCodec<S> + Send + Sync + 'static,
+        step_codec: impl StepCodec<S> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            sessions: Arc::new(Mutex::new(Sessions {
+                controllers: HashMap::new(),
+                next_id: 0,
+            })),
+            factory: Arc::new(factory),
+            exp_codec: Arc::new(exp_codec),
+            step_codec: Arc::new(step_codec),
+        }
+    }
+
+    /// Wraps the service in a tonic server (e.g., for
+    /// `tonic::transport::Server::add_service`)
+    pub fn into_server(self) -> NavigationServer<Self> {
+        NavigationServer::new(self)
+    }
+
+    /// Returns the number of open sessions
+    pub fn len(&self) -> usize {
+        self.lock().controllers.len()
+    }
+
+    /// Returns whether or not there are no open sessions
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Sessions<T, S>> {
+        self.sessions.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn session(&self, id: u64) -> Result<SharedController<T, S>, Status> {
+        self.lock()
+            .controllers
+            .get(&SessionId(id))
+            .cloned()
+            .ok_or_else(|| Status::not_found(format!("no session {}", id)))
+    }
+
+    /// Runs `f` on the controller of a session on a thread where blocking is
+    /// acceptable (providers and checkers may take a long time)
+    async fn with<R: Send + 'static>(
+        &self,
+        id: u64,
+        f: impl FnOnce(&mut SendController<T, S>) -> R + Send + 'static,
+    ) -> Result<R, Status> {
+        let controller = self.session(id)?;
+        tokio::task::spawn_blocking(move || controller.with(f))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))
+    }
+
+    /// Runs a meta-level operation on a session and returns its new state
+    async fn update(
+        &self,
+        id: u64,
+        f: impl FnOnce(
+            &mut SendController<T, S>,
+        ) -> Result<(), PbnError<T::EarlyCutoff>>
+        + Send
+        + 'static,
+    ) -> Result<Response<proto::SessionState>, Status> {
+        let exp_codec = self.exp_codec.clone();
+        self.with(id, move |c| {
+            f(c).map_err(status)?;
+            Ok(state(id, c, exp_codec.as_ref()))
+        })
+        .await?
+        .map(Response::new)
+    }
+}
+
+impl<T, S> Clone for NavigationService<T, S>
+where
+    T: Timer,
+    S: Step,
+{
+    fn clone(&self) -> Self {
+        Self {
+            sessions: self.sessions.clone(),
+            factory: self.factory.clone(),
+            exp_codec: self.exp_codec.clone(),
+            step_codec: self.step_codec.clone(),
+        }
+    }
+}
+
+fn state<T: Timer, S: Step>(
+    id: u64,
+    c: &SendController<T, S>,
+    exp_codec: &(dyn Codec<S::Exp> + Send + Sync),
+) -> proto::SessionState {
+    proto::SessionState {
+        session: id,
+        expression: exp_codec.encode_bytes(c.working_expression()),
+        valid: c.valid(),
+        can_undo: c.can_undo(),
+        version: c.version(),
+    }
+}
+
+/// Converts a navigation error into the closest gRPC status
+fn status<C: core::fmt::Display>(e: PbnError<C>) -> Status {
+    let message = e.to_string();
+    match e {
+        PbnError::Cutoff(_) => Status::deadline_exceeded(message),
+        PbnError::InapplicableStep(_) | PbnError::InvalidDecision(_) => {
+            Status::failed_precondition(message)
+        }
+        PbnError::Provider(_) => Status::internal(message),
+    }
+}
+
+/// Splits encoded steps into pages of at most `page_size` steps (or a single
+/// page if `page_size` is zero), always returning at least one page
+fn pages(steps: Vec<Vec<u8>>, page_size: u32) -> Vec<proto::StepPage> {
+    let total = steps.len() as u32;
+    let size = match page_size {
+        0 => steps.len().max(1),
+        n => n as usize,
+    };
+    let mut pages = vec![];
+    let mut steps = steps.into_iter().peekable();
+    let mut offset = 0;
+    loop {
+        let page: Vec<Vec<u8>> = steps.by_ref().take(size).collect();
+        let len = page.len() as u32;
+        pages.push(proto::StepPage {
+            steps: page,
+            offset,
+            total,
+        });
+        offset += len;
+        if steps.peek().is_none() {
+            return pages;
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl<T, S> Navigation for NavigationService<T, S>
+where
+    T: Timer + Send + 'static,
+    S: Step + Send + 'static,
+    S::Exp: Send + Sync,
+{
+    type ProvideStream =
+        tokio_stream::Iter<std::vec::IntoIter<Result<proto::StepPage, Status>>>;
+
+    async fn create_session(
+        &self,
+        request: Request<proto::CreateSessionRequest>,
+    ) -> Result<Response<proto::SessionState>, Status> {
+        let start = self
+            .exp_codec
+            .decode_bytes(&request.get_ref().start)
+            .ok_or_else(|| Status::invalid_argument("malformed expression"))?;
+        let factory = self.factory.clone();
+        let controller = tokio::task::spawn_blocking(move || factory(start))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+        let mut sessions = self.lock();
+        let id = sessions.next_id;
+        sessions.next_id += 1;
+        let reply = state(id, &controller, self.exp_codec.as_ref());
+        sessions
+            .controllers
+            .insert(SessionId(id), SharedController::new(controller));
+        Ok(Response::new(reply))
+    }
+
+    async fn provide(
+        &self,
+        request: Request<proto::ProvideRequest>,
+    ) -> Result<Response<Self::ProvideStream>, Status> {
+        let proto::ProvideRequest { session, page_size } = request.into_inner();
+        let step_codec = self.step_codec.clone();
+        let steps = self
+            .with(session, move |c| {
+                let steps = c.provide().map_err(status)?;
+                Ok::<_, Status>(
+                    steps.iter().map(|s| step_codec.encode_bytes(s)).collect(),
+                )
+            })
+            .await??;
+        let pages = pages(steps, page_size).into_iter().map(Ok);
+        Ok(Response::new(tokio_stream::iter(pages.collect::<Vec<_>>())))
+    }
+
+    async fn decide(
+        &self,
+        request: Request<proto::DecideRequest>,
+    ) -> Result<Response<proto::SessionState>, Status> {
+        let proto::DecideRequest { session, step } = request.into_inner();
+        let step = self
+            .step_codec
+            .decode_bytes(&step)
+            .ok_or_else(|| Status::invalid_argument("malformed step"))?;
+        self.update(session, move |c| c.decide(step)).await
+    }
+
+    async fn undo(
+        &self,
+        request: Request<proto::SessionRequest>,
+    ) -> Result<Response<proto::SessionState>, Status> {
+        self.update(request.get_ref().session, |c| c.undo()).await
+    }
+
+    async fn get_state(
+        &self,
+        request: Request<proto::SessionRequest>,
+    ) -> Result<Response<proto::SessionState>, Status> {
+        self.update(request.get_ref().session, |_| Ok(())).await
+    }
+
+    async fn close_session(
+        &self,
+        request: Request<proto::SessionRequest>,
+    ) -> Result<Response<proto::SessionState>, Status> {
+        let id = request.get_ref().session;
+        let controller =
+            self.lock().controllers.remove(&SessionId(id)).ok_or_else(
+                || Status::not_found(format!("no session {}", id)),
+            )?;
+        let exp_codec = self.exp_codec.clone();
+        let reply = tokio::task::spawn_blocking(move || {
+            controller.with(|c| state(id, c, exp_codec.as_ref()))
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(reply))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        AllEqual, Fill, FillCodec, Fills, SlotsCodec, budget,
+    };
+
+    use tokio_stream::StreamExt;
+
+    fn service() -> NavigationService<crate::budget::TickBudget, Fill> {
+        NavigationService::new(
+            |start| {
+                SendController::new_send(
+                    budget(),
+                    Fills { digits: 2 },
+                    AllEqual(1),
+                    start,
+                    true,
+                )
+            },
+            SlotsCodec,
+            FillCodec,
+        )
+    }
+
+    fn run<F: Future>(f: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(f)
+    }
+
+    fn session(id: u64) -> Request<proto::SessionRequest> {
+        Request::new(proto::SessionRequest { session: id })
+    }
+
+    #[test]
+    fn sessions_navigate_over_grpc() {
+        run(async {
+            let service = service();
+            let start = SlotsCodec.encode_bytes(&[Some(1), None, None]);
+            let created = service
+                .create_session(Request::new(proto::CreateSessionRequest {
+                    start,
+                }))
+                .await
+                .unwrap()
+                .into_inner();
+            assert!(!created.valid && !created.can_undo);
+
+            let request = proto::ProvideRequest {
+                session: created.session,
+                page_size: 3,
+            };
+            let pages: Vec<_> = service
+                .provide(Request::new(request))
+                .await
+                .unwrap()
+                .into_inner()
+                .map(Result::unwrap)
+                .collect()
+                .await;
+            let offsets: Vec<_> = pages.iter().map(|p| p.offset).collect();
+            assert_eq!(offsets, [0, 3]);
+            assert!(pages.iter().all(|p| p.total == 4));
+            let steps: Vec<_> = pages
+                .iter()
+                .flat_map(|p| &p.steps)
+                .map(|s| FillCodec.decode_bytes(s).unwrap())
+                .collect();
+            assert_eq!(steps, [Fill(1, 0), Fill(1, 1), Fill(2, 0), Fill(2, 1)]);
+
+            for step in [Fill(1, 1), Fill(2, 1)] {
+                service
+                    .decide(Request::new(proto::DecideRequest {
+                        session: created.session,
+                        step: FillCodec.encode_bytes(&step),
+                    }))
+                    .await
+                    .unwrap();
+            }
+            let state = service
+                .get_state(session(created.session))
+                .await
+                .unwrap()
+                .into_inner();
+            assert!(state.valid && state.can_undo);
+            assert_ne!(state.version, created.version);
+
+            let undone = service.undo(session(created.session)).await.unwrap();
+            assert!(!undone.get_ref().valid);
+            let closed = service
+                .close_session(session(created.session))
+                .await
+                .unwrap();
+            assert_eq!(
+                closed.get_ref().expression,
+                undone.get_ref().expression
+            );
+            assert!(service.is_empty());
+        });
+    }
+
+    #[test]
+    fn errors_map_to_statuses() {
+        run(async {
+            let service = service();
+            let missing = service.get_state(session(7)).await.unwrap_err();
+            assert_eq!(missing.code(), tonic::Code::NotFound);
+
+            let request = proto::CreateSessionRequest {
+                start: b"[1]".to_vec(),
+            };
+            let malformed = service
+                .create_session(Request::new(request))
+                .await
+                .unwrap_err();
+            assert_eq!(malformed.code(), tonic::Code::InvalidArgument);
+
+            let start = SlotsCodec.encode_bytes(&[Some(1); 3]);
+            let created = service
+                .create_session(Request::new(proto::CreateSessionRequest {
+                    start,
+                }))
+                .await
+                .unwrap()
+                .into_inner();
+            let pages: Vec<_> = service
+                .provide(Request::new(proto::ProvideRequest {
+                    session: created.session,
+                    page_size: 0,
+                }))
+                .await
+                .unwrap()
+                .into_inner()
+                .collect()
+                .await;
+            assert_eq!(pages.len(), 1);
+            assert!(pages[0].as_ref().unwrap().steps.is_empty());
+
+            let undo = service.undo(session(created.session)).await;
+            assert_eq!(
+                undo.unwrap_err().code(),
+                tonic::Code::FailedPrecondition
+            );
+            let decide = service
+                .decide(Request::new(proto::DecideRequest {
+                    session: created.session,
+                    step: FillCodec.encode_bytes(&Fill(0, 0)),
+                }))
+                .await;
+            assert_eq!(
+                decide.unwrap_err().code(),
+                tonic::Code::FailedPrecondition
+            );
+        });
+    }
+}
@@ -0,0 +1,564 @@
+//! Pluggable storage for [`Controller`](crate::Controller) history
+//!
+//! A [`Controller`](crate::Controller) that saves history stores the working
+//! expression before every change so that it can be restored by "undo". By
+//! default, full copies are stored in a [`VecHistory`]; for large expressions,
+//! a [`PatchHistory`] stores only the differences between successive
+//! expressions instead, and a [`FileHistory`] keeps them on disk so that the
+//! session can be resumed after the process exits.
+
+use alloc::vec::Vec;
+
+/// A store of previous working expressions (used as a stack)
+pub trait HistoryStore<E> {
+    /// Saves the expression `prev`, which was just replaced by `next`
+    fn push(&mut self, prev: E, next: &E);
+
+    /// Removes and returns the most recently saved expression, given the
+    /// expression `current` that replaced it (or returns `None` if the store
+    /// is empty)
+    fn pop(&mut self, current: &E) -> Option<E>;
+
+    /// Returns the number of saved expressions
+    fn len(&self) -> usize;
+
+    /// Returns whether or not there are no saved expressions
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all saved expressions, given the expression `current` that the
+    /// session continues from
+    fn clear(&mut self, current: &E);
+}
+
+/// A history store that saves full copies of expressions
+#[derive(Debug, Clone)]
+pub struct VecHistory<E> {
+    states: Vec<E>,
+}
+
+impl<E> VecHistory<E> {
+    /// Creates a new empty store
+    pub fn new() -> Self {
+        Self { states: Vec::new() }
+    }
+}
+
+impl<E> Default for VecHistory<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E> HistoryStore<E> for VecHistory<E> {
+    fn push(&mut self, prev: E, _next: &E) {
+        self.states.push(prev);
+    }
+
+    fn pop(&mut self, _current: &E) -> Option<E> {
+        self.states.pop()
+    }
+
+    fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    fn clear(&mut self, _current: &E) {
+        self.states.clear();
+    }
+}
+
+/// Expressions that can be represented as differences from one another
+pub trait DiffableExp: Sized {
+    /// A representation of the difference between two expressions
+    type Patch;
+
+    /// Returns a patch that transforms `self` into `other`
+    fn diff(&self, other: &Self) -> Self::Patch;
+
+    /// Returns the result of applying a patch (computed by [`Self::diff`]
+    /// from `self`) to `self`
+    fn patch(&self, patch: &Self::Patch) -> Self;
+}
+
+/// A history store that saves patches between successive expressions rather
+/// than full copies
+///
+/// Each saved expression is reconstructed on "undo" by patching the
+/// expression that replaced it.
+#[derive(Debug, Clone)]
+pub struct PatchHistory<E: DiffableExp> {
+    patches: Vec<E::Patch>,
+}
+
+impl<E: DiffableExp> PatchHistory<E> {
+    /// Creates a new empty store
+    pub fn new() -> Self {
+        Self {
+            patches: Vec::new(),
+        }
+    }
+}
+
+impl<E: DiffableExp> Default for PatchHistory<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E: DiffableExp> HistoryStore<E> for PatchHistory<E> {
+    fn push(&mut self, prev: E, next: &E) {
+        self.patches.push(next.diff(&prev));
+    }
+
+    fn pop(&mut self, current: &E) -> Option<E> {
+        self.patches.pop().map(|p| current.patch(&p))
+    }
+
+    fn len(&self) -> usize {
+        self.patches.len()
+    }
+
+    fn clear(&mut self, _current: &E) {
+        self.patches.clear();
+    }
+}
+
+#[cfg(feature = "std")]
+mod file {
+    use super::HistoryStore;
+    use crate::{
+        Controller, Step, StepProvider, Timer, ValidityChecker,
+        json::{self, Value},
+        trace::Codec,
+    };
+
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, BufRead, BufReader, Seek, SeekFrom, Write};
+    use std::marker::PhantomData;
+    use std::path::Path;
+
+    /// Where an expression is recorded in the file
+    #[derive(Debug, Clone, Copy)]
+    enum Location {
+        /// The `"start"` field of the record at an offset
+        Start(u64),
+        /// The `"prev"` field of the record at an offset
+        Prev(u64),
+        /// The `"next"` field of the record at an offset
+        Next(u64),
+    }
+
+    /// A history store backed by an append-only file of JSON lines
+    ///
+    /// Only the file offsets of saved expressions are kept in memory; the
+    /// expressions themselves are written (with `codec`) as soon as they are
+    /// saved, so even very long sessions survive a crash and can be continued
+    /// with [`Controller::resume`]. Write errors do not interrupt the session;
+    /// the first one is kept and can be retrieved with [`Self::take_error`].
+    ///
+    /// The file consists of three kinds of records:
+    /// - `{"start": e}` begins a session (or restarts it after it is cleared)
+    ///   from `e`,
+    /// - `{"prev": e1, "next": e2}` saves `e1`, which was replaced by `e2`,
+    ///   and
+    /// - `{"pop": true}` removes the most recently saved expression.
+    pub struct FileHistory<E, X: Codec<E>> {
+        file: File,
+        codec: X,
+        stack: Vec<u64>,
+        start: Location,
+        current: Location,
+        error: Option<io::Error>,
+        _exp: PhantomData<fn(E) -> E>,
+    }
+
+    fn invalid(msg: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, msg)
+    }
+
+    impl<E, X: Codec<E>> FileHistory<E, X> {
+        /// Creates a (truncated) file for a session starting from `start`
+        pub fn create(
+            path: impl AsRef<Path>,
+            codec: X,
+            start: &E,
+        ) -> io::Result<Self> {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(path)?;
+            let mut store = Self {
+                file,
+                codec,
+                stack: vec![],
+                start: Location::Start(0),
+                current: Location::Start(0),
+                error: None,
+                _exp: PhantomData,
+            };
+            store.begin(start)?;
+            Ok(store)
+        }
+
+        /// Opens a file previously written by a [`FileHistory`]
+        ///
+        /// A final record that was only partially written (e.g., because the
+        /// process crashed) is discarded.
+        pub fn open(path: impl AsRef<Path>, codec: X) -> io::Result<Self> {
+            let mut file =
+                OpenOptions::new().read(true).write(true).open(path)?;
+            let mut stack = vec![];
+            let mut start = None;
+            let mut current = None;
+            let mut offset = 0;
+            let mut reader = BufReader::new(&mut file);
+            let mut line = String::new();
+            loop {
+                line.clear();
+                let n = reader.read_line(&mut line)?;
+                if n == 0 || !line.ends_with('\n') {
+                    break;
+                }
+                let v = json::parse(&line)
+                    .map_err(|_| invalid("malformed history record"))?;
+                if v.get("start").is_some() {
+                    stack.clear();
+                    start = Some(Location::Start(offset));
+                    current = start;
+                } else if v.get("prev").is_some() && v.get("next").is_some() {
+                    stack.push(offset);
+                    current = Some(Location::Next(offset));
+                } else if v.get("pop").is_some() {
+                    let p = stack.pop().ok_or_else(|| {
+                        invalid("history record pops an empty history")
+                    })?;
+                    current = Some(Location::Prev(p));
+                } else {
+                    return Err(invalid("unknown history record"));
+                }
+                offset += n as u64;
+            }
+            let (Some(start), Some(current)) = (start, current) else {
+                return Err(invalid("history has no start record"));
+            };
+            file.set_len(offset)?;
+            Ok(Self {
+                file,
+                codec,
+                stack,
+                start,
+                current,
+                error: None,
+                _exp: PhantomData,
+            })
+        }
+
+        /// Returns the expression that the session started from (or was last
+        /// cleared at)
+        pub fn start(&mut self) -> io::Result<E> {
+            self.read(self.start)
+        }
+
+        /// Returns the most recent working expression of the session
+        pub fn current(&mut self) -> io::Result<E> {
+            self.read(self.current)
+        }
+
+        /// Returns (and clears) the first write error encountered (if any)
+        pub fn take_error(&mut self) -> Option<io::Error> {
+            self.error.take()
+        }
+
+        fn begin(&mut self, start: &E) -> io::Result<()> {
+            let offset = self
+                .append(vec![("start".to_owned(), self.codec.encode(start))])?;
+            self.stack.clear();
+            self.start = Location::Start(offset);
+            self.current = self.start;
+            Ok(())
+        }
+
+        /// Appends a record and returns its offset
+        fn append(&mut self, fields: Vec<(String, Value)>) -> io::Result<u64> {
+            let offset = self.file.seek(SeekFrom::End(0))?;
+            let line = format!("{}\n", Value::Object(fields));
+            self.file.write_all(line.as_bytes())?;
+            self.file.flush()?;
+            Ok(offset)
+        }
+
+        fn read(&mut self, loc: Location) -> io::Result<E> {
+            let (offset, field) = match loc {
+                Location::Start(o) => (o, "start"),
+                Location::Prev(o) => (o, "prev"),
+                Location::Next(o) => (o, "next"),
+            };
+            self.file.seek(SeekFrom::Start(offset))?;
+            let mut line = String::new();
+            BufReader::new(&mut self.file).read_line(&mut line)?;
+            json::parse(&line)
+                .ok()
+                .and_then(|v| self.codec.decode(v.get(field)?))
+                .ok_or_else(|| invalid("undecodable history record"))
+        }
+
+        fn result<R>(&mut self, r: io::Result<R>) -> Option<R> {
+            match r {
+                Ok(x) => Some(x),
+                Err(e) => {
+                    if self.error.is_none() {
+                        self.error = Some(e);
+                    }
+                    None
+                }
+            }
+        }
+    }
+
+    impl<E, X: Codec<E>> HistoryStore<E> for FileHistory<E, X> {
+        fn push(&mut self, prev: E, next: &E) {
+            let r = self.append(vec![
+                ("prev".to_owned(), self.codec.encode(&prev)),
+                ("next".to_owned(), self.codec.encode(next)),
+            ]);
+            if let Some(offset) = self.result(r) {
+                self.stack.push(offset);
+                self.current = Location::Next(offset);
+            }
+        }
+
+        fn pop(&mut self, _current: &E) -> Option<E> {
+            let offset = *self.stack.last()?;
+            let r = self.read(Location::Prev(offset));
+            let prev = self.result(r)?;
+            let r = self.append(vec![("pop".to_owned(), Value::Bool(true))]);
+            self.result(r)?;
+            self.stack.pop();
+            self.current = Location::Prev(offset);
+            Some(prev)
+        }
+
+        fn len(&self) -> usize {
+            self.stack.len()
+        }
+
+        fn clear(&mut self, current: &E) {
+            let r = self.begin(current);
+            self.result(r);
+        }
+    }
+
+    impl<T: Timer, S: Step> Controller<T, S>
+    where
+        S::Exp: 'static,
+    {
+        /// Create a new controller that continues the session saved in a
+        /// [`FileHistory`], whose working expression is the most recent one in
+        /// the store
+        ///
+        /// Decisions made before the controller was created can be undone,
+        /// but are not part of its [`Self::provenance`].
+        pub fn resume<X: Codec<S::Exp> + 'static>(
+            timer: T,
+            provider: impl StepProvider<T, Step = S> + 'static,
+            checker: impl ValidityChecker<Exp = S::Exp> + 'static,
+            mut store: FileHistory<S::Exp, X>,
+        ) -> io::Result<Self> {
+            let start = store.start()?;
+            let state = store.current()?;
+            let mut controller = Self::from_boxed(
+                timer,
+                Box::new(provider),
+                Box::new(checker),
+                state,
+                Some(Box::new(store)),
+            );
+            controller.start = start;
+            Ok(controller)
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+pub use file::FileHistory;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Controller;
+    use crate::testing::{AllEqual, Fill, Fills, Slots, budget, empty};
+
+    use alloc::boxed::Box;
+
+    /// Patches list the slots that differ (with their contents after patching)
+    impl DiffableExp for Slots {
+        type Patch = Vec<(usize, Option<u8>)>;
+
+        fn diff(&self, other: &Self) -> Self::Patch {
+            (0..self.len())
+                .filter(|&i| self[i] != other[i])
+                .map(|i| (i, other[i]))
+                .collect()
+        }
+
+        fn patch(&self, patch: &Self::Patch) -> Self {
+            let mut e = *self;
+            for &(i, x) in patch {
+                e[i] = x;
+            }
+            e
+        }
+    }
+
+    #[test]
+    fn patch_histories_store_only_differences() {
+        let mut store = PatchHistory::new();
+        let states = [empty(), [Some(1), None, None], [Some(1), Some(2), None]];
+        for w in states.windows(2) {
+            store.push(w[0], &w[1]);
+        }
+        assert_eq!(store.len(), 2);
+        assert!(store.patches.iter().all(|p| p.len() == 1));
+        assert_eq!(store.pop(&states[2]), Some(states[1]));
+        assert_eq!(store.pop(&states[1]), Some(states[0]));
+        assert_eq!(store.pop(&states[0]), None);
+    }
+
+    #[test]
+    fn controllers_undo_through_patch_histories() {
+        let mut c = Controller::new(
+            budget(),
+            Fills { digits: 2 },
+            AllEqual(1),
+            empty(),
+            false,
+        )
+        .with_history(Box::new(PatchHistory::new()));
+        for step in [Fill(0, 1), Fill(2, 0), Fill(1, 1)] {
+            c.decide(step).unwrap();
+        }
+        c.undo().unwrap();
+        assert_eq!(*c.working_expression(), [Some(1), None, Some(0)]);
+        c.undo().unwrap();
+        c.undo().unwrap();
+        assert_eq!(*c.working_expression(), empty());
+        assert!(!c.can_undo());
+    }
+
+    #[cfg(feature = "std")]
+    mod file {
+        use super::*;
+        use crate::testing::SlotsCodec;
+
+        use std::path::PathBuf;
+
+        /// Returns a fresh path in the temporary directory for a test
+        fn path(name: &str) -> PathBuf {
+            let path = std::env::temp_dir().join(format!(
+                "pbn-{}-{}.jsonl",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_file(&path);
+            path
+        }
+
+        #[test]
+        fn file_histories_reopen_where_they_left_off() {
+            let path = path("reopen");
+            let a = [Some(1), None, None];
+            let b = [Some(1), Some(0), None];
+            let mut store =
+                FileHistory::create(&path, SlotsCodec, &empty()).unwrap();
+            store.push(empty(), &a);
+            store.push(a, &b);
+            assert_eq!(store.pop(&b), Some(a));
+            assert!(store.take_error().is_none());
+
+            let mut store = FileHistory::open(&path, SlotsCodec).unwrap();
+            assert_eq!(store.len(), 1);
+            assert_eq!(store.start().unwrap(), empty());
+            assert_eq!(store.current().unwrap(), a);
+            assert_eq!(store.pop(&a), Some(empty()));
+            assert_eq!(store.pop(&empty()), None);
+            std::fs::remove_file(&path).unwrap();
+        }
+
+        #[test]
+        fn file_histories_discard_partial_records() {
+            use std::io::Write;
+
+            let path = path("partial");
+            let a = [Some(1), None, None];
+            let mut store =
+                FileHistory::create(&path, SlotsCodec, &empty()).unwrap();
+            store.push(empty(), &a);
+            drop(store);
+            let mut file = std::fs::OpenOptions::new()
+                .append(true)
+                .open(&path)
+                .unwrap();
+            file.write_all(b"{\"prev\": [1, nu").unwrap();
+            drop(file);
+
+            let mut store = FileHistory::open(&path, SlotsCodec).unwrap();
+            assert_eq!(store.len(), 1);
+            assert_eq!(store.current().unwrap(), a);
+            store.push(a, &[Some(1); 3]);
+            let mut store = FileHistory::open(&path, SlotsCodec).unwrap();
+            assert_eq!(store.len(), 2);
+            assert_eq!(store.current().unwrap(), [Some(1); 3]);
+            std::fs::remove_file(&path).unwrap();
+        }
+
+        #[test]
+        fn file_histories_reject_foreign_files() {
+            let path = path("foreign");
+            std::fs::write(&path, "{\"pop\": true}\n").unwrap();
+            assert!(FileHistory::open(&path, SlotsCodec).is_err());
+            std::fs::write(&path, "not json\n").unwrap();
+            assert!(FileHistory::open(&path, SlotsCodec).is_err());
+            std::fs::remove_file(&path).unwrap();
+        }
+
+        #[test]
+        fn controllers_resume_file_histories() {
+            let path = path("resume");
+            let store =
+                FileHistory::create(&path, SlotsCodec, &empty()).unwrap();
+            let mut c = Controller::new(
+                budget(),
+                Fills { digits: 2 },
+                AllEqual(1),
+                empty(),
+                false,
+            )
+            .with_history(Box::new(store));
+            c.decide(Fill(0, 1)).unwrap();
+            c.decide(Fill(1, 1)).unwrap();
+            drop(c);
+
+            let store = FileHistory::open(&path, SlotsCodec).unwrap();
+            let mut c = Controller::resume(
+                budget(),
+                Fills { digits: 2 },
+                AllEqual(1),
+                store,
+            )
+            .unwrap();
+            assert_eq!(*c.working_expression(), [Some(1), Some(1), None]);
+            c.decide(Fill(2, 1)).unwrap();
+            assert!(c.valid());
+            for _ in 0..3 {
+                c.undo().unwrap();
+            }
+            assert_eq!(*c.working_expression(), empty());
+            assert!(!c.can_undo());
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! Navigation centered on the holes of the working expression
+//!
+//! Many expression types are partial programs with holes, and user interfaces
+//! often let the programmer pick a hole and then see the options for filling
+//! it. Expressions that implement [`HoleOriented`] list their open holes, and
+//! steps that implement [`TargetedStep`] report which hole they affect, so
+//! that [`Controller::provide_for_hole`] can provide only the steps for a
+//! particular hole. (See also [`location`](crate::location) for steps that
+//! touch arbitrary regions of expressions.)
+
+use crate::{
+    Controller, PbnError, Step, StepProvider, Timer, ValidityChecker,
+    history::HistoryStore,
+};
+
+use alloc::vec::Vec;
+
+/// Expressions with (identifiable) holes
+pub trait HoleOriented {
+    /// The notion of hole locations
+    type Hole: PartialEq;
+
+    /// Returns the open holes of the expression
+    fn holes(&self) -> Vec<Self::Hole>;
+}
+
+/// Steps that affect a particular hole
+pub trait TargetedStep: Step<Exp: HoleOriented> {
+    /// Returns the hole that the step affects (or `None` if it does not
+    /// affect a particular hole)
+    fn target(&self) -> Option<<Self::Exp as HoleOriented>::Hole>;
+}
+
+impl<T, S, P, C, H> Controller<T, S, P, C, H>
+where
+    T: Timer,
+    S: Step<Exp: HoleOriented>,
+    P: StepProvider<T, Step = S> + ?Sized,
+    C: ValidityChecker<Exp = S::Exp> + ?Sized,
+    H: HistoryStore<S::Exp> + ?Sized,
+{
+    /// Returns the open holes of the working expression
+    pub fn holes(&self) -> Vec<<S::Exp as HoleOriented>::Hole> {
+        self.state.holes()
+    }
+
+    /// Ask the synthesizer to provide a list of possible next steps that
+    /// affect the hole `hole` (see [`Self::provide`])
+    pub fn provide_for_hole(
+        &mut self,
+        hole: &<S::Exp as HoleOriented>::Hole,
+    ) -> Result<Vec<S>, PbnError<T::EarlyCutoff>>
+    where
+        S: TargetedStep,
+    {
+        let mut steps = self.provide()?;
+        steps.retain(|step| step.target().as_ref() == Some(hole));
+        Ok(steps)
+    }
+}
@@ -16,14 +16,486 @@
 //! The [`Controller`] struct can be used to conveniently manage a Programming
 //! by Navigation interactive session. Its API (and implementation) is a good
 //! starting point to see how all the components hook together.
+//!
+//! # Features
+//!
+//! - `std` (enabled by default): enables the modules and combinators that
+//!   need the standard library (threads, clocks, and I/O). Without it, the
+//!   core traits, most combinators, and [`Controller`] are available under
+//!   `no_std` with `alloc`.
+//! - `derive`: enables `#[derive(Step)]` for enums whose variants each name
+//!   a function that applies them (taking the expression followed by the
+//!   fields of the variant):
+//!
+//! ```ignore
+//! #[derive(Step)]
+//! #[step(exp = Vec<u8>)]
+//! enum Edit {
+//!     #[step(apply = push)] // fn push(e: &Vec<u8>, x: &u8) -> Option<Vec<u8>>
+//!     Push(u8),
+//!     #[step(apply = pop)] // fn pop(e: &Vec<u8>) -> Option<Vec<u8>>
+//!     Pop,
+//! }
+//! ```
+//!
+//! - `arbitrary`: implements `arbitrary::Arbitrary` for
+//!   [`fuzz::Decisions`], so fuzz targets can take decisions as (part of)
+//!   their input.
+//! - `grpc`: enables the [`grpc`] module, a tonic service (defined in
+//!   `proto/pbn.proto`) that hosts navigation sessions. Building it compiles
+//!   the proto with a vendored `protoc` unless `PROTOC` is set.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::{
+    boxed::Box, collections::BTreeMap, string::String, sync::Arc, vec, vec::Vec,
+};
+use core::any::{Any, TypeId};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+#[cfg(feature = "derive")]
+pub use pbn_derive::Step;
+
+#[cfg(feature = "std")]
+pub mod bench;
+pub mod budget;
+#[cfg(feature = "std")]
+pub mod cache;
+pub mod canon;
+pub mod derivation;
+#[cfg(feature = "std")]
+pub mod diff;
+pub mod differential;
+#[cfg(feature = "std")]
+pub mod distributed;
+pub mod equivalence;
+pub mod erased;
+pub mod estimate;
+pub mod feedback;
+pub mod follow_up;
+pub mod fork;
+pub mod fuzz;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod history;
+pub mod hole;
+pub mod json;
+pub mod location;
+pub mod lsp;
+pub mod merge;
+pub mod oracle;
+#[cfg(feature = "std")]
+pub mod parallel;
+pub mod phase;
+pub mod policy;
+#[cfg(feature = "std")]
+pub mod prefetch;
+#[cfg(feature = "std")]
+pub mod profile;
+pub mod proposal;
+pub mod rank;
+pub mod recording;
+pub mod render;
+mod rng;
+pub mod sample;
+#[cfg(feature = "std")]
+pub mod session;
+#[cfg(feature = "std")]
+pub mod shared;
+pub mod shrink;
+#[cfg(feature = "std")]
+pub mod simulation;
+pub mod solve;
+#[cfg(feature = "std")]
+pub mod soundness;
+mod sync;
+#[cfg(feature = "std")]
+pub mod telemetry;
+#[cfg(test)]
+mod testing;
+#[cfg(feature = "std")]
+pub mod throttle;
+#[cfg(feature = "std")]
+pub mod trace;
+pub mod typestate;
 
 /// A cooperative timer used for early cutoff when synthesizing
 pub trait Timer {
     /// The possible reasons for early cutoff (e.g., out of time, out of memory)
-    type EarlyCutoff: std::error::Error;
+    type EarlyCutoff: core::error::Error;
 
     /// A cooperative "tick" of the timer
     fn tick(&self) -> Result<(), Self::EarlyCutoff>;
+
+    /// Stops the timer from consuming its budget until [`Self::resume`] is
+    /// called (e.g., while a human is deciding)
+    ///
+    /// The default implementation does nothing.
+    fn pause(&self) {}
+
+    /// Resumes a timer stopped by [`Self::pause`]
+    ///
+    /// The default implementation does nothing.
+    fn resume(&self) {}
+}
+
+/// The ways a step provider can fail to provide steps
+#[derive(Debug)]
+pub enum ProviderError<C> {
+    /// The provider was cut off early by the timer
+    Cutoff(C),
+    /// The provider failed for a reason other than a timer cutoff (e.g., an
+    /// I/O error or a solver crash)
+    Failure(Box<dyn core::error::Error + Send + Sync>),
+}
+
+impl<C> ProviderError<C> {
+    /// Creates a hard failure from an error
+    pub fn failure(e: impl core::error::Error + Send + Sync + 'static) -> Self {
+        ProviderError::Failure(Box::new(e))
+    }
+}
+
+impl<C> From<C> for ProviderError<C> {
+    fn from(cutoff: C) -> Self {
+        ProviderError::Cutoff(cutoff)
+    }
+}
+
+impl<C: core::fmt::Display> core::fmt::Display for ProviderError<C> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ProviderError::Cutoff(c) => write!(f, "cut off early: {}", c),
+            ProviderError::Failure(e) => write!(f, "provider failed: {}", e),
+        }
+    }
+}
+
+impl<C: core::error::Error + 'static> core::error::Error for ProviderError<C> {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            ProviderError::Cutoff(c) => Some(c),
+            ProviderError::Failure(e) => Some(e.as_ref()),
+        }
+    }
+}
+
+/// The ways an operation on a Programming by Navigation session can fail
+#[derive(Debug)]
+pub enum PbnError<C> {
+    /// The provider was cut off early by the timer
+    Cutoff(C),
+    /// A decided step was not applicable to the working expression (with an
+    /// explanation, if the step provides one)
+    InapplicableStep(Option<String>),
+    /// A meta-level operation was requested that is not currently possible
+    /// (e.g., "undo" without history)
+    InvalidDecision(&'static str),
+    /// The provider failed for a reason other than a timer cutoff (e.g., an
+    /// I/O error or a solver crash)
+    Provider(Box<dyn core::error::Error + Send + Sync>),
+}
+
+impl<C> From<C> for PbnError<C> {
+    fn from(cutoff: C) -> Self {
+        PbnError::Cutoff(cutoff)
+    }
+}
+
+impl<C: core::fmt::Display> core::fmt::Display for PbnError<C> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            PbnError::Cutoff(c) => write!(f, "cut off early: {}", c),
+            PbnError::InapplicableStep(None) => {
+                write!(f, "step is not applicable")
+            }
+            PbnError::InapplicableStep(Some(why)) => {
+                write!(f, "step is not applicable: {}", why)
+            }
+            PbnError::InvalidDecision(why) => {
+                write!(f, "invalid decision: {}", why)
+            }
+            PbnError::Provider(e) => write!(f, "provider failed: {}", e),
+        }
+    }
+}
+
+impl<C> From<ProviderError<C>> for PbnError<C> {
+    fn from(e: ProviderError<C>) -> Self {
+        match e {
+            ProviderError::Cutoff(c) => PbnError::Cutoff(c),
+            ProviderError::Failure(e) => PbnError::Provider(e),
+        }
+    }
+}
+
+impl<C: core::error::Error + 'static> core::error::Error for PbnError<C> {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            PbnError::Cutoff(c) => Some(c),
+            PbnError::Provider(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// An invalid argument to a constructor or configuration method (with an
+/// explanation)
+///
+/// Methods that would panic on invalid arguments have non-panicking `try_*`
+/// counterparts that return this error instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidArgument(pub &'static str);
+
+impl core::fmt::Display for InvalidArgument {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "invalid argument: {}", self.0)
+    }
+}
+
+impl core::error::Error for InvalidArgument {}
+
+impl InvalidArgument {
+    /// Returns `value` if `ok` holds, and otherwise this error
+    pub(crate) fn check<X>(
+        ok: bool,
+        why: &'static str,
+        value: impl FnOnce() -> X,
+    ) -> Result<X, Self> {
+        if ok { Ok(value()) } else { Err(Self(why)) }
+    }
+
+    /// Panics with the explanation (for the panicking counterparts of
+    /// `try_*` methods)
+    #[track_caller]
+    pub(crate) fn unwrap<X>(result: Result<X, Self>) -> X {
+        match result {
+            Ok(x) => x,
+            Err(InvalidArgument(why)) => panic!("{}", why),
+        }
+    }
+}
+
+/// The ways a decision made at an observed version can fail (see
+/// [`Controller::decide_at`])
+#[derive(Debug)]
+pub enum DecideError<S, C> {
+    /// The working expression changed since the observed version (e.g.,
+    /// because another user decided first)
+    Conflict {
+        /// The current version
+        version: u64,
+        /// The steps provided for the current working expression
+        steps: Vec<S>,
+    },
+    /// The decision (or re-providing steps after a conflict) failed
+    Failed(PbnError<C>),
+}
+
+impl<S, C> From<PbnError<C>> for DecideError<S, C> {
+    fn from(e: PbnError<C>) -> Self {
+        DecideError::Failed(e)
+    }
+}
+
+impl<S, C: core::fmt::Display> core::fmt::Display for DecideError<S, C> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            DecideError::Conflict { version, .. } => write!(
+                f,
+                "working expression changed (now at version {})",
+                version
+            ),
+            DecideError::Failed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl<S: core::fmt::Debug, C: core::error::Error + 'static> core::error::Error
+    for DecideError<S, C>
+{
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            DecideError::Conflict { .. } => None,
+            DecideError::Failed(e) => Some(e),
+        }
+    }
+}
+
+/// A progress report from a long-running step provider
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Progress {
+    /// The fraction of work completed (between 0 and 1), if known
+    pub fraction: Option<f64>,
+    /// A description of the current phase of work, if any
+    pub phase: Option<String>,
+}
+
+impl Progress {
+    /// Creates a progress report with only a completed fraction
+    pub fn fraction(fraction: f64) -> Self {
+        Self {
+            fraction: Some(fraction),
+            phase: None,
+        }
+    }
+
+    /// Creates a progress report with only a phase description
+    pub fn phase(phase: impl Into<String>) -> Self {
+        Self {
+            fraction: None,
+            phase: Some(phase.into()),
+        }
+    }
+}
+
+/// The quality of a provided step set
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ProvisionQuality {
+    /// The step set is complete
+    #[default]
+    Exact,
+    /// The step set is sound but may be incomplete (e.g., because a provider
+    /// was cut off or used heuristics)
+    Approximate,
+}
+
+type ProgressObservers =
+    Arc<sync::Mutex<Vec<Box<dyn FnMut(&Progress) + Send + 'static>>>>;
+
+/// A handle that step providers use to report progress to observers (and
+/// the quality of the step sets they provide to the [`Controller`])
+///
+/// Handles are cheap to clone; all clones report to the same observers.
+#[derive(Clone, Default)]
+pub struct ProgressReporter {
+    observers: ProgressObservers,
+    approximate: Arc<AtomicBool>,
+}
+
+impl ProgressReporter {
+    /// Creates a new reporter with no observers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an observer that is called with every progress report
+    pub fn observe(&self, observer: impl FnMut(&Progress) + Send + 'static) {
+        self.observers.lock().push(Box::new(observer));
+    }
+
+    /// Reports progress to all observers
+    pub fn report(&self, progress: Progress) {
+        for o in self.observers.lock().iter_mut() {
+            o(&progress);
+        }
+    }
+
+    /// Marks the step set currently being provided as approximate (see
+    /// [`Controller::last_provision_quality`])
+    ///
+    /// Combinators share the handle with their sub-providers, so a mark made
+    /// by any sub-provider applies to the whole step set.
+    pub fn mark_approximate(&self) {
+        self.approximate.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns the quality of the step set provided since the last call (and
+    /// resets it)
+    fn take_quality(&self) -> ProvisionQuality {
+        if self.approximate.swap(false, Ordering::Relaxed) {
+            ProvisionQuality::Approximate
+        } else {
+            ProvisionQuality::Exact
+        }
+    }
+}
+
+/// Extra data about a [`Controller`] session that step providers can use
+/// (e.g., a library of components, an environment, or a specification)
+///
+/// A context holds at most one value of each type. Contexts are immutable
+/// and cheap to clone; the controller gives providers a new context whenever
+/// it is updated (see [`Controller::update_context`] and
+/// [`StepProvider::set_context`]).
+#[derive(Clone, Default)]
+pub struct Context {
+    values: Arc<BTreeMap<TypeId, Arc<dyn Any + Send + Sync>>>,
+}
+
+impl Context {
+    /// Creates a new, empty context
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the value of type `X` in the context (if any)
+    pub fn get<X: Any>(&self) -> Option<&X> {
+        self.values.get(&TypeId::of::<X>())?.downcast_ref()
+    }
+
+    /// Returns the context with the value of type `X` set to `value`
+    pub fn with<X: Any + Send + Sync>(mut self, value: X) -> Self {
+        Arc::make_mut(&mut self.values)
+            .insert(TypeId::of::<X>(), Arc::new(value));
+        self
+    }
+
+    /// Returns the context without a value of type `X`
+    pub fn without<X: Any>(mut self) -> Self {
+        Arc::make_mut(&mut self.values).remove(&TypeId::of::<X>());
+        self
+    }
+
+    /// Returns whether or not the context holds no values
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl core::fmt::Debug for Context {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Context")
+            .field("len", &self.values.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A mark that a decider can place on part of the working expression
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Mark {
+    /// The marked part should be kept as is
+    Keep,
+    /// The marked part is wrong and should be changed
+    Wrong,
+    /// A domain-specific mark
+    Custom(String),
+}
+
+/// An annotation on part of the working expression
+///
+/// Parts of the expression are identified by paths (sequences of child
+/// indexes from the root), whose interpretation is up to the expression type.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Annotation {
+    /// The path to the annotated part of the expression
+    pub path: Vec<usize>,
+    /// The mark placed on the annotated part
+    pub mark: Mark,
+}
+
+/// Feedback that a decider can give on a provided step (see
+/// [`Controller::give_feedback`])
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Feedback {
+    /// Steps like this one are helpful
+    Liked,
+    /// Steps like this one are unhelpful
+    Disliked,
+    /// Steps like this one should never be provided again
+    Never,
 }
 
 /// The interface for steps (also defines the notion of expression)
@@ -37,6 +509,110 @@ pub trait Step {
 
     /// Returns the result of applying a step to an expression (which may fail)
     fn apply(&self, e: &Self::Exp) -> Option<Self::Exp>;
+
+    /// Returns the results of applying each of a list of steps to an
+    /// expression
+    ///
+    /// Steps can override this method to amortize work shared among the
+    /// applications (e.g., a single traversal of the expression that fills
+    /// many holes). The default implementation calls [`Self::apply`] on each
+    /// step.
+    fn apply_all(steps: &[Self], e: &Self::Exp) -> Vec<Option<Self::Exp>>
+    where
+        Self: Sized,
+    {
+        steps.iter().map(|s| s.apply(e)).collect()
+    }
+
+    /// Returns whether or not the step applies to an expression
+    ///
+    /// Steps with cheap preconditions can override this method to avoid
+    /// computing the result. The default implementation calls
+    /// [`Self::apply`].
+    fn applicable(&self, e: &Self::Exp) -> bool {
+        self.apply(e).is_some()
+    }
+
+    /// Returns a human-readable explanation of why the step does not apply to
+    /// an expression (e.g., because the expression changed since the step was
+    /// provided)
+    ///
+    /// This should be cheap to compute. The default implementation returns
+    /// `None` (no explanation available).
+    fn why_inapplicable(&self, e: &Self::Exp) -> Option<String> {
+        let _ = e;
+        None
+    }
+
+    /// Returns the name of the provider that provided this step (if known)
+    ///
+    /// The default implementation returns `None`.
+    fn source(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// A user-defined category of steps (e.g., "refactoring" or "completion")
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StepCategory(pub String);
+
+impl StepCategory {
+    /// Creates a new step category
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(name.into())
+    }
+
+    /// Returns the name of the category
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for StepCategory {
+    fn from(name: &str) -> Self {
+        Self::new(name)
+    }
+}
+
+/// The interface for steps that can describe themselves to front-ends
+pub trait DescribedStep: Step {
+    /// Returns a human-readable description of the step
+    fn description(&self) -> String;
+
+    /// Returns the category of the step (if any)
+    ///
+    /// The default implementation returns `None`, in which case front-ends
+    /// fall back to categorizing the step by its [`Step::source`].
+    fn category(&self) -> Option<StepCategory> {
+        None
+    }
+}
+
+/// A group of steps belonging to the same category
+pub struct StepGroup<S> {
+    /// The category of the steps
+    pub category: StepCategory,
+    /// The steps in the category (in provided order)
+    pub steps: Vec<S>,
+}
+
+/// Groups steps by category (groups are ordered by first appearance)
+pub fn group_steps<S>(
+    steps: Vec<S>,
+    classify: impl Fn(&S) -> StepCategory,
+) -> Vec<StepGroup<S>> {
+    let mut groups: Vec<StepGroup<S>> = vec![];
+    for step in steps {
+        let category = classify(&step);
+        match groups.iter_mut().find(|g| g.category == category) {
+            Some(g) => g.steps.push(step),
+            None => groups.push(StepGroup {
+                category,
+                steps: vec![step],
+            }),
+        }
+    }
+    groups
 }
 
 /// The interface for validity checking
@@ -48,141 +624,3589 @@ pub trait ValidityChecker {
     fn check(&self, e: &Self::Exp) -> bool;
 }
 
-/// The interface for step providers
+/// The interface for validity checkers that also measure how close an
+/// expression is to being valid
 ///
-/// To be a valid solution to the Programming By Navigation Synthesis Problem,
-/// step providers must satisfy the Validity, Strong Completeness, and Strong
-/// Soundness properties in Section 3.2 of
-/// [Programming by Navigation](https://dl.acm.org/doi/10.1145/3729264).
-pub trait StepProvider<T: Timer> {
-    /// The notion of steps that the step provider provides
-    type Step: Step;
+/// Scores are useful as heuristics for automated search (see
+/// [`solve::ScoreGuided`]).
+pub trait ScoredValidityChecker: ValidityChecker {
+    /// Returns the score of the expression (e.g., the number of passing
+    /// tests); higher scores should be closer to valid
+    fn score(&self, e: &Self::Exp) -> f64;
+}
 
-    /// Returns a set of provided steps given a current working expression
-    fn provide(
-        &mut self,
-        timer: &T,
-        e: &<Self::Step as Step>::Exp,
-    ) -> Result<Vec<Self::Step>, T::EarlyCutoff>;
+/// The interface for resource budgets on expressions (e.g., on their size or
+/// depth)
+pub trait ResourceChecker {
+    /// The notion of expressions to use for Programming by Navigation
+    type Exp;
+
+    /// Returns whether or not the expression is within the budget
+    fn within_budget(&self, e: &Self::Exp) -> bool;
 }
 
-/// A composition of other step providers (all provided steps are concatenated)
-pub struct CompoundProvider<T: Timer, S: Step> {
-    providers: Vec<Box<dyn StepProvider<T, Step = S>>>,
+/// A resource budget that bounds a user-supplied measure of expressions
+pub struct MeasureBudget<E, F> {
+    measure: F,
+    limit: usize,
+    _exp: core::marker::PhantomData<fn(&E)>,
 }
 
-impl<T: Timer, S: Step> CompoundProvider<T, S> {
-    /// Creates a new [`CompoundProvider`] from a list of existing providers
-    pub fn new(providers: Vec<Box<dyn StepProvider<T, Step = S>>>) -> Self {
-        Self { providers }
+impl<E, F: Fn(&E) -> usize> MeasureBudget<E, F> {
+    /// Creates a new [`MeasureBudget`] that allows expressions whose measure
+    /// is at most `limit`
+    pub fn new(measure: F, limit: usize) -> Self {
+        Self {
+            measure,
+            limit,
+            _exp: core::marker::PhantomData,
+        }
     }
 }
 
-impl<T: Timer, S: Step> StepProvider<T> for CompoundProvider<T, S> {
-    type Step = S;
+impl<E, F: Fn(&E) -> usize> ResourceChecker for MeasureBudget<E, F> {
+    type Exp = E;
 
-    fn provide(
-        &mut self,
-        timer: &T,
-        e: &<Self::Step as Step>::Exp,
-    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
-        let mut steps = vec![];
-        for p in &mut self.providers {
-            steps.extend(p.provide(timer, e)?);
-        }
-        Ok(steps)
+    fn within_budget(&self, e: &E) -> bool {
+        (self.measure)(e) <= self.limit
     }
 }
 
-/// A provider that returns the first provided step set that is nonempty (or
-/// an empty set if there is none)
-pub struct FallbackProvider<T: Timer, S: Step> {
-    providers: Vec<Box<dyn StepProvider<T, Step = S>>>,
-}
+/// The interface for reachability oracles
+///
+/// A reachability oracle approximates whether some valid expression can still
+/// be reached from a given expression (the key ingredient of Strong
+/// Soundness).
+pub trait ReachabilityOracle {
+    /// The notion of expressions to use for Programming by Navigation
+    type Exp;
 
-impl<T: Timer, S: Step> FallbackProvider<T, S> {
-    /// Creates a new [`FallbackProvider`] from a list of existing providers
-    pub fn new(providers: Vec<Box<dyn StepProvider<T, Step = S>>>) -> Self {
-        Self { providers }
-    }
+    /// Returns whether or not a valid expression is reachable from `e`
+    fn reachable(&self, e: &Self::Exp) -> bool;
 }
 
-impl<T: Timer, S: Step> StepProvider<T> for FallbackProvider<T, S> {
-    type Step = S;
+/// The current version of the capability protocol between controllers and
+/// step providers (see [`Capabilities`])
+pub const CAPABILITIES_VERSION: u32 = 1;
 
-    fn provide(
-        &mut self,
-        timer: &T,
-        e: &<Self::Step as Step>::Exp,
-    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
-        for p in &mut self.providers {
-            let steps = p.provide(timer, e)?;
-            if !steps.is_empty() {
-                return Ok(steps);
-            }
-        }
-        Ok(vec![])
-    }
+/// The interaction modes supported by a step provider or a [`Controller`]
+///
+/// A controller negotiates the richest mode that both it and its provider
+/// support (see [`Controller::capabilities`]) rather than probing for
+/// extension traits on every call. Incremental and batch provision are only
+/// used when negotiated. Partial and annotated provision are advisory (e.g.,
+/// for deciding whether an interface should offer annotations), since the
+/// default implementations of [`StepProvider::provide_partial`] and
+/// [`StepProvider::provide_annotated`] are always correct.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// The version of the capability protocol (see [`CAPABILITIES_VERSION`])
+    pub version: u32,
+    /// Incremental provision (see [`IncrementalStepProvider`])
+    pub incremental: bool,
+    /// Batch provision (see [`BatchStepProvider`])
+    pub batch: bool,
+    /// Sound partial step sets on cutoff (see
+    /// [`StepProvider::provide_partial`])
+    pub partial: bool,
+    /// Step sets that respond to the decider's annotations (see
+    /// [`StepProvider::provide_annotated`])
+    pub annotated: bool,
 }
 
-/// A Programming by Navigation "controller" that abstracts away the underlying
-/// step provider and validity checker to manage a Programming by Navigation
-/// interactive session
-pub struct Controller<T: Timer, S: Step> {
-    timer: T,
-    provider: Box<dyn StepProvider<T, Step = S> + 'static>,
-    checker: Box<dyn ValidityChecker<Exp = S::Exp> + 'static>,
-    state: S::Exp,
-    history: Option<Vec<S::Exp>>,
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self::none()
+    }
 }
 
-impl<T: Timer, S: Step> Controller<T, S> {
-    /// Create a new controller (history can be saved to enable meta-level
-    /// "undo" operations in the interactive process)
-    pub fn new(
-        timer: T,
-        provider: impl StepProvider<T, Step = S> + 'static,
-        checker: impl ValidityChecker<Exp = S::Exp> + 'static,
-        start: S::Exp,
-        save_history: bool,
-    ) -> Self {
+impl Capabilities {
+    /// Returns the capabilities of the current version with no interaction
+    /// modes beyond [`StepProvider::provide`]
+    pub const fn none() -> Self {
         Self {
-            timer,
-            provider: Box::new(provider),
-            checker: Box::new(checker),
-            state: start,
-            history: if save_history { Some(vec![]) } else { None },
+            version: CAPABILITIES_VERSION,
+            incremental: false,
+            batch: false,
+            partial: false,
+            annotated: false,
         }
     }
 
-    /// Ask the synthesizer to provide a list of possible next steps
-    pub fn provide(&mut self) -> Result<Vec<S>, T::EarlyCutoff> {
-        self.provider.provide(&self.timer, &self.state)
+    /// Returns the capabilities of the current version with every
+    /// interaction mode
+    pub const fn all() -> Self {
+        Self {
+            version: CAPABILITIES_VERSION,
+            incremental: true,
+            batch: true,
+            partial: true,
+            annotated: true,
+        }
     }
 
-    /// Decide which step to take (**must** be selected from among the ones that
-    /// are provided by the [`provide`] function)
-    pub fn decide(&mut self, step: S) {
-        match &mut self.history {
-            None => (),
-            Some(his) => his.push(self.state.clone()),
-        };
-        self.state = step.apply(&self.state).unwrap();
+    /// Returns the richest capabilities supported by both `self` and
+    /// `other`, at the older of their versions
+    pub fn negotiate(&self, other: &Self) -> Self {
+        Self {
+            version: self.version.min(other.version),
+            incremental: self.incremental && other.incremental,
+            batch: self.batch && other.batch,
+            partial: self.partial && other.partial,
+            annotated: self.annotated && other.annotated,
+        }
     }
 
-    /// Returns a reference to the current working expression
-    pub fn working_expression(&self) -> &S::Exp {
-        &self.state
+    /// Returns the interaction modes supported by either `self` or `other`,
+    /// at the older of their versions (e.g., for a combinator that passes a
+    /// mode on to whichever of its sub-providers supports it)
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            version: self.version.min(other.version),
+            incremental: self.incremental || other.incremental,
+            batch: self.batch || other.batch,
+            partial: self.partial || other.partial,
+            annotated: self.annotated || other.annotated,
+        }
     }
 
-    /// Returns the current working expression and drops self
-    pub fn end(self) -> S::Exp {
+    /// Returns the capabilities of a wrapper that forwards
+    /// [`StepProvider::provide_partial`] and
+    /// [`StepProvider::provide_annotated`] (but not incremental or batch
+    /// provision) to a provider with these capabilities
+    pub fn forwarded(&self) -> Self {
+        Self {
+            incremental: false,
+            batch: false,
+            ..*self
+        }
+    }
+
+    /// Returns the names of the supported interaction modes (e.g., for
+    /// session metadata)
+    pub fn names(&self) -> Vec<&'static str> {
+        [
+            (self.incremental, "incremental"),
+            (self.batch, "batch"),
+            (self.partial, "partial"),
+            (self.annotated, "annotated"),
+        ]
+        .into_iter()
+        .filter_map(|(supported, name)| supported.then_some(name))
+        .collect()
+    }
+}
+
+/// The interface for step providers
+///
+/// To be a valid solution to the Programming By Navigation Synthesis Problem,
+/// step providers must satisfy the Validity, Strong Completeness, and Strong
+/// Soundness properties in Section 3.2 of
+/// [Programming by Navigation](https://dl.acm.org/doi/10.1145/3729264).
+pub trait StepProvider<T: Timer> {
+    /// The notion of steps that the step provider provides
+    type Step: Step;
+
+    /// Returns a set of provided steps given a current working expression
+    fn provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff>;
+
+    /// Returns a set of provided steps given a current working expression,
+    /// along with the reason for early cutoff (if any)
+    ///
+    /// Unlike [`Self::provide`], this method can return the (sound) steps
+    /// found so far when cut off; in that case, the returned steps need not be
+    /// complete. The default implementation returns no steps on cutoff.
+    fn provide_partial(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> (Vec<Self::Step>, Option<T::EarlyCutoff>) {
+        match self.provide(timer, e) {
+            Ok(steps) => (steps, None),
+            Err(ec) => (vec![], Some(ec)),
+        }
+    }
+
+    /// Returns a set of provided steps given a current working expression,
+    /// distinguishing timer cutoffs from hard failures
+    ///
+    /// Providers that can fail for reasons other than a timer cutoff (e.g.,
+    /// providers backed by a remote service) should override this method to
+    /// report such failures as [`ProviderError::Failure`]. The default
+    /// implementation calls [`Self::provide`], which cannot fail otherwise.
+    fn try_provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, ProviderError<T::EarlyCutoff>> {
+        Ok(self.provide(timer, e)?)
+    }
+
+    /// Returns a set of provided steps given a current working expression and
+    /// the decider's annotations on it
+    ///
+    /// The default implementation ignores the annotations.
+    fn provide_annotated(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+        annotations: &[Annotation],
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        let _ = annotations;
+        self.provide(timer, e)
+    }
+
+    /// Called when a session using this provider starts at expression `start`
+    ///
+    /// Along with the other lifecycle hooks, this lets providers maintain
+    /// (incremental) state that is consistent with the session. The default
+    /// implementation does nothing.
+    fn on_session_start(&mut self, start: &<Self::Step as Step>::Exp) {
+        let _ = start;
+    }
+
+    /// Called when `step` is decided on, resulting in the expression `e`
+    ///
+    /// The default implementation does nothing.
+    fn on_decide(&mut self, step: &Self::Step, e: &<Self::Step as Step>::Exp) {
+        let _ = (step, e);
+    }
+
+    /// Called when a decision is undone, restoring the expression `e`
+    ///
+    /// The default implementation does nothing.
+    fn on_undo(&mut self, e: &<Self::Step as Step>::Exp) {
+        let _ = e;
+    }
+
+    /// Called when the working expression `prev` is replaced by `e` outside
+    /// of navigation (e.g., by a hand edit in an external editor), which
+    /// invalidates any state derived from previous expressions
+    ///
+    /// The default implementation treats this as the start of a new session
+    /// at `e` (see [`Self::on_session_start`]).
+    fn on_external_edit(
+        &mut self,
+        prev: &<Self::Step as Step>::Exp,
+        e: &<Self::Step as Step>::Exp,
+    ) {
+        let _ = prev;
+        self.on_session_start(e);
+    }
+
+    /// Called when the notion of validity of the session is refined (or
+    /// replaced), with the new validity predicate
+    ///
+    /// Providers can use this to make their future step sets respect the
+    /// refined goal. The default implementation does nothing.
+    fn on_refine(
+        &mut self,
+        valid: &dyn Fn(&<Self::Step as Step>::Exp) -> bool,
+    ) {
+        let _ = valid;
+    }
+
+    /// Called when the decider gives feedback on a provided step
+    ///
+    /// Providers can use this to re-rank or filter their future step sets
+    /// (see also [`feedback::FeedbackRanked`]). The default implementation
+    /// does nothing.
+    fn on_feedback(&mut self, step: &Self::Step, feedback: Feedback) {
+        let _ = (step, feedback);
+    }
+
+    /// Called to give the provider a handle for reporting the progress of
+    /// long-running calls to [`Self::provide`]
+    ///
+    /// The default implementation ignores the handle.
+    fn set_progress(&mut self, reporter: ProgressReporter) {
+        let _ = reporter;
+    }
+
+    /// Called once when a [`Controller`] session using this provider is
+    /// created (after [`Self::on_session_start`]), so that the provider can
+    /// precompute what it needs (e.g., indexes or banks of terms) before the
+    /// first call to [`Self::provide`]
+    ///
+    /// Warming up is best effort: the controller ignores cutoffs, and a
+    /// provider that is cut off should finish its preparation lazily. The
+    /// default implementation does nothing.
+    fn warmup(&mut self, timer: &T) -> Result<(), T::EarlyCutoff> {
+        let _ = timer;
+        Ok(())
+    }
+
+    /// Called with the context of the session whenever it is updated (see
+    /// [`Controller::update_context`])
+    ///
+    /// Providers that depend on the context should keep it and read it in
+    /// [`Self::provide`]; providers that cache step sets should discard them.
+    /// The default implementation ignores the context.
+    fn set_context(&mut self, context: Context) {
+        let _ = context;
+    }
+
+    /// Returns the interaction modes that this provider supports
+    ///
+    /// Providers that override [`Self::provide_partial`] or
+    /// [`Self::provide_annotated`] should override this method to declare
+    /// them. The default implementation declares incremental and batch
+    /// provision if [`Self::as_incremental`] and [`Self::as_batch`] return
+    /// `Some`, respectively.
+    fn capabilities(&mut self) -> Capabilities {
+        Capabilities {
+            incremental: self.as_incremental().is_some(),
+            batch: self.as_batch().is_some(),
+            ..Capabilities::none()
+        }
+    }
+
+    /// Returns this provider as an [`IncrementalStepProvider`] (if it is one)
+    ///
+    /// Incremental providers should override this method to return
+    /// `Some(self)` so that the [`Controller`] can detect and prefer the
+    /// incremental interface. The default implementation returns `None`.
+    fn as_incremental(
+        &mut self,
+    ) -> Option<&mut dyn IncrementalStepProvider<T, Step = Self::Step>> {
+        None
+    }
+
+    /// Returns this provider as a [`BatchStepProvider`] (if it is one)
+    ///
+    /// Batch providers should override this method to return `Some(self)`
+    /// so that callers can request several step sets in one call. The
+    /// default implementation returns `None`.
+    fn as_batch(
+        &mut self,
+    ) -> Option<&mut dyn BatchStepProvider<T, Step = Self::Step>> {
+        None
+    }
+}
+
+/// The interface for step providers that can reuse work across successive
+/// expressions
+///
+/// Incremental providers receive the previous expression and the step that
+/// was applied to it in addition to the current expression, so they can
+/// update their candidate step sets rather than recomputing them from scratch.
+/// Implementations should also override [`StepProvider::as_incremental`].
+pub trait IncrementalStepProvider<T: Timer>: StepProvider<T> {
+    /// Returns a set of provided steps given the current working expression
+    /// `e`, which was obtained by applying `step` to `prev`
+    fn provide_incremental(
+        &mut self,
+        timer: &T,
+        prev: &<Self::Step as Step>::Exp,
+        step: &Self::Step,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff>;
+}
+
+/// The interface for step providers that can provide step sets for several
+/// expressions in one call
+///
+/// Providers with a high latency per call (e.g., providers backed by a remote
+/// service or a subprocess) can answer a whole batch of candidate expressions
+/// in one round trip. Batches are requested for expressions other than the
+/// one the lifecycle hooks have synchronized the provider to (e.g., by
+/// [`prefetch::Prefetcher`] and [`Controller::solve_deepening`]), so each
+/// step set should be the one [`StepProvider::provide`] would return for its
+/// expression. Implementations should also override
+/// [`StepProvider::as_batch`].
+pub trait BatchStepProvider<T: Timer>: StepProvider<T> {
+    /// Returns the provided steps for each of the expressions `es` (in
+    /// order)
+    fn provide_batch(
+        &mut self,
+        timer: &T,
+        es: &[<Self::Step as Step>::Exp],
+    ) -> Result<Vec<Vec<Self::Step>>, ProviderError<T::EarlyCutoff>>;
+}
+
+impl<T: Timer, P: StepProvider<T> + ?Sized> StepProvider<T> for Box<P> {
+    type Step = P::Step;
+
+    fn provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        (**self).provide(timer, e)
+    }
+
+    fn provide_partial(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> (Vec<Self::Step>, Option<T::EarlyCutoff>) {
+        (**self).provide_partial(timer, e)
+    }
+
+    fn try_provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, ProviderError<T::EarlyCutoff>> {
+        (**self).try_provide(timer, e)
+    }
+
+    fn provide_annotated(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+        annotations: &[Annotation],
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        (**self).provide_annotated(timer, e, annotations)
+    }
+
+    fn on_session_start(&mut self, start: &<Self::Step as Step>::Exp) {
+        (**self).on_session_start(start)
+    }
+
+    fn on_decide(&mut self, step: &Self::Step, e: &<Self::Step as Step>::Exp) {
+        (**self).on_decide(step, e)
+    }
+
+    fn on_undo(&mut self, e: &<Self::Step as Step>::Exp) {
+        (**self).on_undo(e)
+    }
+
+    fn on_external_edit(
+        &mut self,
+        prev: &<Self::Step as Step>::Exp,
+        e: &<Self::Step as Step>::Exp,
+    ) {
+        (**self).on_external_edit(prev, e)
+    }
+
+    fn on_refine(
+        &mut self,
+        valid: &dyn Fn(&<Self::Step as Step>::Exp) -> bool,
+    ) {
+        (**self).on_refine(valid)
+    }
+
+    fn on_feedback(&mut self, step: &Self::Step, feedback: Feedback) {
+        (**self).on_feedback(step, feedback)
+    }
+
+    fn set_progress(&mut self, reporter: ProgressReporter) {
+        (**self).set_progress(reporter)
+    }
+
+    fn set_context(&mut self, context: Context) {
+        (**self).set_context(context)
+    }
+
+    fn warmup(&mut self, timer: &T) -> Result<(), T::EarlyCutoff> {
+        (**self).warmup(timer)
+    }
+
+    fn capabilities(&mut self) -> Capabilities {
+        (**self).capabilities()
+    }
+
+    fn as_incremental(
+        &mut self,
+    ) -> Option<&mut dyn IncrementalStepProvider<T, Step = Self::Step>> {
+        (**self).as_incremental()
+    }
+
+    fn as_batch(
+        &mut self,
+    ) -> Option<&mut dyn BatchStepProvider<T, Step = Self::Step>> {
+        (**self).as_batch()
+    }
+}
+
+/// The interface for complete step enumerators
+///
+/// Unlike step providers, step enumerators make no attempt to be Strongly
+/// Sound; they simply enumerate every step that applies to an expression.
+pub trait StepEnumerator {
+    /// The notion of steps that the step enumerator enumerates
+    type Step: Step;
+
+    /// Returns every step that applies to `e`
+    fn enumerate(&self, e: &<Self::Step as Step>::Exp) -> Vec<Self::Step>;
+}
+
+/// The interface for automated step deciders (policies)
+pub trait Decider<S: Step> {
+    /// Returns the index of the step to take among the nonempty list of
+    /// provided `steps` for the working expression `e` (or `None` to stop
+    /// navigating)
+    fn decide(&mut self, e: &S::Exp, steps: &[S]) -> Option<usize>;
+}
+
+/// A step tagged with the name of the provider that provided it
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Tagged<S> {
+    /// The name of the provider that provided the step
+    pub name: Arc<str>,
+    /// The underlying step
+    pub step: S,
+}
+
+impl<S: Step> Step for Tagged<S> {
+    type Exp = S::Exp;
+
+    fn apply(&self, e: &Self::Exp) -> Option<Self::Exp> {
+        self.step.apply(e)
+    }
+
+    fn applicable(&self, e: &Self::Exp) -> bool {
+        self.step.applicable(e)
+    }
+
+    fn why_inapplicable(&self, e: &Self::Exp) -> Option<String> {
+        self.step.why_inapplicable(e)
+    }
+
+    fn source(&self) -> Option<&str> {
+        Some(&self.name)
+    }
+}
+
+impl<S: DescribedStep> DescribedStep for Tagged<S> {
+    fn description(&self) -> String {
+        self.step.description()
+    }
+
+    fn category(&self) -> Option<StepCategory> {
+        self.step.category()
+    }
+}
+
+/// A provider that tags all steps provided by another provider with a name
+pub struct TaggedProvider<P> {
+    name: Arc<str>,
+    inner: P,
+}
+
+impl<P> TaggedProvider<P> {
+    /// Creates a new [`TaggedProvider`]
+    pub fn new(name: impl Into<Arc<str>>, inner: P) -> Self {
+        Self {
+            name: name.into(),
+            inner,
+        }
+    }
+}
+
+impl<T: Timer, P: StepProvider<T>> StepProvider<T> for TaggedProvider<P> {
+    type Step = Tagged<P::Step>;
+
+    fn provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        Ok(self
+            .inner
+            .provide(timer, e)?
+            .into_iter()
+            .map(|step| Tagged {
+                name: self.name.clone(),
+                step,
+            })
+            .collect())
+    }
+
+    fn provide_partial(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> (Vec<Self::Step>, Option<T::EarlyCutoff>) {
+        let (steps, cutoff) = self.inner.provide_partial(timer, e);
+        let steps = steps
+            .into_iter()
+            .map(|step| Tagged {
+                name: self.name.clone(),
+                step,
+            })
+            .collect();
+        (steps, cutoff)
+    }
+
+    fn try_provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, ProviderError<T::EarlyCutoff>> {
+        Ok(self
+            .inner
+            .try_provide(timer, e)?
+            .into_iter()
+            .map(|step| Tagged {
+                name: self.name.clone(),
+                step,
+            })
+            .collect())
+    }
+
+    fn provide_annotated(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+        annotations: &[Annotation],
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        Ok(self
+            .inner
+            .provide_annotated(timer, e, annotations)?
+            .into_iter()
+            .map(|step| Tagged {
+                name: self.name.clone(),
+                step,
+            })
+            .collect())
+    }
+
+    fn capabilities(&mut self) -> Capabilities {
+        self.inner.capabilities().forwarded()
+    }
+
+    fn on_session_start(&mut self, start: &<Self::Step as Step>::Exp) {
+        self.inner.on_session_start(start);
+    }
+
+    fn on_decide(&mut self, step: &Self::Step, e: &<Self::Step as Step>::Exp) {
+        self.inner.on_decide(&step.step, e);
+    }
+
+    fn on_undo(&mut self, e: &<Self::Step as Step>::Exp) {
+        self.inner.on_undo(e);
+    }
+
+    fn on_external_edit(
+        &mut self,
+        prev: &<Self::Step as Step>::Exp,
+        e: &<Self::Step as Step>::Exp,
+    ) {
+        self.inner.on_external_edit(prev, e);
+    }
+
+    fn on_refine(
+        &mut self,
+        valid: &dyn Fn(&<Self::Step as Step>::Exp) -> bool,
+    ) {
+        self.inner.on_refine(valid);
+    }
+
+    fn on_feedback(&mut self, step: &Self::Step, feedback: Feedback) {
+        self.inner.on_feedback(&step.step, feedback);
+    }
+
+    fn set_progress(&mut self, reporter: ProgressReporter) {
+        self.inner.set_progress(reporter);
+    }
+
+    fn set_context(&mut self, context: Context) {
+        self.inner.set_context(context);
+    }
+
+    fn warmup(&mut self, timer: &T) -> Result<(), T::EarlyCutoff> {
+        self.inner.warmup(timer)
+    }
+}
+
+/// Warms up every provider in order (see [`StepProvider::warmup`]), even
+/// after one of them is cut off, returning the first cutoff
+fn warmup_all<'a, T, P>(
+    providers: impl IntoIterator<Item = &'a mut P>,
+    timer: &T,
+) -> Result<(), T::EarlyCutoff>
+where
+    T: Timer,
+    P: StepProvider<T> + ?Sized + 'a,
+{
+    let mut cutoff = None;
+    for p in providers {
+        if let Err(ec) = p.warmup(timer) {
+            cutoff.get_or_insert(ec);
+        }
+    }
+    cutoff.map_or(Ok(()), Err)
+}
+
+/// Returns the capabilities that a combinator inherits from `providers` by
+/// passing partial and annotated provision (but not incremental or batch
+/// provision) on to them
+fn combined_capabilities<'a, T, P>(
+    providers: impl IntoIterator<Item = &'a mut P>,
+) -> Capabilities
+where
+    T: Timer,
+    P: StepProvider<T> + ?Sized + 'a,
+{
+    let subs = providers
+        .into_iter()
+        .map(|p| p.capabilities())
+        .reduce(|a, b| a.union(&b))
+        .unwrap_or_default();
+    subs.forwarded()
+}
+
+/// Named step providers (for use in combinators that tag steps)
+pub type NamedProviders<T, S> =
+    Vec<(String, Box<dyn StepProvider<T, Step = S>>)>;
+
+fn tag_all<T: Timer + 'static, S: Step + 'static>(
+    providers: NamedProviders<T, S>,
+) -> Vec<Box<dyn StepProvider<T, Step = Tagged<S>>>> {
+    providers
+        .into_iter()
+        .map(|(name, p)| {
+            Box::new(TaggedProvider::new(name, p))
+                as Box<dyn StepProvider<T, Step = Tagged<S>>>
+        })
+        .collect()
+}
+
+/// A comparison function on steps
+pub type StepComparator<S> = Box<dyn Fn(&S, &S) -> core::cmp::Ordering>;
+
+/// How a combinator orders the steps provided by its sub-providers
+///
+/// Deciders (and user interfaces) often rely on the order of provided steps,
+/// so combinators that merge the step sets of several sub-providers document
+/// which order they guarantee. Combinators that return the step set of a
+/// single sub-provider (such as [`FallbackProvider`]) preserve its order.
+#[derive(Default)]
+pub enum StepOrder<S> {
+    /// All steps of the first sub-provider (in order), then all steps of the
+    /// second, and so on
+    #[default]
+    Concatenate,
+    /// One step of each sub-provider in turn (in order), skipping
+    /// sub-providers that have run out of steps (see also
+    /// [`InterleavedCompoundProvider`] for weighted interleaving)
+    Interleave,
+    /// The concatenated steps, sorted by a comparison function with a stable
+    /// sort (so equal steps keep their concatenated order)
+    SortBy(StepComparator<S>),
+}
+
+impl<S> StepOrder<S> {
+    /// Sorts the concatenated steps by a key (see [`Self::SortBy`])
+    pub fn sort_by_key<K: Ord>(key: impl Fn(&S) -> K + 'static) -> Self {
+        StepOrder::SortBy(Box::new(move |a, b| key(a).cmp(&key(b))))
+    }
+
+    /// Combines the step sets of sub-providers (in sub-provider order)
+    fn arrange(&self, step_sets: Vec<Vec<S>>) -> Vec<S> {
+        match self {
+            StepOrder::Concatenate => step_sets.into_iter().flatten().collect(),
+            StepOrder::Interleave => {
+                interleave(step_sets, &mut core::iter::repeat(1))
+            }
+            StepOrder::SortBy(cmp) => {
+                let mut steps: Vec<S> =
+                    step_sets.into_iter().flatten().collect();
+                steps.sort_by(|a, b| cmp(a, b));
+                steps
+            }
+        }
+    }
+}
+
+/// Interleaves step sets, taking up to the next weight of steps from each set
+/// in each round
+fn interleave<S>(
+    step_sets: Vec<Vec<S>>,
+    weights: &mut dyn Iterator<Item = usize>,
+) -> Vec<S> {
+    let weights: Vec<usize> = weights.take(step_sets.len()).collect();
+    let total = step_sets.iter().map(|ss| ss.len()).sum();
+    let mut iters: Vec<_> =
+        step_sets.into_iter().map(|ss| ss.into_iter()).collect();
+    let mut steps = Vec::with_capacity(total);
+    while steps.len() < total {
+        for (it, w) in iters.iter_mut().zip(&weights) {
+            steps.extend(it.take(*w));
+        }
+    }
+    steps
+}
+
+/// A composition of other step providers
+///
+/// By default, all provided steps are concatenated in sub-provider order;
+/// see [`Self::with_order`] for other orders.
+pub struct CompoundProvider<T: Timer, S: Step> {
+    providers: Vec<Box<dyn StepProvider<T, Step = S>>>,
+    order: StepOrder<S>,
+}
+
+impl<T: Timer, S: Step> CompoundProvider<T, S> {
+    /// Creates a new [`CompoundProvider`] from a list of existing providers
+    pub fn new(providers: Vec<Box<dyn StepProvider<T, Step = S>>>) -> Self {
+        Self {
+            providers,
+            order: StepOrder::Concatenate,
+        }
+    }
+
+    /// Orders provided steps according to `order` (instead of concatenating
+    /// them)
+    pub fn with_order(mut self, order: StepOrder<S>) -> Self {
+        self.order = order;
+        self
+    }
+}
+
+impl<T: Timer + 'static, S: Step + 'static> CompoundProvider<T, Tagged<S>> {
+    /// Creates a new [`CompoundProvider`] from a list of named providers,
+    /// tagging every provided step with the name of its provider
+    pub fn named(providers: NamedProviders<T, S>) -> Self {
+        Self::new(tag_all(providers))
+    }
+}
+
+impl<T: Timer, S: Step> StepProvider<T> for CompoundProvider<T, S> {
+    type Step = S;
+
+    fn provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        let mut step_sets = vec![];
+        for p in &mut self.providers {
+            step_sets.push(p.provide(timer, e)?);
+        }
+        Ok(self.order.arrange(step_sets))
+    }
+
+    fn provide_partial(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> (Vec<Self::Step>, Option<T::EarlyCutoff>) {
+        let mut step_sets = vec![];
+        let mut cutoff = None;
+        for p in &mut self.providers {
+            let (ss, c) = p.provide_partial(timer, e);
+            step_sets.push(ss);
+            if c.is_some() {
+                cutoff = c;
+                break;
+            }
+        }
+        (self.order.arrange(step_sets), cutoff)
+    }
+
+    fn try_provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, ProviderError<T::EarlyCutoff>> {
+        let mut step_sets = vec![];
+        for p in &mut self.providers {
+            step_sets.push(p.try_provide(timer, e)?);
+        }
+        Ok(self.order.arrange(step_sets))
+    }
+
+    fn provide_annotated(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+        annotations: &[Annotation],
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        let mut step_sets = vec![];
+        for p in &mut self.providers {
+            step_sets.push(p.provide_annotated(timer, e, annotations)?);
+        }
+        Ok(self.order.arrange(step_sets))
+    }
+
+    fn capabilities(&mut self) -> Capabilities {
+        Capabilities {
+            partial: true,
+            ..combined_capabilities(&mut self.providers)
+        }
+    }
+
+    fn on_session_start(&mut self, start: &<Self::Step as Step>::Exp) {
+        for p in &mut self.providers {
+            p.on_session_start(start);
+        }
+    }
+
+    fn on_decide(&mut self, step: &Self::Step, e: &<Self::Step as Step>::Exp) {
+        for p in &mut self.providers {
+            p.on_decide(step, e);
+        }
+    }
+
+    fn on_undo(&mut self, e: &<Self::Step as Step>::Exp) {
+        for p in &mut self.providers {
+            p.on_undo(e);
+        }
+    }
+
+    fn on_external_edit(
+        &mut self,
+        prev: &<Self::Step as Step>::Exp,
+        e: &<Self::Step as Step>::Exp,
+    ) {
+        for p in &mut self.providers {
+            p.on_external_edit(prev, e);
+        }
+    }
+
+    fn on_refine(
+        &mut self,
+        valid: &dyn Fn(&<Self::Step as Step>::Exp) -> bool,
+    ) {
+        for p in &mut self.providers {
+            p.on_refine(valid);
+        }
+    }
+
+    fn on_feedback(&mut self, step: &Self::Step, feedback: Feedback) {
+        for p in &mut self.providers {
+            p.on_feedback(step, feedback);
+        }
+    }
+
+    fn set_progress(&mut self, reporter: ProgressReporter) {
+        for p in &mut self.providers {
+            p.set_progress(reporter.clone());
+        }
+    }
+
+    fn set_context(&mut self, context: Context) {
+        for p in &mut self.providers {
+            p.set_context(context.clone());
+        }
+    }
+
+    fn warmup(&mut self, timer: &T) -> Result<(), T::EarlyCutoff> {
+        warmup_all(&mut self.providers, timer)
+    }
+}
+
+/// A composition of other step providers in which provided steps are
+/// interleaved (rather than concatenated)
+///
+/// In each round, the provider at index `i` contributes up to `weights[i]`
+/// of its remaining steps (in order), so one prolific provider does not bury
+/// the suggestions of the others. With all weights equal to one, this is a
+/// round-robin interleaving.
+pub struct InterleavedCompoundProvider<T: Timer, S: Step> {
+    providers: Vec<Box<dyn StepProvider<T, Step = S>>>,
+    weights: Vec<usize>,
+}
+
+impl<T: Timer, S: Step> InterleavedCompoundProvider<T, S> {
+    /// Creates a new round-robin [`InterleavedCompoundProvider`] from a list
+    /// of existing providers
+    pub fn new(providers: Vec<Box<dyn StepProvider<T, Step = S>>>) -> Self {
+        let weights = vec![1; providers.len()];
+        Self { providers, weights }
+    }
+
+    /// Creates a new weighted [`InterleavedCompoundProvider`] from a list of
+    /// existing providers and their (positive) weights
+    ///
+    /// # Panics
+    ///
+    /// Panics if some weight is zero (see [`Self::try_weighted`])
+    #[track_caller]
+    pub fn weighted(
+        providers: Vec<(usize, Box<dyn StepProvider<T, Step = S>>)>,
+    ) -> Self {
+        InvalidArgument::unwrap(Self::try_weighted(providers))
+    }
+
+    /// Creates a new weighted [`InterleavedCompoundProvider`] (see
+    /// [`Self::weighted`]), or fails if some weight is zero
+    pub fn try_weighted(
+        providers: Vec<(usize, Box<dyn StepProvider<T, Step = S>>)>,
+    ) -> Result<Self, InvalidArgument> {
+        let (weights, providers): (Vec<_>, Vec<_>) =
+            providers.into_iter().unzip();
+        InvalidArgument::check(
+            weights.iter().all(|w| *w > 0),
+            "weights must be positive",
+            || Self { providers, weights },
+        )
+    }
+
+    fn interleave(&self, step_sets: Vec<Vec<S>>) -> Vec<S> {
+        interleave(step_sets, &mut self.weights.iter().copied())
+    }
+}
+
+impl<T: Timer, S: Step> StepProvider<T> for InterleavedCompoundProvider<T, S> {
+    type Step = S;
+
+    fn provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        let mut step_sets = vec![];
+        for p in &mut self.providers {
+            step_sets.push(p.provide(timer, e)?);
+        }
+        Ok(self.interleave(step_sets))
+    }
+
+    fn provide_partial(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> (Vec<Self::Step>, Option<T::EarlyCutoff>) {
+        let mut step_sets = vec![];
+        let mut cutoff = None;
+        for p in &mut self.providers {
+            let (ss, c) = p.provide_partial(timer, e);
+            step_sets.push(ss);
+            if c.is_some() {
+                cutoff = c;
+                break;
+            }
+        }
+        (self.interleave(step_sets), cutoff)
+    }
+
+    fn try_provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, ProviderError<T::EarlyCutoff>> {
+        let mut step_sets = vec![];
+        for p in &mut self.providers {
+            step_sets.push(p.try_provide(timer, e)?);
+        }
+        Ok(self.interleave(step_sets))
+    }
+
+    fn provide_annotated(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+        annotations: &[Annotation],
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        let mut step_sets = vec![];
+        for p in &mut self.providers {
+            step_sets.push(p.provide_annotated(timer, e, annotations)?);
+        }
+        Ok(self.interleave(step_sets))
+    }
+
+    fn capabilities(&mut self) -> Capabilities {
+        Capabilities {
+            partial: true,
+            ..combined_capabilities(&mut self.providers)
+        }
+    }
+
+    fn on_session_start(&mut self, start: &<Self::Step as Step>::Exp) {
+        for p in &mut self.providers {
+            p.on_session_start(start);
+        }
+    }
+
+    fn on_decide(&mut self, step: &Self::Step, e: &<Self::Step as Step>::Exp) {
+        for p in &mut self.providers {
+            p.on_decide(step, e);
+        }
+    }
+
+    fn on_undo(&mut self, e: &<Self::Step as Step>::Exp) {
+        for p in &mut self.providers {
+            p.on_undo(e);
+        }
+    }
+
+    fn on_external_edit(
+        &mut self,
+        prev: &<Self::Step as Step>::Exp,
+        e: &<Self::Step as Step>::Exp,
+    ) {
+        for p in &mut self.providers {
+            p.on_external_edit(prev, e);
+        }
+    }
+
+    fn on_refine(
+        &mut self,
+        valid: &dyn Fn(&<Self::Step as Step>::Exp) -> bool,
+    ) {
+        for p in &mut self.providers {
+            p.on_refine(valid);
+        }
+    }
+
+    fn on_feedback(&mut self, step: &Self::Step, feedback: Feedback) {
+        for p in &mut self.providers {
+            p.on_feedback(step, feedback);
+        }
+    }
+
+    fn set_progress(&mut self, reporter: ProgressReporter) {
+        for p in &mut self.providers {
+            p.set_progress(reporter.clone());
+        }
+    }
+
+    fn set_context(&mut self, context: Context) {
+        for p in &mut self.providers {
+            p.set_context(context.clone());
+        }
+    }
+
+    fn warmup(&mut self, timer: &T) -> Result<(), T::EarlyCutoff> {
+        warmup_all(&mut self.providers, timer)
+    }
+}
+
+/// A provider that returns the first provided step set that is nonempty (or
+/// an empty set if there is none)
+///
+/// By default, a hard failure of a sub-provider (see
+/// [`StepProvider::try_provide`]) is returned immediately; with
+/// [`Self::fall_through_on_failure`], the next sub-provider is tried instead.
+pub struct FallbackProvider<T: Timer, S: Step> {
+    providers: Vec<Box<dyn StepProvider<T, Step = S>>>,
+    fall_through: bool,
+}
+
+impl<T: Timer, S: Step> FallbackProvider<T, S> {
+    /// Creates a new [`FallbackProvider`] from a list of existing providers
+    pub fn new(providers: Vec<Box<dyn StepProvider<T, Step = S>>>) -> Self {
+        Self {
+            providers,
+            fall_through: false,
+        }
+    }
+
+    /// Tries the next sub-provider when a sub-provider fails (rather than
+    /// returning the failure); if no sub-provider then produces a nonempty
+    /// step set, the first failure is returned
+    pub fn fall_through_on_failure(mut self) -> Self {
+        self.fall_through = true;
+        self
+    }
+}
+
+impl<T: Timer + 'static, S: Step + 'static> FallbackProvider<T, Tagged<S>> {
+    /// Creates a new [`FallbackProvider`] from a list of named providers,
+    /// tagging every provided step with the name of its provider
+    pub fn named(providers: NamedProviders<T, S>) -> Self {
+        Self::new(tag_all(providers))
+    }
+}
+
+impl<T: Timer, S: Step> StepProvider<T> for FallbackProvider<T, S> {
+    type Step = S;
+
+    fn provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        for p in &mut self.providers {
+            let steps = p.provide(timer, e)?;
+            if !steps.is_empty() {
+                return Ok(steps);
+            }
+        }
+        Ok(vec![])
+    }
+
+    fn provide_partial(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> (Vec<Self::Step>, Option<T::EarlyCutoff>) {
+        for p in &mut self.providers {
+            let (steps, cutoff) = p.provide_partial(timer, e);
+            if !steps.is_empty() || cutoff.is_some() {
+                return (steps, cutoff);
+            }
+        }
+        (vec![], None)
+    }
+
+    fn try_provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, ProviderError<T::EarlyCutoff>> {
+        let mut failure = None;
+        for p in &mut self.providers {
+            match p.try_provide(timer, e) {
+                Ok(steps) if !steps.is_empty() => return Ok(steps),
+                Ok(_) => (),
+                Err(ProviderError::Failure(err)) if self.fall_through => {
+                    failure.get_or_insert(err);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        match failure {
+            Some(err) => Err(ProviderError::Failure(err)),
+            None => Ok(vec![]),
+        }
+    }
+
+    fn provide_annotated(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+        annotations: &[Annotation],
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        for p in &mut self.providers {
+            let steps = p.provide_annotated(timer, e, annotations)?;
+            if !steps.is_empty() {
+                return Ok(steps);
+            }
+        }
+        Ok(vec![])
+    }
+
+    fn capabilities(&mut self) -> Capabilities {
+        Capabilities {
+            partial: true,
+            ..combined_capabilities(&mut self.providers)
+        }
+    }
+
+    fn on_session_start(&mut self, start: &<Self::Step as Step>::Exp) {
+        for p in &mut self.providers {
+            p.on_session_start(start);
+        }
+    }
+
+    fn on_decide(&mut self, step: &Self::Step, e: &<Self::Step as Step>::Exp) {
+        for p in &mut self.providers {
+            p.on_decide(step, e);
+        }
+    }
+
+    fn on_undo(&mut self, e: &<Self::Step as Step>::Exp) {
+        for p in &mut self.providers {
+            p.on_undo(e);
+        }
+    }
+
+    fn on_external_edit(
+        &mut self,
+        prev: &<Self::Step as Step>::Exp,
+        e: &<Self::Step as Step>::Exp,
+    ) {
+        for p in &mut self.providers {
+            p.on_external_edit(prev, e);
+        }
+    }
+
+    fn on_refine(
+        &mut self,
+        valid: &dyn Fn(&<Self::Step as Step>::Exp) -> bool,
+    ) {
+        for p in &mut self.providers {
+            p.on_refine(valid);
+        }
+    }
+
+    fn on_feedback(&mut self, step: &Self::Step, feedback: Feedback) {
+        for p in &mut self.providers {
+            p.on_feedback(step, feedback);
+        }
+    }
+
+    fn set_progress(&mut self, reporter: ProgressReporter) {
+        for p in &mut self.providers {
+            p.set_progress(reporter.clone());
+        }
+    }
+
+    fn set_context(&mut self, context: Context) {
+        for p in &mut self.providers {
+            p.set_context(context.clone());
+        }
+    }
+
+    fn warmup(&mut self, timer: &T) -> Result<(), T::EarlyCutoff> {
+        warmup_all(&mut self.providers, timer)
+    }
+}
+
+/// A function that creates the sub-timer for the provider at a particular
+/// index from an overall timer
+pub type SubTimer<T> = Box<dyn Fn(&T, usize) -> T>;
+
+/// A composition of other step providers that isolates their cutoffs
+///
+/// Each sub-provider is run with its own sub-timer (created from the overall
+/// timer by a user-supplied function), so one slow sub-provider cannot starve
+/// the rest. Sub-providers are called with [`StepProvider::provide_partial`],
+/// so the steps found by a sub-provider before it was cut off are kept. The
+/// steps of all sub-providers are concatenated (or ordered according to
+/// [`Self::with_order`]), and the sub-providers that were cut off are
+/// recorded (see [`Self::cutoffs`]). The overall timer is still ticked
+/// between sub-providers; if it cuts off, [`StepProvider::provide_partial`]
+/// returns the steps of the sub-providers that were run.
+pub struct BestEffortCompoundProvider<T: Timer, S: Step> {
+    providers: Vec<Box<dyn StepProvider<T, Step = S>>>,
+    sub_timer: SubTimer<T>,
+    cutoffs: Vec<(usize, T::EarlyCutoff)>,
+    order: StepOrder<S>,
+    progress: ProgressReporter,
+}
+
+impl<T: Timer, S: Step> BestEffortCompoundProvider<T, S> {
+    /// Creates a new [`BestEffortCompoundProvider`] from a list of existing
+    /// providers and a function that creates the sub-timer for the provider
+    /// at a particular index from the overall timer
+    pub fn new(
+        providers: Vec<Box<dyn StepProvider<T, Step = S>>>,
+        sub_timer: impl Fn(&T, usize) -> T + 'static,
+    ) -> Self {
+        Self {
+            providers,
+            sub_timer: Box::new(sub_timer),
+            cutoffs: vec![],
+            order: StepOrder::Concatenate,
+            progress: ProgressReporter::new(),
+        }
+    }
+
+    /// Orders provided steps according to `order` (instead of concatenating
+    /// them)
+    pub fn with_order(mut self, order: StepOrder<S>) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Returns the indexes of the sub-providers that were cut off during the
+    /// most recent call to [`StepProvider::provide`] (along with the reasons)
+    pub fn cutoffs(&self) -> &[(usize, T::EarlyCutoff)] {
+        &self.cutoffs
+    }
+}
+
+impl<T: Timer, S: Step> StepProvider<T> for BestEffortCompoundProvider<T, S> {
+    type Step = S;
+
+    fn provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        match self.provide_partial(timer, e) {
+            (steps, None) => Ok(steps),
+            (_, Some(ec)) => Err(ec),
+        }
+    }
+
+    fn provide_partial(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> (Vec<Self::Step>, Option<T::EarlyCutoff>) {
+        self.cutoffs.clear();
+        let mut step_sets = vec![];
+        let mut overall = None;
+        for (i, p) in self.providers.iter_mut().enumerate() {
+            if let Err(ec) = timer.tick() {
+                overall = Some(ec);
+                break;
+            }
+            let sub_timer = (self.sub_timer)(timer, i);
+            let (ss, cutoff) = p.provide_partial(&sub_timer, e);
+            step_sets.push(ss);
+            if let Some(ec) = cutoff {
+                self.cutoffs.push((i, ec));
+            }
+        }
+        if !self.cutoffs.is_empty() {
+            self.progress.mark_approximate();
+        }
+        (self.order.arrange(step_sets), overall)
+    }
+
+    fn capabilities(&mut self) -> Capabilities {
+        Capabilities {
+            partial: true,
+            annotated: false,
+            ..combined_capabilities(&mut self.providers)
+        }
+    }
+
+    fn on_session_start(&mut self, start: &<Self::Step as Step>::Exp) {
+        for p in &mut self.providers {
+            p.on_session_start(start);
+        }
+    }
+
+    fn on_decide(&mut self, step: &Self::Step, e: &<Self::Step as Step>::Exp) {
+        for p in &mut self.providers {
+            p.on_decide(step, e);
+        }
+    }
+
+    fn on_undo(&mut self, e: &<Self::Step as Step>::Exp) {
+        for p in &mut self.providers {
+            p.on_undo(e);
+        }
+    }
+
+    fn on_external_edit(
+        &mut self,
+        prev: &<Self::Step as Step>::Exp,
+        e: &<Self::Step as Step>::Exp,
+    ) {
+        for p in &mut self.providers {
+            p.on_external_edit(prev, e);
+        }
+    }
+
+    fn on_refine(
+        &mut self,
+        valid: &dyn Fn(&<Self::Step as Step>::Exp) -> bool,
+    ) {
+        for p in &mut self.providers {
+            p.on_refine(valid);
+        }
+    }
+
+    fn on_feedback(&mut self, step: &Self::Step, feedback: Feedback) {
+        for p in &mut self.providers {
+            p.on_feedback(step, feedback);
+        }
+    }
+
+    fn set_progress(&mut self, reporter: ProgressReporter) {
+        for p in &mut self.providers {
+            p.set_progress(reporter.clone());
+        }
+        self.progress = reporter;
+    }
+
+    fn set_context(&mut self, context: Context) {
+        for p in &mut self.providers {
+            p.set_context(context.clone());
+        }
+    }
+
+    fn warmup(&mut self, timer: &T) -> Result<(), T::EarlyCutoff> {
+        let mut cutoff = None;
+        for (i, p) in self.providers.iter_mut().enumerate() {
+            if let Err(ec) = timer.tick() {
+                cutoff.get_or_insert(ec);
+            }
+            let _ = p.warmup(&(self.sub_timer)(timer, i));
+        }
+        cutoff.map_or(Ok(()), Err)
+    }
+}
+
+/// A shared flag used to cooperatively cancel running step providers
+///
+/// Timers can observe a cancellation token in their [`Timer::tick`]
+/// implementation to cut off providers whose results are no longer needed.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<core::sync::atomic::AtomicBool>,
+}
+
+impl CancelToken {
+    /// Creates a new (not yet cancelled) token
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the token (and all its clones)
+    pub fn cancel(&self) {
+        self.cancelled
+            .store(true, core::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Returns whether or not the token has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(core::sync::atomic::Ordering::Relaxed)
+    }
+}
+
+/// A function that creates the timer for the racing provider at a particular
+/// index from an overall timer and the cancellation token of the race
+///
+/// The created timer should cut its provider off once the token is cancelled
+/// (by checking [`CancelToken::is_cancelled`] in [`Timer::tick`]): a race
+/// only returns once every provider has returned, so a losing provider whose
+/// timer ignores the token blocks the race until it finishes on its own.
+#[cfg(feature = "std")]
+pub type RacerTimer<T> = Box<dyn Fn(&T, usize, &CancelToken) -> T>;
+
+/// A provider that runs other step providers concurrently and returns the
+/// first provided step set that is nonempty
+///
+/// This is the parallel analogue of [`FallbackProvider`]: once one
+/// sub-provider produces a nonempty step set, the cancellation token of the
+/// race is cancelled so that the timers of the other sub-providers (created by
+/// a user-supplied function; see [`RacerTimer`]) can cut them off. The first
+/// nonempty step set is returned even if the overall timer has run out in the
+/// meantime. If no sub-provider produces a nonempty step set and some
+/// sub-provider was cut off, the first such cutoff is returned; otherwise,
+/// the empty set is returned.
+#[cfg(feature = "std")]
+pub struct RaceProvider<T: Timer, S: Step> {
+    providers: Vec<Box<dyn StepProvider<T, Step = S> + Send>>,
+    racer_timer: RacerTimer<T>,
+}
+
+#[cfg(feature = "std")]
+impl<T: Timer, S: Step> RaceProvider<T, S> {
+    /// Creates a new [`RaceProvider`] from a list of existing providers and a
+    /// function that creates the timer for the provider at a particular index
+    pub fn new(
+        providers: Vec<Box<dyn StepProvider<T, Step = S> + Send>>,
+        racer_timer: impl Fn(&T, usize, &CancelToken) -> T + 'static,
+    ) -> Self {
+        Self {
+            providers,
+            racer_timer: Box::new(racer_timer),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T, S> StepProvider<T> for RaceProvider<T, S>
+where
+    T: Timer + Send,
+    T::EarlyCutoff: Send,
+    S: Step + Send,
+    S::Exp: Sync,
+{
+    type Step = S;
+
+    fn provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        self.provide_annotated(timer, e, &[])
+    }
+
+    fn provide_annotated(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+        annotations: &[Annotation],
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        let token = CancelToken::new();
+        let timers: Vec<T> = (0..self.providers.len())
+            .map(|i| (self.racer_timer)(timer, i, &token))
+            .collect();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut winner = None;
+        let mut cutoff = None;
+        std::thread::scope(|scope| {
+            for (p, t) in self.providers.iter_mut().zip(timers) {
+                let tx = tx.clone();
+                scope.spawn(move || {
+                    let _ = tx.send(p.provide_annotated(&t, e, annotations));
+                });
+            }
+            drop(tx);
+            for result in rx {
+                match result {
+                    Ok(steps) if !steps.is_empty() => {
+                        token.cancel();
+                        winner = Some(steps);
+                        break;
+                    }
+                    Ok(_) => (),
+                    Err(ec) => {
+                        if cutoff.is_none() {
+                            cutoff = Some(ec)
+                        }
+                    }
+                }
+            }
+        });
+        match (winner, cutoff) {
+            (Some(steps), _) => Ok(steps),
+            (None, Some(ec)) => Err(ec),
+            (None, None) => {
+                timer.tick()?;
+                Ok(vec![])
+            }
+        }
+    }
+
+    fn capabilities(&mut self) -> Capabilities {
+        Capabilities {
+            partial: false,
+            ..combined_capabilities(&mut self.providers)
+        }
+    }
+
+    fn on_session_start(&mut self, start: &<Self::Step as Step>::Exp) {
+        for p in &mut self.providers {
+            p.on_session_start(start);
+        }
+    }
+
+    fn on_decide(&mut self, step: &Self::Step, e: &<Self::Step as Step>::Exp) {
+        for p in &mut self.providers {
+            p.on_decide(step, e);
+        }
+    }
+
+    fn on_undo(&mut self, e: &<Self::Step as Step>::Exp) {
+        for p in &mut self.providers {
+            p.on_undo(e);
+        }
+    }
+
+    fn on_external_edit(
+        &mut self,
+        prev: &<Self::Step as Step>::Exp,
+        e: &<Self::Step as Step>::Exp,
+    ) {
+        for p in &mut self.providers {
+            p.on_external_edit(prev, e);
+        }
+    }
+
+    fn on_refine(
+        &mut self,
+        valid: &dyn Fn(&<Self::Step as Step>::Exp) -> bool,
+    ) {
+        for p in &mut self.providers {
+            p.on_refine(valid);
+        }
+    }
+
+    fn on_feedback(&mut self, step: &Self::Step, feedback: Feedback) {
+        for p in &mut self.providers {
+            p.on_feedback(step, feedback);
+        }
+    }
+
+    fn set_progress(&mut self, reporter: ProgressReporter) {
+        for p in &mut self.providers {
+            p.set_progress(reporter.clone());
+        }
+    }
+
+    fn set_context(&mut self, context: Context) {
+        for p in &mut self.providers {
+            p.set_context(context.clone());
+        }
+    }
+
+    fn warmup(&mut self, timer: &T) -> Result<(), T::EarlyCutoff> {
+        warmup_all(&mut self.providers, timer)
+    }
+}
+
+/// A function that configures a provider for a particular attempt
+pub type Configure<P> = Box<dyn FnMut(&mut P, usize)>;
+
+/// A provider that retries another provider with progressively looser
+/// parameters whenever it is cut off (e.g., for iterative deepening)
+///
+/// Before attempt `i` (starting from 0), a user-supplied function is called
+/// to configure the inner provider for that attempt (e.g., to set its search
+/// depth to `i + 1`). If every attempt is cut off, the last cutoff is
+/// returned.
+pub struct RetryProvider<P> {
+    inner: P,
+    configure: Configure<P>,
+    max_attempts: usize,
+}
+
+impl<P> RetryProvider<P> {
+    /// Creates a new [`RetryProvider`] that makes at most `max_attempts`
+    /// attempts (which must be positive)
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_attempts` is zero (see [`Self::try_new`])
+    #[track_caller]
+    pub fn new(
+        inner: P,
+        configure: impl FnMut(&mut P, usize) + 'static,
+        max_attempts: usize,
+    ) -> Self {
+        InvalidArgument::unwrap(Self::try_new(inner, configure, max_attempts))
+    }
+
+    /// Creates a new [`RetryProvider`] (see [`Self::new`]), or fails if
+    /// `max_attempts` is zero
+    pub fn try_new(
+        inner: P,
+        configure: impl FnMut(&mut P, usize) + 'static,
+        max_attempts: usize,
+    ) -> Result<Self, InvalidArgument> {
+        InvalidArgument::check(
+            max_attempts > 0,
+            "max_attempts must be positive",
+            || Self {
+                inner,
+                configure: Box::new(configure),
+                max_attempts,
+            },
+        )
+    }
+
+    /// Returns the inner provider and drops self
+    pub fn into_inner(self) -> P {
+        self.inner
+    }
+}
+
+impl<T: Timer, P: StepProvider<T>> StepProvider<T> for RetryProvider<P> {
+    type Step = P::Step;
+
+    fn provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        let mut attempt = 0;
+        loop {
+            (self.configure)(&mut self.inner, attempt);
+            match self.inner.provide(timer, e) {
+                Ok(steps) => return Ok(steps),
+                Err(ec) if attempt + 1 >= self.max_attempts => {
+                    return Err(ec);
+                }
+                Err(_) => attempt += 1,
+            }
+        }
+    }
+
+    fn provide_partial(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> (Vec<Self::Step>, Option<T::EarlyCutoff>) {
+        let mut attempt = 0;
+        loop {
+            (self.configure)(&mut self.inner, attempt);
+            match self.inner.provide_partial(timer, e) {
+                (_, Some(_)) if attempt + 1 < self.max_attempts => attempt += 1,
+                result => return result,
+            }
+        }
+    }
+
+    fn capabilities(&mut self) -> Capabilities {
+        Capabilities {
+            annotated: false,
+            ..self.inner.capabilities().forwarded()
+        }
+    }
+
+    fn on_session_start(&mut self, start: &<Self::Step as Step>::Exp) {
+        self.inner.on_session_start(start);
+    }
+
+    fn on_decide(&mut self, step: &Self::Step, e: &<Self::Step as Step>::Exp) {
+        self.inner.on_decide(step, e);
+    }
+
+    fn on_undo(&mut self, e: &<Self::Step as Step>::Exp) {
+        self.inner.on_undo(e);
+    }
+
+    fn on_external_edit(
+        &mut self,
+        prev: &<Self::Step as Step>::Exp,
+        e: &<Self::Step as Step>::Exp,
+    ) {
+        self.inner.on_external_edit(prev, e);
+    }
+
+    fn on_refine(
+        &mut self,
+        valid: &dyn Fn(&<Self::Step as Step>::Exp) -> bool,
+    ) {
+        self.inner.on_refine(valid);
+    }
+
+    fn on_feedback(&mut self, step: &Self::Step, feedback: Feedback) {
+        self.inner.on_feedback(step, feedback);
+    }
+
+    fn set_progress(&mut self, reporter: ProgressReporter) {
+        self.inner.set_progress(reporter);
+    }
+
+    fn set_context(&mut self, context: Context) {
+        self.inner.set_context(context);
+    }
+
+    fn warmup(&mut self, timer: &T) -> Result<(), T::EarlyCutoff> {
+        self.inner.warmup(timer)
+    }
+}
+
+/// The truncation state of a [`TakeProvider`]
+struct TakeState<S> {
+    truncated: bool,
+    rest: Vec<S>,
+}
+
+/// A handle for querying the truncation state of a [`TakeProvider`] (and
+/// requesting more of its steps) after it has been moved into a combinator or
+/// [`Controller`]
+pub struct TakeHandle<S> {
+    state: Arc<sync::Mutex<TakeState<S>>>,
+}
+
+impl<S> Clone for TakeHandle<S> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<S> TakeHandle<S> {
+    /// Returns whether or not the most recently provided step set was
+    /// truncated (in which case it is not necessarily Strongly Complete)
+    pub fn truncated(&self) -> bool {
+        self.state.lock().truncated
+    }
+
+    /// Returns the number of steps that were truncated from the most recently
+    /// provided step set and have not yet been requested
+    pub fn remaining(&self) -> usize {
+        self.state.lock().rest.len()
+    }
+
+    /// Returns (up to) the next `n` steps that were truncated from the most
+    /// recently provided step set
+    pub fn more(&self, n: usize) -> Vec<S> {
+        let mut state = self.state.lock();
+        let n = n.min(state.rest.len());
+        let steps: Vec<S> = state.rest.drain(..n).collect();
+        state.truncated = !state.rest.is_empty();
+        steps
+    }
+}
+
+/// A function that compares steps for ranking
+pub type Ranking<S> = Box<dyn Fn(&S, &S) -> core::cmp::Ordering>;
+
+/// A provider that truncates the step set provided by another provider to at
+/// most a fixed number of steps (optionally after ranking them)
+///
+/// Whether truncation occurred can be queried (and the truncated steps can be
+/// requested) via a [`TakeHandle`]; truncated step sets are also marked as
+/// approximate (see [`ProgressReporter::mark_approximate`]).
+pub struct TakeProvider<P, S> {
+    inner: P,
+    limit: usize,
+    ranking: Option<Ranking<S>>,
+    state: Arc<sync::Mutex<TakeState<S>>>,
+    progress: ProgressReporter,
+}
+
+impl<P, S> TakeProvider<P, S> {
+    /// Creates a new [`TakeProvider`] that provides at most `limit` steps
+    pub fn new(inner: P, limit: usize) -> Self {
+        Self {
+            inner,
+            limit,
+            ranking: None,
+            state: Arc::new(sync::Mutex::new(TakeState {
+                truncated: false,
+                rest: vec![],
+            })),
+            progress: ProgressReporter::new(),
+        }
+    }
+
+    /// Ranks the provided steps (with a stable sort) before truncating them
+    pub fn ranked(
+        mut self,
+        cmp: impl Fn(&S, &S) -> core::cmp::Ordering + 'static,
+    ) -> Self {
+        self.ranking = Some(Box::new(cmp));
+        self
+    }
+
+    /// Returns a handle for querying the truncation state of this provider
+    pub fn handle(&self) -> TakeHandle<S> {
+        TakeHandle {
+            state: self.state.clone(),
+        }
+    }
+
+    fn take(&self, mut steps: Vec<S>) -> Vec<S> {
+        if let Some(cmp) = &self.ranking {
+            steps.sort_by(cmp);
+        }
+        let rest = steps.split_off(self.limit.min(steps.len()));
+        if !rest.is_empty() {
+            self.progress.mark_approximate();
+        }
+        let mut state = self.state.lock();
+        state.truncated = !rest.is_empty();
+        state.rest = rest;
+        steps
+    }
+}
+
+impl<T: Timer, P: StepProvider<T>> StepProvider<T>
+    for TakeProvider<P, P::Step>
+{
+    type Step = P::Step;
+
+    fn provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        let steps = self.inner.provide(timer, e)?;
+        Ok(self.take(steps))
+    }
+
+    fn provide_partial(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> (Vec<Self::Step>, Option<T::EarlyCutoff>) {
+        let (steps, cutoff) = self.inner.provide_partial(timer, e);
+        (self.take(steps), cutoff)
+    }
+
+    fn try_provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, ProviderError<T::EarlyCutoff>> {
+        let steps = self.inner.try_provide(timer, e)?;
+        Ok(self.take(steps))
+    }
+
+    fn provide_annotated(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+        annotations: &[Annotation],
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        let steps = self.inner.provide_annotated(timer, e, annotations)?;
+        Ok(self.take(steps))
+    }
+
+    fn capabilities(&mut self) -> Capabilities {
+        self.inner.capabilities().forwarded()
+    }
+
+    fn on_session_start(&mut self, start: &<Self::Step as Step>::Exp) {
+        self.inner.on_session_start(start);
+    }
+
+    fn on_decide(&mut self, step: &Self::Step, e: &<Self::Step as Step>::Exp) {
+        self.inner.on_decide(step, e);
+    }
+
+    fn on_undo(&mut self, e: &<Self::Step as Step>::Exp) {
+        self.inner.on_undo(e);
+    }
+
+    fn on_external_edit(
+        &mut self,
+        prev: &<Self::Step as Step>::Exp,
+        e: &<Self::Step as Step>::Exp,
+    ) {
+        self.inner.on_external_edit(prev, e);
+    }
+
+    fn on_refine(
+        &mut self,
+        valid: &dyn Fn(&<Self::Step as Step>::Exp) -> bool,
+    ) {
+        self.inner.on_refine(valid);
+    }
+
+    fn on_feedback(&mut self, step: &Self::Step, feedback: Feedback) {
+        self.inner.on_feedback(step, feedback);
+    }
+
+    fn set_progress(&mut self, reporter: ProgressReporter) {
+        self.inner.set_progress(reporter.clone());
+        self.progress = reporter;
+    }
+
+    fn set_context(&mut self, context: Context) {
+        self.inner.set_context(context);
+    }
+
+    fn warmup(&mut self, timer: &T) -> Result<(), T::EarlyCutoff> {
+        self.inner.warmup(timer)
+    }
+}
+
+/// A provider that sorts the steps provided by another provider by a key
+/// (with a stable sort), so that step sets are provided in a deterministic
+/// order
+///
+/// Along with explicit seeds for the randomized components of the crate (see,
+/// e.g., [`differential::Config::seed`] and [`estimate::Config::seed`]), this
+/// makes sessions exactly reproducible across runs, provided that the inner
+/// provider itself provides the same step set for the same expression. (The
+/// step set provided by a racing provider such as `RaceProvider` can depend on
+/// thread timing, which sorting does not remove.)
+pub struct Deterministic<P, F> {
+    inner: P,
+    key: F,
+}
+
+impl<P, F> Deterministic<P, F> {
+    /// Creates a new [`Deterministic`] provider that sorts steps by `key`
+    pub fn new(inner: P, key: F) -> Self {
+        Self { inner, key }
+    }
+
+    /// Returns the inner provider and drops self
+    pub fn into_inner(self) -> P {
+        self.inner
+    }
+
+    fn sort<S, K: Ord>(&self, mut steps: Vec<S>) -> Vec<S>
+    where
+        F: Fn(&S) -> K,
+    {
+        steps.sort_by_key(&self.key);
+        steps
+    }
+}
+
+impl<T, P, F, K> StepProvider<T> for Deterministic<P, F>
+where
+    T: Timer,
+    P: StepProvider<T>,
+    F: Fn(&P::Step) -> K,
+    K: Ord,
+{
+    type Step = P::Step;
+
+    fn provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        let steps = self.inner.provide(timer, e)?;
+        Ok(self.sort(steps))
+    }
+
+    fn provide_partial(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> (Vec<Self::Step>, Option<T::EarlyCutoff>) {
+        let (steps, cutoff) = self.inner.provide_partial(timer, e);
+        (self.sort(steps), cutoff)
+    }
+
+    fn try_provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, ProviderError<T::EarlyCutoff>> {
+        let steps = self.inner.try_provide(timer, e)?;
+        Ok(self.sort(steps))
+    }
+
+    fn provide_annotated(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+        annotations: &[Annotation],
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        let steps = self.inner.provide_annotated(timer, e, annotations)?;
+        Ok(self.sort(steps))
+    }
+
+    fn capabilities(&mut self) -> Capabilities {
+        self.inner.capabilities().forwarded()
+    }
+
+    fn on_session_start(&mut self, start: &<Self::Step as Step>::Exp) {
+        self.inner.on_session_start(start);
+    }
+
+    fn on_decide(&mut self, step: &Self::Step, e: &<Self::Step as Step>::Exp) {
+        self.inner.on_decide(step, e);
+    }
+
+    fn on_undo(&mut self, e: &<Self::Step as Step>::Exp) {
+        self.inner.on_undo(e);
+    }
+
+    fn on_external_edit(
+        &mut self,
+        prev: &<Self::Step as Step>::Exp,
+        e: &<Self::Step as Step>::Exp,
+    ) {
+        self.inner.on_external_edit(prev, e);
+    }
+
+    fn on_refine(
+        &mut self,
+        valid: &dyn Fn(&<Self::Step as Step>::Exp) -> bool,
+    ) {
+        self.inner.on_refine(valid);
+    }
+
+    fn on_feedback(&mut self, step: &Self::Step, feedback: Feedback) {
+        self.inner.on_feedback(step, feedback);
+    }
+
+    fn set_progress(&mut self, reporter: ProgressReporter) {
+        self.inner.set_progress(reporter);
+    }
+
+    fn set_context(&mut self, context: Context) {
+        self.inner.set_context(context);
+    }
+
+    fn warmup(&mut self, timer: &T) -> Result<(), T::EarlyCutoff> {
+        self.inner.warmup(timer)
+    }
+}
+
+/// A debugging wrapper that checks the steps provided by another provider
+///
+/// After every call to the inner provider, this wrapper asserts that every
+/// provided step applies to the current expression and (optionally) that a
+/// [`ReachabilityOracle`] considers a valid expression reachable from the
+/// result. Violations indicate that the inner provider is not Strongly Sound.
+pub struct CheckedProvider<P, E> {
+    inner: P,
+    oracle: Option<Box<dyn ReachabilityOracle<Exp = E>>>,
+}
+
+impl<P, E> CheckedProvider<P, E> {
+    /// Creates a new [`CheckedProvider`] that only checks applicability
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            oracle: None,
+        }
+    }
+
+    /// Additionally checks that a valid expression is reachable after
+    /// applying each provided step
+    pub fn with_oracle(
+        mut self,
+        oracle: impl ReachabilityOracle<Exp = E> + 'static,
+    ) -> Self {
+        self.oracle = Some(Box::new(oracle));
+        self
+    }
+
+    /// Returns the inner provider and drops self
+    pub fn into_inner(self) -> P {
+        self.inner
+    }
+
+    fn check<S: Step<Exp = E>>(&self, e: &E, steps: &[S]) {
+        for (i, step) in steps.iter().enumerate() {
+            let e2 = match step.apply(e) {
+                Some(e2) => e2,
+                None => match step.why_inapplicable(e) {
+                    Some(why) => {
+                        panic!("provided step {} does not apply: {}", i, why)
+                    }
+                    None => panic!("provided step {} does not apply", i),
+                },
+            };
+            if let Some(o) = &self.oracle {
+                assert!(
+                    o.reachable(&e2),
+                    "provided step {} cannot reach a valid expression",
+                    i
+                );
+            }
+        }
+    }
+}
+
+impl<T: Timer, P: StepProvider<T>> StepProvider<T>
+    for CheckedProvider<P, <P::Step as Step>::Exp>
+{
+    type Step = P::Step;
+
+    /// Provides the steps of the inner provider
+    ///
+    /// # Panics
+    ///
+    /// Panics if a provided step does not apply to `e` or if the oracle
+    /// reports that no valid expression is reachable after applying it
+    fn provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        let steps = self.inner.provide(timer, e)?;
+        self.check(e, &steps);
+        Ok(steps)
+    }
+
+    fn provide_partial(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> (Vec<Self::Step>, Option<T::EarlyCutoff>) {
+        let (steps, cutoff) = self.inner.provide_partial(timer, e);
+        self.check(e, &steps);
+        (steps, cutoff)
+    }
+
+    fn try_provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, ProviderError<T::EarlyCutoff>> {
+        let steps = self.inner.try_provide(timer, e)?;
+        self.check(e, &steps);
+        Ok(steps)
+    }
+
+    fn provide_annotated(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+        annotations: &[Annotation],
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        let steps = self.inner.provide_annotated(timer, e, annotations)?;
+        self.check(e, &steps);
+        Ok(steps)
+    }
+
+    fn capabilities(&mut self) -> Capabilities {
+        self.inner.capabilities()
+    }
+
+    fn as_incremental(
+        &mut self,
+    ) -> Option<&mut dyn IncrementalStepProvider<T, Step = Self::Step>> {
+        self.inner.as_incremental()?;
+        Some(self)
+    }
+
+    fn as_batch(
+        &mut self,
+    ) -> Option<&mut dyn BatchStepProvider<T, Step = Self::Step>> {
+        self.inner.as_batch()?;
+        Some(self)
+    }
+
+    fn on_session_start(&mut self, start: &<Self::Step as Step>::Exp) {
+        self.inner.on_session_start(start);
+    }
+
+    fn on_decide(&mut self, step: &Self::Step, e: &<Self::Step as Step>::Exp) {
+        self.inner.on_decide(step, e);
+    }
+
+    fn on_undo(&mut self, e: &<Self::Step as Step>::Exp) {
+        self.inner.on_undo(e);
+    }
+
+    fn on_external_edit(
+        &mut self,
+        prev: &<Self::Step as Step>::Exp,
+        e: &<Self::Step as Step>::Exp,
+    ) {
+        self.inner.on_external_edit(prev, e);
+    }
+
+    fn on_refine(
+        &mut self,
+        valid: &dyn Fn(&<Self::Step as Step>::Exp) -> bool,
+    ) {
+        self.inner.on_refine(valid);
+    }
+
+    fn on_feedback(&mut self, step: &Self::Step, feedback: Feedback) {
+        self.inner.on_feedback(step, feedback);
+    }
+
+    fn set_progress(&mut self, reporter: ProgressReporter) {
+        self.inner.set_progress(reporter);
+    }
+
+    fn set_context(&mut self, context: Context) {
+        self.inner.set_context(context);
+    }
+
+    fn warmup(&mut self, timer: &T) -> Result<(), T::EarlyCutoff> {
+        self.inner.warmup(timer)
+    }
+}
+
+impl<T: Timer, P: StepProvider<T>> IncrementalStepProvider<T>
+    for CheckedProvider<P, <P::Step as Step>::Exp>
+{
+    /// Provides the steps of the inner provider (incrementally, if it is an
+    /// [`IncrementalStepProvider`])
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as [`StepProvider::provide`]
+    fn provide_incremental(
+        &mut self,
+        timer: &T,
+        prev: &<Self::Step as Step>::Exp,
+        step: &Self::Step,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        let steps = match self.inner.as_incremental() {
+            Some(inner) => inner.provide_incremental(timer, prev, step, e)?,
+            None => self.inner.provide(timer, e)?,
+        };
+        self.check(e, &steps);
+        Ok(steps)
+    }
+}
+
+impl<T: Timer, P: StepProvider<T>> BatchStepProvider<T>
+    for CheckedProvider<P, <P::Step as Step>::Exp>
+{
+    /// Provides the steps of the inner provider (in one batch, if it is a
+    /// [`BatchStepProvider`])
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as [`StepProvider::provide`]
+    fn provide_batch(
+        &mut self,
+        timer: &T,
+        es: &[<Self::Step as Step>::Exp],
+    ) -> Result<Vec<Vec<Self::Step>>, ProviderError<T::EarlyCutoff>> {
+        let step_sets = match self.inner.as_batch() {
+            Some(inner) => inner.provide_batch(timer, es)?,
+            None => es
+                .iter()
+                .map(|e| self.inner.try_provide(timer, e))
+                .collect::<Result<_, _>>()?,
+        };
+        for (e, steps) in es.iter().zip(&step_sets) {
+            self.check(e, steps);
+        }
+        Ok(step_sets)
+    }
+}
+
+/// A Programming by Navigation "controller" that abstracts away the underlying
+/// step provider and validity checker to manage a Programming by Navigation
+/// interactive session
+///
+/// The `P`, `C`, and `H` parameters determine how the provider, checker, and
+/// history store are stored; the defaults are appropriate unless the
+/// controller must be sent across threads (see [`SendController`]).
+pub struct Controller<
+    T: Timer,
+    S: Step,
+    P: ?Sized = dyn StepProvider<T, Step = S>,
+    C: ?Sized = dyn ValidityChecker<Exp = <S as Step>::Exp>,
+    H: ?Sized = dyn history::HistoryStore<<S as Step>::Exp>,
+> {
+    timer: T,
+    provider: Box<P>,
+    checkers: Vec<Box<C>>,
+    start: S::Exp,
+    state: S::Exp,
+    history: Option<Box<H>>,
+    decisions: Vec<Decision<S>>,
+    last_prev: Option<S::Exp>,
+    progress: ProgressReporter,
+    annotations: Vec<Annotation>,
+    resync: ResyncPolicy,
+    auto_advance: usize,
+    auto_applied: usize,
+    pause_timer: bool,
+    /// Incremented whenever the working expression changes
+    epoch: u64,
+    /// The epoch, shared with snapshots
+    version: Arc<AtomicU64>,
+    /// The most recent snapshot of the working expression (if still current)
+    snapshot: sync::Mutex<Option<Arc<S::Exp>>>,
+    scorer: Option<Box<dyn ScoredValidityChecker<Exp = S::Exp> + Send>>,
+    resources: Option<Resources<S::Exp>>,
+    /// The quality of the most recently provided step set
+    quality: ProvisionQuality,
+    context: Context,
+    /// The interaction modes supported by the controller
+    supported: Capabilities,
+    /// The interaction modes negotiated with the provider
+    capabilities: Capabilities,
+    /// Results of applying steps to the working expression (if caching is
+    /// enabled)
+    applied: sync::Mutex<Option<ApplyCache<S>>>,
+    #[cfg(feature = "std")]
+    telemetry: Option<Box<dyn telemetry::TelemetrySink>>,
+}
+
+/// The results of applying steps to the working expression of a
+/// [`Controller`], reused by decisions (see [`Controller::with_apply_cache`])
+struct ApplyCache<S: Step> {
+    eq: fn(&S, &S) -> bool,
+    clone: fn(&S) -> S,
+    entries: Vec<(S, S::Exp)>,
+}
+
+/// A change to the working expression in a [`Controller`] session
+enum Action<S: Step> {
+    /// A step was decided on
+    Step(S),
+    /// The working expression was edited externally (with the result)
+    Edit(S::Exp),
+}
+
+/// A change made in a [`Controller`] session
+struct Decision<S: Step> {
+    action: Action<S>,
+    meta: DecisionMeta,
+    #[cfg(feature = "std")]
+    time: std::time::SystemTime,
+}
+
+impl<S: Step + Clone> Clone for Action<S> {
+    fn clone(&self) -> Self {
+        match self {
+            Action::Step(step) => Action::Step(step.clone()),
+            Action::Edit(e) => Action::Edit(e.clone()),
+        }
+    }
+}
+
+impl<S: Step + Clone> Clone for Decision<S> {
+    fn clone(&self) -> Self {
+        Self {
+            action: self.action.clone(),
+            meta: self.meta.clone(),
+            #[cfg(feature = "std")]
+            time: self.time,
+        }
+    }
+}
+
+impl<S: Step> Decision<S> {
+    fn step(&self) -> Option<&S> {
+        match &self.action {
+            Action::Step(step) => Some(step),
+            Action::Edit(_) => None,
+        }
+    }
+}
+
+/// Metadata attached to a change in a [`Controller`] session (see
+/// [`Controller::decide_with_meta`])
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DecisionMeta {
+    /// The identity of whoever made the change (e.g., a user or a decider)
+    pub author: Option<String>,
+    /// A free-form note (e.g., the reason for the change)
+    pub note: Option<String>,
+}
+
+impl DecisionMeta {
+    /// Creates new, empty metadata
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the author of the change
+    pub fn with_author(mut self, author: impl Into<String>) -> Self {
+        self.author = Some(author.into());
+        self
+    }
+
+    /// Sets the note on the change
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.note = Some(note.into());
+        self
+    }
+}
+
+/// A change in the timeline of a [`Controller`] session (see
+/// [`Controller::timeline`])
+pub struct TimelineEntry<'a, S: Step> {
+    /// The step that was decided on (or `None` for an external edit)
+    pub step: Option<&'a S>,
+    /// The metadata of the change
+    pub meta: &'a DecisionMeta,
+    /// When the change was made
+    #[cfg(feature = "std")]
+    pub time: std::time::SystemTime,
+}
+
+/// How a [`Controller`] resynchronizes its provider after an external edit
+/// (see [`Controller::apply_external_edit`])
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResyncPolicy {
+    /// Notify the provider with [`StepProvider::on_external_edit`] (which, by
+    /// default, restarts it)
+    #[default]
+    Notify,
+    /// Restart the provider with [`StepProvider::on_session_start`],
+    /// discarding all state derived from previous expressions
+    Restart,
+    /// Do not notify the provider (for edits known not to affect any state
+    /// the provider derives from expressions)
+    Trust,
+}
+
+/// How a [`Controller`] enforces its resource budget (see
+/// [`Controller::with_resource_checker`])
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourcePolicy {
+    /// Do not provide steps whose results exceed the budget, and refuse to
+    /// decide on them
+    Refuse,
+    /// Allow steps whose results exceed the budget (which can be detected
+    /// with [`Controller::exceeds_budget`])
+    Flag,
+}
+
+type Resources<E> = (Box<dyn ResourceChecker<Exp = E> + Send>, ResourcePolicy);
+
+/// A provided step with the result of applying it to the working expression
+pub type AppliedStep<S> = (S, Option<<S as Step>::Exp>);
+
+/// A preview of the result of deciding on a provided step
+#[derive(Debug, Clone, PartialEq)]
+pub enum Preview<E> {
+    /// The preview has not been computed (e.g., because the timer cut off
+    /// previewing)
+    Pending,
+    /// The step does not apply to the working expression
+    Inapplicable,
+    /// The step results in an expression
+    Ready {
+        /// The resulting expression
+        exp: E,
+        /// Whether or not the resulting expression is valid
+        valid: bool,
+    },
+}
+
+/// An immutable view of the working expression of a [`Controller`] (see
+/// [`Controller::snapshot`])
+///
+/// Snapshots are cheap to clone and can detect whether the working expression
+/// has changed since they were taken without access to the controller.
+#[derive(Debug)]
+pub struct Snapshot<E> {
+    exp: Arc<E>,
+    version: u64,
+    live: Arc<AtomicU64>,
+}
+
+impl<E> Clone for Snapshot<E> {
+    fn clone(&self) -> Self {
+        Self {
+            exp: self.exp.clone(),
+            version: self.version,
+            live: self.live.clone(),
+        }
+    }
+}
+
+impl<E> Snapshot<E> {
+    /// Returns the working expression when the snapshot was taken
+    pub fn expression(&self) -> &E {
+        &self.exp
+    }
+
+    /// Returns the version of the working expression when the snapshot was
+    /// taken (see [`Controller::version`])
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Returns whether or not the working expression of the controller has
+    /// changed since the snapshot was taken
+    pub fn is_stale(&self) -> bool {
+        self.live.load(Ordering::Acquire) != self.version
+    }
+}
+
+/// A provided step together with a preview of its result (see
+/// [`Controller::provide_with_previews`])
+#[derive(Debug, Clone)]
+pub struct PreviewedStep<S: Step> {
+    /// The provided step
+    pub step: S,
+    /// The preview of the result of deciding on the step
+    pub preview: Preview<S::Exp>,
+    epoch: u64,
+}
+
+/// A provided step set annotated with information about validity
+pub struct TerminalProvision<S> {
+    /// The provided steps
+    pub steps: Vec<S>,
+    /// Whether or not the current working expression is already valid
+    pub valid: bool,
+    /// For each provided step, whether or not applying it results in a valid
+    /// expression (or `None` if the lookahead was cut off before checking)
+    pub terminal: Vec<Option<bool>>,
+}
+
+impl<S> TerminalProvision<S> {
+    /// Returns whether or not some provided step is known to result in a
+    /// valid expression
+    pub fn any_terminal(&self) -> bool {
+        self.terminal.contains(&Some(true))
+    }
+}
+
+/// The provenance of a state in a [`Controller`] session
+pub struct Provenance<'a, S> {
+    /// The step that produced the state
+    pub step: &'a S,
+    /// The name of the provider that provided the step (if known)
+    pub source: Option<&'a str>,
+}
+
+/// A [`Controller`] whose provider, checker, and history store can be sent
+/// across threads
+pub type SendController<T, S> = Controller<
+    T,
+    S,
+    dyn StepProvider<T, Step = S> + Send,
+    dyn ValidityChecker<Exp = <S as Step>::Exp> + Send + Sync,
+    dyn history::HistoryStore<<S as Step>::Exp> + Send,
+>;
+
+impl<T: Timer, S: Step> Controller<T, S>
+where
+    S::Exp: 'static,
+{
+    /// Create a new controller (history can be saved to enable meta-level
+    /// "undo" operations in the interactive process)
+    ///
+    /// Since the default history store is a boxed trait object (which must be
+    /// `'static`), expressions must be `'static` too; for expressions that
+    /// borrow data, use [`Self::from_boxed`] with a concrete store type.
+    pub fn new(
+        timer: T,
+        provider: impl StepProvider<T, Step = S> + 'static,
+        checker: impl ValidityChecker<Exp = S::Exp> + 'static,
+        start: S::Exp,
+        save_history: bool,
+    ) -> Self {
+        let history: Option<Box<dyn history::HistoryStore<S::Exp>>> =
+            if save_history {
+                Some(Box::new(history::VecHistory::new()))
+            } else {
+                None
+            };
+        Self::from_boxed(
+            timer,
+            Box::new(provider),
+            Box::new(checker),
+            start,
+            history,
+        )
+    }
+}
+
+impl<T: Timer, S: Step> SendController<T, S>
+where
+    S::Exp: Send + 'static,
+{
+    /// Create a new controller whose provider and checker can be sent across
+    /// threads (see [`Controller::new`])
+    pub fn new_send(
+        timer: T,
+        provider: impl StepProvider<T, Step = S> + Send + 'static,
+        checker: impl ValidityChecker<Exp = S::Exp> + Send + Sync + 'static,
+        start: S::Exp,
+        save_history: bool,
+    ) -> Self {
+        let history: Option<Box<dyn history::HistoryStore<S::Exp> + Send>> =
+            if save_history {
+                Some(Box::new(history::VecHistory::new()))
+            } else {
+                None
+            };
+        Self::from_boxed(
+            timer,
+            Box::new(provider),
+            Box::new(checker),
+            start,
+            history,
+        )
+    }
+}
+
+impl<T, S, P, C, H> Controller<T, S, P, C, H>
+where
+    T: Timer,
+    S: Step,
+    P: StepProvider<T, Step = S> + ?Sized,
+    C: ValidityChecker<Exp = S::Exp> + ?Sized,
+    H: history::HistoryStore<S::Exp> + ?Sized,
+{
+    /// Create a new controller from an already boxed provider, checker, and
+    /// (optional) history store (see [`Self::new`])
+    ///
+    /// Unlike [`Self::new`], this works for every choice of the `P`, `C`,
+    /// and `H` parameters, e.g., for expressions that borrow data (by using a
+    /// concrete store type such as [`history::VecHistory`] for `H`).
+    pub fn from_boxed(
+        timer: T,
+        mut provider: Box<P>,
+        checker: Box<C>,
+        start: S::Exp,
+        history: Option<Box<H>>,
+    ) -> Self {
+        let progress = ProgressReporter::new();
+        provider.set_progress(progress.clone());
+        provider.on_session_start(&start);
+        let _ = provider.warmup(&timer);
+        let supported = Capabilities::all();
+        let capabilities = provider.capabilities().negotiate(&supported);
+        Self {
+            timer,
+            provider,
+            checkers: vec![checker],
+            start: start.clone(),
+            state: start,
+            history,
+            decisions: vec![],
+            last_prev: None,
+            progress,
+            annotations: vec![],
+            resync: ResyncPolicy::default(),
+            auto_advance: 0,
+            auto_applied: 0,
+            pause_timer: false,
+            epoch: 0,
+            version: Arc::new(AtomicU64::new(0)),
+            snapshot: sync::Mutex::new(None),
+            scorer: None,
+            resources: None,
+            quality: ProvisionQuality::Exact,
+            context: Context::new(),
+            supported,
+            capabilities,
+            applied: sync::Mutex::new(None),
+            #[cfg(feature = "std")]
+            telemetry: None,
+        }
+    }
+
+    /// Replaces the history store of the session (enabling history if it was
+    /// not saved), discarding any saved history
+    pub fn with_history(mut self, store: Box<H>) -> Self {
+        self.history = Some(store);
+        self
+    }
+
+    /// Restricts the interaction modes that the controller uses with its
+    /// provider to `supported` (e.g., those that a server and its clients
+    /// support), renegotiating them with the provider (see
+    /// [`Self::capabilities`])
+    pub fn with_capabilities(mut self, supported: Capabilities) -> Self {
+        self.supported = supported;
+        self.renegotiate();
+        self
+    }
+
+    /// Returns the interaction modes negotiated with the provider: the
+    /// richest ones that both the controller and the provider support
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    fn renegotiate(&mut self) {
+        self.capabilities =
+            self.provider.capabilities().negotiate(&self.supported);
+    }
+
+    /// Enables mixed-initiative navigation: whenever the provider provides
+    /// exactly one step for an invalid working expression, [`Self::provide`]
+    /// decides on it automatically, up to `max` times per call (see
+    /// [`Self::auto_applied`])
+    ///
+    /// Each automatically applied step is a separate decision that can be
+    /// undone. A `max` of zero disables auto-advancing (the default).
+    pub fn with_auto_advance(mut self, max: usize) -> Self {
+        self.auto_advance = max;
+        self
+    }
+
+    /// Pauses the session timer (see [`Timer::pause`]) whenever the
+    /// controller is not using it, so that the budget is not consumed while
+    /// the decider is thinking between a call to [`Self::provide`] and the
+    /// next decision
+    pub fn with_timer_pausing(mut self, pause: bool) -> Self {
+        self.pause_timer = pause;
+        if pause {
+            self.timer.pause();
+        } else {
+            self.timer.resume();
+        }
+        self
+    }
+
+    /// Resumes the session timer before using it (if timer pausing is
+    /// enabled)
+    fn resume_timer(&self) {
+        if self.pause_timer {
+            self.timer.resume();
+        }
+    }
+
+    /// Pauses the session timer after using it (if timer pausing is enabled)
+    fn pause_timer(&self) {
+        if self.pause_timer {
+            self.timer.pause();
+        }
+    }
+
+    /// Sets how the provider is resynchronized after external edits (see
+    /// [`Self::apply_external_edit`])
+    pub fn with_resync_policy(mut self, policy: ResyncPolicy) -> Self {
+        self.resync = policy;
+        self
+    }
+
+    /// Attaches a scoring checker, whose score of the working expression is
+    /// reported by [`Self::score`]
+    ///
+    /// The scorer does not affect validity; to also require it, add it as a
+    /// validity checker too.
+    pub fn with_scorer(
+        mut self,
+        scorer: impl ScoredValidityChecker<Exp = S::Exp> + Send + 'static,
+    ) -> Self {
+        self.scorer = Some(Box::new(scorer));
+        self
+    }
+
+    /// Attaches a resource budget (e.g., on the size of expressions), which
+    /// is enforced according to `policy`
+    pub fn with_resource_checker(
+        mut self,
+        checker: impl ResourceChecker<Exp = S::Exp> + Send + 'static,
+        policy: ResourcePolicy,
+    ) -> Self {
+        self.resources = Some((Box::new(checker), policy));
+        self
+    }
+
+    /// Attaches a telemetry sink that records an event for every call to the
+    /// provider, decision, and undo in the session
+    #[cfg(feature = "std")]
+    pub fn with_telemetry(
+        mut self,
+        sink: impl telemetry::TelemetrySink + 'static,
+    ) -> Self {
+        self.telemetry = Some(Box::new(sink));
+        self
+    }
+
+    #[cfg(feature = "std")]
+    fn record(&mut self, kind: telemetry::EventKind) {
+        if let Some(sink) = &mut self.telemetry {
+            sink.record(telemetry::Event {
+                time: std::time::SystemTime::now(),
+                depth: self.decisions.len(),
+                kind,
+            });
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn record_provide(
+        &mut self,
+        start: std::time::Instant,
+        steps: usize,
+        cut_off: bool,
+    ) {
+        self.record(telemetry::EventKind::Provide {
+            latency: start.elapsed(),
+            steps,
+            cut_off,
+        });
+    }
+
+    /// Ask the synthesizer to provide a list of possible next steps
+    ///
+    /// If the decider has annotated the working expression, the annotations
+    /// are passed to the provider (see [`StepProvider::provide_annotated`]).
+    /// Otherwise, if the provider is an [`IncrementalStepProvider`] and the
+    /// current working expression was obtained by a decision, the incremental
+    /// interface is used.
+    ///
+    /// If auto-advancing is enabled (see [`Self::with_auto_advance`]), forced
+    /// steps are decided on before the steps for the next genuine decision
+    /// point are returned (which removes the annotations; see
+    /// [`Self::annotate`]).
+    pub fn provide(&mut self) -> Result<Vec<S>, PbnError<T::EarlyCutoff>> {
+        self.provide_using(None)
+    }
+
+    /// Ask the synthesizer to provide a list of possible next steps under
+    /// `timer` rather than the session timer (see [`Self::provide`])
+    ///
+    /// This allows individual calls to run with tighter or looser budgets
+    /// than the session, e.g., a quick pass followed by a deeper one on
+    /// demand.
+    pub fn provide_with_timer(
+        &mut self,
+        timer: &T,
+    ) -> Result<Vec<S>, PbnError<T::EarlyCutoff>> {
+        self.provide_using(Some(timer))
+    }
+
+    fn provide_using(
+        &mut self,
+        timer: Option<&T>,
+    ) -> Result<Vec<S>, PbnError<T::EarlyCutoff>> {
+        let mut applied = 0;
+        let result = loop {
+            let mut steps = match self.provide_tracked(timer) {
+                Ok(steps) => steps,
+                Err(e) => break Err(e),
+            };
+            if applied >= self.auto_advance || steps.len() != 1 || self.valid()
+            {
+                break Ok(steps);
+            }
+            if let Some(step) = steps.pop()
+                && let Err(e) = self.decide(step)
+            {
+                break Err(e);
+            }
+            applied += 1;
+        };
+        self.auto_applied = applied;
+        result
+    }
+
+    /// Returns the quality of the step set most recently provided (e.g., so
+    /// that a decider can provide again with a bigger budget, see
+    /// [`Self::provide_with_timer`], if it was approximate)
+    ///
+    /// Providers mark their step sets as approximate with
+    /// [`ProgressReporter::mark_approximate`]; step sets from
+    /// [`Self::provide_partial`] that were cut off are also approximate.
+    pub fn last_provision_quality(&self) -> ProvisionQuality {
+        self.quality
+    }
+
+    /// Returns the steps that were decided on automatically by the most
+    /// recent call to [`Self::provide`], in order (see
+    /// [`Self::with_auto_advance`])
+    pub fn auto_applied(&self) -> Vec<&S> {
+        let mut steps: Vec<&S> = self
+            .decisions
+            .iter()
+            .rev()
+            .take(self.auto_applied)
+            .filter_map(Decision::step)
+            .collect();
+        steps.reverse();
+        steps
+    }
+
+    fn provide_tracked(
+        &mut self,
+        timer: Option<&T>,
+    ) -> Result<Vec<S>, PbnError<T::EarlyCutoff>> {
+        #[cfg(feature = "std")]
+        let start = std::time::Instant::now();
+        if timer.is_none() {
+            self.resume_timer();
+        }
+        self.progress.take_quality();
+        let result = self.provide_untracked(timer);
+        self.quality = self.progress.take_quality();
+        if timer.is_none() {
+            self.pause_timer();
+        }
+        #[cfg(feature = "std")]
+        self.record_provide(
+            start,
+            result.as_ref().map_or(0, Vec::len),
+            result.is_err(),
+        );
+        result
+    }
+
+    fn provide_untracked(
+        &mut self,
+        timer: Option<&T>,
+    ) -> Result<Vec<S>, PbnError<T::EarlyCutoff>> {
+        let steps = self.provide_unfiltered(timer)?;
+        let Some((checker, ResourcePolicy::Refuse)) = &self.resources else {
+            return Ok(steps);
+        };
+        let results = S::apply_all(&steps, &self.state);
+        Ok(steps
+            .into_iter()
+            .zip(results)
+            .filter(|(_, e)| {
+                e.as_ref().is_none_or(|e| checker.within_budget(e))
+            })
+            .map(|(step, _)| step)
+            .collect())
+    }
+
+    fn provide_unfiltered(
+        &mut self,
+        timer: Option<&T>,
+    ) -> Result<Vec<S>, PbnError<T::EarlyCutoff>> {
+        let timer = timer.unwrap_or(&self.timer);
+        if !self.annotations.is_empty() {
+            return Ok(self.provider.provide_annotated(
+                timer,
+                &self.state,
+                &self.annotations,
+            )?);
+        }
+        if let (Some(prev), Some(step)) = (
+            &self.last_prev,
+            self.decisions.last().and_then(Decision::step),
+        ) && self.capabilities.incremental
+            && let Some(p) = self.provider.as_incremental()
+        {
+            return Ok(p.provide_incremental(
+                timer,
+                prev,
+                step,
+                &self.state,
+            )?);
+        }
+        Ok(self.provider.try_provide(timer, &self.state)?)
+    }
+
+    /// Ask the synthesizer to provide a list of possible next steps, along
+    /// with whether the current working expression is already valid and
+    /// which provided steps lead immediately to a valid expression
+    ///
+    /// The lookahead is performed under the session timer; if the timer cuts
+    /// off the lookahead, the remaining steps are left unchecked rather than
+    /// failing the whole call.
+    pub fn provide_terminal(
+        &mut self,
+    ) -> Result<TerminalProvision<S>, PbnError<T::EarlyCutoff>> {
+        let steps = self.provide()?;
+        let mut terminal = Vec::with_capacity(steps.len());
+        self.resume_timer();
+        for e in S::apply_all(&steps, &self.state) {
+            if self.timer.tick().is_err() {
+                break;
+            }
+            terminal.push(Some(e.is_some_and(|e| self.check(&e))));
+        }
+        self.pause_timer();
+        terminal.resize(steps.len(), None);
+        Ok(TerminalProvision {
+            steps,
+            valid: self.valid(),
+            terminal,
+        })
+    }
+
+    /// Ask the synthesizer to provide the lists of possible next steps for
+    /// several candidate expressions (in order), independently of the working
+    /// expression
+    ///
+    /// If batch provision was negotiated (see [`Self::capabilities`]), all of
+    /// the step sets are requested in one call; otherwise, they are requested
+    /// one at a time.
+    pub fn provide_batch(
+        &mut self,
+        es: &[S::Exp],
+    ) -> Result<Vec<Vec<S>>, PbnError<T::EarlyCutoff>> {
+        self.resume_timer();
+        let batch = if self.capabilities.batch {
+            self.provider.as_batch()
+        } else {
+            None
+        };
+        let result = match batch {
+            Some(p) => p.provide_batch(&self.timer, es),
+            None => es
+                .iter()
+                .map(|e| self.provider.try_provide(&self.timer, e))
+                .collect(),
+        };
+        self.pause_timer();
+        Ok(result?)
+    }
+
+    /// Ask the synthesizer to provide a list of possible next steps, each
+    /// with the result of applying it to the working expression (see
+    /// [`Step::apply_all`])
+    pub fn provide_applied(
+        &mut self,
+    ) -> Result<Vec<AppliedStep<S>>, PbnError<T::EarlyCutoff>> {
+        let steps = self.provide()?;
+        let results = S::apply_all(&steps, &self.state);
+        for (step, result) in steps.iter().zip(&results) {
+            if let Some(e) = result {
+                self.remember_applied(step, e);
+            }
+        }
+        Ok(steps.into_iter().zip(results).collect())
+    }
+
+    /// Ask the synthesizer to provide a list of possible next steps, each
+    /// with a preview of the expression that deciding on it would result in
+    /// (and its validity)
+    ///
+    /// Previewing is performed under the session timer; if the timer cuts
+    /// off previewing, the remaining previews are left
+    /// [`Preview::Pending`] (see [`Self::complete_preview`]). Deciding on a
+    /// previewed step with [`Self::decide_previewed`] reuses its preview
+    /// rather than applying the step again.
+    pub fn provide_with_previews(
+        &mut self,
+    ) -> Result<Vec<PreviewedStep<S>>, PbnError<T::EarlyCutoff>> {
+        let steps = self.provide()?;
+        let mut cut_off = false;
+        self.resume_timer();
+        let previews = steps
+            .into_iter()
+            .map(|step| {
+                cut_off = cut_off || self.timer.tick().is_err();
+                let mut p = PreviewedStep {
+                    step,
+                    preview: Preview::Pending,
+                    epoch: self.epoch,
+                };
+                if !cut_off {
+                    self.complete_preview(&mut p);
+                }
+                p
+            })
+            .collect();
+        self.pause_timer();
+        Ok(previews)
+    }
+
+    /// Computes the preview of a previewed step if it is pending (or
+    /// recomputes it if the working expression has changed since)
+    pub fn complete_preview(&self, p: &mut PreviewedStep<S>) {
+        if p.epoch == self.epoch && !matches!(p.preview, Preview::Pending) {
+            return;
+        }
+        p.epoch = self.epoch;
+        p.preview = match self.apply(&p.step) {
+            Some(exp) => Preview::Ready {
+                valid: self.check(&exp),
+                exp,
+            },
+            None => Preview::Inapplicable,
+        };
+    }
+
+    /// Ask the synthesizer to provide a list of possible next steps, keeping
+    /// the steps found so far if it is cut off (see
+    /// [`StepProvider::provide_partial`])
+    pub fn provide_partial(
+        &mut self,
+    ) -> (Vec<S>, Option<PbnError<T::EarlyCutoff>>) {
+        #[cfg(feature = "std")]
+        let start = std::time::Instant::now();
+        self.resume_timer();
+        self.progress.take_quality();
+        let (steps, cutoff) =
+            self.provider.provide_partial(&self.timer, &self.state);
+        self.quality = match cutoff {
+            Some(_) => ProvisionQuality::Approximate,
+            None => self.progress.take_quality(),
+        };
+        self.pause_timer();
+        #[cfg(feature = "std")]
+        self.record_provide(start, steps.len(), cutoff.is_some());
+        (steps, cutoff.map(PbnError::Cutoff))
+    }
+
+    /// Adds an observer that is called with every progress report from the
+    /// provider
+    pub fn observe_progress(
+        &mut self,
+        observer: impl FnMut(&Progress) + Send + 'static,
+    ) {
+        self.progress.observe(observer)
+    }
+
+    /// Provide a list of possible next steps, grouped by a classifier
+    pub fn provide_grouped_by(
+        &mut self,
+        classify: impl Fn(&S) -> StepCategory,
+    ) -> Result<Vec<StepGroup<S>>, PbnError<T::EarlyCutoff>> {
+        Ok(group_steps(self.provide()?, classify))
+    }
+
+    /// Returns whether or not a step applies to the current working
+    /// expression (e.g., to detect stale steps after the expression changed)
+    pub fn applicable(&self, step: &S) -> bool {
+        step.applicable(&self.state)
+    }
+
+    /// Decide which step to take (**must** be selected from among the ones that
+    /// are provided by the [`provide`] function)
+    ///
+    /// If the step is not applicable to the working expression, the session
+    /// is left unchanged and [`PbnError::InapplicableStep`] is returned.
+    pub fn decide(&mut self, step: S) -> Result<(), PbnError<T::EarlyCutoff>> {
+        self.decide_with_meta(step, DecisionMeta::new())
+    }
+
+    /// Decide on a step, recording metadata about the decision in the
+    /// timeline of the session (see [`Self::decide`] and [`Self::timeline`])
+    pub fn decide_with_meta(
+        &mut self,
+        step: S,
+        meta: DecisionMeta,
+    ) -> Result<(), PbnError<T::EarlyCutoff>> {
+        let next = match self.take_applied(&step) {
+            Some(next) => next,
+            None => step.apply(&self.state).ok_or_else(|| {
+                PbnError::InapplicableStep(step.why_inapplicable(&self.state))
+            })?,
+        };
+        self.refuse_over_budget(&next)?;
+        self.commit(step, next, meta);
+        Ok(())
+    }
+
+    /// Applies a step to the working expression, reusing (and otherwise
+    /// caching) the result if the apply cache is enabled (see
+    /// [`Self::with_apply_cache`])
+    fn apply(&self, step: &S) -> Option<S::Exp> {
+        if let Some(cache) = &*self.applied.lock()
+            && let Some((_, e)) =
+                cache.entries.iter().find(|(s, _)| (cache.eq)(s, step))
+        {
+            return Some(e.clone());
+        }
+        let next = step.apply(&self.state)?;
+        self.remember_applied(step, &next);
+        Some(next)
+    }
+
+    /// Caches the result of applying a step to the working expression (if
+    /// the apply cache is enabled)
+    fn remember_applied(&self, step: &S, e: &S::Exp) {
+        if let Some(cache) = &mut *self.applied.lock()
+            && !cache.entries.iter().any(|(s, _)| (cache.eq)(s, step))
+        {
+            cache.entries.push(((cache.clone)(step), e.clone()));
+        }
+    }
+
+    /// Removes and returns the cached result of applying a step to the
+    /// working expression (if any)
+    fn take_applied(&mut self, step: &S) -> Option<S::Exp> {
+        let mut applied = self.applied.lock();
+        let cache = applied.as_mut()?;
+        let i = cache
+            .entries
+            .iter()
+            .position(|(s, _)| (cache.eq)(s, step))?;
+        Some(cache.entries.swap_remove(i).1)
+    }
+
+    /// Returns whether or not the result of a step exceeds the resource
+    /// budget of the session (see [`Self::with_resource_checker`])
+    pub fn exceeds_budget(&self, step: &S) -> bool {
+        self.resources.as_ref().is_some_and(|(checker, _)| {
+            self.apply(step).is_some_and(|e| !checker.within_budget(&e))
+        })
+    }
+
+    /// Returns whether or not the working expression exceeds the resource
+    /// budget of the session (e.g., after an external edit)
+    pub fn over_budget(&self) -> bool {
+        self.resources
+            .as_ref()
+            .is_some_and(|(checker, _)| !checker.within_budget(&self.state))
+    }
+
+    fn refuse_over_budget(
+        &self,
+        next: &S::Exp,
+    ) -> Result<(), PbnError<T::EarlyCutoff>> {
+        match &self.resources {
+            Some((checker, ResourcePolicy::Refuse))
+                if !checker.within_budget(next) =>
+            {
+                Err(PbnError::InvalidDecision("step exceeds resource budget"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Decide which step to take (see [`Self::decide`]), provided that the
+    /// working expression is still at the `version` at which the step was
+    /// chosen (see [`Self::version`])
+    ///
+    /// Otherwise, the decision is not made and steps are provided for the
+    /// current working expression instead, so that concurrent deciders
+    /// sharing a session cannot decide on steps chosen for a stale working
+    /// expression.
+    pub fn decide_at(
+        &mut self,
+        version: u64,
+        step: S,
+    ) -> Result<(), DecideError<S, T::EarlyCutoff>> {
+        if version != self.epoch {
+            let steps = self.provide()?;
+            return Err(DecideError::Conflict {
+                version: self.epoch,
+                steps,
+            });
+        }
+        Ok(self.decide(step)?)
+    }
+
+    /// Decide which step to take from among the ones provided by
+    /// [`Self::provide_with_previews`] (see [`Self::decide`])
+    ///
+    /// If the step was previewed since the working expression last changed,
+    /// the preview is used as the result without applying the step again.
+    pub fn decide_previewed(
+        &mut self,
+        p: PreviewedStep<S>,
+    ) -> Result<(), PbnError<T::EarlyCutoff>> {
+        match p.preview {
+            Preview::Ready { exp, .. } if p.epoch == self.epoch => {
+                self.refuse_over_budget(&exp)?;
+                self.commit(p.step, exp, DecisionMeta::new());
+                Ok(())
+            }
+            _ => self.decide(p.step),
+        }
+    }
+
+    /// Records that the working expression changed (which also removes the
+    /// annotations on the previous one)
+    pub(crate) fn bump_epoch(&mut self) {
+        self.annotations.clear();
+        self.epoch = self.epoch.wrapping_add(1);
+        self.version.store(self.epoch, Ordering::Release);
+        *self.snapshot.lock() = None;
+        if let Some(cache) = &mut *self.applied.lock() {
+            cache.entries.clear();
+        }
+    }
+
+    /// Replaces the working expression with `next`, the result of `step`
+    fn commit(&mut self, step: S, next: S::Exp, meta: DecisionMeta) {
+        self.bump_epoch();
+        let prev = core::mem::replace(&mut self.state, next);
+        if let Some(his) = &mut self.history {
+            his.push(prev.clone(), &self.state);
+        }
+        self.provider.on_decide(&step, &self.state);
+        self.last_prev = Some(prev);
+        self.auto_applied = 0;
+        #[cfg(feature = "std")]
+        let source = step.source().map(str::to_owned);
+        self.decisions.push(Decision {
+            action: Action::Step(step),
+            meta,
+            #[cfg(feature = "std")]
+            time: std::time::SystemTime::now(),
+        });
+        #[cfg(feature = "std")]
+        self.record(telemetry::EventKind::Decide { source });
+    }
+
+    /// Replaces the working expression by editing it outside of navigation
+    /// (e.g., to accept a hand edit made in an external editor)
+    ///
+    /// The edit is recorded in the history like a decision (so it can be
+    /// undone), and the provider is resynchronized according to the
+    /// session's [`ResyncPolicy`].
+    pub fn apply_external_edit(&mut self, f: impl FnOnce(&S::Exp) -> S::Exp) {
+        self.bump_epoch();
+        let next = f(&self.state);
+        let prev = core::mem::replace(&mut self.state, next);
+        match self.resync {
+            ResyncPolicy::Notify => {
+                self.provider.on_external_edit(&prev, &self.state)
+            }
+            ResyncPolicy::Restart => {
+                self.provider.on_session_start(&self.state)
+            }
+            ResyncPolicy::Trust => (),
+        }
+        if let Some(his) = &mut self.history {
+            his.push(prev, &self.state);
+        }
+        self.last_prev = None;
+        self.auto_applied = 0;
+        self.decisions.push(Decision {
+            action: Action::Edit(self.state.clone()),
+            meta: DecisionMeta::new(),
+            #[cfg(feature = "std")]
+            time: std::time::SystemTime::now(),
+        });
+        #[cfg(feature = "std")]
+        self.record(telemetry::EventKind::ExternalEdit);
+    }
+
+    /// Returns a reference to the current working expression
+    pub fn working_expression(&self) -> &S::Exp {
+        &self.state
+    }
+
+    /// Returns the current working expression and drops self
+    pub fn end(self) -> S::Exp {
         self.state
     }
 
+    /// Annotates part of the working expression with a mark (which will be
+    /// passed to the provider on subsequent calls to [`Self::provide`])
+    ///
+    /// Annotations only apply to the expression they were placed on: they are
+    /// removed whenever the working expression changes (by [`Self::decide`],
+    /// [`Self::undo`], [`Self::apply_external_edit`], or auto-advancing in
+    /// [`Self::provide`]), since paths into the old expression need not
+    /// identify the same parts of the new one.
+    pub fn annotate(&mut self, path: Vec<usize>, mark: Mark) {
+        self.annotations.push(Annotation { path, mark });
+    }
+
+    /// Gives feedback on a provided step, which is passed to the provider to
+    /// influence subsequent calls to [`Self::provide`] (see
+    /// [`feedback::FeedbackRanked`] for a provider that learns from it)
+    pub fn give_feedback(&mut self, step: &S, feedback: Feedback) {
+        self.provider.on_feedback(step, feedback);
+    }
+
+    /// Sets the value of type `X` in the context of the session and gives the
+    /// updated context to the provider (see [`StepProvider::set_context`])
+    pub fn update_context<X: Any + Send + Sync>(&mut self, value: X) {
+        self.set_context(self.context.clone().with(value));
+    }
+
+    /// Replaces the context of the session and gives it to the provider (see
+    /// [`StepProvider::set_context`])
+    pub fn set_context(&mut self, context: Context) {
+        self.context = context;
+        self.provider.set_context(self.context.clone());
+    }
+
+    /// Returns the context of the session
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    /// Removes all annotations on the working expression
+    pub fn clear_annotations(&mut self) {
+        self.annotations.clear();
+    }
+
+    /// Returns the annotations on the working expression
+    pub fn annotations(&self) -> &[Annotation] {
+        &self.annotations
+    }
+
+    /// Returns the provenance of each state reached by a decision in the
+    /// session so far (in order, skipping states reached by external edits)
+    pub fn provenance(&self) -> Vec<Provenance<'_, S>> {
+        self.decisions
+            .iter()
+            .filter_map(Decision::step)
+            .map(|step| Provenance {
+                step,
+                source: step.source(),
+            })
+            .collect()
+    }
+
+    /// Returns the changes made to the working expression since the start of
+    /// the session, in order (excluding undone changes)
+    pub fn timeline(&self) -> Vec<TimelineEntry<'_, S>> {
+        self.decisions
+            .iter()
+            .map(|d| TimelineEntry {
+                step: d.step(),
+                meta: &d.meta,
+                #[cfg(feature = "std")]
+                time: d.time,
+            })
+            .collect()
+    }
+
+    /// Returns the version of the working expression, which changes whenever
+    /// the working expression does
+    pub fn version(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Returns an immutable snapshot of the current working expression
+    ///
+    /// The expression is cloned at most once per version.
+    pub fn snapshot(&self) -> Snapshot<S::Exp> {
+        let exp = self
+            .snapshot
+            .lock()
+            .get_or_insert_with(|| Arc::new(self.state.clone()))
+            .clone();
+        Snapshot {
+            exp,
+            version: self.epoch,
+            live: self.version.clone(),
+        }
+    }
+
     /// Returns whether or not the current working expression is valid
     pub fn valid(&self) -> bool {
-        self.checker.check(&self.state)
+        self.check(&self.state)
+    }
+
+    /// Returns the score of the current working expression (if a scorer is
+    /// attached; see [`Self::with_scorer`])
+    pub fn score(&self) -> Option<f64> {
+        self.scorer.as_ref().map(|s| s.score(&self.state))
+    }
+
+    fn check(&self, e: &S::Exp) -> bool {
+        self.checkers.iter().all(|c| c.check(e))
+    }
+
+    /// Refines the notion of validity of the session by conjoining an
+    /// additional validity checker (and notifies the provider)
+    pub fn refine_checker(&mut self, checker: Box<C>) {
+        self.checkers.push(checker);
+        self.notify_refine();
+    }
+
+    /// Replaces the notion of validity of the session (and notifies the
+    /// provider)
+    pub fn replace_checker(&mut self, checker: Box<C>) {
+        self.checkers = vec![checker];
+        self.notify_refine();
+    }
+
+    fn notify_refine(&mut self) {
+        let checkers = &self.checkers;
+        let valid = |e: &S::Exp| checkers.iter().all(|c| c.check(e));
+        self.provider.on_refine(&valid);
     }
 
     /// Returns whether or not meta-level "undo" is applicable
@@ -195,11 +4219,307 @@ impl<T: Timer, S: Step> Controller<T, S> {
 
     /// Perform a meta-level "undo" operation
     ///
-    /// # Panics
+    /// Returns [`PbnError::InvalidDecision`] if "undo" is not applicable (can
+    /// be checked with [`Self::can_undo`])
+    pub fn undo(&mut self) -> Result<(), PbnError<T::EarlyCutoff>> {
+        self.state = self
+            .history
+            .as_mut()
+            .and_then(|his| his.pop(&self.state))
+            .ok_or(PbnError::InvalidDecision("nothing to undo"))?;
+        self.bump_epoch();
+        self.decisions.pop();
+        self.last_prev = None;
+        self.auto_applied = 0;
+        self.provider.on_undo(&self.state);
+        #[cfg(feature = "std")]
+        self.record(telemetry::EventKind::Undo);
+        Ok(())
+    }
+}
+
+impl<T, S, P, C, H> Controller<T, S, P, C, H>
+where
+    T: Timer,
+    S: Step + Clone + PartialEq,
+    P: StepProvider<T, Step = S> + ?Sized,
+    C: ValidityChecker<Exp = S::Exp> + ?Sized,
+    H: history::HistoryStore<S::Exp> + ?Sized,
+{
+    /// Caches the results of applying steps to the working expression (when
+    /// previewing them, checking them against the resource budget, or
+    /// providing them with [`Self::provide_applied`]) so that deciding on an
+    /// equal step reuses the result instead of applying it again
     ///
-    /// Panics if "undo" is not applicable (can be checked with
-    /// [`Self::can_undo`])
-    pub fn undo(&mut self) {
-        self.state = self.history.as_mut().unwrap().pop().unwrap();
+    /// This is worthwhile for steps that are expensive to apply (e.g., whole
+    /// program transformations). Cached results are kept until the working
+    /// expression changes.
+    pub fn with_apply_cache(self) -> Self {
+        *self.applied.lock() = Some(ApplyCache {
+            eq: S::eq,
+            clone: S::clone,
+            entries: vec![],
+        });
+        self
+    }
+}
+
+impl<T, S, P, C, H> Controller<T, S, P, C, H>
+where
+    T: Timer,
+    S: DescribedStep,
+    P: StepProvider<T, Step = S> + ?Sized,
+    C: ValidityChecker<Exp = S::Exp> + ?Sized,
+    H: history::HistoryStore<S::Exp> + ?Sized,
+{
+    /// Provide a list of possible next steps, grouped by
+    /// [`DescribedStep::category`] (falling back to [`Step::source`], then to
+    /// an "other" category)
+    pub fn provide_grouped(
+        &mut self,
+    ) -> Result<Vec<StepGroup<S>>, PbnError<T::EarlyCutoff>> {
+        self.provide_grouped_by(|step| {
+            step.category().unwrap_or_else(|| {
+                StepCategory::new(step.source().unwrap_or("other"))
+            })
+        })
+    }
+}
+
+/// A function that creates fresh step providers
+pub type ProviderFactory<T, S> =
+    Arc<dyn Fn() -> Box<dyn StepProvider<T, Step = S>> + Send + Sync>;
+
+/// A synthesis task: a named start expression, a way to create step
+/// providers, and a validity checker
+///
+/// Problems can be stored and cloned (e.g., by benchmark suites and servers)
+/// and used to spawn any number of independent [`Controller`]s.
+pub struct Problem<T: Timer, S: Step, C> {
+    name: String,
+    start: S::Exp,
+    provider_factory: ProviderFactory<T, S>,
+    checker: C,
+}
+
+impl<T: Timer, S: Step, C: Clone> Clone for Problem<T, S, C> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            start: self.start.clone(),
+            provider_factory: self.provider_factory.clone(),
+            checker: self.checker.clone(),
+        }
+    }
+}
+
+impl<T: Timer, S: Step, C> Problem<T, S, C> {
+    /// Creates a new problem
+    pub fn new(
+        name: impl Into<String>,
+        start: S::Exp,
+        provider_factory: impl Fn() -> Box<dyn StepProvider<T, Step = S>>
+        + Send
+        + Sync
+        + 'static,
+        checker: C,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            start,
+            provider_factory: Arc::new(provider_factory),
+            checker,
+        }
+    }
+
+    /// Returns the name of the problem
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the start expression of the problem
+    pub fn start(&self) -> &S::Exp {
+        &self.start
+    }
+
+    /// Returns the validity checker of the problem
+    pub fn checker(&self) -> &C {
+        &self.checker
+    }
+
+    /// Creates a fresh step provider for the problem
+    pub fn provider(&self) -> Box<dyn StepProvider<T, Step = S>> {
+        (self.provider_factory)()
+    }
+}
+
+impl<T, S, C> Problem<T, S, C>
+where
+    T: Timer + 'static,
+    S: Step + 'static,
+    C: ValidityChecker<Exp = S::Exp> + Clone + 'static,
+{
+    /// Spawns a new controller for the problem (see [`Controller::new`])
+    pub fn controller(&self, timer: T, save_history: bool) -> Controller<T, S> {
+        Controller::new(
+            timer,
+            self.provider(),
+            self.checker.clone(),
+            self.start.clone(),
+            save_history,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::{OutOfBudget, TickBudget};
+    use crate::testing::{self, Fill, Fixed};
+    use core::marker::PhantomData;
+
+    type Boxed = Box<dyn StepProvider<TickBudget, Step = Fill>>;
+
+    /// Providers of `[#0 := 1, #0 := 2, #0 := 3]` and `[#1 := 2, #1 := 1]`
+    fn fixed() -> Vec<Boxed> {
+        vec![
+            Box::new(Fixed(vec![Fill(0, 1), Fill(0, 2), Fill(0, 3)])),
+            Box::new(Fixed(vec![Fill(1, 2), Fill(1, 1)])),
+        ]
+    }
+
+    fn provide(mut p: impl StepProvider<TickBudget, Step = Fill>) -> Vec<Fill> {
+        p.provide(&testing::budget(), &testing::empty()).unwrap()
+    }
+
+    #[test]
+    fn compound_providers_concatenate_by_default() {
+        assert_eq!(
+            provide(CompoundProvider::new(fixed())),
+            [Fill(0, 1), Fill(0, 2), Fill(0, 3), Fill(1, 2), Fill(1, 1)]
+        );
+    }
+
+    #[test]
+    fn compound_providers_interleave() {
+        let p =
+            CompoundProvider::new(fixed()).with_order(StepOrder::Interleave);
+        assert_eq!(
+            provide(p),
+            [Fill(0, 1), Fill(1, 2), Fill(0, 2), Fill(1, 1), Fill(0, 3)]
+        );
+    }
+
+    #[test]
+    fn compound_providers_sort_stably() {
+        let p = CompoundProvider::new(fixed())
+            .with_order(StepOrder::sort_by_key(|s: &Fill| s.1));
+        assert_eq!(
+            provide(p),
+            [Fill(0, 1), Fill(1, 1), Fill(0, 2), Fill(1, 2), Fill(0, 3)]
+        );
+    }
+
+    #[test]
+    fn best_effort_compound_providers_follow_their_order() {
+        let sub_timer = |t: &TickBudget, _| t.clone();
+        let p = BestEffortCompoundProvider::new(fixed(), sub_timer)
+            .with_order(StepOrder::Interleave);
+        assert_eq!(
+            provide(p),
+            [Fill(0, 1), Fill(1, 2), Fill(0, 2), Fill(1, 1), Fill(0, 3)]
+        );
+        let p = BestEffortCompoundProvider::new(fixed(), sub_timer);
+        assert_eq!(
+            provide(p),
+            [Fill(0, 1), Fill(0, 2), Fill(0, 3), Fill(1, 2), Fill(1, 1)]
+        );
+    }
+
+    #[test]
+    fn interleaved_compound_providers_follow_their_weights() {
+        let p = InterleavedCompoundProvider::new(fixed());
+        assert_eq!(
+            provide(p),
+            [Fill(0, 1), Fill(1, 2), Fill(0, 2), Fill(1, 1), Fill(0, 3)]
+        );
+        let weighted = fixed().into_iter().zip([2, 1]).map(|(p, w)| (w, p));
+        let p = InterleavedCompoundProvider::weighted(weighted.collect());
+        assert_eq!(
+            provide(p),
+            [Fill(0, 1), Fill(0, 2), Fill(1, 2), Fill(0, 3), Fill(1, 1)]
+        );
+    }
+
+    #[test]
+    fn fallback_providers_preserve_the_order_of_their_choice() {
+        let mut providers = fixed();
+        providers.insert(0, Box::new(Fixed(vec![])));
+        providers.swap(1, 2);
+        assert_eq!(
+            provide(FallbackProvider::new(providers)),
+            [Fill(1, 2), Fill(1, 1)]
+        );
+    }
+
+    /// Drops the first character of a borrowed string
+    #[derive(Debug, Clone, PartialEq)]
+    struct Skip<'a>(PhantomData<&'a str>);
+
+    impl<'a> Step for Skip<'a> {
+        type Exp = &'a str;
+
+        fn apply(&self, e: &&'a str) -> Option<&'a str> {
+            let mut chars = e.chars();
+            chars.next()?;
+            Some(chars.as_str())
+        }
+    }
+
+    struct Skips<'a>(PhantomData<&'a str>);
+
+    impl<'a> StepProvider<TickBudget> for Skips<'a> {
+        type Step = Skip<'a>;
+
+        fn provide(
+            &mut self,
+            _timer: &TickBudget,
+            e: &&'a str,
+        ) -> Result<Vec<Skip<'a>>, OutOfBudget> {
+            Ok(e.chars()
+                .next()
+                .map(|_| Skip(PhantomData))
+                .into_iter()
+                .collect())
+        }
+    }
+
+    struct Empty<'a>(PhantomData<&'a str>);
+
+    impl<'a> ValidityChecker for Empty<'a> {
+        type Exp = &'a str;
+
+        fn check(&self, e: &&'a str) -> bool {
+            e.is_empty()
+        }
+    }
+
+    #[test]
+    fn controllers_navigate_borrowed_expressions() {
+        let text = String::from("ab");
+        let mut c: Controller<_, _, _, _, history::VecHistory<&str>> =
+            Controller::from_boxed(
+                testing::budget(),
+                Box::new(Skips(PhantomData)),
+                Box::new(Empty(PhantomData)),
+                text.as_str(),
+                Some(Box::new(history::VecHistory::new())),
+            );
+        while !c.valid() {
+            let step = c.provide().unwrap().remove(0);
+            c.decide(step).unwrap();
+        }
+        c.undo().unwrap();
+        assert_eq!(*c.working_expression(), "b");
     }
 }
@@ -0,0 +1,69 @@
+//! Navigation restricted to a selection of the working expression
+//!
+//! Editor integrations usually know which part of the program the programmer
+//! is looking at (e.g., the selection or cursor). Steps that implement
+//! [`LocatedStep`] report the [`Region`] of the expression they touch, so that
+//! [`Controller::provide_at`] can provide only the steps that are relevant to
+//! the current selection. (See also [`hole`](crate::hole) for expressions
+//! whose locations are holes.)
+
+use crate::{
+    Controller, PbnError, Step, StepProvider, Timer, ValidityChecker,
+    history::HistoryStore,
+};
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// Regions of expressions (e.g., spans of source text)
+pub trait Region {
+    /// Returns whether or not the regions overlap
+    fn intersects(&self, other: &Self) -> bool;
+}
+
+/// Spans of positions, where an empty span (e.g., a cursor) intersects the
+/// spans it touches
+impl Region for Range<usize> {
+    fn intersects(&self, other: &Self) -> bool {
+        if self.is_empty() || other.is_empty() {
+            self.start <= other.end && other.start <= self.end
+        } else {
+            self.start < other.end && other.start < self.end
+        }
+    }
+}
+
+/// Steps that touch a particular region of an expression
+pub trait LocatedStep: Step {
+    /// The notion of regions
+    type Region: Region;
+
+    /// Returns the region of `e` that the step touches (or `None` if it
+    /// touches the expression as a whole)
+    fn region(&self, e: &Self::Exp) -> Option<Self::Region>;
+}
+
+impl<T, S, P, C, H> Controller<T, S, P, C, H>
+where
+    T: Timer,
+    S: LocatedStep,
+    P: StepProvider<T, Step = S> + ?Sized,
+    C: ValidityChecker<Exp = S::Exp> + ?Sized,
+    H: HistoryStore<S::Exp> + ?Sized,
+{
+    /// Ask the synthesizer to provide a list of possible next steps that
+    /// touch a region intersecting `selection` (see [`Self::provide`])
+    ///
+    /// Steps that touch the expression as a whole are always provided.
+    pub fn provide_at(
+        &mut self,
+        selection: &S::Region,
+    ) -> Result<Vec<S>, PbnError<T::EarlyCutoff>> {
+        let mut steps = self.provide()?;
+        steps.retain(|step| {
+            step.region(&self.state)
+                .is_none_or(|r| r.intersects(selection))
+        });
+        Ok(steps)
+    }
+}
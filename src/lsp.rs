@@ -0,0 +1,242 @@
+//! A bridge from navigation to Language Server Protocol code actions
+//!
+//! Editors already have a user interface that fits Programming by Navigation
+//! well: the quick-fix menu. A [`CodeActionBridge`] turns provided steps into
+//! LSP code actions and decided steps into workspace edits, given an
+//! [`LspConverter`] that knows how to title steps and how expressions are laid
+//! out in source files. The types in this module mirror the corresponding LSP
+//! structures and serialize to the JSON shapes that the protocol expects, so an
+//! editor extension only needs to forward them.
+
+use crate::{
+    Controller, PbnError, Step, StepProvider, Timer, ValidityChecker,
+    history::HistoryStore, json::Value,
+};
+
+use alloc::{borrow::ToOwned, string::String, vec, vec::Vec};
+
+/// A position in a text document (zero-based)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    /// The line
+    pub line: u32,
+    /// The character offset within the line (in UTF-16 code units)
+    pub character: u32,
+}
+
+/// A range in a text document
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    /// The start of the range (inclusive)
+    pub start: Position,
+    /// The end of the range (exclusive)
+    pub end: Position,
+}
+
+/// A replacement of a range of a text document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    /// The range to replace
+    pub range: Range,
+    /// The replacement text
+    pub new_text: String,
+}
+
+/// A set of text edits to (possibly many) documents
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorkspaceEdit {
+    /// The edits to each document (identified by URI)
+    pub changes: Vec<(String, Vec<TextEdit>)>,
+}
+
+/// A code action that decides on a provided step
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeAction {
+    /// The title shown in the quick-fix menu
+    pub title: String,
+    /// The LSP kind of the action (e.g., `"quickfix"`)
+    pub kind: String,
+    /// Whether or not the action is the only provided step
+    pub is_preferred: bool,
+    /// The edit that deciding on the step makes (if previewed)
+    pub edit: Option<WorkspaceEdit>,
+    /// The index of the step among the provided steps
+    pub index: usize,
+}
+
+/// The conversions between steps, expressions, and editor concepts needed by
+/// a [`CodeActionBridge`]
+pub trait LspConverter<S: Step> {
+    /// Returns the title of a step
+    fn title(&self, step: &S) -> String;
+
+    /// Returns the LSP kind of the code action for a step
+    fn kind(&self, _step: &S) -> String {
+        "quickfix".to_owned()
+    }
+
+    /// Returns the workspace edit that turns the source of `before` into the
+    /// source of `after`
+    fn edit(&self, before: &S::Exp, after: &S::Exp) -> WorkspaceEdit;
+}
+
+/// A bridge from provided steps to code actions (and from chosen code actions
+/// to workspace edits)
+pub struct CodeActionBridge<X> {
+    converter: X,
+    preview: bool,
+}
+
+impl<X> CodeActionBridge<X> {
+    /// Creates a new [`CodeActionBridge`] that uses `converter`
+    pub fn new(converter: X) -> Self {
+        Self {
+            converter,
+            preview: false,
+        }
+    }
+
+    /// Sets whether or not code actions include the edit that deciding on
+    /// their step would make (which requires applying every provided step)
+    pub fn with_previews(mut self, preview: bool) -> Self {
+        self.preview = preview;
+        self
+    }
+
+    /// Returns the code actions for the steps provided for `e` (skipping
+    /// inapplicable steps)
+    pub fn code_actions<S>(&self, e: &S::Exp, steps: &[S]) -> Vec<CodeAction>
+    where
+        S: Step,
+        X: LspConverter<S>,
+    {
+        let is_preferred = steps.len() == 1;
+        let action = |index, step, edit| CodeAction {
+            title: self.converter.title(step),
+            kind: self.converter.kind(step),
+            is_preferred,
+            edit,
+            index,
+        };
+        if !self.preview {
+            return steps
+                .iter()
+                .enumerate()
+                .filter(|(_, step)| step.applicable(e))
+                .map(|(i, step)| action(i, step, None))
+                .collect();
+        }
+        steps
+            .iter()
+            .zip(S::apply_all(steps, e))
+            .enumerate()
+            .filter_map(|(i, (step, next))| {
+                let edit = self.converter.edit(e, &next?);
+                Some(action(i, step, Some(edit)))
+            })
+            .collect()
+    }
+
+    /// Decides on the step of a chosen code action and returns the
+    /// corresponding workspace edit
+    pub fn decide<T, S, P, C, H>(
+        &self,
+        controller: &mut Controller<T, S, P, C, H>,
+        mut steps: Vec<S>,
+        action: &CodeAction,
+    ) -> Result<WorkspaceEdit, PbnError<T::EarlyCutoff>>
+    where
+        T: Timer,
+        S: Step,
+        P: StepProvider<T, Step = S> + ?Sized,
+        C: ValidityChecker<Exp = S::Exp> + ?Sized,
+        H: HistoryStore<S::Exp> + ?Sized,
+        X: LspConverter<S>,
+    {
+        if action.index >= steps.len() {
+            return Err(PbnError::InvalidDecision("no such provided step"));
+        }
+        let before = controller.working_expression().clone();
+        controller.decide(steps.swap_remove(action.index))?;
+        Ok(self
+            .converter
+            .edit(&before, controller.working_expression()))
+    }
+}
+
+/// Returns the index of the step of a code action from the `data` field of
+/// its JSON (e.g., in a `codeAction/resolve` request)
+pub fn action_index(data: &Value) -> Option<usize> {
+    Some(data.get("index")?.as_u64()? as usize)
+}
+
+fn field(key: &str, value: Value) -> (String, Value) {
+    (key.to_owned(), value)
+}
+
+impl Position {
+    /// Returns the position as an LSP JSON object
+    pub fn to_json(&self) -> Value {
+        Value::Object(vec![
+            field("line", Value::Number(self.line as f64)),
+            field("character", Value::Number(self.character as f64)),
+        ])
+    }
+}
+
+impl Range {
+    /// Returns the range as an LSP JSON object
+    pub fn to_json(&self) -> Value {
+        Value::Object(vec![
+            field("start", self.start.to_json()),
+            field("end", self.end.to_json()),
+        ])
+    }
+}
+
+impl TextEdit {
+    /// Returns the text edit as an LSP JSON object
+    pub fn to_json(&self) -> Value {
+        Value::Object(vec![
+            field("range", self.range.to_json()),
+            field("newText", Value::String(self.new_text.clone())),
+        ])
+    }
+}
+
+impl WorkspaceEdit {
+    /// Returns the workspace edit as an LSP JSON object
+    pub fn to_json(&self) -> Value {
+        let changes = self
+            .changes
+            .iter()
+            .map(|(uri, edits)| {
+                let edits = edits.iter().map(TextEdit::to_json).collect();
+                (uri.clone(), Value::Array(edits))
+            })
+            .collect();
+        Value::Object(vec![field("changes", Value::Object(changes))])
+    }
+}
+
+impl CodeAction {
+    /// Returns the code action as an LSP JSON object
+    pub fn to_json(&self) -> Value {
+        let mut fields = vec![
+            field("title", Value::String(self.title.clone())),
+            field("kind", Value::String(self.kind.clone())),
+            field("isPreferred", Value::Bool(self.is_preferred)),
+        ];
+        if let Some(edit) = &self.edit {
+            fields.push(field("edit", edit.to_json()));
+        }
+        fields.push(field(
+            "data",
+            Value::Object(vec![field(
+                "index",
+                Value::Number(self.index as f64),
+            )]),
+        ));
+        Value::Object(fields)
+    }
+}
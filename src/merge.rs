@@ -0,0 +1,207 @@
+//! Merging divergent explorations of the same program
+//!
+//! Two collaborators (or one programmer in two sessions) may navigate from
+//! the same expression toward independent parts of the program, e.g. by
+//! filling different holes. A [`Branch`] records such an exploration as the
+//! steps decided from a common base expression, and [`merge`] combines two
+//! branches into one expression according to a [`MergeStrategy`]: by
+//! replaying the steps of one branch on top of the other, or by a
+//! user-supplied three-way [`ExpMerger`]. Branches can be taken from a
+//! session as a whole ([`Controller::branch`]) or from an entry of its undo
+//! history ([`Controller::branch_since`]).
+
+use crate::{
+    Action, Controller, PbnError, Step, StepProvider, Timer, ValidityChecker,
+    history::HistoryStore,
+};
+
+use alloc::{boxed::Box, vec::Vec};
+
+/// A sequence of steps decided from a base expression
+#[derive(Debug, Clone)]
+pub struct Branch<S: Step> {
+    /// The expression the branch starts from
+    pub base: S::Exp,
+    /// The steps decided on the branch (in order)
+    pub steps: Vec<S>,
+}
+
+impl<S: Step> Branch<S> {
+    /// Returns the expression at the end of the branch (if every step
+    /// applies)
+    pub fn result(&self) -> Option<S::Exp> {
+        replay(self.base.clone(), &self.steps)
+    }
+}
+
+fn replay<S: Step>(e: S::Exp, steps: &[S]) -> Option<S::Exp> {
+    steps.iter().try_fold(e, |e, step| step.apply(&e))
+}
+
+/// A three-way merge of expressions
+pub trait ExpMerger<E> {
+    /// Returns an expression that combines the changes from `base` to `a`
+    /// with the changes from `base` to `b` (or `None` if they conflict)
+    fn merge(&self, base: &E, a: &E, b: &E) -> Option<E>;
+}
+
+impl<E, F: Fn(&E, &E, &E) -> Option<E>> ExpMerger<E> for F {
+    fn merge(&self, base: &E, a: &E, b: &E) -> Option<E> {
+        self(base, a, b)
+    }
+}
+
+/// How to combine two branches
+pub enum MergeStrategy<E> {
+    /// Applies the steps of the second branch to the result of the first
+    /// (the branches conflict if any step does not apply)
+    Replay,
+    /// Merges the results of the branches with a three-way merge
+    Merger(Box<dyn ExpMerger<E>>),
+    /// Tries [`MergeStrategy::Replay`], falling back to a three-way merge
+    ReplayOr(Box<dyn ExpMerger<E>>),
+}
+
+/// Combines two branches from the same base expression into one expression
+/// (or returns `None` if they conflict)
+pub fn merge<S: Step>(
+    a: &Branch<S>,
+    b: &Branch<S>,
+    strategy: &MergeStrategy<S::Exp>,
+) -> Option<S::Exp> {
+    let a_exp = a.result()?;
+    let three_way = |merger: &dyn ExpMerger<S::Exp>| {
+        merger.merge(&a.base, &a_exp, &b.result()?)
+    };
+    match strategy {
+        MergeStrategy::Replay => replay(a_exp.clone(), &b.steps),
+        MergeStrategy::Merger(merger) => three_way(merger.as_ref()),
+        MergeStrategy::ReplayOr(merger) => replay(a_exp.clone(), &b.steps)
+            .or_else(|| three_way(merger.as_ref())),
+    }
+}
+
+impl<T, S, P, C, H> Controller<T, S, P, C, H>
+where
+    T: Timer,
+    S: Step + Clone,
+    P: StepProvider<T, Step = S> + ?Sized,
+    C: ValidityChecker<Exp = S::Exp> + ?Sized,
+    H: HistoryStore<S::Exp> + ?Sized,
+{
+    /// Returns the session so far as a branch from its start expression (or
+    /// `None` if the working expression was edited externally)
+    pub fn branch(&self) -> Option<Branch<S>> {
+        self.branch_since(self.decisions.len())
+    }
+
+    /// Returns the last `k` changes of the session as a branch from the
+    /// working expression before them (or `None` if there are fewer than `k`
+    /// changes or one of them is an external edit)
+    ///
+    /// These are the changes that `k` calls to [`Self::undo`] would revert,
+    /// so collaborators can branch from a common entry of their history.
+    /// Since history stores only save expressions (not the steps between
+    /// them), the branch is reconstructed from the timeline of the session
+    /// (see [`Self::timeline`]), which mirrors the history.
+    pub fn branch_since(&self, k: usize) -> Option<Branch<S>> {
+        let split = self.decisions.len().checked_sub(k)?;
+        let (before, after) = self.decisions.split_at(split);
+        let base =
+            before.iter().try_fold(self.start.clone(), |e, d| {
+                match &d.action {
+                    Action::Step(step) => step.apply(&e),
+                    Action::Edit(e) => Some(e.clone()),
+                }
+            })?;
+        let steps = after.iter().map(|d| match &d.action {
+            Action::Step(step) => Some(step.clone()),
+            Action::Edit(_) => None,
+        });
+        Some(Branch {
+            base,
+            steps: steps.collect::<Option<_>>()?,
+        })
+    }
+
+    /// Replaces the working expression with the merge of two branches (as an
+    /// external edit; see [`Self::apply_external_edit`])
+    ///
+    /// Returns [`PbnError::InvalidDecision`] if the branches conflict.
+    pub fn merge(
+        &mut self,
+        a: &Branch<S>,
+        b: &Branch<S>,
+        strategy: &MergeStrategy<S::Exp>,
+    ) -> Result<(), PbnError<T::EarlyCutoff>> {
+        let merged = merge(a, b, strategy)
+            .ok_or(PbnError::InvalidDecision("branches conflict"))?;
+        self.apply_external_edit(|_| merged);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, AllEqual, Fill, Fills, Slots};
+
+    use alloc::vec;
+
+    fn controller() -> Controller<crate::budget::TickBudget, Fill> {
+        Controller::new(
+            testing::budget(),
+            Fills { digits: 2 },
+            AllEqual(1),
+            testing::empty(),
+            true,
+        )
+    }
+
+    #[test]
+    fn branches_from_history_entries() {
+        let mut c = controller();
+        c.decide(Fill(0, 1)).unwrap();
+        c.apply_external_edit(|e| [e[0], Some(0), None]);
+        c.decide(Fill(2, 1)).unwrap();
+
+        assert!(c.branch().is_none());
+        assert!(c.branch_since(2).is_none());
+        assert!(c.branch_since(4).is_none());
+        let branch = c.branch_since(1).unwrap();
+        assert_eq!(branch.base, [Some(1), Some(0), None]);
+        assert_eq!(branch.steps, [Fill(2, 1)]);
+        assert_eq!(branch.result().as_ref(), Some(c.working_expression()));
+
+        c.undo().unwrap();
+        assert_eq!(c.working_expression(), &branch.base);
+        assert_eq!(c.branch_since(0).unwrap().base, branch.base);
+    }
+
+    #[test]
+    fn merges_independent_branches() {
+        let mut a = controller();
+        a.decide(Fill(0, 1)).unwrap();
+        let mut b = controller();
+        b.decide(Fill(1, 1)).unwrap();
+        b.decide(Fill(2, 1)).unwrap();
+        let (a, b) = (a.branch().unwrap(), b.branch().unwrap());
+
+        let mut c = controller();
+        c.merge(&a, &b, &MergeStrategy::Replay).unwrap();
+        assert!(c.valid());
+
+        let conflicting = Branch {
+            base: testing::empty(),
+            steps: vec![Fill(0, 0)],
+        };
+        let first: fn(&Slots, &Slots, &Slots) -> Option<Slots> =
+            |_, a, _| Some(*a);
+        assert!(merge(&a, &conflicting, &MergeStrategy::Replay).is_none());
+        assert_eq!(
+            merge(&a, &conflicting, &MergeStrategy::ReplayOr(Box::new(first))),
+            a.result()
+        );
+        assert!(c.merge(&a, &conflicting, &MergeStrategy::Replay).is_err());
+    }
+}
@@ -0,0 +1,488 @@
+//! Reference step providers computed by brute force
+//!
+//! The [`OracleProvider`] computes provided steps by exhaustive reachability
+//! analysis over a [`StepEnumerator`] up to a depth bound. It is slow, but it
+//! is Strongly Sound and Strongly Complete with respect to all valid
+//! expressions reachable within the bound, which makes it a useful reference
+//! implementation for differential testing of faster providers. It can also
+//! enumerate every valid expression within the bound, ranked by a
+//! [`SolutionRanker`], or incrementally from a resumable [`Cursor`].
+
+use crate::{
+    InvalidArgument, Step, StepEnumerator, StepProvider, Timer,
+    ValidityChecker,
+    canon::{CanonVisited, ExpCanon, Visited},
+    json::Value,
+    rank::{Pareto, SolutionRanker},
+};
+
+use alloc::{borrow::ToOwned, boxed::Box, vec, vec::Vec};
+use core::cell::RefCell;
+
+type Exp<E> = <<E as StepEnumerator>::Step as Step>::Exp;
+
+/// A position in an exhaustive enumeration (see
+/// [`OracleProvider::enumerate_valid`])
+///
+/// A cursor identifies the next expression to explore by the indexes of the
+/// enumerated steps that reach it, so it can be saved (see
+/// [`Self::to_json`]) and resumed in another process, provided that the step
+/// enumerator enumerates steps in a deterministic order. An enumeration can
+/// also be split into shards (see [`Self::shard`]), each of which explores a
+/// disjoint share of the steps from the start expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    next: Option<Vec<usize>>,
+    shard: usize,
+    shards: usize,
+}
+
+impl Default for Cursor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cursor {
+    /// Creates a cursor at the start of an enumeration
+    pub fn new() -> Self {
+        Self::shard(0, 1)
+    }
+
+    /// Creates a cursor at the start of shard `shard` of `shards` (positive)
+    /// shards of an enumeration
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shard` is not less than `shards` (see [`Self::try_shard`])
+    #[track_caller]
+    pub fn shard(shard: usize, shards: usize) -> Self {
+        InvalidArgument::unwrap(Self::try_shard(shard, shards))
+    }
+
+    /// Creates a cursor at the start of a shard (see [`Self::shard`]), or
+    /// fails if `shard` is not less than `shards`
+    pub fn try_shard(
+        shard: usize,
+        shards: usize,
+    ) -> Result<Self, InvalidArgument> {
+        InvalidArgument::check(shard < shards, "no such shard", || Self {
+            next: Some(vec![]),
+            shard,
+            shards,
+        })
+    }
+
+    /// Returns whether or not the enumeration is finished
+    pub fn is_done(&self) -> bool {
+        self.next.is_none()
+    }
+
+    /// Returns the cursor as a JSON object
+    pub fn to_json(&self) -> Value {
+        let num = |n: usize| Value::Number(n as f64);
+        Value::Object(vec![
+            (
+                "next".to_owned(),
+                self.next.as_ref().map_or(Value::Null, |path| {
+                    Value::Array(path.iter().copied().map(num).collect())
+                }),
+            ),
+            ("shard".to_owned(), num(self.shard)),
+            ("shards".to_owned(), num(self.shards)),
+        ])
+    }
+
+    /// Reads a cursor from a JSON object (as written by [`Self::to_json`])
+    pub fn from_json(v: &Value) -> Option<Self> {
+        let num = |v: &Value| Some(v.as_u64()? as usize);
+        let next = match v.get("next")? {
+            Value::Null => None,
+            path => {
+                Some(path.as_array()?.iter().map(num).collect::<Option<_>>()?)
+            }
+        };
+        let shard = num(v.get("shard")?)?;
+        let shards = num(v.get("shards")?)?;
+        (shard < shards).then_some(Self {
+            next,
+            shard,
+            shards,
+        })
+    }
+}
+
+/// The ways resuming an enumeration from a [`Cursor`] can fail
+#[derive(Debug)]
+pub enum ResumeError<C> {
+    /// The enumeration was cut off early by the timer
+    Cutoff(C),
+    /// The cursor does not identify an expression reachable from the start
+    /// expression (e.g., it was saved by an enumeration with a different
+    /// start expression, step enumerator, or depth bound)
+    Mismatch,
+}
+
+impl<C> From<C> for ResumeError<C> {
+    fn from(cutoff: C) -> Self {
+        ResumeError::Cutoff(cutoff)
+    }
+}
+
+impl<C: core::fmt::Display> core::fmt::Display for ResumeError<C> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ResumeError::Cutoff(c) => write!(f, "cut off early: {}", c),
+            ResumeError::Mismatch => {
+                write!(f, "cursor does not match the enumeration")
+            }
+        }
+    }
+}
+
+impl<C: core::error::Error + 'static> core::error::Error for ResumeError<C> {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            ResumeError::Cutoff(c) => Some(c),
+            ResumeError::Mismatch => None,
+        }
+    }
+}
+
+/// An expression explored by an enumeration, with its enumerated steps
+struct Level<S: Step> {
+    exp: S::Exp,
+    steps: Vec<S>,
+}
+
+/// A step provider that provides exactly the enumerated steps from which a
+/// valid expression is reachable within a bounded number of steps
+pub struct OracleProvider<E: StepEnumerator> {
+    enumerator: E,
+    checker: Box<dyn ValidityChecker<Exp = <E::Step as Step>::Exp>>,
+    depth: usize,
+    visited: Option<RefCell<Box<dyn Visited<Exp<E>>>>>,
+}
+
+impl<E: StepEnumerator> OracleProvider<E> {
+    /// Creates a new [`OracleProvider`] that considers valid expressions
+    /// reachable in at most `depth` steps (including the provided step)
+    pub fn new(
+        enumerator: E,
+        checker: impl ValidityChecker<Exp = <E::Step as Step>::Exp> + 'static,
+        depth: usize,
+    ) -> Self {
+        Self {
+            enumerator,
+            checker: Box::new(checker),
+            depth,
+            visited: None,
+        }
+    }
+
+    /// Skips expressions that are equivalent (according to `canon`) to one
+    /// already explored with at least as many remaining steps
+    pub fn with_canon(mut self, canon: impl ExpCanon<Exp<E>> + 'static) -> Self
+    where
+        Exp<E>: 'static,
+    {
+        self.visited = Some(RefCell::new(Box::new(CanonVisited::new(canon))));
+        self
+    }
+
+    /// Returns whether or not a valid expression is reachable from `e` in at
+    /// most `depth` steps
+    pub fn reachable<T: Timer>(
+        &self,
+        timer: &T,
+        e: &<E::Step as Step>::Exp,
+        depth: usize,
+    ) -> Result<bool, T::EarlyCutoff> {
+        self.forget();
+        self.search(timer, e, depth, depth)
+    }
+
+    /// Returns the Pareto set (according to `ranker`) of the valid
+    /// expressions reachable from `e` in at most `depth` steps (without
+    /// navigating beyond valid expressions)
+    pub fn pareto<T: Timer>(
+        &self,
+        timer: &T,
+        e: &<E::Step as Step>::Exp,
+        ranker: &SolutionRanker<Exp<E>>,
+    ) -> Result<Pareto<Exp<E>>, T::EarlyCutoff>
+    where
+        Exp<E>: PartialEq,
+    {
+        let mut pareto = Pareto::default();
+        self.collect(timer, e, 0, ranker, &mut pareto)?;
+        Ok(pareto)
+    }
+
+    fn collect<T: Timer>(
+        &self,
+        timer: &T,
+        e: &<E::Step as Step>::Exp,
+        depth: usize,
+        ranker: &SolutionRanker<Exp<E>>,
+        pareto: &mut Pareto<Exp<E>>,
+    ) -> Result<(), T::EarlyCutoff>
+    where
+        Exp<E>: PartialEq,
+    {
+        timer.tick()?;
+        if self.checker.check(e) {
+            pareto.insert(ranker.measure(e.clone(), depth));
+            return Ok(());
+        }
+        if depth == self.depth {
+            return Ok(());
+        }
+        for step in self.enumerator.enumerate(e) {
+            if let Some(e2) = step.apply(e) {
+                self.collect(timer, &e2, depth + 1, ranker, pareto)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Enumerates the valid expressions reachable from `start` in at most
+    /// `depth` steps (without navigating beyond valid expressions), calling
+    /// `visit` on each one with the number of steps that reach it, starting
+    /// from the position of `cursor`
+    ///
+    /// If `timer` cuts off the enumeration, `cursor` is left at the first
+    /// expression that was not explored, so calling this method again with
+    /// the same cursor continues the enumeration exactly where it left off.
+    /// If `cursor` does not identify an expression reachable from `start`
+    /// within the depth bound, this method fails with
+    /// [`ResumeError::Mismatch`] and leaves `cursor` unchanged.
+    pub fn enumerate_valid<T: Timer>(
+        &self,
+        timer: &T,
+        start: &Exp<E>,
+        cursor: &mut Cursor,
+        mut visit: impl FnMut(&Exp<E>, usize),
+    ) -> Result<(), ResumeError<T::EarlyCutoff>> {
+        let Some(path) = &mut cursor.next else {
+            return Ok(());
+        };
+        if path.len() > self.depth {
+            return Err(ResumeError::Mismatch);
+        }
+        let mut stack: Vec<Level<E::Step>> = Vec::with_capacity(path.len());
+        let mut e = start.clone();
+        for &i in path.iter() {
+            let steps = self.enumerator.enumerate(&e);
+            let Some(next) = steps.get(i).and_then(|step| step.apply(&e))
+            else {
+                return Err(ResumeError::Mismatch);
+            };
+            stack.push(Level { exp: e, steps });
+            e = next;
+        }
+        loop {
+            timer.tick()?;
+            let descend = if self.checker.check(&e) {
+                if !path.is_empty() || cursor.shard == 0 {
+                    visit(&e, path.len());
+                }
+                false
+            } else {
+                path.len() < self.depth
+            };
+            if descend {
+                let steps = self.enumerator.enumerate(&e);
+                stack.push(Level { exp: e, steps });
+                path.push(0);
+            } else if let Some(i) = path.last_mut() {
+                *i += 1;
+            } else {
+                cursor.next = None;
+                return Ok(());
+            }
+            // Find the next expression in depth-first order
+            e = loop {
+                let k = path.len() - 1;
+                let mut i = path[k];
+                if k == 0 {
+                    // Skip to the next step of this shard
+                    let n = cursor.shards;
+                    i += (cursor.shard + n - i % n) % n;
+                }
+                let level = &stack[k];
+                if i >= level.steps.len() {
+                    stack.pop();
+                    path.pop();
+                    match path.last_mut() {
+                        Some(j) => *j += 1,
+                        None => {
+                            cursor.next = None;
+                            return Ok(());
+                        }
+                    }
+                    continue;
+                }
+                path[k] = i;
+                match level.steps[i].apply(&level.exp) {
+                    Some(next) => break next,
+                    None => path[k] += 1,
+                }
+            };
+        }
+    }
+
+    /// Clears the visited set (which only records expressions from which no
+    /// valid expression is reachable within the remaining depth)
+    fn forget(&self) {
+        if let Some(v) = &self.visited {
+            v.borrow_mut().clear();
+        }
+    }
+
+    /// Like [`Self::reachable`], but without clearing the visited set (`max`
+    /// is the depth bound of the whole search)
+    fn search<T: Timer>(
+        &self,
+        timer: &T,
+        e: &<E::Step as Step>::Exp,
+        depth: usize,
+        max: usize,
+    ) -> Result<bool, T::EarlyCutoff> {
+        timer.tick()?;
+        if let Some(v) = &self.visited
+            && !v.borrow_mut().visit(e, max - depth)
+        {
+            return Ok(false);
+        }
+        if self.checker.check(e) {
+            return Ok(true);
+        }
+        if depth == 0 {
+            return Ok(false);
+        }
+        for step in self.enumerator.enumerate(e) {
+            if let Some(e2) = step.apply(e)
+                && self.search(timer, &e2, depth - 1, max)?
+            {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl<T: Timer, E: StepEnumerator> StepProvider<T> for OracleProvider<E> {
+    type Step = E::Step;
+
+    fn provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        let mut steps = vec![];
+        if self.depth == 0 {
+            return Ok(steps);
+        }
+        self.forget();
+        for step in self.enumerator.enumerate(e) {
+            if let Some(e2) = step.apply(e)
+                && self.search(timer, &e2, self.depth - 1, self.depth - 1)?
+            {
+                steps.push(step);
+                self.forget();
+            }
+        }
+        Ok(steps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::TickBudget;
+    use crate::testing::{AllEqual, Fills, Slots, budget, empty};
+
+    fn oracle() -> OracleProvider<Fills> {
+        OracleProvider::new(Fills { digits: 2 }, AllEqual(1), 3)
+    }
+
+    /// Enumerates to the end, `ticks` ticks at a time, round-tripping the
+    /// cursor through JSON between chunks
+    fn chunked(ticks: usize, mut cursor: Cursor) -> Vec<(Slots, usize)> {
+        let oracle = oracle();
+        let mut found = vec![];
+        while !cursor.is_done() {
+            let timer = TickBudget::new(ticks);
+            let _ = oracle.enumerate_valid(
+                &timer,
+                &empty(),
+                &mut cursor,
+                |e, n| found.push((*e, n)),
+            );
+            cursor = Cursor::from_json(&cursor.to_json()).unwrap();
+        }
+        found
+    }
+
+    #[test]
+    fn resumed_enumerations_match_uninterrupted_ones() {
+        let mut all = vec![];
+        let mut cursor = Cursor::new();
+        oracle()
+            .enumerate_valid(&budget(), &empty(), &mut cursor, |e, n| {
+                all.push((*e, n))
+            })
+            .unwrap();
+        assert!(cursor.is_done());
+        // Six orders in which to fill three slots with ones
+        assert_eq!(all, vec![([Some(1); 3], 3); 6]);
+        for ticks in [1, 2, 7] {
+            assert_eq!(chunked(ticks, Cursor::new()), all);
+        }
+    }
+
+    #[test]
+    fn shards_split_enumerations() {
+        let mut all = chunked(100, Cursor::new());
+        let mut sharded: Vec<_> = (0..4)
+            .flat_map(|i| chunked(3, Cursor::shard(i, 4)))
+            .collect();
+        all.sort();
+        sharded.sort();
+        assert_eq!(sharded, all);
+        assert!(Cursor::try_shard(4, 4).is_err());
+    }
+
+    #[test]
+    fn mismatched_cursors_fail_to_resume() {
+        let oracle = OracleProvider::new(Fills { digits: 2 }, AllEqual(1), 3);
+        let mut cursor = Cursor::new();
+        assert!(matches!(
+            oracle.enumerate_valid(
+                &TickBudget::new(5),
+                &empty(),
+                &mut cursor,
+                |_, _| ()
+            ),
+            Err(ResumeError::Cutoff(_))
+        ));
+        let saved = cursor.clone();
+        let full = [Some(0); 3];
+        assert!(matches!(
+            oracle.enumerate_valid(&budget(), &full, &mut cursor, |_, _| ()),
+            Err(ResumeError::Mismatch)
+        ));
+        assert_eq!(cursor, saved);
+        let shallow = OracleProvider::new(Fills { digits: 2 }, AllEqual(1), 1);
+        assert!(matches!(
+            shallow.enumerate_valid(
+                &budget(),
+                &empty(),
+                &mut cursor,
+                |_, _| ()
+            ),
+            Err(ResumeError::Mismatch)
+        ));
+    }
+}
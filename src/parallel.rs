@@ -0,0 +1,329 @@
+//! Parallel automated search with work stealing
+//!
+//! A [`ParallelSearch`] explores the expressions reachable from a start
+//! expression on a pool of threads, each with its own step provider (created
+//! by a factory, since providers are stateful). Every thread explores its own
+//! frontier depth-first and steals the shallowest frontier nodes of other
+//! threads when it runs out of work. Threads share the best valid expression
+//! found so far (to prune paths that cannot improve on it), an optional
+//! visited set of canonical expressions (see [`ExpCanon`]), and the timer,
+//! whose early cutoff stops all threads.
+
+use crate::{
+    InvalidArgument, ProviderError, Step, StepProvider, Timer, ValidityChecker,
+    canon::{BackendVisited, CanonVisited, ExpCanon, Visited, VisitedBackend},
+    derivation::Derivation,
+    sync::Mutex,
+};
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// A node of the search frontier: an expression and the steps that reach it
+struct Node<S: Step> {
+    exp: S::Exp,
+    path: Vec<S>,
+}
+
+type SharedVisited<E> = Mutex<Box<dyn Visited<E> + Send>>;
+
+/// The result of a [`ParallelSearch`]
+#[derive(Debug, Clone)]
+pub struct SearchResult<S: Step> {
+    /// The valid expression reachable in the fewest steps that was found (if
+    /// any), along with the steps that reach it
+    pub best: Option<(S::Exp, Vec<S>)>,
+    /// Whether or not the search was cut off by the timer before it explored
+    /// every expression that could improve on the best one
+    pub cut_off: bool,
+    /// The number of expressions explored
+    pub explored: usize,
+}
+
+impl<S: Step + Clone> SearchResult<S> {
+    /// Returns the derivation of the best valid expression from the start
+    /// expression of the search (if one was found)
+    pub fn derivation(&self, start: &S::Exp) -> Option<Derivation<S>> {
+        let (_, path) = self.best.as_ref()?;
+        Derivation::new(start.clone(), path.clone())
+    }
+}
+
+/// An automated search that runs on a pool of threads
+pub struct ParallelSearch<S: Step, F> {
+    factory: F,
+    checker: Box<dyn ValidityChecker<Exp = S::Exp> + Sync>,
+    threads: usize,
+    visited: Option<SharedVisited<S::Exp>>,
+}
+
+impl<S, F> ParallelSearch<S, F>
+where
+    S: Step + Clone + Send,
+    S::Exp: Send + Sync,
+{
+    /// Creates a new [`ParallelSearch`] on `threads` (positive) threads, each
+    /// of which uses a provider created by `factory`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threads` is zero (see [`Self::try_new`])
+    #[track_caller]
+    pub fn new(
+        factory: F,
+        checker: impl ValidityChecker<Exp = S::Exp> + Sync + 'static,
+        threads: usize,
+    ) -> Self {
+        InvalidArgument::unwrap(Self::try_new(factory, checker, threads))
+    }
+
+    /// Creates a new [`ParallelSearch`] (see [`Self::new`]), or fails if
+    /// `threads` is zero
+    pub fn try_new(
+        factory: F,
+        checker: impl ValidityChecker<Exp = S::Exp> + Sync + 'static,
+        threads: usize,
+    ) -> Result<Self, InvalidArgument> {
+        InvalidArgument::check(
+            threads > 0,
+            "at least one thread is required",
+            || Self {
+                factory,
+                checker: Box::new(checker),
+                threads,
+                visited: None,
+            },
+        )
+    }
+
+    /// Skips expressions that are equivalent (according to `canon`) to one
+    /// already reached in at most as many steps (by any thread)
+    pub fn with_canon<X>(mut self, canon: X) -> Self
+    where
+        X: ExpCanon<S::Exp> + Send + 'static,
+        X::Key: Send,
+        S::Exp: 'static,
+    {
+        self.visited = Some(Mutex::new(Box::new(CanonVisited::new(canon))));
+        self
+    }
+
+    /// Like [`Self::with_canon`], but stores the visited expressions with
+    /// `backend` (e.g., to bound the memory of very large searches)
+    ///
+    /// # Panics
+    ///
+    /// Panics if the parameters of `backend` are out of range (see
+    /// [`Self::try_with_canon_backend`])
+    #[track_caller]
+    pub fn with_canon_backend<X>(
+        self,
+        canon: X,
+        backend: VisitedBackend,
+    ) -> Self
+    where
+        X: ExpCanon<S::Exp> + Send + 'static,
+        X::Key: Clone + std::hash::Hash + Send,
+        S::Exp: 'static,
+    {
+        InvalidArgument::unwrap(self.try_with_canon_backend(canon, backend))
+    }
+
+    /// Like [`Self::with_canon_backend`], but fails if the parameters of
+    /// `backend` are out of range
+    pub fn try_with_canon_backend<X>(
+        mut self,
+        canon: X,
+        backend: VisitedBackend,
+    ) -> Result<Self, InvalidArgument>
+    where
+        X: ExpCanon<S::Exp> + Send + 'static,
+        X::Key: Clone + std::hash::Hash + Send,
+        S::Exp: 'static,
+    {
+        let visited = BackendVisited::try_new(canon, backend)?;
+        self.visited = Some(Mutex::new(Box::new(visited)));
+        Ok(self)
+    }
+
+    /// Runs the search from `start` until every expression that could
+    /// improve on the best valid expression has been explored or `timer` is
+    /// cut off
+    pub fn run<T, P>(&mut self, timer: &T, start: &S::Exp) -> SearchResult<S>
+    where
+        T: Timer + Sync,
+        P: StepProvider<T, Step = S>,
+        F: Fn() -> P + Sync,
+    {
+        if let Some(v) = &self.visited {
+            v.lock().clear();
+        }
+        let shared = Shared {
+            queues: (0..self.threads).map(|_| Mutex::default()).collect(),
+            pending: AtomicUsize::new(1),
+            best: Mutex::new(None),
+            best_depth: AtomicUsize::new(usize::MAX),
+            cancelled: AtomicBool::new(false),
+            cut_off: AtomicBool::new(false),
+            explored: AtomicUsize::new(0),
+            checker: &*self.checker,
+            visited: self.visited.as_ref(),
+        };
+        shared.queues[0].lock().push_back(Node {
+            exp: start.clone(),
+            path: vec![],
+        });
+        std::thread::scope(|scope| {
+            for i in 0..self.threads {
+                let shared = &shared;
+                let factory = &self.factory;
+                scope.spawn(move || {
+                    let mut provider = factory();
+                    provider.on_session_start(start);
+                    shared.work(i, timer, &mut provider);
+                });
+            }
+        });
+        SearchResult {
+            best: shared.best.lock().take(),
+            cut_off: shared.cut_off.into_inner(),
+            explored: shared.explored.into_inner(),
+        }
+    }
+}
+
+/// The state shared by the threads of a search
+struct Shared<'a, S: Step> {
+    queues: Vec<Mutex<VecDeque<Node<S>>>>,
+    /// The number of nodes that have been queued but not fully processed
+    pending: AtomicUsize,
+    best: Mutex<Option<(S::Exp, Vec<S>)>>,
+    best_depth: AtomicUsize,
+    cancelled: AtomicBool,
+    cut_off: AtomicBool,
+    explored: AtomicUsize,
+    checker: &'a (dyn ValidityChecker<Exp = S::Exp> + Sync),
+    visited: Option<&'a SharedVisited<S::Exp>>,
+}
+
+impl<S> Shared<'_, S>
+where
+    S: Step + Clone + Send,
+    S::Exp: Send,
+{
+    /// Takes a node from the back of the queue of thread `i`, or else steals
+    /// one from the front of the queue of another thread
+    fn take(&self, i: usize) -> Option<Node<S>> {
+        if let Some(node) = self.queues[i].lock().pop_back() {
+            return Some(node);
+        }
+        let n = self.queues.len();
+        (1..n).find_map(|k| self.queues[(i + k) % n].lock().pop_front())
+    }
+
+    fn cancel(&self, cut_off: bool) {
+        if cut_off {
+            self.cut_off.store(true, Ordering::Relaxed);
+        }
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    fn work<T: Timer>(
+        &self,
+        i: usize,
+        timer: &T,
+        provider: &mut impl StepProvider<T, Step = S>,
+    ) {
+        while !self.cancelled.load(Ordering::Relaxed) {
+            let Some(node) = self.take(i) else {
+                if self.pending.load(Ordering::Acquire) == 0 {
+                    return;
+                }
+                std::thread::yield_now();
+                continue;
+            };
+            self.process(i, timer, provider, node);
+            self.pending.fetch_sub(1, Ordering::Release);
+        }
+    }
+
+    fn process<T: Timer>(
+        &self,
+        i: usize,
+        timer: &T,
+        provider: &mut impl StepProvider<T, Step = S>,
+        node: Node<S>,
+    ) {
+        if timer.tick().is_err() {
+            return self.cancel(true);
+        }
+        let depth = node.path.len();
+        if depth >= self.best_depth.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Some(v) = self.visited
+            && !v.lock().visit(&node.exp, depth)
+        {
+            return;
+        }
+        self.explored.fetch_add(1, Ordering::Relaxed);
+        if self.checker.check(&node.exp) {
+            let mut best = self.best.lock();
+            if depth < self.best_depth.load(Ordering::Relaxed) {
+                self.best_depth.store(depth, Ordering::Relaxed);
+                *best = Some((node.exp, node.path));
+            }
+            return;
+        }
+        if depth + 1 >= self.best_depth.load(Ordering::Relaxed) {
+            return;
+        }
+        let steps = match provider.try_provide(timer, &node.exp) {
+            Ok(steps) => steps,
+            Err(ProviderError::Cutoff(_)) => return self.cancel(true),
+            Err(ProviderError::Failure(_)) => return,
+        };
+        let results = S::apply_all(&steps, &node.exp);
+        let children: Vec<_> = steps
+            .into_iter()
+            .zip(results)
+            .rev()
+            .filter_map(|(step, exp)| {
+                let exp = exp?;
+                let mut path = node.path.clone();
+                path.push(step);
+                Some(Node { exp, path })
+            })
+            .collect();
+        self.pending.fetch_add(children.len(), Ordering::AcqRel);
+        self.queues[i].lock().extend(children);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{AllEqual, Fill, Fills, Slots, budget, empty};
+
+    #[test]
+    fn out_of_range_backends_are_rejected() {
+        let search = || {
+            ParallelSearch::<Fill, _>::new(
+                || Fills { digits: 2 },
+                AllEqual(1),
+                2,
+            )
+        };
+        let canon = |e: &Slots| *e;
+        let bloom = |false_positive_rate| VisitedBackend::Bloom {
+            capacity: 64,
+            false_positive_rate,
+        };
+        assert!(search().try_with_canon_backend(canon, bloom(1.5)).is_err());
+        let mut search =
+            search().try_with_canon_backend(canon, bloom(0.01)).unwrap();
+        let result = search.run(&budget(), &empty());
+        assert!(!result.cut_off);
+        assert_eq!(result.best.unwrap().0, [Some(1); 3]);
+    }
+}
@@ -0,0 +1,245 @@
+//! Multi-phase navigation
+//!
+//! Some navigation sessions naturally proceed through named phases (e.g.,
+//! "choose skeleton" and then "fill holes"), each best served by its own step
+//! provider. A [`PhasedProvider`] delegates to the provider of the current
+//! phase, which is the first phase whose completion predicate does not hold
+//! on the working expression. Because the phase is determined by the
+//! expression alone, it stays coherent across undo.
+
+use crate::{
+    Annotation, Capabilities, Context, Feedback, InvalidArgument, Progress,
+    ProgressReporter, ProviderError, Step, StepProvider, Timer,
+};
+
+use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// A predicate that determines whether a phase is complete
+pub type Completion<E> = Box<dyn Fn(&E) -> bool>;
+
+/// A named phase of navigation
+pub struct Phase<T: Timer, S: Step> {
+    name: String,
+    provider: Box<dyn StepProvider<T, Step = S>>,
+    complete: Completion<S::Exp>,
+}
+
+impl<T: Timer, S: Step> Phase<T, S> {
+    /// Creates a new phase that uses `provider` until `complete` holds on the
+    /// working expression
+    pub fn new(
+        name: impl Into<String>,
+        provider: impl StepProvider<T, Step = S> + 'static,
+        complete: impl Fn(&S::Exp) -> bool + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            provider: Box::new(provider),
+            complete: Box::new(complete),
+        }
+    }
+
+    /// Returns the name of the phase
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// A handle for observing the current phase of a [`PhasedProvider`]
+#[derive(Clone)]
+pub struct PhaseHandle {
+    names: Arc<[String]>,
+    current: Arc<AtomicUsize>,
+}
+
+impl PhaseHandle {
+    /// Returns the index of the current phase
+    pub fn index(&self) -> usize {
+        self.current.load(Ordering::Relaxed)
+    }
+
+    /// Returns the name of the current phase
+    pub fn name(&self) -> &str {
+        &self.names[self.index()]
+    }
+}
+
+/// A step provider that delegates to the provider of the current phase
+///
+/// The current phase is the first phase whose completion predicate does not
+/// hold on the working expression (or the last phase, if all of them hold).
+/// Whenever the phase changes, it is reported as [`Progress::phase`].
+pub struct PhasedProvider<T: Timer, S: Step> {
+    phases: Vec<Phase<T, S>>,
+    current: Arc<AtomicUsize>,
+    names: Arc<[String]>,
+    progress: ProgressReporter,
+}
+
+impl<T: Timer, S: Step> PhasedProvider<T, S> {
+    /// Creates a new [`PhasedProvider`] from a (nonempty) list of phases in
+    /// order
+    ///
+    /// # Panics
+    ///
+    /// Panics if `phases` is empty (see [`Self::try_new`])
+    #[track_caller]
+    pub fn new(phases: Vec<Phase<T, S>>) -> Self {
+        InvalidArgument::unwrap(Self::try_new(phases))
+    }
+
+    /// Creates a new [`PhasedProvider`] (see [`Self::new`]), or fails if
+    /// `phases` is empty
+    pub fn try_new(phases: Vec<Phase<T, S>>) -> Result<Self, InvalidArgument> {
+        InvalidArgument::check(
+            !phases.is_empty(),
+            "at least one phase is required",
+            || {
+                let names = phases.iter().map(|p| p.name.clone()).collect();
+                Self {
+                    phases,
+                    current: Arc::new(AtomicUsize::new(0)),
+                    names,
+                    progress: ProgressReporter::new(),
+                }
+            },
+        )
+    }
+
+    /// Returns a handle for observing the current phase
+    pub fn handle(&self) -> PhaseHandle {
+        PhaseHandle {
+            names: self.names.clone(),
+            current: self.current.clone(),
+        }
+    }
+
+    /// Returns the index of the phase for an expression
+    pub fn phase_of(&self, e: &S::Exp) -> usize {
+        self.phases
+            .iter()
+            .position(|p| !(p.complete)(e))
+            .unwrap_or(self.phases.len() - 1)
+    }
+
+    /// Updates the current phase for `e` and returns its provider
+    fn enter(&mut self, e: &S::Exp) -> &mut Box<dyn StepProvider<T, Step = S>> {
+        let i = self.phase_of(e);
+        if self.current.swap(i, Ordering::Relaxed) != i {
+            self.progress
+                .report(Progress::phase(self.phases[i].name.clone()));
+        }
+        &mut self.phases[i].provider
+    }
+}
+
+impl<T: Timer, S: Step> StepProvider<T> for PhasedProvider<T, S> {
+    type Step = S;
+
+    fn provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        self.enter(e).provide(timer, e)
+    }
+
+    fn provide_partial(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> (Vec<Self::Step>, Option<T::EarlyCutoff>) {
+        self.enter(e).provide_partial(timer, e)
+    }
+
+    fn try_provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, ProviderError<T::EarlyCutoff>> {
+        self.enter(e).try_provide(timer, e)
+    }
+
+    fn provide_annotated(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+        annotations: &[Annotation],
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        self.enter(e).provide_annotated(timer, e, annotations)
+    }
+
+    fn capabilities(&mut self) -> Capabilities {
+        crate::combined_capabilities(
+            self.phases.iter_mut().map(|p| &mut p.provider),
+        )
+    }
+
+    fn on_session_start(&mut self, start: &<Self::Step as Step>::Exp) {
+        for p in &mut self.phases {
+            p.provider.on_session_start(start);
+        }
+        self.enter(start);
+    }
+
+    fn on_decide(&mut self, step: &Self::Step, e: &<Self::Step as Step>::Exp) {
+        for p in &mut self.phases {
+            p.provider.on_decide(step, e);
+        }
+        self.enter(e);
+    }
+
+    fn on_undo(&mut self, e: &<Self::Step as Step>::Exp) {
+        for p in &mut self.phases {
+            p.provider.on_undo(e);
+        }
+        self.enter(e);
+    }
+
+    fn on_external_edit(
+        &mut self,
+        prev: &<Self::Step as Step>::Exp,
+        e: &<Self::Step as Step>::Exp,
+    ) {
+        for p in &mut self.phases {
+            p.provider.on_external_edit(prev, e);
+        }
+        self.enter(e);
+    }
+
+    fn on_refine(
+        &mut self,
+        valid: &dyn Fn(&<Self::Step as Step>::Exp) -> bool,
+    ) {
+        for p in &mut self.phases {
+            p.provider.on_refine(valid);
+        }
+    }
+
+    fn on_feedback(&mut self, step: &Self::Step, feedback: Feedback) {
+        for p in &mut self.phases {
+            p.provider.on_feedback(step, feedback);
+        }
+    }
+
+    fn set_progress(&mut self, reporter: ProgressReporter) {
+        for p in &mut self.phases {
+            p.provider.set_progress(reporter.clone());
+        }
+        self.progress = reporter;
+    }
+
+    fn set_context(&mut self, context: Context) {
+        for p in &mut self.phases {
+            p.provider.set_context(context.clone());
+        }
+    }
+
+    fn warmup(&mut self, timer: &T) -> Result<(), T::EarlyCutoff> {
+        crate::warmup_all(
+            self.phases.iter_mut().map(|p| &mut p.provider),
+            timer,
+        )
+    }
+}
@@ -0,0 +1,250 @@
+//! Rule-based automated step selection
+//!
+//! A [`RulePolicy`] is a [`Decider`] assembled from simple rules, either with
+//! a builder or from a tiny text language (see [`RulePolicy::parse`]), so that
+//! automation can be configured without writing a new decider for every
+//! heuristic tweak. Rules match steps by their [`DescribedStep`] category or
+//! description, or by their [`Step::source`](crate::Step::source):
+//!
+//! ```text
+//! never category destructive
+//! prefer label "map"
+//! avoid source slow-provider
+//! lowest cost
+//! ```
+//!
+//! Steps matched by a `never` rule are never decided on. Among the remaining
+//! steps, the policy decides on the best one according to the other rules,
+//! compared in order (so later rules break ties of earlier ones), and then
+//! according to the order in which the steps were provided.
+
+use crate::{Decider, DescribedStep};
+
+use alloc::{
+    borrow::ToOwned, collections::BTreeMap, string::String, sync::Arc, vec::Vec,
+};
+use core::cmp::Ordering;
+
+/// A numeric measure of steps (e.g., their cost)
+pub type Metric<S> = Arc<dyn Fn(&S) -> f64>;
+
+/// A way of matching steps
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Matcher {
+    /// Steps in a category (see [`DescribedStep::category`])
+    Category(String),
+    /// Steps whose description contains a string (see
+    /// [`DescribedStep::description`])
+    Label(String),
+    /// Steps from a source (see [`Step::source`](crate::Step::source))
+    Source(String),
+}
+
+impl Matcher {
+    /// Returns whether or not the step matches
+    pub fn matches<S: DescribedStep>(&self, step: &S) -> bool {
+        match self {
+            Matcher::Category(c) => {
+                step.category().is_some_and(|sc| sc.name() == c)
+            }
+            Matcher::Label(l) => step.description().contains(l.as_str()),
+            Matcher::Source(s) => step.source() == Some(s.as_str()),
+        }
+    }
+}
+
+enum Criterion<S> {
+    Prefer(Matcher),
+    Avoid(Matcher),
+    Lowest(Metric<S>),
+    Highest(Metric<S>),
+}
+
+impl<S: DescribedStep> Criterion<S> {
+    /// Returns the rank of a step (lower is better)
+    fn rank(&self, step: &S) -> f64 {
+        match self {
+            Criterion::Prefer(m) => (!m.matches(step)) as u8 as f64,
+            Criterion::Avoid(m) => m.matches(step) as u8 as f64,
+            Criterion::Lowest(f) => f(step),
+            Criterion::Highest(f) => -f(step),
+        }
+    }
+}
+
+/// An error in a rule of a [`RulePolicy`] (see [`RulePolicy::parse`])
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleError {
+    /// The (one-based) line of the rule
+    pub line: usize,
+    /// A description of the problem
+    pub message: String,
+}
+
+impl core::fmt::Display for RuleError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "invalid rule on line {}: {}", self.line, self.message)
+    }
+}
+
+impl core::error::Error for RuleError {}
+
+/// A decider that follows a list of rules
+pub struct RulePolicy<S> {
+    never: Vec<Matcher>,
+    criteria: Vec<Criterion<S>>,
+    metrics: BTreeMap<String, Metric<S>>,
+}
+
+impl<S> Default for RulePolicy<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> RulePolicy<S> {
+    /// Creates a new [`RulePolicy`] (which decides on the first provided
+    /// step)
+    pub fn new() -> Self {
+        Self {
+            never: Vec::new(),
+            criteria: Vec::new(),
+            metrics: BTreeMap::new(),
+        }
+    }
+
+    /// Never decides on steps matched by `m`
+    pub fn never(mut self, m: Matcher) -> Self {
+        self.never.push(m);
+        self
+    }
+
+    /// Prefers steps matched by `m`
+    pub fn prefer(mut self, m: Matcher) -> Self {
+        self.criteria.push(Criterion::Prefer(m));
+        self
+    }
+
+    /// Prefers steps not matched by `m`
+    pub fn avoid(mut self, m: Matcher) -> Self {
+        self.criteria.push(Criterion::Avoid(m));
+        self
+    }
+
+    /// Prefers steps with lower values of a metric
+    pub fn lowest(mut self, metric: impl Fn(&S) -> f64 + 'static) -> Self {
+        self.criteria.push(Criterion::Lowest(Arc::new(metric)));
+        self
+    }
+
+    /// Prefers steps with higher values of a metric
+    pub fn highest(mut self, metric: impl Fn(&S) -> f64 + 'static) -> Self {
+        self.criteria.push(Criterion::Highest(Arc::new(metric)));
+        self
+    }
+
+    /// Names a metric so that rules parsed afterward (see [`Self::parse`])
+    /// can refer to it
+    pub fn with_metric(
+        mut self,
+        name: &str,
+        metric: impl Fn(&S) -> f64 + 'static,
+    ) -> Self {
+        self.metrics.insert(name.to_owned(), Arc::new(metric));
+        self
+    }
+
+    /// Adds the rules of a text, one per line (or separated by semicolons)
+    ///
+    /// Each rule is one of the following (where `<matcher>` is `category`,
+    /// `label`, or `source` followed by a name, which can be quoted):
+    ///
+    /// - `never <matcher>`
+    /// - `prefer <matcher>`
+    /// - `avoid <matcher>`
+    /// - `lowest <metric>` or `highest <metric>`, for a metric named with
+    ///   [`Self::with_metric`]
+    ///
+    /// Blank lines and lines starting with `#` are ignored.
+    pub fn parse(mut self, rules: &str) -> Result<Self, RuleError> {
+        for (i, line) in rules.lines().enumerate() {
+            for rule in line.split(';') {
+                let rule = rule.trim();
+                if rule.is_empty() || rule.starts_with('#') {
+                    continue;
+                }
+                let error = |message: &str| RuleError {
+                    line: i + 1,
+                    message: message.to_owned(),
+                };
+                let (verb, rest) =
+                    rule.split_once(char::is_whitespace).unwrap_or((rule, ""));
+                let rest = rest.trim();
+                let m = || {
+                    matcher(rest).ok_or_else(|| {
+                        error("expected category, label, or source")
+                    })
+                };
+                self = match verb {
+                    "never" => self.never(m()?),
+                    "prefer" => self.prefer(m()?),
+                    "avoid" => self.avoid(m()?),
+                    "lowest" | "highest" => {
+                        let metric = self
+                            .metrics
+                            .get(unquote(rest))
+                            .ok_or_else(|| error("unknown metric"))?
+                            .clone();
+                        self.criteria.push(if verb == "lowest" {
+                            Criterion::Lowest(metric)
+                        } else {
+                            Criterion::Highest(metric)
+                        });
+                        self
+                    }
+                    _ => return Err(error("unknown rule")),
+                };
+            }
+        }
+        Ok(self)
+    }
+}
+
+fn unquote(s: &str) -> &str {
+    s.strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .unwrap_or(s)
+}
+
+fn matcher(s: &str) -> Option<Matcher> {
+    let (kind, name) = s.split_once(char::is_whitespace)?;
+    let name = unquote(name.trim()).to_owned();
+    match kind {
+        "category" => Some(Matcher::Category(name)),
+        "label" => Some(Matcher::Label(name)),
+        "source" => Some(Matcher::Source(name)),
+        _ => None,
+    }
+}
+
+impl<S: DescribedStep> Decider<S> for RulePolicy<S> {
+    fn decide(&mut self, _e: &S::Exp, steps: &[S]) -> Option<usize> {
+        let ranks = |step: &S| -> Vec<f64> {
+            self.criteria.iter().map(|c| c.rank(step)).collect()
+        };
+        steps
+            .iter()
+            .enumerate()
+            .filter(|(_, step)| !self.never.iter().any(|m| m.matches(*step)))
+            .map(|(i, step)| (ranks(step), i))
+            .min_by(|(a, i), (b, j)| {
+                a.iter()
+                    .zip(b)
+                    .map(|(x, y)| x.total_cmp(y))
+                    .find(|o| o.is_ne())
+                    .unwrap_or(Ordering::Equal)
+                    .then(i.cmp(j))
+            })
+            .map(|(_, i)| i)
+    }
+}
@@ -0,0 +1,452 @@
+//! Speculative prefetching of provided step sets
+//!
+//! A [`Prefetcher`] wraps a step provider and, whenever a step is decided on,
+//! speculatively computes the provided steps for the new working expression
+//! (and for the expressions reached by its most likely next steps) on a
+//! background thread. If the decider then picks a predicted step, the next
+//! call to [`StepProvider::provide`] is answered from the cache. If the
+//! wrapped provider is a [`BatchStepProvider`], the predicted expressions are
+//! prefetched in one call.
+//!
+//! A request for an expression that is not cached waits for the computation
+//! in flight on the background thread (which may be for that expression)
+//! rather than cancelling it, and the background thread skips expressions
+//! that are already cached.
+
+use crate::{
+    BatchStepProvider, CancelToken, Capabilities, Context, Feedback,
+    IncrementalStepProvider, ProgressReporter, ProviderError, Step,
+    StepProvider, Timer, sync::Mutex,
+};
+
+use std::collections::VecDeque;
+use std::sync::{Arc, MutexGuard};
+use std::thread::JoinHandle;
+
+type Boxed<T, S> = Box<dyn StepProvider<T, Step = S> + Send>;
+type Inner<T, S> = Arc<Mutex<Boxed<T, S>>>;
+type Cache<S> = Arc<Mutex<VecDeque<(<S as Step>::Exp, Vec<S>)>>>;
+
+fn lookup<S: Step + Clone>(cache: &Cache<S>, e: &S::Exp) -> Option<Vec<S>>
+where
+    S::Exp: PartialEq,
+{
+    cache
+        .lock()
+        .iter()
+        .find(|(e2, _)| e2 == e)
+        .map(|(_, steps)| steps.clone())
+}
+
+/// A function that creates the timer (budget) for a background prefetch that
+/// should stop once the given token is cancelled
+pub type PrefetchTimer<T> = Arc<dyn Fn(&CancelToken) -> T + Send + Sync>;
+
+/// A function that returns the indexes of the steps (provided for an
+/// expression) that are most likely to be decided on next, most likely first
+pub type Predictor<S> =
+    Arc<dyn Fn(&<S as Step>::Exp, &[S]) -> Vec<usize> + Send + Sync>;
+
+/// A step provider wrapper that speculatively prefetches provided step sets on
+/// a background thread
+pub struct Prefetcher<T: Timer, S: Step> {
+    inner: Inner<T, S>,
+    cache: Cache<S>,
+    capacity: usize,
+    width: usize,
+    prefetch_timer: PrefetchTimer<T>,
+    predictor: Predictor<S>,
+    background: Option<(CancelToken, JoinHandle<()>)>,
+}
+
+impl<T, S> Prefetcher<T, S>
+where
+    T: Timer + Send + 'static,
+    S: Step + Clone + Send + 'static,
+    S::Exp: PartialEq + Send,
+{
+    /// Creates a new [`Prefetcher`] that, after each decision, prefetches the
+    /// provided steps for the new working expression and for the results of
+    /// (at most) `width` of its provided steps
+    ///
+    /// By default, the first `width` provided steps are predicted to be the
+    /// most likely (see [`Self::with_predictor`]).
+    pub fn new(
+        inner: impl StepProvider<T, Step = S> + Send + 'static,
+        prefetch_timer: impl Fn(&CancelToken) -> T + Send + Sync + 'static,
+        width: usize,
+    ) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Box::new(inner))),
+            cache: Arc::new(Mutex::new(VecDeque::new())),
+            capacity: 4 * (width + 1),
+            width,
+            prefetch_timer: Arc::new(prefetch_timer),
+            predictor: Arc::new(|_, steps| (0..steps.len()).collect()),
+            background: None,
+        }
+    }
+
+    /// Sets the function used to predict the most likely next steps
+    pub fn with_predictor(
+        mut self,
+        predictor: impl Fn(&S::Exp, &[S]) -> Vec<usize> + Send + Sync + 'static,
+    ) -> Self {
+        self.predictor = Arc::new(predictor);
+        self
+    }
+
+    fn spawn(&mut self, e: S::Exp) {
+        let token = CancelToken::new();
+        let inner = self.inner.clone();
+        let cache = self.cache.clone();
+        let capacity = self.capacity;
+        let width = self.width;
+        let timer = (self.prefetch_timer)(&token);
+        let predictor = self.predictor.clone();
+        let t = token.clone();
+        let handle = std::thread::spawn(move || {
+            // The inner provider is locked for each computation (and until
+            // its result is cached), so that a request for an expression that
+            // is not cached can wait for the computation in flight
+            let insert = |e: S::Exp, steps: Vec<S>| {
+                let mut cache = cache.lock();
+                if cache.len() >= capacity {
+                    cache.pop_front();
+                }
+                cache.push_back((e, steps));
+            };
+            let steps = match lookup(&cache, &e) {
+                Some(steps) => steps,
+                None => {
+                    let mut inner = inner.lock();
+                    let Ok(steps) = inner.provide(&timer, &e) else {
+                        return;
+                    };
+                    insert(e.clone(), steps.clone());
+                    steps
+                }
+            };
+            let predicted: Vec<S::Exp> = predictor(&e, &steps)
+                .into_iter()
+                .take(width)
+                .filter_map(|i| steps.get(i).and_then(|s| s.apply(&e)))
+                .filter(|e2| lookup(&cache, e2).is_none())
+                .collect();
+            if predicted.is_empty() || t.is_cancelled() {
+                return;
+            }
+            let mut batch = inner.lock();
+            if let Some(p) = batch.as_batch() {
+                if let Ok(provided) = p.provide_batch(&timer, &predicted) {
+                    for (e2, steps2) in predicted.into_iter().zip(provided) {
+                        insert(e2, steps2);
+                    }
+                }
+                return;
+            }
+            drop(batch);
+            for e2 in predicted {
+                if t.is_cancelled() {
+                    return;
+                }
+                if lookup(&cache, &e2).is_some() {
+                    continue;
+                }
+                let mut inner = inner.lock();
+                match inner.provide(&timer, &e2) {
+                    Ok(steps2) => insert(e2, steps2),
+                    Err(_) => return,
+                }
+            }
+        });
+        self.background = Some((token, handle));
+    }
+}
+
+impl<T: Timer, S: Step> Prefetcher<T, S>
+where
+    S: Clone,
+    S::Exp: PartialEq,
+{
+    /// Returns the cached steps for `e` if there are any (after waiting for
+    /// the computation in flight), and the locked inner provider otherwise
+    fn cached(
+        &self,
+        e: &S::Exp,
+    ) -> Result<Vec<S>, MutexGuard<'_, Boxed<T, S>>> {
+        if let Some(steps) = lookup(&self.cache, e) {
+            return Ok(steps);
+        }
+        let inner = self.inner.lock();
+        match lookup(&self.cache, e) {
+            Some(steps) => Ok(steps),
+            None => Err(inner),
+        }
+    }
+}
+
+impl<T: Timer, S: Step> Prefetcher<T, S> {
+    fn cancel(&mut self) {
+        if let Some((token, handle)) = self.background.take() {
+            token.cancel();
+            let _ = handle.join();
+        }
+    }
+}
+
+impl<T: Timer, S: Step> Drop for Prefetcher<T, S> {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+impl<T, S> StepProvider<T> for Prefetcher<T, S>
+where
+    T: Timer + Send + 'static,
+    S: Step + Clone + Send + 'static,
+    S::Exp: PartialEq + Send,
+{
+    type Step = S;
+
+    fn provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        match self.cached(e) {
+            Ok(steps) => Ok(steps),
+            Err(mut inner) => inner.provide(timer, e),
+        }
+    }
+
+    fn try_provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, ProviderError<T::EarlyCutoff>> {
+        match self.cached(e) {
+            Ok(steps) => Ok(steps),
+            Err(mut inner) => inner.try_provide(timer, e),
+        }
+    }
+
+    fn capabilities(&mut self) -> Capabilities {
+        Capabilities {
+            partial: false,
+            annotated: false,
+            ..self.inner.lock().capabilities()
+        }
+    }
+
+    fn as_incremental(
+        &mut self,
+    ) -> Option<&mut dyn IncrementalStepProvider<T, Step = Self::Step>> {
+        self.inner.lock().as_incremental()?;
+        Some(self)
+    }
+
+    fn as_batch(
+        &mut self,
+    ) -> Option<&mut dyn BatchStepProvider<T, Step = Self::Step>> {
+        self.inner.lock().as_batch()?;
+        Some(self)
+    }
+
+    fn on_session_start(&mut self, start: &<Self::Step as Step>::Exp) {
+        self.cancel();
+        self.cache.lock().clear();
+        self.inner.lock().on_session_start(start);
+    }
+
+    fn on_decide(&mut self, step: &Self::Step, e: &<Self::Step as Step>::Exp) {
+        self.cancel();
+        self.inner.lock().on_decide(step, e);
+        self.spawn(e.clone());
+    }
+
+    fn on_undo(&mut self, e: &<Self::Step as Step>::Exp) {
+        self.cancel();
+        self.cache.lock().clear();
+        self.inner.lock().on_undo(e);
+    }
+
+    fn on_external_edit(
+        &mut self,
+        prev: &<Self::Step as Step>::Exp,
+        e: &<Self::Step as Step>::Exp,
+    ) {
+        self.cancel();
+        self.cache.lock().clear();
+        self.inner.lock().on_external_edit(prev, e);
+    }
+
+    fn on_refine(
+        &mut self,
+        valid: &dyn Fn(&<Self::Step as Step>::Exp) -> bool,
+    ) {
+        self.cancel();
+        self.cache.lock().clear();
+        self.inner.lock().on_refine(valid);
+    }
+
+    fn on_feedback(&mut self, step: &Self::Step, feedback: Feedback) {
+        self.cancel();
+        self.cache.lock().clear();
+        self.inner.lock().on_feedback(step, feedback);
+    }
+
+    fn set_progress(&mut self, reporter: ProgressReporter) {
+        self.inner.lock().set_progress(reporter);
+    }
+
+    fn set_context(&mut self, context: Context) {
+        self.cancel();
+        self.cache.lock().clear();
+        self.inner.lock().set_context(context);
+    }
+
+    fn warmup(&mut self, timer: &T) -> Result<(), T::EarlyCutoff> {
+        self.inner.lock().warmup(timer)
+    }
+}
+
+impl<T, S> IncrementalStepProvider<T> for Prefetcher<T, S>
+where
+    T: Timer + Send + 'static,
+    S: Step + Clone + Send + 'static,
+    S::Exp: PartialEq + Send,
+{
+    fn provide_incremental(
+        &mut self,
+        timer: &T,
+        prev: &<Self::Step as Step>::Exp,
+        step: &Self::Step,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        let mut inner = match self.cached(e) {
+            Ok(steps) => return Ok(steps),
+            Err(inner) => inner,
+        };
+        match inner.as_incremental() {
+            Some(p) => p.provide_incremental(timer, prev, step, e),
+            None => inner.provide(timer, e),
+        }
+    }
+}
+
+impl<T, S> BatchStepProvider<T> for Prefetcher<T, S>
+where
+    T: Timer + Send + 'static,
+    S: Step + Clone + Send + 'static,
+    S::Exp: PartialEq + Send,
+{
+    /// Provides the steps for each expression, requesting the ones that are
+    /// not cached from the wrapped provider in one call
+    fn provide_batch(
+        &mut self,
+        timer: &T,
+        es: &[<Self::Step as Step>::Exp],
+    ) -> Result<Vec<Vec<Self::Step>>, ProviderError<T::EarlyCutoff>> {
+        let mut step_sets: Vec<Option<Vec<S>>> =
+            es.iter().map(|e| self.cached(e).ok()).collect();
+        let missing: Vec<S::Exp> = es
+            .iter()
+            .zip(&step_sets)
+            .filter(|(_, steps)| steps.is_none())
+            .map(|(e, _)| e.clone())
+            .collect();
+        if !missing.is_empty() {
+            let mut inner = self.inner.lock();
+            let provided = match inner.as_batch() {
+                Some(p) => p.provide_batch(timer, &missing)?,
+                None => missing
+                    .iter()
+                    .map(|e| inner.try_provide(timer, e))
+                    .collect::<Result<_, _>>()?,
+            };
+            let mut provided = provided.into_iter();
+            for steps in step_sets.iter_mut().filter(|s| s.is_none()) {
+                *steps = provided.next();
+            }
+        }
+        Ok(step_sets
+            .into_iter()
+            .map(Option::unwrap_or_default)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StepEnumerator;
+    use crate::budget::{OutOfBudget, TickBudget};
+    use crate::testing::{Fill, Fills, Slots, budget, empty};
+
+    use std::sync::Mutex as StdMutex;
+    use std::time::Duration;
+
+    /// Provides fills, logging every expression it is called on
+    struct Logged(Arc<StdMutex<Vec<Slots>>>);
+
+    impl StepProvider<TickBudget> for Logged {
+        type Step = Fill;
+
+        fn provide(
+            &mut self,
+            timer: &TickBudget,
+            e: &Slots,
+        ) -> Result<Vec<Fill>, OutOfBudget> {
+            self.0.lock().unwrap().push(*e);
+            Fills { digits: 2 }.provide(timer, e)
+        }
+    }
+
+    fn prefetcher() -> (Prefetcher<TickBudget, Fill>, Arc<StdMutex<Vec<Slots>>>)
+    {
+        let log = Arc::new(StdMutex::new(vec![]));
+        let p = Prefetcher::new(Logged(log.clone()), |_| budget(), 2);
+        (p, log)
+    }
+
+    /// Waits until the cache holds `n` step sets
+    fn wait_for(p: &Prefetcher<TickBudget, Fill>, n: usize) {
+        while p.cache.lock().len() < n {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn calls(log: &StdMutex<Vec<Slots>>, e: &Slots) -> usize {
+        log.lock().unwrap().iter().filter(|e2| *e2 == e).count()
+    }
+
+    #[test]
+    fn predicted_expressions_are_answered_from_the_cache() {
+        let (mut p, log) = prefetcher();
+        let e = [Some(1), None, None];
+        p.on_decide(&Fill(0, 1), &e);
+        // The new working expression and the results of its first two steps
+        wait_for(&p, 3);
+        let predicted = [Some(1), Some(0), None];
+        for e in [e, predicted] {
+            let steps = p.provide(&budget(), &e).unwrap();
+            assert_eq!(steps, Fills { digits: 2 }.enumerate(&e));
+            assert_eq!(calls(&log, &e), 1);
+        }
+        p.provide(&budget(), &[Some(1), None, Some(0)]).unwrap();
+        assert_eq!(log.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn undoing_clears_the_cache() {
+        let (mut p, log) = prefetcher();
+        let e = [Some(1), None, None];
+        p.on_decide(&Fill(0, 1), &e);
+        wait_for(&p, 3);
+        p.on_undo(&empty());
+        assert!(p.cache.lock().is_empty());
+        p.provide(&budget(), &e).unwrap();
+        assert_eq!(calls(&log, &e), 2);
+    }
+}
@@ -0,0 +1,382 @@
+//! Profiling of step provider pipelines
+//!
+//! Deep stacks of combinators (see, e.g.,
+//! [`CompoundProvider`](crate::CompoundProvider) and
+//! [`FallbackProvider`](crate::FallbackProvider)) make it hard to tell which
+//! sub-provider is slow. A [`Profiler`] describes the combinator tree as a
+//! tree of [`ProfileHandle`]s; wrapping each provider of the tree in the
+//! [`ProfiledProvider`] of its handle attributes time, step counts, cutoffs,
+//! and (optionally) allocations to it. The resulting [`ProviderProfile`]
+//! prints as a flame-style text tree.
+//!
+//! Allocations are only counted if [`CountingAllocator`] is installed as the
+//! global allocator; since the count is global, allocations made by other
+//! threads during a call are attributed to it too.
+
+use crate::{
+    Annotation, Capabilities, Context, Feedback, ProgressReporter,
+    ProviderError, Step, StepProvider, Timer, sync::Mutex,
+};
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// A global allocator that counts allocations (for use with a [`Profiler`])
+///
+/// Install it with `#[global_allocator]`:
+///
+/// ```ignore
+/// #[global_allocator]
+/// static ALLOC: pbn::profile::CountingAllocator =
+///     pbn::profile::CountingAllocator;
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CountingAllocator;
+
+impl CountingAllocator {
+    /// Returns the number of allocations made so far
+    pub fn allocations() -> usize {
+        ALLOCATIONS.load(Ordering::Relaxed)
+    }
+}
+
+// SAFETY: all allocation is delegated to the system allocator
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        // SAFETY: the caller upholds the contract of `GlobalAlloc::alloc`
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: the caller upholds the contract of `GlobalAlloc::dealloc`
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(
+        &self,
+        ptr: *mut u8,
+        layout: Layout,
+        new_size: usize,
+    ) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        // SAFETY: the caller upholds the contract of `GlobalAlloc::realloc`
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+/// The statistics recorded for a node of the provider tree
+#[derive(Debug, Default)]
+struct Stats {
+    name: String,
+    parent: Option<usize>,
+    calls: usize,
+    time: Duration,
+    steps: usize,
+    cutoffs: usize,
+    allocations: usize,
+}
+
+/// A recorder of the statistics of a tree of providers
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    nodes: Arc<Mutex<Vec<Stats>>>,
+}
+
+impl Profiler {
+    /// Creates a new [`Profiler`] (with an empty tree)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn add(&self, name: &str, parent: Option<usize>) -> ProfileHandle {
+        let mut nodes = self.nodes.lock();
+        nodes.push(Stats {
+            name: name.to_owned(),
+            parent,
+            ..Stats::default()
+        });
+        ProfileHandle {
+            profiler: self.clone(),
+            index: nodes.len() - 1,
+        }
+    }
+
+    /// Adds a root to the provider tree
+    pub fn root(&self, name: &str) -> ProfileHandle {
+        self.add(name, None)
+    }
+
+    /// Forgets all recorded statistics (keeping the tree)
+    pub fn reset(&self) {
+        for stats in self.nodes.lock().iter_mut() {
+            *stats = Stats {
+                name: core::mem::take(&mut stats.name),
+                parent: stats.parent,
+                ..Stats::default()
+            };
+        }
+    }
+
+    /// Returns the statistics recorded so far
+    pub fn report(&self) -> ProviderProfile {
+        let nodes = self.nodes.lock();
+        let counted = CountingAllocator::allocations() > 0;
+        fn build(nodes: &[Stats], i: usize, counted: bool) -> ProfileNode {
+            let children: Vec<_> = (0..nodes.len())
+                .filter(|&j| nodes[j].parent == Some(i))
+                .map(|j| build(nodes, j, counted))
+                .collect();
+            let stats = &nodes[i];
+            let nested = children.iter().map(|c| c.time).sum();
+            ProfileNode {
+                name: stats.name.clone(),
+                calls: stats.calls,
+                time: stats.time,
+                self_time: stats.time.saturating_sub(nested),
+                steps: stats.steps,
+                cutoffs: stats.cutoffs,
+                allocations: counted.then_some(stats.allocations),
+                children,
+            }
+        }
+        ProviderProfile {
+            roots: (0..nodes.len())
+                .filter(|&i| nodes[i].parent.is_none())
+                .map(|i| build(&nodes, i, counted))
+                .collect(),
+        }
+    }
+}
+
+/// A node of the provider tree of a [`Profiler`]
+#[derive(Debug, Clone)]
+pub struct ProfileHandle {
+    profiler: Profiler,
+    index: usize,
+}
+
+impl ProfileHandle {
+    /// Adds a child to this node (e.g., for a sub-provider of a combinator)
+    pub fn child(&self, name: &str) -> ProfileHandle {
+        self.profiler.add(name, Some(self.index))
+    }
+
+    /// Wraps the provider of this node
+    pub fn wrap<P>(&self, inner: P) -> ProfiledProvider<P> {
+        ProfiledProvider {
+            inner,
+            handle: self.clone(),
+        }
+    }
+
+    fn record(&self, start: (Instant, usize), steps: usize, cut_off: bool) {
+        let time = start.0.elapsed();
+        let allocations = CountingAllocator::allocations() - start.1;
+        let mut nodes = self.profiler.nodes.lock();
+        let stats = &mut nodes[self.index];
+        stats.calls += 1;
+        stats.time += time;
+        stats.steps += steps;
+        stats.cutoffs += cut_off as usize;
+        stats.allocations += allocations;
+    }
+}
+
+/// A step provider wrapper that records statistics for a node of a
+/// [`Profiler`]
+pub struct ProfiledProvider<P> {
+    inner: P,
+    handle: ProfileHandle,
+}
+
+impl<P> ProfiledProvider<P> {
+    /// Returns the inner provider and drops self
+    pub fn into_inner(self) -> P {
+        self.inner
+    }
+
+    fn profiled<S, X>(
+        &mut self,
+        f: impl FnOnce(&mut P) -> Result<Vec<S>, X>,
+    ) -> Result<Vec<S>, X> {
+        let start = (Instant::now(), CountingAllocator::allocations());
+        let result = f(&mut self.inner);
+        let steps = result.as_ref().map_or(0, Vec::len);
+        self.handle.record(start, steps, result.is_err());
+        result
+    }
+}
+
+impl<T: Timer, P: StepProvider<T>> StepProvider<T> for ProfiledProvider<P> {
+    type Step = P::Step;
+
+    fn provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        self.profiled(|p| p.provide(timer, e))
+    }
+
+    fn provide_partial(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> (Vec<Self::Step>, Option<T::EarlyCutoff>) {
+        let start = (Instant::now(), CountingAllocator::allocations());
+        let (steps, cutoff) = self.inner.provide_partial(timer, e);
+        self.handle.record(start, steps.len(), cutoff.is_some());
+        (steps, cutoff)
+    }
+
+    fn try_provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, ProviderError<T::EarlyCutoff>> {
+        self.profiled(|p| p.try_provide(timer, e))
+    }
+
+    fn provide_annotated(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+        annotations: &[Annotation],
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        self.profiled(|p| p.provide_annotated(timer, e, annotations))
+    }
+
+    fn capabilities(&mut self) -> Capabilities {
+        self.inner.capabilities().forwarded()
+    }
+
+    fn on_session_start(&mut self, start: &<Self::Step as Step>::Exp) {
+        self.inner.on_session_start(start);
+    }
+
+    fn on_decide(&mut self, step: &Self::Step, e: &<Self::Step as Step>::Exp) {
+        self.inner.on_decide(step, e);
+    }
+
+    fn on_undo(&mut self, e: &<Self::Step as Step>::Exp) {
+        self.inner.on_undo(e);
+    }
+
+    fn on_external_edit(
+        &mut self,
+        prev: &<Self::Step as Step>::Exp,
+        e: &<Self::Step as Step>::Exp,
+    ) {
+        self.inner.on_external_edit(prev, e);
+    }
+
+    fn on_refine(
+        &mut self,
+        valid: &dyn Fn(&<Self::Step as Step>::Exp) -> bool,
+    ) {
+        self.inner.on_refine(valid);
+    }
+
+    fn on_feedback(&mut self, step: &Self::Step, feedback: Feedback) {
+        self.inner.on_feedback(step, feedback);
+    }
+
+    fn set_progress(&mut self, reporter: ProgressReporter) {
+        self.inner.set_progress(reporter);
+    }
+
+    fn set_context(&mut self, context: Context) {
+        self.inner.set_context(context);
+    }
+
+    fn warmup(&mut self, timer: &T) -> Result<(), T::EarlyCutoff> {
+        self.inner.warmup(timer)
+    }
+}
+
+/// The statistics of a node of a provider tree
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileNode {
+    /// The name of the node
+    pub name: String,
+    /// The number of calls to the provider
+    pub calls: usize,
+    /// The total time spent in the provider (including its children)
+    pub time: Duration,
+    /// The time spent in the provider outside of its children
+    pub self_time: Duration,
+    /// The total number of steps provided
+    pub steps: usize,
+    /// The number of calls that were cut off (or failed)
+    pub cutoffs: usize,
+    /// The number of allocations made by the provider (including its
+    /// children), if counted (see [`CountingAllocator`])
+    pub allocations: Option<usize>,
+    /// The children of the node
+    pub children: Vec<ProfileNode>,
+}
+
+/// The statistics recorded by a [`Profiler`], one tree per root
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ProviderProfile {
+    /// The roots of the provider tree
+    pub roots: Vec<ProfileNode>,
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+impl ProfileNode {
+    fn write(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        depth: usize,
+        total: Duration,
+    ) -> fmt::Result {
+        let share = if total.is_zero() {
+            0.0
+        } else {
+            self.time.as_secs_f64() / total.as_secs_f64()
+        };
+        write!(
+            f,
+            "{:indent$}{} {:5.1}% {:.3}ms (self {:.3}ms), {} calls, {} steps",
+            "",
+            self.name,
+            share * 100.0,
+            millis(self.time),
+            millis(self.self_time),
+            self.calls,
+            self.steps,
+            indent = 2 * depth,
+        )?;
+        if self.cutoffs > 0 {
+            write!(f, ", {} cutoffs", self.cutoffs)?;
+        }
+        if let Some(allocations) = self.allocations {
+            write!(f, ", {} allocations", allocations)?;
+        }
+        writeln!(f)?;
+        for child in &self.children {
+            child.write(f, depth + 1, total)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ProviderProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.roots.iter().map(|r| r.time).sum();
+        for root in &self.roots {
+            root.write(f, 0, total)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,110 @@
+//! Step providers backed by unverified proposers (e.g., language models)
+//!
+//! A proposer is any procedure that suggests candidate steps without
+//! guaranteeing that they are applicable (or sound). The [`ProposalProvider`]
+//! adapter filters these candidates so that the provided set never contains
+//! steps that fail to apply.
+
+use crate::{ReachabilityOracle, Step, StepProvider, Timer};
+
+use alloc::{boxed::Box, vec, vec::Vec};
+
+/// The reasons a proposed step can be rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    /// The step does not apply to the current expression
+    Inapplicable,
+    /// The step applies, but the oracle reports no reachable valid expression
+    Unsound,
+}
+
+/// A proposer of (unverified) candidate steps
+///
+/// Proposers may call out to arbitrary backends (e.g., a local model or a
+/// remote HTTP service); any such call should tick the timer cooperatively.
+pub type Proposer<T, S> = Box<
+    dyn FnMut(
+        &T,
+        &<S as Step>::Exp,
+    ) -> Result<Vec<S>, <T as Timer>::EarlyCutoff>,
+>;
+
+/// A hook that is called with every rejected proposal
+pub type RejectionHook<S> = Box<dyn FnMut(&<S as Step>::Exp, &S, Rejection)>;
+
+/// A step provider that filters the output of a [`Proposer`] through
+/// [`Step::apply`] and (optionally) a [`ReachabilityOracle`]
+pub struct ProposalProvider<T: Timer, S: Step> {
+    proposer: Proposer<T, S>,
+    oracle: Option<Box<dyn ReachabilityOracle<Exp = S::Exp>>>,
+    on_reject: Option<RejectionHook<S>>,
+}
+
+impl<T: Timer, S: Step> ProposalProvider<T, S> {
+    /// Creates a new [`ProposalProvider`] from a proposer
+    pub fn new(
+        proposer: impl FnMut(&T, &S::Exp) -> Result<Vec<S>, T::EarlyCutoff>
+        + 'static,
+    ) -> Self {
+        Self {
+            proposer: Box::new(proposer),
+            oracle: None,
+            on_reject: None,
+        }
+    }
+
+    /// Additionally filters out proposed steps whose result is deemed dead by
+    /// a reachability oracle
+    pub fn with_oracle(
+        mut self,
+        oracle: impl ReachabilityOracle<Exp = S::Exp> + 'static,
+    ) -> Self {
+        self.oracle = Some(Box::new(oracle));
+        self
+    }
+
+    /// Sets a hook that is called with every rejected proposal (e.g., for
+    /// logging)
+    pub fn on_reject(
+        mut self,
+        hook: impl FnMut(&S::Exp, &S, Rejection) + 'static,
+    ) -> Self {
+        self.on_reject = Some(Box::new(hook));
+        self
+    }
+
+    fn judge(&self, e: &S::Exp, step: &S) -> Option<Rejection> {
+        match step.apply(e) {
+            None => Some(Rejection::Inapplicable),
+            Some(e2) => match &self.oracle {
+                Some(o) if !o.reachable(&e2) => Some(Rejection::Unsound),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl<T: Timer, S: Step> StepProvider<T> for ProposalProvider<T, S> {
+    type Step = S;
+
+    fn provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        let proposals = (self.proposer)(timer, e)?;
+        let mut steps = vec![];
+        for step in proposals {
+            timer.tick()?;
+            match self.judge(e, &step) {
+                None => steps.push(step),
+                Some(r) => {
+                    if let Some(hook) = &mut self.on_reject {
+                        hook(e, &step, r)
+                    }
+                }
+            }
+        }
+        Ok(steps)
+    }
+}
@@ -0,0 +1,182 @@
+//! Multi-objective ranking of valid expressions
+//!
+//! When many valid expressions are reachable, the first one found is rarely
+//! the one the programmer wants. A [`SolutionRanker`] measures each valid
+//! expression against a list of [`Objective`]s (e.g., size, cost, or the
+//! number of decisions needed to reach it) and keeps the [`Pareto`] set of
+//! expressions that no other expression improves on in every objective,
+//! ordered lexicographically by the objectives (in the order they were added).
+
+use alloc::{boxed::Box, vec::Vec};
+use core::cmp::Ordering;
+
+/// An objective to minimize
+pub trait Objective<E> {
+    /// Returns the cost of a valid expression reachable in `depth` decisions
+    /// (lower is better)
+    fn cost(&self, e: &E, depth: usize) -> f64;
+}
+
+impl<E, F: Fn(&E) -> f64> Objective<E> for F {
+    fn cost(&self, e: &E, _depth: usize) -> f64 {
+        self(e)
+    }
+}
+
+/// The objective of reaching a valid expression in as few decisions as
+/// possible
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Depth;
+
+impl<E> Objective<E> for Depth {
+    fn cost(&self, _e: &E, depth: usize) -> f64 {
+        depth as f64
+    }
+}
+
+/// A valid expression with its cost for each objective
+#[derive(Debug, Clone)]
+pub struct Ranked<E> {
+    /// The valid expression
+    pub exp: E,
+    /// The number of decisions needed to reach the expression
+    pub depth: usize,
+    /// The cost of the expression for each objective (in order)
+    pub costs: Vec<f64>,
+}
+
+impl<E> Ranked<E> {
+    /// Returns whether or not `self` is at least as good as `other` in every
+    /// objective and better in at least one
+    pub fn dominates(&self, other: &Self) -> bool {
+        let pairs = || self.costs.iter().zip(&other.costs);
+        pairs().all(|(a, b)| a <= b) && pairs().any(|(a, b)| a < b)
+    }
+
+    fn lexicographic(&self, other: &Self) -> Ordering {
+        self.costs
+            .iter()
+            .zip(&other.costs)
+            .map(|(a, b)| a.total_cmp(b))
+            .find(|o| o.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+/// A set of valid expressions, none of which dominates another, ordered
+/// lexicographically by their costs
+#[derive(Debug, Clone)]
+pub struct Pareto<E> {
+    front: Vec<Ranked<E>>,
+}
+
+impl<E> Default for Pareto<E> {
+    fn default() -> Self {
+        Self { front: Vec::new() }
+    }
+}
+
+impl<E: PartialEq> Pareto<E> {
+    /// Adds a ranked expression unless it is dominated or already in the
+    /// set, removing the expressions it dominates; returns whether or not it
+    /// was added
+    pub fn insert(&mut self, r: Ranked<E>) -> bool {
+        self.front.retain(|other| !r.dominates(other));
+        if self
+            .front
+            .iter()
+            .any(|other| other.exp == r.exp || other.dominates(&r))
+        {
+            return false;
+        }
+        let i = self
+            .front
+            .partition_point(|other| other.lexicographic(&r).is_le());
+        self.front.insert(i, r);
+        true
+    }
+}
+
+impl<E> Pareto<E> {
+    /// Returns the best expression (the lexicographically least; if any)
+    pub fn best(&self) -> Option<&Ranked<E>> {
+        self.front.first()
+    }
+
+    /// Returns the ranked expressions (best first)
+    pub fn as_slice(&self) -> &[Ranked<E>] {
+        &self.front
+    }
+
+    /// Returns the ranked expressions (best first)
+    pub fn into_vec(self) -> Vec<Ranked<E>> {
+        self.front
+    }
+
+    /// Returns the number of expressions in the set
+    pub fn len(&self) -> usize {
+        self.front.len()
+    }
+
+    /// Returns whether or not the set is empty
+    pub fn is_empty(&self) -> bool {
+        self.front.is_empty()
+    }
+}
+
+/// A ranking of valid expressions by a list of objectives
+pub struct SolutionRanker<E> {
+    objectives: Vec<Box<dyn Objective<E>>>,
+}
+
+impl<E> Default for SolutionRanker<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E> SolutionRanker<E> {
+    /// Creates a new [`SolutionRanker`] with no objectives (under which no
+    /// expression dominates another)
+    pub fn new() -> Self {
+        Self {
+            objectives: Vec::new(),
+        }
+    }
+
+    /// Adds an objective, which breaks ties between the objectives added so
+    /// far
+    pub fn with_objective(
+        mut self,
+        objective: impl Objective<E> + 'static,
+    ) -> Self {
+        self.objectives.push(Box::new(objective));
+        self
+    }
+
+    /// Measures a valid expression reachable in `depth` decisions
+    pub fn measure(&self, exp: E, depth: usize) -> Ranked<E> {
+        let costs = self.objectives.iter().map(|o| o.cost(&exp, depth));
+        Ranked {
+            costs: costs.collect(),
+            exp,
+            depth,
+        }
+    }
+
+    /// Ranks valid expressions (each with the number of decisions needed to
+    /// reach it)
+    pub fn rank(
+        &self,
+        solutions: impl IntoIterator<Item = (E, usize)>,
+    ) -> Pareto<E>
+    where
+        E: PartialEq,
+    {
+        let mut pareto = Pareto::default();
+        for (exp, depth) in solutions {
+            pareto.insert(self.measure(exp, depth));
+        }
+        pareto
+    }
+}
@@ -0,0 +1,359 @@
+//! Recording and replaying provider calls for debugging
+//!
+//! A [`RecordingProvider`] records the exact inputs of every call to the
+//! provider it wraps (the expression, a copy of the timer as it was at the
+//! time of the call, and any annotations) along with the outcome. The
+//! [`Recording`] handle can later re-invoke any provider on a recorded input
+//! (e.g., under a debugger) with [`Recording::replay`], and compare the
+//! recorded outcomes with those of a changed provider with
+//! [`Recording::compare`].
+
+use crate::{
+    Annotation, Capabilities, Context, Feedback, ProgressReporter,
+    ProviderError, Step, StepProvider, Timer, sync,
+};
+
+use alloc::{format, string::String, sync::Arc, vec, vec::Vec};
+
+/// The provider method that was called
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Method {
+    /// [`StepProvider::provide`]
+    Provide,
+    /// [`StepProvider::provide_partial`]
+    ProvidePartial,
+    /// [`StepProvider::try_provide`]
+    TryProvide,
+    /// [`StepProvider::provide_annotated`] (with the annotations)
+    ProvideAnnotated(Vec<Annotation>),
+}
+
+/// The outcome of a provider call
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome<S> {
+    /// The provider provided steps
+    Provided(Vec<S>),
+    /// The provider was cut off by the timer (with the steps found so far,
+    /// for [`Method::ProvidePartial`])
+    CutOff(Vec<S>),
+    /// The provider failed (with the description of the failure)
+    Failed(String),
+}
+
+/// A recorded provider call
+pub struct ProvideCall<T, S: Step> {
+    /// The method that was called
+    pub method: Method,
+    /// The working expression
+    pub exp: S::Exp,
+    /// A copy of the timer from just before the call
+    pub timer: T,
+    /// The outcome of the call
+    pub outcome: Outcome<S>,
+}
+
+impl<T: Clone, S: Step + Clone> Clone for ProvideCall<T, S> {
+    fn clone(&self) -> Self {
+        Self {
+            method: self.method.clone(),
+            exp: self.exp.clone(),
+            timer: self.timer.clone(),
+            outcome: self.outcome.clone(),
+        }
+    }
+}
+
+/// A recorded call whose outcome differs when replayed (see
+/// [`Recording::compare`])
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence<S> {
+    /// The index of the call in the recording
+    pub index: usize,
+    /// The recorded outcome
+    pub recorded: Outcome<S>,
+    /// The outcome of the replayed call
+    pub replayed: Outcome<S>,
+}
+
+/// A handle to the calls recorded by a [`RecordingProvider`] (which remains
+/// usable after the provider has been moved into a combinator or
+/// [`Controller`](crate::Controller))
+pub struct Recording<T, S: Step> {
+    calls: Arc<sync::Mutex<Vec<ProvideCall<T, S>>>>,
+}
+
+impl<T, S: Step> Clone for Recording<T, S> {
+    fn clone(&self) -> Self {
+        Self {
+            calls: self.calls.clone(),
+        }
+    }
+}
+
+impl<T: Timer + Clone, S: Step + Clone> Recording<T, S> {
+    /// Returns the number of recorded calls
+    pub fn len(&self) -> usize {
+        self.calls.lock().len()
+    }
+
+    /// Returns whether or not no calls were recorded
+    pub fn is_empty(&self) -> bool {
+        self.calls.lock().is_empty()
+    }
+
+    /// Returns the recorded call at `index` (if any)
+    pub fn get(&self, index: usize) -> Option<ProvideCall<T, S>> {
+        self.calls.lock().get(index).cloned()
+    }
+
+    /// Returns all recorded calls, in order
+    pub fn calls(&self) -> Vec<ProvideCall<T, S>> {
+        self.calls.lock().clone()
+    }
+
+    /// Forgets all recorded calls
+    pub fn clear(&self) {
+        self.calls.lock().clear();
+    }
+
+    /// Calls `provider` on the recorded input at `index` (with the same
+    /// method, expression, and a fresh copy of the recorded timer) and
+    /// returns the outcome, or `None` if there is no such call
+    ///
+    /// Only the inputs of the call are reproduced, not the internal state of
+    /// the recorded provider (e.g., from lifecycle hooks).
+    pub fn replay<P>(
+        &self,
+        index: usize,
+        provider: &mut P,
+    ) -> Option<Outcome<S>>
+    where
+        P: StepProvider<T, Step = S> + ?Sized,
+    {
+        let (method, exp, timer) = {
+            let calls = self.calls.lock();
+            let call = calls.get(index)?;
+            (call.method.clone(), call.exp.clone(), call.timer.clone())
+        };
+        Some(invoke(provider, &method, &timer, &exp))
+    }
+
+    /// Replays every recorded call on `provider` (see [`Self::replay`]) and
+    /// returns the calls whose outcomes differ (including the order of the
+    /// provided steps)
+    pub fn compare<P>(&self, provider: &mut P) -> Vec<Divergence<S>>
+    where
+        P: StepProvider<T, Step = S> + ?Sized,
+        S: PartialEq,
+    {
+        let mut divergences = vec![];
+        for (index, call) in self.calls().into_iter().enumerate() {
+            let replayed =
+                invoke(provider, &call.method, &call.timer, &call.exp);
+            if replayed != call.outcome {
+                divergences.push(Divergence {
+                    index,
+                    recorded: call.outcome,
+                    replayed,
+                });
+            }
+        }
+        divergences
+    }
+}
+
+/// Calls a provider method, returning its outcome
+fn invoke<T, S, P>(
+    provider: &mut P,
+    method: &Method,
+    timer: &T,
+    e: &S::Exp,
+) -> Outcome<S>
+where
+    T: Timer,
+    S: Step,
+    P: StepProvider<T, Step = S> + ?Sized,
+{
+    let cut_off = |_| Outcome::CutOff(vec![]);
+    match method {
+        Method::Provide => provider
+            .provide(timer, e)
+            .map_or_else(cut_off, Outcome::Provided),
+        Method::ProvidePartial => match provider.provide_partial(timer, e) {
+            (steps, None) => Outcome::Provided(steps),
+            (steps, Some(_)) => Outcome::CutOff(steps),
+        },
+        Method::TryProvide => match provider.try_provide(timer, e) {
+            Ok(steps) => Outcome::Provided(steps),
+            Err(ProviderError::Cutoff(_)) => Outcome::CutOff(vec![]),
+            Err(ProviderError::Failure(err)) => {
+                Outcome::Failed(format!("{}", err))
+            }
+        },
+        Method::ProvideAnnotated(annotations) => provider
+            .provide_annotated(timer, e, annotations)
+            .map_or_else(cut_off, Outcome::Provided),
+    }
+}
+
+/// A provider that records every call to another provider (see
+/// [`Recording`])
+pub struct RecordingProvider<P, T, S: Step> {
+    inner: P,
+    recording: Recording<T, S>,
+}
+
+impl<P, T, S: Step> RecordingProvider<P, T, S> {
+    /// Creates a new [`RecordingProvider`] wrapping `inner`
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            recording: Recording {
+                calls: Arc::new(sync::Mutex::new(vec![])),
+            },
+        }
+    }
+
+    /// Returns a handle to the recorded calls
+    pub fn recording(&self) -> Recording<T, S> {
+        self.recording.clone()
+    }
+
+    /// Returns the inner provider and drops self
+    pub fn into_inner(self) -> P {
+        self.inner
+    }
+}
+
+impl<P, T, S> StepProvider<T> for RecordingProvider<P, T, S>
+where
+    T: Timer + Clone,
+    S: Step + Clone,
+    P: StepProvider<T, Step = S>,
+{
+    type Step = S;
+
+    fn provide(
+        &mut self,
+        timer: &T,
+        e: &S::Exp,
+    ) -> Result<Vec<S>, T::EarlyCutoff> {
+        let before = timer.clone();
+        let result = self.inner.provide(timer, e);
+        let outcome = match &result {
+            Ok(steps) => Outcome::Provided(steps.clone()),
+            Err(_) => Outcome::CutOff(vec![]),
+        };
+        self.recording.calls.lock().push(ProvideCall {
+            method: Method::Provide,
+            exp: e.clone(),
+            timer: before,
+            outcome,
+        });
+        result
+    }
+
+    fn provide_partial(
+        &mut self,
+        timer: &T,
+        e: &S::Exp,
+    ) -> (Vec<S>, Option<T::EarlyCutoff>) {
+        let before = timer.clone();
+        let (steps, cutoff) = self.inner.provide_partial(timer, e);
+        let outcome = match &cutoff {
+            None => Outcome::Provided(steps.clone()),
+            Some(_) => Outcome::CutOff(steps.clone()),
+        };
+        self.recording.calls.lock().push(ProvideCall {
+            method: Method::ProvidePartial,
+            exp: e.clone(),
+            timer: before,
+            outcome,
+        });
+        (steps, cutoff)
+    }
+
+    fn try_provide(
+        &mut self,
+        timer: &T,
+        e: &S::Exp,
+    ) -> Result<Vec<S>, ProviderError<T::EarlyCutoff>> {
+        let before = timer.clone();
+        let result = self.inner.try_provide(timer, e);
+        let outcome = match &result {
+            Ok(steps) => Outcome::Provided(steps.clone()),
+            Err(ProviderError::Cutoff(_)) => Outcome::CutOff(vec![]),
+            Err(ProviderError::Failure(err)) => {
+                Outcome::Failed(format!("{}", err))
+            }
+        };
+        self.recording.calls.lock().push(ProvideCall {
+            method: Method::TryProvide,
+            exp: e.clone(),
+            timer: before,
+            outcome,
+        });
+        result
+    }
+
+    fn provide_annotated(
+        &mut self,
+        timer: &T,
+        e: &S::Exp,
+        annotations: &[Annotation],
+    ) -> Result<Vec<S>, T::EarlyCutoff> {
+        let before = timer.clone();
+        let result = self.inner.provide_annotated(timer, e, annotations);
+        let outcome = match &result {
+            Ok(steps) => Outcome::Provided(steps.clone()),
+            Err(_) => Outcome::CutOff(vec![]),
+        };
+        self.recording.calls.lock().push(ProvideCall {
+            method: Method::ProvideAnnotated(annotations.to_vec()),
+            exp: e.clone(),
+            timer: before,
+            outcome,
+        });
+        result
+    }
+
+    fn capabilities(&mut self) -> Capabilities {
+        self.inner.capabilities().forwarded()
+    }
+
+    fn on_session_start(&mut self, start: &S::Exp) {
+        self.inner.on_session_start(start);
+    }
+
+    fn on_decide(&mut self, step: &S, e: &S::Exp) {
+        self.inner.on_decide(step, e);
+    }
+
+    fn on_undo(&mut self, e: &S::Exp) {
+        self.inner.on_undo(e);
+    }
+
+    fn on_external_edit(&mut self, prev: &S::Exp, e: &S::Exp) {
+        self.inner.on_external_edit(prev, e);
+    }
+
+    fn on_refine(&mut self, valid: &dyn Fn(&S::Exp) -> bool) {
+        self.inner.on_refine(valid);
+    }
+
+    fn on_feedback(&mut self, step: &S, feedback: Feedback) {
+        self.inner.on_feedback(step, feedback);
+    }
+
+    fn set_progress(&mut self, reporter: ProgressReporter) {
+        self.inner.set_progress(reporter);
+    }
+
+    fn set_context(&mut self, context: Context) {
+        self.inner.set_context(context);
+    }
+
+    fn warmup(&mut self, timer: &T) -> Result<(), T::EarlyCutoff> {
+        self.inner.warmup(timer)
+    }
+}
@@ -0,0 +1,315 @@
+//! Rendering expressions and steps for front-ends
+//!
+//! Types that implement [`Render`] describe how they look once, as a sequence
+//! of [`Style`]d text spans, and every front-end picks the output [`Target`]
+//! it needs: plain text, ANSI-colored terminal text, or HTML fragments. The
+//! [`Controller`] (see [`Controller::render`]) and
+//! [`Derivation`](crate::derivation::Derivation) (see
+//! [`Derivation::render_to`](crate::derivation::Derivation::render_to)) use
+//! these renderings, as do the exporters of sessions: Graphviz graphs (see
+//! [`to_dot`] and [`Controller::export_dot`]) and, with the `std` feature,
+//! session traces (see `trace::RenderCodec`).
+
+use crate::{
+    Action, Controller, Step, StepProvider, Timer, ValidityChecker,
+    history::HistoryStore,
+};
+
+use alloc::{borrow::ToOwned, format, string::String, vec::Vec};
+
+/// The roles of text spans, which targets may display differently
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Style {
+    /// Ordinary text
+    Plain,
+    /// Keywords and operators
+    Keyword,
+    /// Names (e.g., of variables or functions)
+    Name,
+    /// Literal values
+    Literal,
+    /// Holes and other incomplete parts
+    Hole,
+    /// Text that deserves attention (e.g., the part a step changes)
+    Emphasis,
+    /// Secondary text (e.g., comments or types)
+    Comment,
+}
+
+impl Style {
+    /// The ANSI escape sequence that starts the style (if any)
+    fn ansi(self) -> Option<&'static str> {
+        match self {
+            Style::Plain => None,
+            Style::Keyword => Some("\x1b[1;35m"),
+            Style::Name => Some("\x1b[36m"),
+            Style::Literal => Some("\x1b[32m"),
+            Style::Hole => Some("\x1b[1;33m"),
+            Style::Emphasis => Some("\x1b[1m"),
+            Style::Comment => Some("\x1b[2m"),
+        }
+    }
+
+    /// The CSS class of the style (if any)
+    fn class(self) -> Option<&'static str> {
+        match self {
+            Style::Plain => None,
+            Style::Keyword => Some("pbn-keyword"),
+            Style::Name => Some("pbn-name"),
+            Style::Literal => Some("pbn-literal"),
+            Style::Hole => Some("pbn-hole"),
+            Style::Emphasis => Some("pbn-emphasis"),
+            Style::Comment => Some("pbn-comment"),
+        }
+    }
+}
+
+/// The output formats of renderings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Target {
+    /// Plain text (styles are ignored)
+    #[default]
+    Plain,
+    /// Text with ANSI color escape sequences, for terminals
+    Ansi,
+    /// An HTML fragment, with styled spans in `<span>` elements whose
+    /// classes are `pbn-keyword`, `pbn-name`, and so on
+    Html,
+}
+
+/// A sequence of styled text spans
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rendering {
+    spans: Vec<(Style, String)>,
+}
+
+impl Rendering {
+    /// Creates a new, empty rendering
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends text with a style
+    pub fn push(&mut self, style: Style, text: &str) -> &mut Self {
+        match self.spans.last_mut() {
+            Some((s, t)) if *s == style => t.push_str(text),
+            _ => self.spans.push((style, text.to_owned())),
+        }
+        self
+    }
+
+    /// Appends plain text
+    pub fn text(&mut self, text: &str) -> &mut Self {
+        self.push(Style::Plain, text)
+    }
+
+    /// Appends the rendering of a value
+    pub fn render(&mut self, x: &(impl Render + ?Sized)) -> &mut Self {
+        x.render(self);
+        self
+    }
+
+    /// Returns the styled spans
+    pub fn spans(&self) -> &[(Style, String)] {
+        &self.spans
+    }
+
+    /// Returns the rendering in an output format
+    pub fn to_target(&self, target: Target) -> String {
+        let mut out = String::new();
+        for (style, text) in &self.spans {
+            match target {
+                Target::Plain => out.push_str(text),
+                Target::Ansi => match style.ansi() {
+                    Some(code) => {
+                        out.push_str(code);
+                        out.push_str(text);
+                        out.push_str("\x1b[0m");
+                    }
+                    None => out.push_str(text),
+                },
+                Target::Html => match style.class() {
+                    Some(class) => {
+                        out.push_str("<span class=\"");
+                        out.push_str(class);
+                        out.push_str("\">");
+                        escape_html(text, &mut out);
+                        out.push_str("</span>");
+                    }
+                    None => escape_html(text, &mut out),
+                },
+            }
+        }
+        out
+    }
+}
+
+impl core::fmt::Display for Rendering {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(&self.to_target(Target::Plain))
+    }
+}
+
+fn escape_html(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+/// Values (e.g., expressions and steps) that can be rendered for front-ends
+pub trait Render {
+    /// Appends the styled spans of the value to a rendering
+    fn render(&self, out: &mut Rendering);
+
+    /// Returns the value rendered in an output format
+    fn render_to(&self, target: Target) -> String {
+        let mut out = Rendering::new();
+        self.render(&mut out);
+        out.to_target(target)
+    }
+}
+
+impl<T, S, P, C, H> Controller<T, S, P, C, H>
+where
+    T: Timer,
+    S: Step<Exp: Render>,
+    P: StepProvider<T, Step = S> + ?Sized,
+    C: ValidityChecker<Exp = S::Exp> + ?Sized,
+    H: HistoryStore<S::Exp> + ?Sized,
+{
+    /// Returns the working expression rendered in an output format
+    pub fn render(&self, target: Target) -> String {
+        self.state.render_to(target)
+    }
+
+    /// Returns the session so far as a graph in the Graphviz DOT language
+    /// (see [`to_dot`])
+    pub fn export_dot(&self) -> String
+    where
+        S: Render,
+    {
+        let mut e = self.start.clone();
+        let mut changes = Vec::with_capacity(self.decisions.len());
+        for d in &self.decisions {
+            let (step, next) = match &d.action {
+                Action::Step(step) => match step.apply(&e) {
+                    Some(next) => (Some(step), next),
+                    None => break,
+                },
+                Action::Edit(next) => (None, next.clone()),
+            };
+            e = next.clone();
+            changes.push((step, next));
+        }
+        to_dot(&self.start, changes.iter().map(|(s, e)| (*s, e)))
+    }
+}
+
+/// Returns a navigation as a graph in the Graphviz DOT language, with the
+/// expressions as nodes and the changes between them as edges (labelled by
+/// their plain-text renderings)
+///
+/// The changes from `start` are given in order, each with the step that made
+/// it (or `None` for an external edit, which is drawn dashed) and the
+/// resulting expression.
+pub fn to_dot<'a, S, E>(
+    start: &E,
+    changes: impl IntoIterator<Item = (Option<&'a S>, &'a E)>,
+) -> String
+where
+    S: Render + 'a,
+    E: Render + 'a,
+{
+    let mut out = String::from(
+        "digraph navigation {\n  node [shape=box, fontname=\"monospace\"];\n",
+    );
+    out.push_str(&format!("  n0 [label={}];\n", dot_string(start)));
+    for (i, (step, e)) in changes.into_iter().enumerate() {
+        out.push_str(&format!("  n{} [label={}];\n", i + 1, dot_string(e)));
+        let edge = match step {
+            Some(step) => format!("label={}", dot_string(step)),
+            None => "label=\"edit\", style=dashed".to_owned(),
+        };
+        out.push_str(&format!("  n{} -> n{} [{}];\n", i, i + 1, edge));
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Returns the plain-text rendering of `x` as a DOT string literal
+fn dot_string(x: &(impl Render + ?Sized)) -> String {
+    let mut out = String::from("\"");
+    for c in x.render_to(Target::Plain).chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, AllEqual, Fill, Fills};
+
+    #[test]
+    fn renders_to_every_target() {
+        let mut out = Rendering::new();
+        out.text("if ").push(Style::Keyword, "<x>").text(" & y");
+        assert_eq!(out.to_target(Target::Plain), "if <x> & y");
+        assert_eq!(out.to_target(Target::Ansi), "if \x1b[1;35m<x>\x1b[0m & y");
+        assert_eq!(
+            out.to_target(Target::Html),
+            "if <span class=\"pbn-keyword\">&lt;x&gt;</span> &amp; y"
+        );
+    }
+
+    #[test]
+    fn exports_sessions_as_dot() {
+        let mut c = Controller::new(
+            testing::budget(),
+            Fills { digits: 2 },
+            AllEqual(1),
+            testing::empty(),
+            true,
+        );
+        c.decide(Fill(0, 1)).unwrap();
+        c.apply_external_edit(|e| [e[0], Some(0), None]);
+        assert_eq!(
+            c.export_dot(),
+            "digraph navigation {
+  node [shape=box, fontname=\"monospace\"];
+  n0 [label=\"[_ _ _]\"];
+  n1 [label=\"[1 _ _]\"];
+  n0 -> n1 [label=\"#0 := 1\"];
+  n2 [label=\"[1 0 _]\"];
+  n1 -> n2 [label=\"edit\", style=dashed];
+}
+"
+        );
+    }
+
+    #[test]
+    fn escapes_dot_labels() {
+        struct Quoted;
+
+        impl Render for Quoted {
+            fn render(&self, out: &mut Rendering) {
+                out.text("say \"hi\\\"\n");
+            }
+        }
+
+        assert_eq!(dot_string(&Quoted), r#""say \"hi\\\"\n""#);
+    }
+}
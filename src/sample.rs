@@ -0,0 +1,119 @@
+//! Random samples of provided steps
+//!
+//! User interfaces sometimes show "a few example options" instead of an
+//! enormous list of provided steps. The functions in this module choose `k`
+//! of `n` steps (uniformly or in proportion to a weight) without shuffling or
+//! sorting the whole list, so their cost depends mostly on `k`. Samples are
+//! determined by explicit seeds (see also
+//! [`Deterministic`](crate::Deterministic)).
+
+use crate::{
+    Controller, PbnError, Step, StepProvider, Timer, ValidityChecker,
+    history::HistoryStore, rng::Rng,
+};
+
+use alloc::{collections::BTreeSet, vec::Vec};
+
+/// Returns the (sorted) indices of a uniformly random sample of `k` of `n`
+/// items (or of all items if there are at most `k`)
+pub fn sample(n: usize, k: usize, seed: u64) -> Vec<usize> {
+    if k >= n {
+        return (0..n).collect();
+    }
+    // Floyd's algorithm
+    let mut rng = Rng::new(seed);
+    let mut chosen = BTreeSet::new();
+    for j in n - k..n {
+        let i = rng.below(j + 1);
+        if !chosen.insert(i) {
+            chosen.insert(j);
+        }
+    }
+    chosen.into_iter().collect()
+}
+
+/// Returns the (sorted) indices of a random sample of `k` of `items`,
+/// chosen one at a time with probability proportional to their
+/// (nonnegative) weights among the items not yet chosen
+///
+/// Items with zero weight are never chosen, so the sample may contain fewer
+/// than `k` items.
+pub fn sample_weighted<X>(
+    items: &[X],
+    k: usize,
+    seed: u64,
+    weight: impl Fn(&X) -> f64,
+) -> Vec<usize> {
+    let weights: Vec<f64> = items.iter().map(|x| weight(x).max(0.0)).collect();
+    let mut total: f64 = weights.iter().sum();
+    let mut rng = Rng::new(seed);
+    let mut chosen = BTreeSet::new();
+    while chosen.len() < k && total > 0.0 {
+        let mut target = rng.unit() * total;
+        let mut pick = None;
+        for (i, &w) in weights.iter().enumerate() {
+            if w == 0.0 || chosen.contains(&i) {
+                continue;
+            }
+            pick = Some(i);
+            if target < w {
+                break;
+            }
+            target -= w;
+        }
+        // Rounding can leave the target past the last item, which is then
+        // chosen
+        let Some(i) = pick else { break };
+        chosen.insert(i);
+        total -= weights[i];
+    }
+    chosen.into_iter().collect()
+}
+
+/// Returns the items at sorted `indices`, in order
+fn select<X>(items: Vec<X>, indices: &[usize]) -> Vec<X> {
+    let mut indices = indices.iter().peekable();
+    items
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| indices.next_if(|&&j| j == *i).is_some())
+        .map(|(_, x)| x)
+        .collect()
+}
+
+impl<T, S, P, C, H> Controller<T, S, P, C, H>
+where
+    T: Timer,
+    S: Step,
+    P: StepProvider<T, Step = S> + ?Sized,
+    C: ValidityChecker<Exp = S::Exp> + ?Sized,
+    H: HistoryStore<S::Exp> + ?Sized,
+{
+    /// Ask the synthesizer to provide a list of possible next steps and
+    /// returns a uniformly random sample of `k` of them, in provided order
+    /// (see [`Self::provide`] and [`sample`])
+    pub fn sample_steps(
+        &mut self,
+        k: usize,
+        seed: u64,
+    ) -> Result<Vec<S>, PbnError<T::EarlyCutoff>> {
+        let steps = self.provide()?;
+        let indices = sample(steps.len(), k, seed);
+        Ok(select(steps, &indices))
+    }
+
+    /// Ask the synthesizer to provide a list of possible next steps and
+    /// returns a random sample of `k` of them chosen in proportion to
+    /// `weight`, in provided order (see [`Self::provide`] and
+    /// [`sample_weighted`])
+    pub fn sample_steps_weighted(
+        &mut self,
+        k: usize,
+        seed: u64,
+        weight: impl Fn(&S) -> f64,
+    ) -> Result<Vec<S>, PbnError<T::EarlyCutoff>> {
+        let steps = self.provide()?;
+        let indices = sample_weighted(&steps, k, seed, weight);
+        Ok(select(steps, &indices))
+    }
+}
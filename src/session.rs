@@ -0,0 +1,162 @@
+//! Management of many concurrent navigation sessions
+//!
+//! A [`SessionManager`] owns many [`Controller`]s keyed by [`SessionId`],
+//! which is useful for server deployments that host navigation sessions for
+//! many users simultaneously.
+
+use crate::{
+    Controller, Step, StepProvider, Timer, ValidityChecker,
+    history::HistoryStore,
+};
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// An identifier for a session managed by a [`SessionManager`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SessionId(pub u64);
+
+impl std::fmt::Display for SessionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+struct Session<T: Timer, S: Step, P: ?Sized, C: ?Sized, H: ?Sized> {
+    controller: Controller<T, S, P, C, H>,
+    last_used: Instant,
+}
+
+/// A callback that is called with every session evicted for being idle
+pub type EvictionHook<T, S, P, C, H> =
+    Box<dyn FnMut(SessionId, Controller<T, S, P, C, H>) + Send>;
+
+/// A collection of navigation sessions keyed by session identifier
+pub struct SessionManager<
+    T: Timer,
+    S: Step,
+    P: ?Sized = dyn StepProvider<T, Step = S>,
+    C: ?Sized = dyn ValidityChecker<Exp = <S as Step>::Exp>,
+    H: ?Sized = dyn HistoryStore<<S as Step>::Exp>,
+> {
+    sessions: HashMap<SessionId, Session<T, S, P, C, H>>,
+    next_id: u64,
+    on_evict: Option<EvictionHook<T, S, P, C, H>>,
+}
+
+impl<T, S, P, C, H> Default for SessionManager<T, S, P, C, H>
+where
+    T: Timer,
+    S: Step,
+    P: ?Sized,
+    C: ?Sized,
+    H: ?Sized,
+{
+    fn default() -> Self {
+        Self {
+            sessions: HashMap::new(),
+            next_id: 0,
+            on_evict: None,
+        }
+    }
+}
+
+impl<T, S, P, C, H> SessionManager<T, S, P, C, H>
+where
+    T: Timer,
+    S: Step,
+    P: ?Sized,
+    C: ?Sized,
+    H: ?Sized,
+{
+    /// Creates a new session manager with no sessions
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets a callback that is called with every session evicted by
+    /// [`Self::expire_idle`] (e.g., to release per-session resources)
+    pub fn on_evict(
+        mut self,
+        hook: impl FnMut(SessionId, Controller<T, S, P, C, H>) + Send + 'static,
+    ) -> Self {
+        self.on_evict = Some(Box::new(hook));
+        self
+    }
+
+    /// Adds a session and returns its (fresh) identifier
+    pub fn create(
+        &mut self,
+        controller: Controller<T, S, P, C, H>,
+    ) -> SessionId {
+        let id = SessionId(self.next_id);
+        self.next_id += 1;
+        self.sessions.insert(
+            id,
+            Session {
+                controller,
+                last_used: Instant::now(),
+            },
+        );
+        id
+    }
+
+    /// Returns the controller of a session (if it exists), marking the
+    /// session as used
+    pub fn get(
+        &mut self,
+        id: SessionId,
+    ) -> Option<&mut Controller<T, S, P, C, H>> {
+        self.sessions.get_mut(&id).map(|s| {
+            s.last_used = Instant::now();
+            &mut s.controller
+        })
+    }
+
+    /// Removes a session and returns its controller (if it exists)
+    pub fn close(
+        &mut self,
+        id: SessionId,
+    ) -> Option<Controller<T, S, P, C, H>> {
+        self.sessions.remove(&id).map(|s| s.controller)
+    }
+
+    /// Evicts every session that has not been used for at least `max_idle`,
+    /// returning the number of evicted sessions
+    pub fn expire_idle(&mut self, max_idle: Duration) -> usize {
+        let now = Instant::now();
+        let mut expired: Vec<SessionId> = self
+            .sessions
+            .iter()
+            .filter(|(_, s)| now.duration_since(s.last_used) >= max_idle)
+            .map(|(id, _)| *id)
+            .collect();
+        expired.sort();
+        for id in &expired {
+            let Some(session) = self.sessions.remove(id) else {
+                continue;
+            };
+            if let Some(hook) = &mut self.on_evict {
+                hook(*id, session.controller);
+            }
+        }
+        expired.len()
+    }
+
+    /// Returns the identifiers of all sessions (in increasing order)
+    pub fn ids(&self) -> Vec<SessionId> {
+        let mut ids: Vec<SessionId> = self.sessions.keys().copied().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Returns the number of sessions
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Returns whether or not there are no sessions
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+}
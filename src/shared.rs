@@ -0,0 +1,100 @@
+//! Thread-safe sharing of a [`Controller`](crate::Controller) by front-ends
+//!
+//! A [`SharedController`] is a cheaply cloneable handle to a
+//! [`SendController`]. Decisions are serialized through a mutex, while the
+//! working expression is published separately so that readers (e.g., request
+//! handlers of a web server) never wait on a long-running provider.
+
+use crate::{PbnError, SendController, Step, Timer};
+
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+/// A cloneable, thread-safe handle to a shared controller
+pub struct SharedController<T: Timer, S: Step> {
+    controller: Arc<Mutex<SendController<T, S>>>,
+    published: Arc<RwLock<Arc<S::Exp>>>,
+}
+
+impl<T: Timer, S: Step> Clone for SharedController<T, S> {
+    fn clone(&self) -> Self {
+        Self {
+            controller: self.controller.clone(),
+            published: self.published.clone(),
+        }
+    }
+}
+
+impl<T: Timer, S: Step> SharedController<T, S> {
+    /// Creates a new shared controller
+    pub fn new(controller: SendController<T, S>) -> Self {
+        let published = Arc::new(RwLock::new(Arc::new(
+            controller.working_expression().clone(),
+        )));
+        Self {
+            controller: Arc::new(Mutex::new(controller)),
+            published,
+        }
+    }
+
+    /// Returns the current working expression (without waiting on other
+    /// operations on the controller)
+    pub fn working_expression(&self) -> Arc<S::Exp> {
+        self.published
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Runs a function with exclusive access to the underlying controller
+    pub fn with<R>(&self, f: impl FnOnce(&mut SendController<T, S>) -> R) -> R {
+        let mut controller = self
+            .controller
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let result = f(&mut controller);
+        *self
+            .published
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) =
+            Arc::new(controller.working_expression().clone());
+        result
+    }
+
+    /// Ask the synthesizer to provide a list of possible next steps (see
+    /// [`Controller::provide`](crate::Controller::provide))
+    ///
+    /// Providing can change the working expression (see
+    /// [`Controller::with_auto_advance`](crate::Controller::with_auto_advance)),
+    /// so the published working expression is refreshed afterward.
+    pub fn provide(&self) -> Result<Vec<S>, PbnError<T::EarlyCutoff>> {
+        self.with(|c| c.provide())
+    }
+
+    /// Decide which step to take (see
+    /// [`Controller::decide`](crate::Controller::decide))
+    pub fn decide(&self, step: S) -> Result<(), PbnError<T::EarlyCutoff>> {
+        self.with(|c| c.decide(step))
+    }
+
+    /// Returns whether or not the current working expression is valid
+    pub fn valid(&self) -> bool {
+        self.controller
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .valid()
+    }
+
+    /// Returns whether or not meta-level "undo" is applicable
+    pub fn can_undo(&self) -> bool {
+        self.controller
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .can_undo()
+    }
+
+    /// Perform a meta-level "undo" operation (see
+    /// [`Controller::undo`](crate::Controller::undo))
+    pub fn undo(&self) -> Result<(), PbnError<T::EarlyCutoff>> {
+        self.with(|c| c.undo())
+    }
+}
@@ -0,0 +1,68 @@
+//! Minimization of navigation traces that trigger failures
+//!
+//! When a recorded session exposes a bug (e.g., a provider panic or a
+//! Strong Soundness violation), the functions in this module shrink its
+//! decision trace to a short prefix or subsequence that still triggers the
+//! failure, in the style of delta debugging.
+
+use alloc::vec::Vec;
+
+/// Shrinks a trace that triggers a failure (as determined by `fails`) to a
+/// shorter trace that still triggers the failure
+///
+/// First, the shortest failing prefix is found; then, chunks of
+/// progressively smaller size are removed while the failure persists. The
+/// input trace is assumed to trigger the failure.
+pub fn shrink<S: Clone>(
+    trace: Vec<S>,
+    mut fails: impl FnMut(&[S]) -> bool,
+) -> Vec<S> {
+    match try_shrink::<S, core::convert::Infallible>(trace, |t| Ok(fails(t))) {
+        Ok(t) => t,
+        Err(e) => match e {},
+    }
+}
+
+/// Like [`shrink`], but the failure predicate may itself fail (e.g., by
+/// being cut off by a timer), in which case shrinking is aborted
+pub fn try_shrink<S: Clone, E>(
+    trace: Vec<S>,
+    mut fails: impl FnMut(&[S]) -> Result<bool, E>,
+) -> Result<Vec<S>, E> {
+    let mut trace = trace;
+    for n in 0..trace.len() {
+        if fails(&trace[..n])? {
+            trace.truncate(n);
+            break;
+        }
+    }
+    let mut chunk = (trace.len() / 2).max(1);
+    while !trace.is_empty() {
+        let mut removed = false;
+        let mut i = 0;
+        while i < trace.len() {
+            let end = (i + chunk).min(trace.len());
+            let mut candidate = trace[..i].to_vec();
+            candidate.extend_from_slice(&trace[end..]);
+            if fails(&candidate)? {
+                trace = candidate;
+                removed = true;
+            } else {
+                i += chunk;
+            }
+        }
+        if !removed {
+            if chunk == 1 {
+                break;
+            }
+            chunk /= 2;
+        }
+    }
+    Ok(trace)
+}
+
+/// Returns whether or not `f` panics (useful as a failure predicate)
+#[cfg(feature = "std")]
+pub fn panics<R>(f: impl FnOnce() -> R) -> bool {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).is_err()
+}
@@ -0,0 +1,259 @@
+//! Simulated users for evaluating designs before running user studies
+//!
+//! A [`SimulatedUser`] is a [`Decider`] that follows a parameterized
+//! [`Model`] of how people choose among provided steps, judging expressions
+//! by a [`ScoredValidityChecker`] (its notion of "closer to valid"):
+//!
+//! - [`Model::Greedy`] decides on the step whose result looks best
+//! - [`Model::NoisyGreedy`] does the same, but decides on a different step
+//!   (uniformly at random) with some probability
+//! - [`Model::Lookahead`] imagines a few steps ahead (with a
+//!   [`StepEnumerator`]) and decides on the step from which the best
+//!   expression can be reached
+//!
+//! A [`Simulation`] runs every model on a set of [`Problem`]s (see
+//! [`bench::run_one`]) and reports how often each model reaches a valid
+//! expression and in how many decisions, so that interfaces and providers can
+//! be compared before recruiting participants.
+
+use crate::{
+    Decider, Problem, ScoredValidityChecker, Step, StepEnumerator, Timer,
+    ValidityChecker,
+    bench::{self, Outcome, Report},
+    rng::Rng,
+};
+
+/// A parameterized model of how a simulated user decides
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Model {
+    /// Decide on the step whose result has the highest score (preferring
+    /// valid results, and breaking ties by the order of provided steps)
+    Greedy,
+    /// Like [`Model::Greedy`], but decide on one of the other steps
+    /// (uniformly at random) with probability `error_rate` (in `[0, 1]`)
+    NoisyGreedy {
+        /// The probability of not deciding on the greedy step
+        error_rate: f64,
+    },
+    /// Decide on the step from which the expression with the highest score
+    /// can be reached in at most `depth` (positive) steps, imagining the
+    /// later steps with a [`StepEnumerator`]
+    ///
+    /// A depth of one is the same as [`Model::Greedy`].
+    Lookahead {
+        /// The number of steps to imagine (including the decided step)
+        depth: usize,
+    },
+}
+
+impl Model {
+    /// Returns a short, human-readable name of the model (e.g., for reports)
+    pub fn name(&self) -> String {
+        match self {
+            Model::Greedy => "greedy".to_owned(),
+            Model::NoisyGreedy { error_rate } => {
+                format!("noisy_greedy({})", error_rate)
+            }
+            Model::Lookahead { depth } => format!("lookahead({})", depth),
+        }
+    }
+}
+
+/// A decider that follows a [`Model`] of a user
+pub struct SimulatedUser<X, N> {
+    model: Model,
+    scorer: X,
+    enumerator: N,
+    rng: Rng,
+}
+
+impl<X, N> SimulatedUser<X, N> {
+    /// Creates a new [`SimulatedUser`] that judges expressions with `scorer`,
+    /// imagines steps with `enumerator` (for [`Model::Lookahead`]), and makes
+    /// random choices from `seed` (for [`Model::NoisyGreedy`])
+    pub fn new(model: Model, scorer: X, enumerator: N, seed: u64) -> Self {
+        Self {
+            model,
+            scorer,
+            enumerator,
+            rng: Rng::new(seed),
+        }
+    }
+
+    /// Returns the model of the user
+    pub fn model(&self) -> Model {
+        self.model
+    }
+}
+
+impl<X, N> SimulatedUser<X, N>
+where
+    N: StepEnumerator,
+    X: ScoredValidityChecker<Exp = <N::Step as Step>::Exp>,
+{
+    /// Returns the best value of an expression reachable from `e` in at most
+    /// `depth` enumerated steps (where valid expressions are best)
+    fn value(&self, e: &X::Exp, depth: usize) -> f64 {
+        if self.scorer.check(e) {
+            return f64::INFINITY;
+        }
+        let mut best = self.scorer.score(e);
+        if depth > 0 {
+            for step in self.enumerator.enumerate(e) {
+                if let Some(e) = step.apply(e) {
+                    best = best.max(self.value(&e, depth - 1));
+                }
+            }
+        }
+        best
+    }
+}
+
+impl<X, N> Decider<N::Step> for SimulatedUser<X, N>
+where
+    N: StepEnumerator,
+    X: ScoredValidityChecker<Exp = <N::Step as Step>::Exp>,
+{
+    fn decide(&mut self, e: &X::Exp, steps: &[N::Step]) -> Option<usize> {
+        let depth = match self.model {
+            Model::Greedy | Model::NoisyGreedy { .. } => 0,
+            Model::Lookahead { depth } => depth.saturating_sub(1),
+        };
+        let best = N::Step::apply_all(steps, e)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, r)| Some((i, self.value(&r?, depth))))
+            .min_by(|(_, a), (_, b)| b.total_cmp(a))
+            .map(|(i, _)| i)?;
+        if let Model::NoisyGreedy { error_rate } = self.model
+            && steps.len() > 1
+            && self.rng.unit() < error_rate
+        {
+            let other = self.rng.below(steps.len() - 1);
+            return Some(if other < best { other } else { other + 1 });
+        }
+        Some(best)
+    }
+}
+
+/// The results of simulating one [`Model`] (see [`Simulation::run`])
+#[derive(Debug, Clone)]
+pub struct ModelReport {
+    /// The simulated model
+    pub model: Model,
+    /// The results of every run of the model (for each problem, in order,
+    /// every trial)
+    pub report: Report,
+}
+
+impl ModelReport {
+    /// Returns the fraction of runs that reached a valid expression
+    pub fn success_rate(&self) -> f64 {
+        self.report.solve_rate()
+    }
+
+    /// Returns the mean number of decisions of the runs that reached a valid
+    /// expression (or `None` if no run did)
+    pub fn mean_decisions(&self) -> Option<f64> {
+        let decisions: Vec<usize> = self
+            .report
+            .results
+            .iter()
+            .filter(|r| r.outcome == Outcome::Solved)
+            .map(|r| r.branching.len())
+            .collect();
+        if decisions.is_empty() {
+            return None;
+        }
+        Some(decisions.iter().sum::<usize>() as f64 / decisions.len() as f64)
+    }
+}
+
+/// A simulation of several models of users on a set of problems
+pub struct Simulation<X, N> {
+    scorer: X,
+    enumerator: N,
+    models: Vec<Model>,
+    trials: usize,
+    seed: u64,
+    max_decisions: usize,
+}
+
+impl<X: Clone, N: Clone> Simulation<X, N> {
+    /// Creates a new [`Simulation`] (with no models) of users that judge
+    /// expressions with `scorer` and imagine steps with `enumerator` (see
+    /// [`SimulatedUser::new`]), stopping every run after `max_decisions`
+    /// decisions
+    pub fn new(scorer: X, enumerator: N, max_decisions: usize) -> Self {
+        Self {
+            scorer,
+            enumerator,
+            models: Vec::new(),
+            trials: 1,
+            seed: 0,
+            max_decisions,
+        }
+    }
+
+    /// Simulates `model` as well
+    pub fn with_model(mut self, model: Model) -> Self {
+        self.models.push(model);
+        self
+    }
+
+    /// Runs every model `trials` times on each problem (e.g., to average over
+    /// the random choices of [`Model::NoisyGreedy`])
+    pub fn with_trials(mut self, trials: usize) -> Self {
+        self.trials = trials;
+        self
+    }
+
+    /// Derives the random choices of the simulated users from `seed`
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Runs every model on `problems`, using a fresh timer for each run, and
+    /// returns the results of each model (in order)
+    pub fn run<'a, T, C>(
+        &self,
+        problems: impl IntoIterator<Item = &'a Problem<T, N::Step, C>> + Clone,
+        mut timer: impl FnMut() -> T,
+    ) -> Vec<ModelReport>
+    where
+        T: Timer + 'static,
+        N: StepEnumerator,
+        N::Step: 'static,
+        X: ScoredValidityChecker<Exp = <N::Step as Step>::Exp>,
+        C: ValidityChecker<Exp = <N::Step as Step>::Exp> + Clone + 'static,
+    {
+        let mut seeds = Rng::new(self.seed);
+        self.models
+            .iter()
+            .map(|&model| {
+                let mut results = Vec::new();
+                for problem in problems.clone() {
+                    for _ in 0..self.trials {
+                        let mut user = SimulatedUser::new(
+                            model,
+                            self.scorer.clone(),
+                            self.enumerator.clone(),
+                            seeds.next_u64(),
+                        );
+                        results.push(bench::run_one(
+                            problem,
+                            timer(),
+                            &mut user,
+                            self.max_decisions,
+                        ));
+                    }
+                }
+                ModelReport {
+                    model,
+                    report: Report { results },
+                }
+            })
+            .collect()
+    }
+}
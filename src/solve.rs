@@ -0,0 +1,1100 @@
+//! Automated navigation with anytime results
+//!
+//! [`Controller::solve`] searches for a valid expression by navigating
+//! automatically from the working expression, exploring the provided steps in
+//! the order preferred by a [`Decider`] (a depth-first search that backtracks
+//! from dead ends). Every valid expression it finds that is reachable in
+//! fewer decisions than the best one so far becomes the new best, so the
+//! search can be stopped at any point with a useful result. The search can be
+//! paused and resumed through a [`PauseHandle`], and its best result can be
+//! committed to the controller to continue navigating interactively.
+//! [`ScoreGuided`] is a policy that prefers steps leading to expressions with
+//! higher scores (see [`ScoredValidityChecker`]).
+//!
+//! When many steps are independent (e.g., filling unrelated holes), the same
+//! expression is reachable by many interleavings of the same steps. For steps
+//! that implement [`CommutingSteps`], [`Controller::solve_commuting`] explores
+//! only one such interleaving (using sleep sets). Similarly, equivalent
+//! expressions can be explored only once by identifying them with an
+//! [`ExpCanon`] (see [`Anytime::with_canon`]).
+//!
+//! Alternatively, [`Controller::solve_deepening`] performs an iterative
+//! deepening search, which finds a valid expression reachable in the fewest
+//! decisions first. Its repeated passes share a transposition table of
+//! provided steps and validity outcomes keyed by canonical expression, so
+//! they do not redo provider work. If batch provision was negotiated with the
+//! provider (see [`Controller::capabilities`]), the steps for all of the
+//! results of an expression's provided steps are requested in one call.
+//!
+//! Domain knowledge about dead ends (e.g., from an abstract interpretation)
+//! can be plugged into any of these searches as a [`Pruner`] (see
+//! [`Anytime::with_pruner`]), which skips subtrees that provably cannot reach
+//! a valid expression.
+
+use crate::{
+    Controller, Decider, InvalidArgument, ScoredValidityChecker, Step,
+    StepProvider, Timer, ValidityChecker,
+    canon::{BackendVisited, CanonVisited, ExpCanon, Visited, VisitedBackend},
+    history::HistoryStore,
+};
+
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc, vec, vec::Vec};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// A policy that decides on the step whose result has the highest score
+/// (ignoring inapplicable steps)
+pub struct ScoreGuided<X> {
+    scorer: X,
+}
+
+impl<X> ScoreGuided<X> {
+    /// Creates a new [`ScoreGuided`] policy that scores with `scorer`
+    pub fn new(scorer: X) -> Self {
+        Self { scorer }
+    }
+}
+
+impl<S: Step, X: ScoredValidityChecker<Exp = S::Exp>> Decider<S>
+    for ScoreGuided<X>
+{
+    fn decide(&mut self, e: &S::Exp, steps: &[S]) -> Option<usize> {
+        S::apply_all(steps, e)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, r)| Some((i, self.scorer.score(&r?))))
+            .min_by(|(_, a), (_, b)| b.total_cmp(a))
+            .map(|(i, _)| i)
+    }
+}
+
+/// Steps whose independence from one another can be detected
+pub trait CommutingSteps: Step {
+    /// Returns whether or not applying `self` and `other` in either order
+    /// always has the same result (whenever both orders apply)
+    fn commutes_with(&self, other: &Self) -> bool;
+}
+
+/// A sound detector of dead ends (e.g., an abstract interpreter)
+pub trait Pruner<E> {
+    /// Returns whether or not no valid expression is reachable from `e`
+    ///
+    /// Returning `false` is always safe; returning `true` for an expression
+    /// from which a valid expression is reachable makes searches incomplete.
+    fn definitely_dead(&self, e: &E) -> bool;
+}
+
+impl<E, F: Fn(&E) -> bool> Pruner<E> for F {
+    fn definitely_dead(&self, e: &E) -> bool {
+        self(e)
+    }
+}
+
+/// The status of an anytime search
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolveStatus {
+    /// The search can make further progress
+    Running,
+    /// The search was paused (see [`PauseHandle`])
+    Paused,
+    /// The search explored every expression reachable in fewer decisions
+    /// than the best one found (so the best result is optimal)
+    Exhausted,
+    /// The search ran out of expressions to explore, but skipped the
+    /// expressions below some for which the provider failed or was cut off
+    /// (so a better result may exist)
+    Incomplete,
+    /// The search ran out of budget
+    OutOfBudget,
+}
+
+/// A shared flag used to pause and resume an anytime search
+#[derive(Debug, Clone, Default)]
+pub struct PauseHandle {
+    paused: Arc<AtomicBool>,
+}
+
+impl PauseHandle {
+    /// Requests that the search pause before its next decision
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    /// Allows the search to continue (the next call to [`Anytime::run`]
+    /// resumes it)
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    /// Returns whether or not a pause has been requested
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+}
+
+trait Search<'a, E> {
+    fn set_visited(&mut self, visited: Box<dyn Visited<E> + 'a>);
+    fn set_pruner(&mut self, pruner: Box<dyn Pruner<E> + 'a>);
+    fn pruned(&self) -> usize;
+    fn advance(&mut self) -> SolveStatus;
+    fn best(&self) -> Option<&E>;
+    fn best_depth(&self) -> Option<usize>;
+    fn commit(&mut self) -> bool;
+    fn abandon(&mut self);
+}
+
+/// A handle to an anytime search started by [`Controller::solve`]
+///
+/// The search makes progress only while [`Self::run`] or [`Self::advance`] is
+/// being called. While it is not running, the controller is left at the
+/// working expression it started from; dropping the handle abandons the
+/// search, whereas [`Self::commit`] navigates the controller to the best
+/// expression found.
+pub struct Anytime<'a, E> {
+    search: Box<dyn Search<'a, E> + 'a>,
+    pause: PauseHandle,
+    status: SolveStatus,
+}
+
+impl<'a, E> Anytime<'a, E> {
+    /// Skips expressions that are equivalent (according to `canon`) to one
+    /// already reached in at most as many decisions
+    ///
+    /// This has no effect on searches started by
+    /// [`Controller::solve_deepening`], which already identify equivalent
+    /// expressions.
+    pub fn with_canon(mut self, canon: impl ExpCanon<E> + 'a) -> Self
+    where
+        E: 'a,
+    {
+        self.search.set_visited(Box::new(CanonVisited::new(canon)));
+        self
+    }
+
+    /// Like [`Self::with_canon`], but stores the visited expressions with
+    /// `backend` (e.g., to bound the memory of very large searches)
+    ///
+    /// # Panics
+    ///
+    /// Panics if the parameters of `backend` are out of range (see
+    /// [`Self::try_with_canon_backend`])
+    #[track_caller]
+    pub fn with_canon_backend<X>(
+        self,
+        canon: X,
+        backend: VisitedBackend,
+    ) -> Self
+    where
+        X: ExpCanon<E> + 'a,
+        X::Key: Clone + core::hash::Hash,
+        E: 'a,
+    {
+        InvalidArgument::unwrap(self.try_with_canon_backend(canon, backend))
+    }
+
+    /// Like [`Self::with_canon_backend`], but fails if the parameters of
+    /// `backend` are out of range
+    pub fn try_with_canon_backend<X>(
+        mut self,
+        canon: X,
+        backend: VisitedBackend,
+    ) -> Result<Self, InvalidArgument>
+    where
+        X: ExpCanon<E> + 'a,
+        X::Key: Clone + core::hash::Hash,
+        E: 'a,
+    {
+        let visited = BackendVisited::try_new(canon, backend)?;
+        self.search.set_visited(Box::new(visited));
+        Ok(self)
+    }
+
+    /// Skips expressions that `pruner` determines cannot reach a valid
+    /// expression (without calling the provider on them)
+    pub fn with_pruner(mut self, pruner: impl Pruner<E> + 'a) -> Self {
+        self.search.set_pruner(Box::new(pruner));
+        self
+    }
+
+    /// Returns the number of expressions skipped by the pruner so far (see
+    /// [`Self::with_pruner`])
+    pub fn pruned(&self) -> usize {
+        self.search.pruned()
+    }
+
+    /// Returns the best valid expression found so far (if any)
+    pub fn best(&self) -> Option<&E> {
+        self.search.best()
+    }
+
+    /// Returns the number of decisions needed to reach the best valid
+    /// expression found so far (if any)
+    pub fn best_depth(&self) -> Option<usize> {
+        self.search.best_depth()
+    }
+
+    /// Returns the status of the search
+    pub fn status(&self) -> SolveStatus {
+        self.status
+    }
+
+    /// Returns a handle that can pause and resume the search
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.pause.clone()
+    }
+
+    /// Requests that the search pause (see [`PauseHandle::pause`])
+    pub fn pause(&self) {
+        self.pause.pause();
+    }
+
+    /// Allows a paused search to continue (see [`PauseHandle::resume`])
+    pub fn resume(&self) {
+        self.pause.resume();
+    }
+
+    /// Makes a single unit of progress (one decision or backtrack) unless
+    /// the search is paused or finished, and returns the resulting status
+    pub fn advance(&mut self) -> SolveStatus {
+        self.status = match self.status {
+            SolveStatus::Exhausted
+            | SolveStatus::Incomplete
+            | SolveStatus::OutOfBudget => self.status,
+            _ if self.pause.is_paused() => SolveStatus::Paused,
+            _ => self.search.advance(),
+        };
+        self.status
+    }
+
+    /// Runs the search until it is paused or finished, and returns the
+    /// resulting status
+    pub fn run(&mut self) -> SolveStatus {
+        loop {
+            match self.advance() {
+                SolveStatus::Running => (),
+                status => return status,
+            }
+        }
+    }
+
+    /// Navigates the controller to the best valid expression found so far
+    /// (by deciding the steps that lead to it), ending the search; returns
+    /// whether or not a valid expression had been found
+    pub fn commit(mut self) -> bool {
+        self.search.commit()
+    }
+}
+
+impl<E> Drop for Anytime<'_, E> {
+    fn drop(&mut self) {
+        self.search.abandon();
+    }
+}
+
+struct Frame<S: Step> {
+    exp: S::Exp,
+    step: Option<S>,
+    remaining: Vec<S>,
+    /// The results of applying the remaining steps
+    results: Vec<Option<S::Exp>>,
+    /// The steps from this frame whose subtrees were already explored
+    explored: Vec<S>,
+    /// The steps that need not be explored from this frame because an
+    /// equivalent interleaving was already explored
+    sleep: Vec<S>,
+}
+
+/// How equivalent interleavings of steps are detected
+struct Reduction<S> {
+    commutes: fn(&S, &S) -> bool,
+    same: fn(&S, &S) -> bool,
+}
+
+struct DepthFirst<'a, T, S, P, C, H, D, B>
+where
+    T: Timer,
+    S: Step,
+    P: ?Sized,
+    C: ?Sized,
+    H: ?Sized,
+{
+    controller: &'a mut Controller<T, S, P, C, H>,
+    policy: D,
+    budget: B,
+    frames: Vec<Frame<S>>,
+    best: Option<(S::Exp, Vec<S>)>,
+    reduction: Option<Reduction<S>>,
+    visited: Option<Box<dyn Visited<S::Exp> + 'a>>,
+    pruner: Option<Box<dyn Pruner<S::Exp> + 'a>>,
+    pruned: usize,
+    /// Whether or not the provider failed (or was cut off) on some expression
+    truncated: bool,
+}
+
+impl<T, S, P, C, H, D, B> DepthFirst<'_, T, S, P, C, H, D, B>
+where
+    T: Timer,
+    S: Step + Clone,
+    P: StepProvider<T, Step = S> + ?Sized,
+    C: ValidityChecker<Exp = S::Exp> + ?Sized,
+    H: HistoryStore<S::Exp> + ?Sized,
+    D: Decider<S>,
+    B: Timer,
+{
+    /// Whether or not a decision from the deepest frame could still lead to
+    /// a better result than the best one
+    fn promising(&self) -> bool {
+        self.best
+            .as_ref()
+            .is_none_or(|(_, path)| self.frames.len() < path.len())
+    }
+
+    /// Expands the expression `e` reached by `step` (if it is not valid and
+    /// the provider succeeds), recording it as the best if it is valid and
+    /// recording a truncation if the provider does not succeed
+    fn visit(&mut self, e: S::Exp, step: Option<S>, sleep: Vec<S>) {
+        if let Some(v) = &mut self.visited
+            && !v.visit(&e, self.frames.len())
+        {
+            return;
+        }
+        if self.controller.check(&e) {
+            let path = self
+                .frames
+                .iter()
+                .filter_map(|f| f.step.clone())
+                .chain(step)
+                .collect();
+            self.best = Some((e, path));
+            return;
+        }
+        if self.pruner.as_ref().is_some_and(|p| p.definitely_dead(&e)) {
+            self.pruned += 1;
+            return;
+        }
+        let c = &mut *self.controller;
+        c.resume_timer();
+        let provided = c.provider.try_provide(&c.timer, &e);
+        c.pause_timer();
+        let Ok(mut remaining) = provided else {
+            self.truncated = true;
+            return;
+        };
+        if let Some(r) = &self.reduction {
+            remaining.retain(|s| !sleep.iter().any(|z| (r.same)(z, s)));
+        }
+        let results = S::apply_all(&remaining, &e);
+        self.frames.push(Frame {
+            exp: e,
+            step,
+            remaining,
+            results,
+            explored: vec![],
+            sleep,
+        });
+    }
+
+    /// The status of a search that has no frames left to explore
+    fn finished(&self) -> SolveStatus {
+        if self.truncated {
+            SolveStatus::Incomplete
+        } else {
+            SolveStatus::Exhausted
+        }
+    }
+
+    /// Returns the provider to the expression of the deepest frame
+    fn resync(&mut self) {
+        if let Some(f) = self.frames.last() {
+            self.controller.provider.on_undo(&f.exp);
+        }
+    }
+}
+
+impl<'a, T, S, P, C, H, D, B> Search<'a, S::Exp>
+    for DepthFirst<'a, T, S, P, C, H, D, B>
+where
+    T: Timer,
+    S: Step + Clone,
+    P: StepProvider<T, Step = S> + ?Sized,
+    C: ValidityChecker<Exp = S::Exp> + ?Sized,
+    H: HistoryStore<S::Exp> + ?Sized,
+    D: Decider<S>,
+    B: Timer,
+{
+    fn set_visited(&mut self, mut visited: Box<dyn Visited<S::Exp> + 'a>) {
+        for (depth, f) in self.frames.iter().enumerate() {
+            visited.visit(&f.exp, depth);
+        }
+        self.visited = Some(visited);
+    }
+
+    fn set_pruner(&mut self, pruner: Box<dyn Pruner<S::Exp> + 'a>) {
+        self.pruner = Some(pruner);
+    }
+
+    fn pruned(&self) -> usize {
+        self.pruned
+    }
+
+    fn advance(&mut self) -> SolveStatus {
+        if self.budget.tick().is_err() {
+            return SolveStatus::OutOfBudget;
+        }
+        let promising = self.promising();
+        let finished = self.finished();
+        let Some(top) = self.frames.last_mut() else {
+            return finished;
+        };
+        let choice = if promising && !top.remaining.is_empty() {
+            self.policy.decide(&top.exp, &top.remaining)
+        } else {
+            None
+        };
+        let Some(i) = choice.filter(|&i| i < top.remaining.len()) else {
+            self.frames.pop();
+            self.resync();
+            return if self.frames.is_empty() {
+                self.finished()
+            } else {
+                SolveStatus::Running
+            };
+        };
+        let step = top.remaining.remove(i);
+        let Some(next) = top.results.remove(i) else {
+            return SolveStatus::Running;
+        };
+        let sleep = match &self.reduction {
+            Some(r) => {
+                let sleep = top
+                    .sleep
+                    .iter()
+                    .chain(&top.explored)
+                    .filter(|z| (r.commutes)(z, &step))
+                    .cloned()
+                    .collect();
+                top.explored.push(step.clone());
+                sleep
+            }
+            None => vec![],
+        };
+        let depth = self.frames.len();
+        self.controller.provider.on_decide(&step, &next);
+        self.visit(next, Some(step), sleep);
+        if self.frames.len() == depth {
+            self.resync();
+        }
+        SolveStatus::Running
+    }
+
+    fn best(&self) -> Option<&S::Exp> {
+        self.best.as_ref().map(|(e, _)| e)
+    }
+
+    fn best_depth(&self) -> Option<usize> {
+        self.best.as_ref().map(|(_, path)| path.len())
+    }
+
+    fn commit(&mut self) -> bool {
+        self.abandon();
+        commit(self.controller, self.best.take())
+    }
+
+    fn abandon(&mut self) {
+        if self.frames.len() > 1 {
+            self.controller.provider.on_undo(&self.controller.state);
+        }
+        self.frames.clear();
+    }
+}
+
+/// Decides the steps of the path to the best expression (if any) on the
+/// controller, returning whether or not all of them could be decided
+fn commit<T, S, P, C, H>(
+    controller: &mut Controller<T, S, P, C, H>,
+    best: Option<(S::Exp, Vec<S>)>,
+) -> bool
+where
+    T: Timer,
+    S: Step,
+    P: StepProvider<T, Step = S> + ?Sized,
+    C: ValidityChecker<Exp = S::Exp> + ?Sized,
+    H: HistoryStore<S::Exp> + ?Sized,
+{
+    let Some((_, path)) = best else {
+        return false;
+    };
+    path.into_iter().all(|step| controller.decide(step).is_ok())
+}
+
+/// What is known about an expression in an iterative deepening search
+struct Entry<S> {
+    valid: Option<bool>,
+    steps: Option<Vec<S>>,
+    /// The greatest number of remaining decisions within which no valid
+    /// expression is reachable (if known)
+    failed: Option<usize>,
+    /// Whether or not no valid expression is reachable at all
+    exhausted: bool,
+}
+
+impl<S> Default for Entry<S> {
+    fn default() -> Self {
+        Self {
+            valid: None,
+            steps: None,
+            failed: None,
+            exhausted: false,
+        }
+    }
+}
+
+struct DeepeningFrame<S: Step> {
+    exp: S::Exp,
+    step: Option<S>,
+    /// The steps yet to be explored (with the results of applying them), in
+    /// reverse order
+    remaining: Vec<(S, Option<S::Exp>)>,
+    /// Whether or not exploration below this frame was limited by the depth
+    /// bound
+    truncated: bool,
+}
+
+struct IterativeDeepening<'a, T, S, P, C, H, X, B>
+where
+    T: Timer,
+    S: Step,
+    P: ?Sized,
+    C: ?Sized,
+    H: ?Sized,
+    X: ExpCanon<S::Exp>,
+{
+    controller: &'a mut Controller<T, S, P, C, H>,
+    canon: X,
+    budget: B,
+    table: BTreeMap<X::Key, Entry<S>>,
+    start: S::Exp,
+    /// The depth bound of the current pass (if one has started)
+    bound: Option<usize>,
+    /// Whether or not the current pass was limited by its depth bound (at the
+    /// start expression)
+    truncated: bool,
+    frames: Vec<DeepeningFrame<S>>,
+    best: Option<(S::Exp, Vec<S>)>,
+    pruner: Option<Box<dyn Pruner<S::Exp> + 'a>>,
+    pruned: usize,
+}
+
+impl<T, S, P, C, H, X, B> IterativeDeepening<'_, T, S, P, C, H, X, B>
+where
+    T: Timer,
+    S: Step + Clone,
+    P: StepProvider<T, Step = S> + ?Sized,
+    C: ValidityChecker<Exp = S::Exp> + ?Sized,
+    H: HistoryStore<S::Exp> + ?Sized,
+    X: ExpCanon<S::Exp>,
+    B: Timer,
+{
+    /// Expands the expression `e` reached by `step` within the depth bound
+    /// (consulting the transposition table), recording it as the best if it
+    /// is valid
+    fn visit(&mut self, e: S::Exp, step: Option<S>) {
+        let remaining = self.bound.unwrap_or(0) - self.frames.len();
+        let entry = self.table.entry(self.canon.canonical_key(&e)).or_default();
+        let valid =
+            *entry.valid.get_or_insert_with(|| self.controller.check(&e));
+        if valid {
+            let path = self
+                .frames
+                .iter()
+                .filter_map(|f| f.step.clone())
+                .chain(step)
+                .collect();
+            self.best = Some((e, path));
+            return;
+        }
+        if entry.exhausted || entry.steps.as_ref().is_some_and(Vec::is_empty) {
+            return;
+        }
+        if self.pruner.as_ref().is_some_and(|p| p.definitely_dead(&e)) {
+            entry.exhausted = true;
+            self.pruned += 1;
+            return;
+        }
+        if remaining == 0 || entry.failed.is_some_and(|r| r >= remaining) {
+            self.truncate();
+            return;
+        }
+        if entry.steps.is_none() {
+            let c = &mut *self.controller;
+            c.resume_timer();
+            entry.steps = c.provider.try_provide(&c.timer, &e).ok();
+            c.pause_timer();
+        }
+        let Some(steps) = &entry.steps else {
+            // The provider may succeed with more time in a later pass
+            self.truncate();
+            return;
+        };
+        let below = remaining - 1;
+        let remaining: Vec<_> = steps
+            .iter()
+            .cloned()
+            .zip(S::apply_all(steps, &e))
+            .rev()
+            .collect();
+        if below > 0 {
+            self.provide_ahead(
+                remaining.iter().rev().filter_map(|(_, r)| r.as_ref()),
+                below,
+            );
+        }
+        self.frames.push(DeepeningFrame {
+            exp: e,
+            step,
+            remaining,
+            truncated: false,
+        });
+    }
+
+    /// Provides the steps for the expressions `es` (which will be expanded
+    /// with `remaining` decisions left) in one call, if batch provision was
+    /// negotiated and they are not in the transposition table
+    fn provide_ahead<'e>(
+        &mut self,
+        es: impl Iterator<Item = &'e S::Exp>,
+        remaining: usize,
+    ) where
+        S::Exp: 'e,
+    {
+        if !self.controller.capabilities.batch {
+            return;
+        }
+        let mut batch = vec![];
+        for e in es {
+            let entry =
+                self.table.entry(self.canon.canonical_key(e)).or_default();
+            let valid =
+                *entry.valid.get_or_insert_with(|| self.controller.check(e));
+            if valid
+                || entry.exhausted
+                || entry.steps.is_some()
+                || entry.failed.is_some_and(|r| r >= remaining)
+                || self.pruner.as_ref().is_some_and(|p| p.definitely_dead(e))
+            {
+                continue;
+            }
+            batch.push(e.clone());
+        }
+        if batch.len() < 2 {
+            return;
+        }
+        let Ok(provided) = self.controller.provide_batch(&batch) else {
+            return;
+        };
+        for (e, steps) in batch.iter().zip(provided) {
+            self.table
+                .entry(self.canon.canonical_key(e))
+                .or_default()
+                .steps = Some(steps);
+        }
+    }
+
+    /// Records that exploration below the deepest frame was limited by the
+    /// depth bound
+    fn truncate(&mut self) {
+        match self.frames.last_mut() {
+            Some(f) => f.truncated = true,
+            None => self.truncated = true,
+        }
+    }
+
+    /// Returns the provider to the expression of the deepest frame
+    fn resync(&mut self) {
+        if let Some(f) = self.frames.last() {
+            self.controller.provider.on_undo(&f.exp);
+        }
+    }
+}
+
+impl<'a, T, S, P, C, H, X, B> Search<'a, S::Exp>
+    for IterativeDeepening<'a, T, S, P, C, H, X, B>
+where
+    T: Timer,
+    S: Step + Clone,
+    P: StepProvider<T, Step = S> + ?Sized,
+    C: ValidityChecker<Exp = S::Exp> + ?Sized,
+    H: HistoryStore<S::Exp> + ?Sized,
+    X: ExpCanon<S::Exp>,
+    B: Timer,
+{
+    fn set_visited(&mut self, _visited: Box<dyn Visited<S::Exp> + 'a>) {}
+
+    fn set_pruner(&mut self, pruner: Box<dyn Pruner<S::Exp> + 'a>) {
+        self.pruner = Some(pruner);
+    }
+
+    fn pruned(&self) -> usize {
+        self.pruned
+    }
+
+    fn advance(&mut self) -> SolveStatus {
+        if self.budget.tick().is_err() {
+            return SolveStatus::OutOfBudget;
+        }
+        if self.best.is_some() {
+            self.abandon();
+            return SolveStatus::Exhausted;
+        }
+        let Some(top) = self.frames.last_mut() else {
+            if self.bound.is_some() && !self.truncated {
+                return SolveStatus::Exhausted;
+            }
+            self.bound = Some(self.bound.map_or(0, |b| b + 1));
+            self.truncated = false;
+            self.visit(self.start.clone(), None);
+            return SolveStatus::Running;
+        };
+        let Some((step, next)) = top.remaining.pop() else {
+            let frame = self.frames.pop().unwrap();
+            let remaining = self.bound.unwrap_or(0) - self.frames.len();
+            let entry = self
+                .table
+                .entry(self.canon.canonical_key(&frame.exp))
+                .or_default();
+            entry.failed = entry.failed.max(Some(remaining));
+            entry.exhausted = !frame.truncated;
+            if frame.truncated {
+                self.truncate();
+            }
+            self.resync();
+            return SolveStatus::Running;
+        };
+        let Some(next) = next else {
+            return SolveStatus::Running;
+        };
+        let depth = self.frames.len();
+        self.controller.provider.on_decide(&step, &next);
+        self.visit(next, Some(step));
+        if self.frames.len() == depth {
+            self.resync();
+        }
+        SolveStatus::Running
+    }
+
+    fn best(&self) -> Option<&S::Exp> {
+        self.best.as_ref().map(|(e, _)| e)
+    }
+
+    fn best_depth(&self) -> Option<usize> {
+        self.best.as_ref().map(|(_, path)| path.len())
+    }
+
+    fn commit(&mut self) -> bool {
+        self.abandon();
+        commit(self.controller, self.best.take())
+    }
+
+    fn abandon(&mut self) {
+        if self.frames.len() > 1 {
+            self.controller.provider.on_undo(&self.controller.state);
+        }
+        self.frames.clear();
+    }
+}
+
+impl<T, S, P, C, H> Controller<T, S, P, C, H>
+where
+    T: Timer,
+    S: Step + Clone,
+    P: StepProvider<T, Step = S> + ?Sized,
+    C: ValidityChecker<Exp = S::Exp> + ?Sized,
+    H: HistoryStore<S::Exp> + ?Sized,
+{
+    /// Starts an anytime search for a valid expression reachable from the
+    /// working expression, exploring provided steps in the order preferred by
+    /// `policy` until `budget` is cut off (see [`Anytime`])
+    ///
+    /// The timer of the controller is used for each call to the provider;
+    /// `budget` is ticked once per unit of progress of the search.
+    pub fn solve<'a>(
+        &'a mut self,
+        policy: impl Decider<S> + 'a,
+        budget: impl Timer + 'a,
+    ) -> Anytime<'a, S::Exp> {
+        self.search(policy, budget, None)
+    }
+
+    fn search<'a>(
+        &'a mut self,
+        policy: impl Decider<S> + 'a,
+        budget: impl Timer + 'a,
+        reduction: Option<Reduction<S>>,
+    ) -> Anytime<'a, S::Exp> {
+        let start = self.state.clone();
+        let mut search = DepthFirst {
+            controller: self,
+            policy,
+            budget,
+            frames: vec![],
+            best: None,
+            reduction,
+            visited: None,
+            pruner: None,
+            pruned: 0,
+            truncated: false,
+        };
+        search.visit(start, None, vec![]);
+        Anytime {
+            search: Box::new(search),
+            pause: PauseHandle::default(),
+            status: SolveStatus::Running,
+        }
+    }
+
+    /// Starts an anytime iterative deepening search for a valid expression
+    /// reachable from the working expression in the fewest decisions, which
+    /// shares provided steps and validity outcomes between its passes in a
+    /// transposition table keyed by `canon` (see [`Anytime`])
+    ///
+    /// Because shallower expressions are explored first, the first valid
+    /// expression found is the best one. The search also stops once a pass
+    /// explores every reachable expression within its depth bound.
+    pub fn solve_deepening<'a, X>(
+        &'a mut self,
+        canon: X,
+        budget: impl Timer + 'a,
+    ) -> Anytime<'a, S::Exp>
+    where
+        X: ExpCanon<S::Exp> + 'a,
+    {
+        let start = self.state.clone();
+        Anytime {
+            search: Box::new(IterativeDeepening {
+                controller: self,
+                canon,
+                budget,
+                table: BTreeMap::new(),
+                start,
+                bound: None,
+                truncated: false,
+                frames: vec![],
+                best: None,
+                pruner: None,
+                pruned: 0,
+            }),
+            pause: PauseHandle::default(),
+            status: SolveStatus::Running,
+        }
+    }
+}
+
+impl<T, S, P, C, H> Controller<T, S, P, C, H>
+where
+    T: Timer,
+    S: CommutingSteps + Clone + PartialEq,
+    P: StepProvider<T, Step = S> + ?Sized,
+    C: ValidityChecker<Exp = S::Exp> + ?Sized,
+    H: HistoryStore<S::Exp> + ?Sized,
+{
+    /// Starts an anytime search like [`Self::solve`], but explores only one
+    /// interleaving of commuting steps (see [`CommutingSteps`])
+    ///
+    /// A step is not explored if an equal step that commutes with every step
+    /// decided since was already explored from an earlier expression on the
+    /// path.
+    pub fn solve_commuting<'a>(
+        &'a mut self,
+        policy: impl Decider<S> + 'a,
+        budget: impl Timer + 'a,
+    ) -> Anytime<'a, S::Exp> {
+        self.search(
+            policy,
+            budget,
+            Some(Reduction {
+                commutes: S::commutes_with,
+                same: S::eq,
+            }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProviderError;
+    use crate::budget::{OutOfBudget, TickBudget};
+    use crate::testing::{AllEqual, Fill, Fills, Slots, budget, empty};
+
+    /// Always decides on the first provided step
+    struct First;
+
+    impl Decider<Fill> for First {
+        fn decide(&mut self, _e: &Slots, _steps: &[Fill]) -> Option<usize> {
+            Some(0)
+        }
+    }
+
+    impl CommutingSteps for Fill {
+        fn commutes_with(&self, other: &Self) -> bool {
+            self.0 != other.0
+        }
+    }
+
+    /// Returns a controller at the empty slots along with a handle to the
+    /// timer that its provider ticks once per call
+    fn controller() -> (Controller<TickBudget, Fill>, TickBudget) {
+        let timer = budget();
+        let c = Controller::new(
+            timer.clone(),
+            Fills { digits: 2 },
+            AllEqual(1),
+            empty(),
+            true,
+        );
+        (c, timer)
+    }
+
+    /// Returns the number of ticks used from a timer made by [`budget`]
+    fn used(timer: &TickBudget) -> usize {
+        budget().remaining() - timer.remaining()
+    }
+
+    #[test]
+    fn solving_finds_and_commits_the_best_expression() {
+        let (mut c, _) = controller();
+        let mut search = c.solve(First, budget());
+        assert_eq!(search.run(), SolveStatus::Exhausted);
+        assert_eq!(search.best(), Some(&[Some(1); 3]));
+        assert_eq!(search.best_depth(), Some(3));
+        assert!(search.commit());
+        assert_eq!(*c.working_expression(), [Some(1); 3]);
+        for _ in 0..3 {
+            c.undo().unwrap();
+        }
+        assert!(!c.can_undo());
+    }
+
+    /// Provides fills, but fails on one expression
+    struct FailsOn(Slots);
+
+    impl StepProvider<TickBudget> for FailsOn {
+        type Step = Fill;
+
+        fn provide(
+            &mut self,
+            timer: &TickBudget,
+            e: &Slots,
+        ) -> Result<Vec<Fill>, OutOfBudget> {
+            Fills { digits: 2 }.provide(timer, e)
+        }
+
+        fn try_provide(
+            &mut self,
+            timer: &TickBudget,
+            e: &Slots,
+        ) -> Result<Vec<Fill>, ProviderError<OutOfBudget>> {
+            if *e == self.0 {
+                return Err(ProviderError::failure(core::fmt::Error));
+            }
+            Ok(self.provide(timer, e)?)
+        }
+    }
+
+    #[test]
+    fn provider_failures_make_searches_incomplete() {
+        let failing = FailsOn([Some(1), None, None]);
+        let mut c =
+            Controller::new(budget(), failing, AllEqual(1), empty(), false);
+        let mut search = c.solve(First, budget());
+        assert_eq!(search.run(), SolveStatus::Incomplete);
+        assert_eq!(search.advance(), SolveStatus::Incomplete);
+        assert_eq!(search.best(), Some(&[Some(1); 3]));
+        drop(search);
+
+        let mut c = Controller::new(
+            budget(),
+            FailsOn(empty()),
+            AllEqual(1),
+            empty(),
+            false,
+        );
+        let mut search = c.solve(First, budget());
+        assert_eq!(search.run(), SolveStatus::Incomplete);
+        assert!(search.best().is_none());
+    }
+
+    #[test]
+    fn abandoned_searches_leave_the_controller_in_place() {
+        let (mut c, _) = controller();
+        c.decide(Fill(1, 1)).unwrap();
+        let start = *c.working_expression();
+        let mut search = c.solve(First, TickBudget::new(4));
+        assert_eq!(search.run(), SolveStatus::OutOfBudget);
+        assert_eq!(search.advance(), SolveStatus::OutOfBudget);
+        drop(search);
+        assert_eq!(*c.working_expression(), start);
+        c.undo().unwrap();
+        assert!(!c.can_undo());
+    }
+
+    #[test]
+    fn searches_pause_and_resume() {
+        let (mut c, _) = controller();
+        let mut search = c.solve(First, budget());
+        search.advance();
+        let handle = search.pause_handle();
+        handle.pause();
+        assert_eq!(search.run(), SolveStatus::Paused);
+        assert!(search.best().is_none());
+        handle.resume();
+        assert_eq!(search.run(), SolveStatus::Exhausted);
+        assert!(search.best().is_some());
+    }
+
+    #[test]
+    fn commuting_searches_explore_one_interleaving() {
+        let (mut c, _) = controller();
+        let full = budget();
+        let mut search = c.solve(First, full.clone());
+        assert_eq!(search.run(), SolveStatus::Exhausted);
+        let best = search.best().copied();
+        drop(search);
+
+        let reduced = budget();
+        let mut search = c.solve_commuting(First, reduced.clone());
+        assert_eq!(search.run(), SolveStatus::Exhausted);
+        assert_eq!(search.best().copied(), best);
+        assert_eq!(search.best_depth(), Some(3));
+        drop(search);
+        assert!(used(&reduced) < used(&full));
+    }
+
+    #[test]
+    fn deepening_searches_share_provided_steps_between_passes() {
+        let (mut c, timer) = controller();
+        let mut search = c.solve_deepening(|e: &Slots| *e, budget());
+        search.run();
+        assert_eq!(search.best(), Some(&[Some(1); 3]));
+        assert_eq!(search.best_depth(), Some(3));
+        assert!(search.commit());
+        assert_eq!(*c.working_expression(), [Some(1); 3]);
+        // At most one call per distinct expression (each slot is empty or
+        // holds one of two digits)
+        assert!(used(&timer) <= 27);
+    }
+
+    #[test]
+    fn out_of_range_backends_are_rejected() {
+        let (mut c, _) = controller();
+        let canon = |e: &Slots| *e;
+        let lru = |capacity| VisitedBackend::Lru { capacity };
+        let search = c.solve(First, budget());
+        assert!(search.try_with_canon_backend(canon, lru(0)).is_err());
+        let mut search = c
+            .solve(First, budget())
+            .try_with_canon_backend(canon, lru(64))
+            .unwrap();
+        assert_eq!(search.run(), SolveStatus::Exhausted);
+        assert_eq!(search.best(), Some(&[Some(1); 3]));
+    }
+}
@@ -0,0 +1,404 @@
+//! Runtime monitoring of Strong Soundness
+//!
+//! A [`SoundnessMonitor`] wraps a provider and, after each decision, checks
+//! that every step provided in the round leading up to it applies and can
+//! still reach a valid expression, according to a (possibly budgeted)
+//! reachability check. Unlike [`CheckedProvider`](crate::CheckedProvider),
+//! violations do not panic; they are collected in a [`SoundnessReport`] that
+//! can be exported as JSON (e.g., by continuous integration benchmarks).
+//!
+//! A bounded [`OracleProvider`](crate::oracle::OracleProvider) makes a good
+//! reachability check:
+//!
+//! ```ignore
+//! let check = move |e: &Exp| oracle.reachable(&TickBudget::new(10_000), e, 5).ok();
+//! let monitor = SoundnessMonitor::new(provider, check);
+//! ```
+
+use crate::{
+    Annotation, Capabilities, Context, Feedback, ProgressReporter,
+    ProviderError, ReachabilityOracle, Step, StepProvider, Timer,
+    json::Value,
+    sync::Mutex,
+    trace::{ExpCodec, StepCodec},
+};
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The ways a provided step can violate Strong Soundness
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// The step does not apply to the expression it was provided for
+    Inapplicable,
+    /// No valid expression is reachable after the step
+    Unreachable,
+}
+
+impl ViolationKind {
+    fn name(self) -> &'static str {
+        match self {
+            ViolationKind::Inapplicable => "inapplicable",
+            ViolationKind::Unreachable => "unreachable",
+        }
+    }
+}
+
+/// A provided step that violates Strong Soundness
+#[derive(Debug, Clone)]
+pub struct Violation<S: Step> {
+    /// The (zero-based) round of provided steps, counted by decisions
+    pub round: usize,
+    /// The expression the step was provided for
+    pub exp: S::Exp,
+    /// The offending step
+    pub step: S,
+    /// The kind of violation
+    pub kind: ViolationKind,
+}
+
+struct ReportState<S: Step> {
+    rounds: usize,
+    checked: usize,
+    inconclusive: usize,
+    violations: Vec<Violation<S>>,
+}
+
+/// A handle to the findings of a [`SoundnessMonitor`] (which remains usable
+/// after the monitor has been moved into a combinator or
+/// [`Controller`](crate::Controller))
+pub struct SoundnessReport<S: Step> {
+    state: Arc<Mutex<ReportState<S>>>,
+    enabled: Arc<AtomicBool>,
+}
+
+impl<S: Step> Clone for SoundnessReport<S> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+            enabled: self.enabled.clone(),
+        }
+    }
+}
+
+impl<S: Step + Clone> SoundnessReport<S> {
+    /// Enables or disables checking (checking is enabled initially)
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Returns whether or not checking is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of rounds of provided steps that were checked
+    pub fn rounds(&self) -> usize {
+        self.state.lock().rounds
+    }
+
+    /// Returns the number of provided steps that were checked
+    pub fn checked(&self) -> usize {
+        self.state.lock().checked
+    }
+
+    /// Returns the number of provided steps whose reachability could not be
+    /// determined (e.g., because the check ran out of budget)
+    pub fn inconclusive(&self) -> usize {
+        self.state.lock().inconclusive
+    }
+
+    /// Returns the violations found so far, in order
+    pub fn violations(&self) -> Vec<Violation<S>> {
+        self.state.lock().violations.clone()
+    }
+
+    /// Returns whether or not no violations were found so far
+    pub fn is_sound(&self) -> bool {
+        self.state.lock().violations.is_empty()
+    }
+
+    /// Returns the report as a JSON object, with the counts of rounds,
+    /// checked steps, and inconclusive checks, and a list of violations
+    /// (each with its `round`, `kind`, `exp`, and `step`)
+    pub fn to_json(
+        &self,
+        exp_codec: &impl ExpCodec<S>,
+        step_codec: &impl StepCodec<S>,
+    ) -> Value {
+        let state = self.state.lock();
+        let violations = state
+            .violations
+            .iter()
+            .map(|v| {
+                Value::Object(vec![
+                    ("round".to_owned(), Value::Number(v.round as f64)),
+                    (
+                        "kind".to_owned(),
+                        Value::String(v.kind.name().to_owned()),
+                    ),
+                    ("exp".to_owned(), exp_codec.encode(&v.exp)),
+                    ("step".to_owned(), step_codec.encode(&v.step)),
+                ])
+            })
+            .collect();
+        Value::Object(vec![
+            ("rounds".to_owned(), Value::Number(state.rounds as f64)),
+            ("checked".to_owned(), Value::Number(state.checked as f64)),
+            (
+                "inconclusive".to_owned(),
+                Value::Number(state.inconclusive as f64),
+            ),
+            ("violations".to_owned(), Value::Array(violations)),
+        ])
+    }
+}
+
+/// A reachability check, returning `None` if reachability could not be
+/// determined
+type Check<E> = Box<dyn Fn(&E) -> Option<bool>>;
+
+/// A provider that checks the Strong Soundness of the steps provided by
+/// another provider after each decision (see [`SoundnessReport`])
+pub struct SoundnessMonitor<P, S: Step> {
+    inner: P,
+    check: Check<S::Exp>,
+    /// The most recently provided steps (and the expression they were
+    /// provided for), which are checked at the next decision
+    pending: Option<(S::Exp, Vec<S>)>,
+    report: SoundnessReport<S>,
+}
+
+impl<P, S: Step + Clone> SoundnessMonitor<P, S> {
+    /// Creates a new [`SoundnessMonitor`] wrapping `inner`, where `check`
+    /// returns whether or not a valid expression is reachable from an
+    /// expression (or `None` if this could not be determined)
+    pub fn new(
+        inner: P,
+        check: impl Fn(&S::Exp) -> Option<bool> + 'static,
+    ) -> Self {
+        Self {
+            inner,
+            check: Box::new(check),
+            pending: None,
+            report: SoundnessReport {
+                state: Arc::new(Mutex::new(ReportState {
+                    rounds: 0,
+                    checked: 0,
+                    inconclusive: 0,
+                    violations: vec![],
+                })),
+                enabled: Arc::new(AtomicBool::new(true)),
+            },
+        }
+    }
+
+    /// Creates a new [`SoundnessMonitor`] wrapping `inner` that checks
+    /// reachability with a [`ReachabilityOracle`]
+    pub fn with_oracle(
+        inner: P,
+        oracle: impl ReachabilityOracle<Exp = S::Exp> + 'static,
+    ) -> Self {
+        Self::new(inner, move |e| Some(oracle.reachable(e)))
+    }
+
+    /// Returns a handle to the findings of the monitor
+    pub fn report(&self) -> SoundnessReport<S> {
+        self.report.clone()
+    }
+
+    /// Returns the inner provider and drops self
+    pub fn into_inner(self) -> P {
+        self.inner
+    }
+
+    /// Remembers provided steps to check at the next decision
+    fn observe(&mut self, e: &S::Exp, steps: &[S]) {
+        self.pending = self
+            .report
+            .is_enabled()
+            .then(|| (e.clone(), steps.to_vec()));
+    }
+
+    /// Checks the most recently provided steps
+    fn verify(&mut self) {
+        let Some((e, steps)) = self.pending.take() else {
+            return;
+        };
+        let mut state = self.report.state.lock();
+        let round = state.rounds;
+        state.rounds += 1;
+        for step in steps {
+            state.checked += 1;
+            let kind = match step.apply(&e) {
+                None => ViolationKind::Inapplicable,
+                Some(next) => match (self.check)(&next) {
+                    Some(true) => continue,
+                    Some(false) => ViolationKind::Unreachable,
+                    None => {
+                        state.inconclusive += 1;
+                        continue;
+                    }
+                },
+            };
+            state.violations.push(Violation {
+                round,
+                exp: e.clone(),
+                step,
+                kind,
+            });
+        }
+    }
+}
+
+impl<T, P, S> StepProvider<T> for SoundnessMonitor<P, S>
+where
+    T: Timer,
+    S: Step + Clone,
+    P: StepProvider<T, Step = S>,
+{
+    type Step = S;
+
+    fn provide(
+        &mut self,
+        timer: &T,
+        e: &S::Exp,
+    ) -> Result<Vec<S>, T::EarlyCutoff> {
+        let steps = self.inner.provide(timer, e)?;
+        self.observe(e, &steps);
+        Ok(steps)
+    }
+
+    fn provide_partial(
+        &mut self,
+        timer: &T,
+        e: &S::Exp,
+    ) -> (Vec<S>, Option<T::EarlyCutoff>) {
+        let (steps, cutoff) = self.inner.provide_partial(timer, e);
+        self.observe(e, &steps);
+        (steps, cutoff)
+    }
+
+    fn try_provide(
+        &mut self,
+        timer: &T,
+        e: &S::Exp,
+    ) -> Result<Vec<S>, ProviderError<T::EarlyCutoff>> {
+        let steps = self.inner.try_provide(timer, e)?;
+        self.observe(e, &steps);
+        Ok(steps)
+    }
+
+    fn provide_annotated(
+        &mut self,
+        timer: &T,
+        e: &S::Exp,
+        annotations: &[Annotation],
+    ) -> Result<Vec<S>, T::EarlyCutoff> {
+        let steps = self.inner.provide_annotated(timer, e, annotations)?;
+        self.observe(e, &steps);
+        Ok(steps)
+    }
+
+    fn capabilities(&mut self) -> Capabilities {
+        self.inner.capabilities().forwarded()
+    }
+
+    fn on_session_start(&mut self, start: &S::Exp) {
+        self.pending = None;
+        self.inner.on_session_start(start);
+    }
+
+    fn on_decide(&mut self, step: &S, e: &S::Exp) {
+        self.verify();
+        self.inner.on_decide(step, e);
+    }
+
+    fn on_undo(&mut self, e: &S::Exp) {
+        self.pending = None;
+        self.inner.on_undo(e);
+    }
+
+    fn on_external_edit(&mut self, prev: &S::Exp, e: &S::Exp) {
+        self.pending = None;
+        self.inner.on_external_edit(prev, e);
+    }
+
+    fn on_refine(&mut self, valid: &dyn Fn(&S::Exp) -> bool) {
+        self.pending = None;
+        self.inner.on_refine(valid);
+    }
+
+    fn on_feedback(&mut self, step: &S, feedback: Feedback) {
+        self.inner.on_feedback(step, feedback);
+    }
+
+    fn set_progress(&mut self, reporter: ProgressReporter) {
+        self.inner.set_progress(reporter);
+    }
+
+    fn set_context(&mut self, context: Context) {
+        self.pending = None;
+        self.inner.set_context(context);
+    }
+
+    fn warmup(&mut self, timer: &T) -> Result<(), T::EarlyCutoff> {
+        self.inner.warmup(timer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::TickBudget;
+    use crate::testing::{Fill, Fixed, Slots, budget, empty};
+
+    type Monitor = SoundnessMonitor<Fixed, Fill>;
+
+    /// Reaches a valid expression unless some slot holds a 2, and cannot
+    /// tell when the last slot holds a 1
+    fn check(e: &Slots) -> Option<bool> {
+        (e[2] != Some(1)).then(|| !e.contains(&Some(2)))
+    }
+
+    fn violation(v: &Violation<Fill>) -> (usize, Slots, Fill, ViolationKind) {
+        (v.round, v.exp, v.step, v.kind)
+    }
+
+    /// Decides on a step, checking the steps provided before it
+    fn decide(monitor: &mut Monitor, step: Fill, e: &Slots) {
+        StepProvider::<TickBudget>::on_decide(monitor, &step, e);
+    }
+
+    #[test]
+    fn violations_are_reported_by_round() {
+        let steps = vec![Fill(0, 1), Fill(1, 2), Fill(2, 1), Fill(1, 1)];
+        let mut monitor = Monitor::new(Fixed(steps), check);
+        let report = monitor.report();
+        let one = [Some(1), None, None];
+
+        monitor.provide(&budget(), &empty()).unwrap();
+        StepProvider::<TickBudget>::on_undo(&mut monitor, &empty());
+        decide(&mut monitor, Fill(0, 1), &one);
+        assert_eq!((report.rounds(), report.checked()), (0, 0));
+
+        monitor.provide(&budget(), &empty()).unwrap();
+        decide(&mut monitor, Fill(0, 1), &one);
+        monitor.provide(&budget(), &one).unwrap();
+        decide(&mut monitor, Fill(1, 1), &[Some(1), Some(1), None]);
+
+        assert_eq!(report.rounds(), 2);
+        assert_eq!(report.checked(), 8);
+        assert_eq!(report.inconclusive(), 2);
+        assert!(!report.is_sound());
+        let violations = report.violations();
+        assert_eq!(
+            violations.iter().map(violation).collect::<Vec<_>>(),
+            vec![
+                (0, empty(), Fill(1, 2), ViolationKind::Unreachable),
+                (1, one, Fill(0, 1), ViolationKind::Inapplicable),
+                (1, one, Fill(1, 2), ViolationKind::Unreachable),
+            ]
+        );
+    }
+}
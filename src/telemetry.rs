@@ -0,0 +1,210 @@
+//! Telemetry for [`Controller`](crate::Controller) sessions
+//!
+//! A [`TelemetrySink`] attached to a controller (see
+//! [`Controller::with_telemetry`](crate::Controller::with_telemetry)) receives
+//! an [`Event`] for every call to the provider, decision, and undo, so that
+//! research deployments can collect consistent measurements regardless of
+//! front-end.
+
+use crate::json::Value;
+
+use std::io::Write;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The kinds of telemetry events
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventKind {
+    /// The provider was asked to provide steps
+    Provide {
+        /// How long the provider took
+        latency: Duration,
+        /// The number of steps provided
+        steps: usize,
+        /// Whether or not the provider was cut off by the timer
+        cut_off: bool,
+    },
+    /// A step was decided
+    Decide {
+        /// The name of the provider that provided the step (if known)
+        source: Option<String>,
+    },
+    /// A decision was undone
+    Undo,
+    /// The working expression was edited outside of navigation
+    ExternalEdit,
+}
+
+/// A telemetry event
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// The time at which the event occurred
+    pub time: SystemTime,
+    /// The number of decisions in the session when the event occurred
+    pub depth: usize,
+    /// The kind of the event
+    pub kind: EventKind,
+}
+
+impl Event {
+    /// Returns the event as a JSON object
+    pub fn to_json(&self) -> Value {
+        let millis = self
+            .time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let mut fields = vec![
+            ("timestamp_ms".to_owned(), Value::Number(millis as f64)),
+            ("depth".to_owned(), Value::Number(self.depth as f64)),
+        ];
+        match &self.kind {
+            EventKind::Provide {
+                latency,
+                steps,
+                cut_off,
+            } => fields.extend([
+                ("event".to_owned(), Value::String("provide".into())),
+                (
+                    "latency_ms".to_owned(),
+                    Value::Number(latency.as_secs_f64() * 1000.0),
+                ),
+                ("steps".to_owned(), Value::Number(*steps as f64)),
+                ("cut_off".to_owned(), Value::Bool(*cut_off)),
+            ]),
+            EventKind::Decide { source } => fields.extend([
+                ("event".to_owned(), Value::String("decide".into())),
+                (
+                    "source".to_owned(),
+                    source.clone().map_or(Value::Null, Value::String),
+                ),
+            ]),
+            EventKind::Undo => {
+                fields.push(("event".to_owned(), Value::String("undo".into())))
+            }
+            EventKind::ExternalEdit => fields.push((
+                "event".to_owned(),
+                Value::String("external_edit".into()),
+            )),
+        }
+        Value::Object(fields)
+    }
+}
+
+/// A destination for telemetry events
+pub trait TelemetrySink: Send {
+    /// Records an event
+    fn record(&mut self, event: Event);
+
+    /// Flushes any buffered events
+    fn flush(&mut self) {}
+}
+
+/// A telemetry sink that collects events in memory, making them visible in
+/// batches
+///
+/// Events are buffered until the batch is full (or the sink is flushed or
+/// dropped), at which point they are published to the shared log returned by
+/// [`Self::events`].
+pub struct MemorySink {
+    batch: Vec<Event>,
+    batch_size: usize,
+    log: Arc<Mutex<Vec<Event>>>,
+}
+
+impl MemorySink {
+    /// Creates a new in-memory sink that publishes events in batches of
+    /// `batch_size` (a batch size of one publishes every event immediately)
+    pub fn new(batch_size: usize) -> Self {
+        Self {
+            batch: vec![],
+            batch_size: batch_size.max(1),
+            log: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    /// Returns a handle to the published events
+    pub fn events(&self) -> Arc<Mutex<Vec<Event>>> {
+        self.log.clone()
+    }
+}
+
+impl TelemetrySink for MemorySink {
+    fn record(&mut self, event: Event) {
+        self.batch.push(event);
+        if self.batch.len() >= self.batch_size {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        self.log
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .append(&mut self.batch);
+    }
+}
+
+impl Drop for MemorySink {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// A telemetry sink that writes events as JSON lines
+///
+/// Output is buffered; it is written when the sink is flushed or dropped.
+/// Write errors do not interrupt the session; the first one is kept and can
+/// be retrieved with [`Self::take_error`].
+pub struct JsonlSink<W: Write + Send> {
+    writer: std::io::BufWriter<W>,
+    error: Option<std::io::Error>,
+}
+
+impl JsonlSink<std::fs::File> {
+    /// Creates a sink that writes to a (truncated) file
+    pub fn create(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        Ok(Self::new(std::fs::File::create(path)?))
+    }
+}
+
+impl<W: Write + Send> JsonlSink<W> {
+    /// Creates a sink that writes to a writer
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: std::io::BufWriter::new(writer),
+            error: None,
+        }
+    }
+
+    /// Returns (and clears) the first write error encountered (if any)
+    pub fn take_error(&mut self) -> Option<std::io::Error> {
+        self.error.take()
+    }
+
+    fn result(&mut self, r: std::io::Result<()>) {
+        if let Err(e) = r
+            && self.error.is_none()
+        {
+            self.error = Some(e);
+        }
+    }
+}
+
+impl<W: Write + Send> TelemetrySink for JsonlSink<W> {
+    fn record(&mut self, event: Event) {
+        let r = writeln!(self.writer, "{}", event.to_json());
+        self.result(r);
+    }
+
+    fn flush(&mut self) {
+        let r = self.writer.flush();
+        self.result(r);
+    }
+}
+
+impl<W: Write + Send> Drop for JsonlSink<W> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
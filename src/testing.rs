@@ -0,0 +1,179 @@
+//! A small navigation domain shared by the unit tests
+//!
+//! Expressions are three slots that are each empty or hold a digit, and
+//! steps fill one empty slot.
+
+use crate::{
+    Step, StepEnumerator, StepProvider, Timer, ValidityChecker,
+    budget::TickBudget,
+    render::{Render, Rendering, Style},
+};
+
+use alloc::{format, vec::Vec};
+
+#[cfg(feature = "std")]
+use crate::{json::Value, trace::Codec};
+
+/// Three slots, each empty or holding a digit
+pub(crate) type Slots = [Option<u8>; 3];
+
+/// Fills the (empty) slot at an index with a digit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) struct Fill(pub(crate) usize, pub(crate) u8);
+
+impl Step for Fill {
+    type Exp = Slots;
+
+    fn apply(&self, e: &Slots) -> Option<Slots> {
+        let mut e = *e;
+        let slot = e.get_mut(self.0)?;
+        if slot.is_some() {
+            return None;
+        }
+        *slot = Some(self.1);
+        Some(e)
+    }
+}
+
+/// Provides every fill of an empty slot with a digit below `digits` (in
+/// order of slot, then digit), ticking the timer once per call
+pub(crate) struct Fills {
+    pub(crate) digits: u8,
+}
+
+impl<T: Timer> StepProvider<T> for Fills {
+    type Step = Fill;
+
+    fn provide(
+        &mut self,
+        timer: &T,
+        e: &Slots,
+    ) -> Result<Vec<Fill>, T::EarlyCutoff> {
+        timer.tick()?;
+        Ok(self.enumerate(e))
+    }
+}
+
+impl StepEnumerator for Fills {
+    type Step = Fill;
+
+    fn enumerate(&self, e: &Slots) -> Vec<Fill> {
+        (0..e.len())
+            .filter(|&i| e[i].is_none())
+            .flat_map(|i| (0..self.digits).map(move |d| Fill(i, d)))
+            .collect()
+    }
+}
+
+/// Provides the same steps (in order) for every expression, ticking the timer
+/// once per call
+pub(crate) struct Fixed(pub(crate) Vec<Fill>);
+
+impl<T: Timer> StepProvider<T> for Fixed {
+    type Step = Fill;
+
+    fn provide(
+        &mut self,
+        timer: &T,
+        _e: &Slots,
+    ) -> Result<Vec<Fill>, T::EarlyCutoff> {
+        timer.tick()?;
+        Ok(self.0.clone())
+    }
+}
+
+/// Accepts the expressions whose slots all hold a particular digit
+#[derive(Clone)]
+pub(crate) struct AllEqual(pub(crate) u8);
+
+impl ValidityChecker for AllEqual {
+    type Exp = Slots;
+
+    fn check(&self, e: &Slots) -> bool {
+        e.iter().all(|x| *x == Some(self.0))
+    }
+}
+
+/// Returns the expression with every slot empty
+pub(crate) fn empty() -> Slots {
+    [None; 3]
+}
+
+/// Returns a timer that is large enough for any test
+pub(crate) fn budget() -> TickBudget {
+    TickBudget::new(100_000)
+}
+
+impl Render for Fill {
+    fn render(&self, out: &mut Rendering) {
+        out.text(&format!("#{} := ", self.0))
+            .push(Style::Literal, &format!("{}", self.1));
+    }
+}
+
+impl Render for Slots {
+    fn render(&self, out: &mut Rendering) {
+        out.text("[");
+        for (i, x) in self.iter().enumerate() {
+            if i > 0 {
+                out.text(" ");
+            }
+            match x {
+                Some(d) => out.push(Style::Literal, &format!("{}", d)),
+                None => out.push(Style::Hole, "_"),
+            };
+        }
+        out.text("]");
+    }
+}
+
+/// Encodes slots as a JSON array of digits and nulls
+#[cfg(feature = "std")]
+pub(crate) struct SlotsCodec;
+
+#[cfg(feature = "std")]
+impl Codec<Slots> for SlotsCodec {
+    fn encode(&self, e: &Slots) -> Value {
+        Value::Array(
+            e.iter()
+                .map(|x| match x {
+                    Some(d) => Value::Number(*d as f64),
+                    None => Value::Null,
+                })
+                .collect(),
+        )
+    }
+
+    fn decode(&self, v: &Value) -> Option<Slots> {
+        let xs = v.as_array()?;
+        let mut e = empty();
+        if xs.len() != e.len() {
+            return None;
+        }
+        for (x, v) in e.iter_mut().zip(xs) {
+            *x = match v {
+                Value::Null => None,
+                v => Some(v.as_u64()? as u8),
+            };
+        }
+        Some(e)
+    }
+}
+
+/// Encodes fills as a JSON array of the index and the digit
+#[cfg(feature = "std")]
+pub(crate) struct FillCodec;
+
+#[cfg(feature = "std")]
+impl Codec<Fill> for FillCodec {
+    fn encode(&self, s: &Fill) -> Value {
+        Value::Array(vec![Value::Number(s.0 as f64), Value::Number(s.1 as f64)])
+    }
+
+    fn decode(&self, v: &Value) -> Option<Fill> {
+        match v.as_array()? {
+            [i, d] => Some(Fill(i.as_u64()? as usize, d.as_u64()? as u8)),
+            _ => None,
+        }
+    }
+}
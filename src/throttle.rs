@@ -0,0 +1,255 @@
+//! Rate limiting of expensive step providers
+//!
+//! User interfaces can call a provider far more often than necessary (e.g.,
+//! on every keystroke or redraw), which is costly for providers backed by
+//! paid or remote services. A [`ThrottledProvider`] enforces a minimum
+//! interval between calls to the underlying provider and answers repeated
+//! requests for the same expression within that interval from its cache.
+
+use crate::{
+    BatchStepProvider, Capabilities, Context, Feedback,
+    IncrementalStepProvider, ProgressReporter, ProviderError, Step,
+    StepProvider, Timer,
+};
+
+use std::time::{Duration, Instant};
+
+/// A call to the underlying provider of a [`ThrottledProvider`]
+struct Call<S: Step> {
+    at: Instant,
+    /// The requested expression (or `None` for a batch)
+    exp: Option<S::Exp>,
+    steps: Vec<S>,
+}
+
+/// A step provider wrapper that enforces a minimum interval between calls to
+/// the underlying provider
+///
+/// A request for the expression of the most recent call within the interval
+/// is coalesced with it (and answered from the cache). A request for any other
+/// expression (or batch of expressions) waits until the interval has passed.
+pub struct ThrottledProvider<P: StepProvider<T>, T: Timer> {
+    inner: P,
+    interval: Duration,
+    last: Option<Call<P::Step>>,
+    coalesced: usize,
+}
+
+impl<P, T> ThrottledProvider<P, T>
+where
+    P: StepProvider<T>,
+    T: Timer,
+    P::Step: Clone,
+    <P::Step as Step>::Exp: PartialEq,
+{
+    /// Creates a new [`ThrottledProvider`] that calls `inner` at most once per
+    /// `interval`
+    pub fn new(inner: P, interval: Duration) -> Self {
+        Self {
+            inner,
+            interval,
+            last: None,
+            coalesced: 0,
+        }
+    }
+
+    /// Returns the number of requests that were answered from the cache
+    pub fn coalesced(&self) -> usize {
+        self.coalesced
+    }
+
+    /// Returns the inner provider and drops self
+    pub fn into_inner(self) -> P {
+        self.inner
+    }
+
+    /// Waits until the interval since the most recent call has passed,
+    /// unless that call was for `e` (in which case its steps are returned)
+    fn wait(
+        &mut self,
+        timer: &T,
+        e: Option<&<P::Step as Step>::Exp>,
+    ) -> Result<Option<Vec<P::Step>>, T::EarlyCutoff> {
+        if let Some(last) = &self.last {
+            let elapsed = last.at.elapsed();
+            if elapsed < self.interval {
+                if e.is_some() && last.exp.as_ref() == e {
+                    self.coalesced += 1;
+                    return Ok(Some(last.steps.clone()));
+                }
+                timer.tick()?;
+                std::thread::sleep(self.interval - elapsed);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Calls `f` on the inner provider, subject to throttling
+    fn throttled<X: From<T::EarlyCutoff>>(
+        &mut self,
+        timer: &T,
+        e: &<P::Step as Step>::Exp,
+        f: impl FnOnce(&mut P) -> Result<Vec<P::Step>, X>,
+    ) -> Result<Vec<P::Step>, X> {
+        if let Some(steps) = self.wait(timer, Some(e))? {
+            return Ok(steps);
+        }
+        let at = Instant::now();
+        let steps = f(&mut self.inner)?;
+        self.last = Some(Call {
+            at,
+            exp: Some(e.clone()),
+            steps: steps.clone(),
+        });
+        Ok(steps)
+    }
+}
+
+impl<P, T> StepProvider<T> for ThrottledProvider<P, T>
+where
+    P: StepProvider<T>,
+    T: Timer,
+    P::Step: Clone,
+    <P::Step as Step>::Exp: PartialEq,
+{
+    type Step = P::Step;
+
+    fn provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        self.throttled(timer, e, |p| p.provide(timer, e))
+    }
+
+    fn try_provide(
+        &mut self,
+        timer: &T,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, ProviderError<T::EarlyCutoff>> {
+        self.throttled(timer, e, |p| p.try_provide(timer, e))
+    }
+
+    fn capabilities(&mut self) -> Capabilities {
+        Capabilities {
+            partial: false,
+            annotated: false,
+            ..self.inner.capabilities()
+        }
+    }
+
+    fn as_incremental(
+        &mut self,
+    ) -> Option<&mut dyn IncrementalStepProvider<T, Step = Self::Step>> {
+        self.inner.as_incremental()?;
+        Some(self)
+    }
+
+    fn as_batch(
+        &mut self,
+    ) -> Option<&mut dyn BatchStepProvider<T, Step = Self::Step>> {
+        self.inner.as_batch()?;
+        Some(self)
+    }
+
+    fn on_session_start(&mut self, start: &<Self::Step as Step>::Exp) {
+        self.last = None;
+        self.inner.on_session_start(start);
+    }
+
+    fn on_decide(&mut self, step: &Self::Step, e: &<Self::Step as Step>::Exp) {
+        self.inner.on_decide(step, e);
+    }
+
+    fn on_undo(&mut self, e: &<Self::Step as Step>::Exp) {
+        self.inner.on_undo(e);
+    }
+
+    fn on_external_edit(
+        &mut self,
+        prev: &<Self::Step as Step>::Exp,
+        e: &<Self::Step as Step>::Exp,
+    ) {
+        self.last = None;
+        self.inner.on_external_edit(prev, e);
+    }
+
+    fn on_refine(
+        &mut self,
+        valid: &dyn Fn(&<Self::Step as Step>::Exp) -> bool,
+    ) {
+        self.last = None;
+        self.inner.on_refine(valid);
+    }
+
+    fn on_feedback(&mut self, step: &Self::Step, feedback: Feedback) {
+        self.last = None;
+        self.inner.on_feedback(step, feedback);
+    }
+
+    fn set_progress(&mut self, reporter: ProgressReporter) {
+        self.inner.set_progress(reporter);
+    }
+
+    fn set_context(&mut self, context: Context) {
+        self.last = None;
+        self.inner.set_context(context);
+    }
+
+    fn warmup(&mut self, timer: &T) -> Result<(), T::EarlyCutoff> {
+        self.inner.warmup(timer)
+    }
+}
+
+impl<P, T> IncrementalStepProvider<T> for ThrottledProvider<P, T>
+where
+    P: StepProvider<T>,
+    T: Timer,
+    P::Step: Clone,
+    <P::Step as Step>::Exp: PartialEq,
+{
+    fn provide_incremental(
+        &mut self,
+        timer: &T,
+        prev: &<Self::Step as Step>::Exp,
+        step: &Self::Step,
+        e: &<Self::Step as Step>::Exp,
+    ) -> Result<Vec<Self::Step>, T::EarlyCutoff> {
+        self.throttled(timer, e, |p| match p.as_incremental() {
+            Some(p) => p.provide_incremental(timer, prev, step, e),
+            None => p.provide(timer, e),
+        })
+    }
+}
+
+impl<P, T> BatchStepProvider<T> for ThrottledProvider<P, T>
+where
+    P: StepProvider<T>,
+    T: Timer,
+    P::Step: Clone,
+    <P::Step as Step>::Exp: PartialEq,
+{
+    /// Provides the steps for each expression in one call to the underlying
+    /// provider (which counts as a single call for throttling)
+    fn provide_batch(
+        &mut self,
+        timer: &T,
+        es: &[<Self::Step as Step>::Exp],
+    ) -> Result<Vec<Vec<Self::Step>>, ProviderError<T::EarlyCutoff>> {
+        self.wait(timer, None)?;
+        let at = Instant::now();
+        let step_sets = match self.inner.as_batch() {
+            Some(p) => p.provide_batch(timer, es)?,
+            None => es
+                .iter()
+                .map(|e| self.inner.try_provide(timer, e))
+                .collect::<Result<_, _>>()?,
+        };
+        self.last = Some(Call {
+            at,
+            exp: None,
+            steps: vec![],
+        });
+        Ok(step_sets)
+    }
+}
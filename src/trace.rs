@@ -0,0 +1,586 @@
+//! Exporting and importing [`Controller`] sessions
+//!
+//! Sessions are exported in a stable, versioned JSON schema:
+//!
+//! ```json
+//! {
+//!   "version": 2,
+//!   "capabilities": ["incremental", "partial"],
+//!   "start": <expression>,
+//!   "steps": [
+//!     { "step": <step>, "provider": "name" | null, "timestamp_ms": 1700000000000 },
+//!     { "edit": <expression>, "timestamp_ms": 1700000000000 }
+//!   ]
+//! }
+//! ```
+//!
+//! where `provider` is the [`Step::source`] of the step, `edit` is the
+//! result of an external edit (see [`Controller::apply_external_edit`]), and
+//! `timestamp_ms` is the time of the change (in milliseconds since the Unix
+//! epoch). The optional `capabilities` are the interaction modes negotiated
+//! with the provider when the session was exported (see
+//! [`Controller::capabilities`]); they are informational and ignored on
+//! import. Version 1 of the schema is the same, but without edits.
+//! Expressions and steps are encoded by user-supplied [`Codec`]s, so the
+//! format does not depend on how (or whether) the user's types are otherwise
+//! serializable.
+//!
+//! The same codecs are used by every interchange format in the crate (e.g.,
+//! [`distributed`](crate::distributed) batches and
+//! [`soundness`](crate::soundness) reports), which name them [`ExpCodec`]s
+//! and [`StepCodec`]s. A single type can implement [`Codec`] for both the
+//! expressions and the steps of a domain (e.g., to share a table of interned
+//! identifiers) and be passed as both. Types that already describe how they
+//! look (see [`Render`]) can be encoded with the same text by a
+//! [`RenderCodec`], and replays can be drawn with [`Replay::to_dot`].
+
+use crate::{
+    Action, Controller, DecisionMeta, InvalidArgument, Step, StepProvider,
+    Timer, ValidityChecker,
+    history::HistoryStore,
+    json::{self, Value},
+    render::{self, Render, Target},
+};
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The version of the session schema written by [`Controller::export_json`]
+/// (earlier versions can still be read)
+pub const VERSION: u64 = 2;
+
+/// A conversion between values of a type and JSON values (or bytes)
+pub trait Codec<X> {
+    /// Encodes a value as JSON
+    fn encode(&self, x: &X) -> Value;
+
+    /// Decodes a value from JSON (or returns `None` if the JSON does not
+    /// represent a value)
+    fn decode(&self, v: &Value) -> Option<X>;
+
+    /// Encodes a value as bytes (by default, the text of its JSON encoding)
+    ///
+    /// Codecs can override this (together with [`Self::decode_bytes`]) with
+    /// a more compact wire format.
+    fn encode_bytes(&self, x: &X) -> Vec<u8> {
+        self.encode(x).to_string().into_bytes()
+    }
+
+    /// Decodes a value from bytes (as written by [`Self::encode_bytes`])
+    fn decode_bytes(&self, bytes: &[u8]) -> Option<X> {
+        let text = std::str::from_utf8(bytes).ok()?;
+        self.decode(&json::parse(text).ok()?)
+    }
+}
+
+/// A [`Codec`] for the expressions of steps of type `S`
+pub trait ExpCodec<S: Step>: Codec<S::Exp> {}
+
+impl<S: Step, X: Codec<S::Exp> + ?Sized> ExpCodec<S> for X {}
+
+/// A [`Codec`] for steps of type `S`
+pub trait StepCodec<S: Step>: Codec<S> {}
+
+impl<S: Step, X: Codec<S> + ?Sized> StepCodec<S> for X {}
+
+/// A codec that represents values as JSON strings via their [`Display`] and
+/// [`FromStr`] implementations
+///
+/// [`Display`]: std::fmt::Display
+/// [`FromStr`]: std::str::FromStr
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DisplayCodec;
+
+impl<X: std::fmt::Display + std::str::FromStr> Codec<X> for DisplayCodec {
+    fn encode(&self, x: &X) -> Value {
+        Value::String(x.to_string())
+    }
+
+    fn decode(&self, v: &Value) -> Option<X> {
+        v.as_str()?.parse().ok()
+    }
+}
+
+/// A codec that represents values as JSON strings via their (plain-text)
+/// [`Render`] and [`FromStr`] implementations
+///
+/// This keeps exported sessions in the notation that front-ends display.
+///
+/// [`FromStr`]: std::str::FromStr
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderCodec;
+
+impl<X: Render + std::str::FromStr> Codec<X> for RenderCodec {
+    fn encode(&self, x: &X) -> Value {
+        Value::String(x.render_to(Target::Plain))
+    }
+
+    fn decode(&self, v: &Value) -> Option<X> {
+        v.as_str()?.parse().ok()
+    }
+}
+
+/// The ways importing a session can fail
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportError {
+    /// The input is not valid JSON
+    Parse(json::ParseError),
+    /// The input was written with an unsupported schema version
+    UnsupportedVersion(u64),
+    /// The input does not follow the session schema (with a description of
+    /// the offending part)
+    Schema(String),
+    /// The start expression could not be decoded
+    Start,
+    /// The step (or edited expression) at the given index could not be
+    /// decoded
+    Step(usize),
+    /// The step at the given index is not applicable to the expression it
+    /// was decided at (or its result exceeds the resource budget of the
+    /// session)
+    Inapplicable(usize),
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::Parse(e) => write!(f, "{}", e),
+            ImportError::UnsupportedVersion(v) => {
+                write!(f, "unsupported session schema version {}", v)
+            }
+            ImportError::Schema(what) => {
+                write!(f, "malformed session: {}", what)
+            }
+            ImportError::Start => {
+                write!(f, "could not decode start expression")
+            }
+            ImportError::Step(i) => write!(f, "could not decode step {}", i),
+            ImportError::Inapplicable(i) => {
+                write!(f, "step {} is not applicable", i)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<json::ParseError> for ImportError {
+    fn from(e: json::ParseError) -> Self {
+        ImportError::Parse(e)
+    }
+}
+
+impl<T, S, P, C, H> Controller<T, S, P, C, H>
+where
+    T: Timer,
+    S: Step,
+    P: StepProvider<T, Step = S> + ?Sized,
+    C: ValidityChecker<Exp = S::Exp> + ?Sized,
+    H: HistoryStore<S::Exp> + ?Sized,
+{
+    /// Exports the session (start expression and decisions) as JSON in the
+    /// schema described in the [module documentation](crate::trace)
+    pub fn export_json(
+        &self,
+        exp_codec: &impl ExpCodec<S>,
+        step_codec: &impl StepCodec<S>,
+    ) -> String {
+        let steps = self
+            .decisions
+            .iter()
+            .map(|d| {
+                let millis = d
+                    .time
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis();
+                let mut fields = match &d.action {
+                    Action::Step(step) => vec![
+                        ("step".to_owned(), step_codec.encode(step)),
+                        (
+                            "provider".to_owned(),
+                            step.source().map_or(Value::Null, |s| {
+                                Value::String(s.into())
+                            }),
+                        ),
+                    ],
+                    Action::Edit(e) => {
+                        vec![("edit".to_owned(), exp_codec.encode(e))]
+                    }
+                };
+                fields.push((
+                    "timestamp_ms".to_owned(),
+                    Value::Number(millis as f64),
+                ));
+                Value::Object(fields)
+            })
+            .collect();
+        Value::Object(vec![
+            ("version".to_owned(), Value::Number(VERSION as f64)),
+            (
+                "capabilities".to_owned(),
+                Value::Array(
+                    self.capabilities
+                        .names()
+                        .into_iter()
+                        .map(|name| Value::String(name.to_owned()))
+                        .collect(),
+                ),
+            ),
+            ("start".to_owned(), exp_codec.encode(&self.start)),
+            ("steps".to_owned(), Value::Array(steps)),
+        ])
+        .to_string()
+    }
+
+    /// Restarts the session from an exported one, replaying its decisions
+    /// (with their original timestamps)
+    ///
+    /// The provider is notified as if the session were started afresh and the
+    /// decisions made interactively. Every decision is checked (against the
+    /// resource budget as well) before the session is restarted, so on error
+    /// the session is left unchanged.
+    pub fn import_json(
+        &mut self,
+        input: &str,
+        exp_codec: &impl ExpCodec<S>,
+        step_codec: &impl StepCodec<S>,
+    ) -> Result<(), ImportError> {
+        let Replay { states, steps, .. } = read(input, exp_codec, step_codec)?;
+        for (i, (recorded, e)) in steps.iter().zip(&states[1..]).enumerate() {
+            if recorded.step.is_some() && self.refuse_over_budget(e).is_err() {
+                return Err(ImportError::Inapplicable(i));
+            }
+        }
+        let mut states = states.into_iter();
+        self.restart(states.next().unwrap());
+        for (RecordedStep { step, time, .. }, e) in
+            steps.into_iter().zip(states)
+        {
+            match step {
+                Some(step) => self.commit(step, e, DecisionMeta::new()),
+                None => self.apply_external_edit(|_| e),
+            }
+            self.decisions.last_mut().unwrap().time = time;
+        }
+        Ok(())
+    }
+
+    fn restart(&mut self, start: S::Exp) {
+        self.state = start.clone();
+        self.start = start;
+        if let Some(his) = &mut self.history {
+            his.clear(&self.state);
+        }
+        self.decisions.clear();
+        self.last_prev = None;
+        self.auto_applied = 0;
+        self.bump_epoch();
+        self.provider.on_session_start(&self.state);
+    }
+}
+
+/// A change recorded in an exported session
+#[derive(Debug, Clone)]
+pub struct RecordedStep<S> {
+    /// The step (or `None` if the change was an external edit)
+    pub step: Option<S>,
+    /// The name of the provider that provided the step (if recorded)
+    pub provider: Option<String>,
+    /// The time at which the step was decided
+    pub time: SystemTime,
+}
+
+/// Reads an exported session (positioned at its start)
+fn read<S: Step>(
+    input: &str,
+    exp_codec: &impl ExpCodec<S>,
+    step_codec: &impl StepCodec<S>,
+) -> Result<Replay<S>, ImportError> {
+    let v = json::parse(input)?;
+    let version = v
+        .get("version")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| ImportError::Schema("missing version".into()))?;
+    if version == 0 || version > VERSION {
+        return Err(ImportError::UnsupportedVersion(version));
+    }
+    let start = v
+        .get("start")
+        .ok_or_else(|| ImportError::Schema("missing start".into()))?;
+    let start = exp_codec.decode(start).ok_or(ImportError::Start)?;
+    let entries = v
+        .get("steps")
+        .and_then(Value::as_array)
+        .ok_or_else(|| ImportError::Schema("missing steps".into()))?;
+
+    let mut states = Vec::with_capacity(entries.len() + 1);
+    let mut steps = Vec::with_capacity(entries.len());
+    states.push(start);
+    for (i, entry) in entries.iter().enumerate() {
+        let time = entry
+            .get("timestamp_ms")
+            .and_then(Value::as_u64)
+            .map(|ms| UNIX_EPOCH + Duration::from_millis(ms))
+            .ok_or_else(|| {
+                ImportError::Schema(format!("bad timestamp for step {}", i))
+            })?;
+        if let Some(edit) = entry.get("edit") {
+            states.push(exp_codec.decode(edit).ok_or(ImportError::Step(i))?);
+            steps.push(RecordedStep {
+                step: None,
+                provider: None,
+                time,
+            });
+            continue;
+        }
+        let step = entry
+            .get("step")
+            .and_then(|s| step_codec.decode(s))
+            .ok_or(ImportError::Step(i))?;
+        let provider = match entry.get("provider") {
+            None | Some(Value::Null) => None,
+            Some(Value::String(s)) => Some(s.clone()),
+            Some(_) => {
+                return Err(ImportError::Schema(format!(
+                    "bad provider for step {}",
+                    i
+                )));
+            }
+        };
+        let e = step
+            .apply(states.last().unwrap())
+            .ok_or(ImportError::Inapplicable(i))?;
+        states.push(e);
+        steps.push(RecordedStep {
+            step: Some(step),
+            provider,
+            time,
+        });
+    }
+    Ok(Replay {
+        states,
+        steps,
+        position: 0,
+    })
+}
+
+/// A read-only view of an exported session that can be stepped through
+/// forward and backward (e.g., for post-hoc visualization of user studies)
+///
+/// Unlike [`Controller::import_json`], no provider or validity checker is
+/// needed to construct a replay.
+#[derive(Debug, Clone)]
+pub struct Replay<S: Step> {
+    states: Vec<S::Exp>,
+    steps: Vec<RecordedStep<S>>,
+    position: usize,
+}
+
+impl<S: Step> Replay<S> {
+    /// Creates a replay of an exported session (positioned at its start)
+    pub fn from_json(
+        input: &str,
+        exp_codec: &impl ExpCodec<S>,
+        step_codec: &impl StepCodec<S>,
+    ) -> Result<Self, ImportError> {
+        read(input, exp_codec, step_codec)
+    }
+
+    /// Returns the number of recorded steps
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Returns whether or not there are no recorded steps
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Returns the number of steps taken to reach the current state
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Returns the current state
+    pub fn state(&self) -> &S::Exp {
+        &self.states[self.position]
+    }
+
+    /// Returns the start expression of the session
+    pub fn start(&self) -> &S::Exp {
+        &self.states[0]
+    }
+
+    /// Returns every state of the session (in order, starting with the start
+    /// expression)
+    pub fn states(&self) -> &[S::Exp] {
+        &self.states
+    }
+
+    /// Returns the recorded steps of the session (in order)
+    pub fn steps(&self) -> &[RecordedStep<S>] {
+        &self.steps
+    }
+
+    /// Returns the session as a graph in the Graphviz DOT language (see
+    /// [`render::to_dot`])
+    pub fn to_dot(&self) -> String
+    where
+        S: Render,
+        S::Exp: Render,
+    {
+        render::to_dot(
+            &self.states[0],
+            self.steps
+                .iter()
+                .zip(&self.states[1..])
+                .map(|(recorded, e)| (recorded.step.as_ref(), e)),
+        )
+    }
+
+    /// Returns the step that produced the current state (if not at the start)
+    pub fn last_step(&self) -> Option<&RecordedStep<S>> {
+        self.position.checked_sub(1).map(|i| &self.steps[i])
+    }
+
+    /// Returns the step that will be taken next (if not at the end)
+    pub fn next_step(&self) -> Option<&RecordedStep<S>> {
+        self.steps.get(self.position)
+    }
+
+    /// Moves forward one step, returning whether or not the position changed
+    pub fn forward(&mut self) -> bool {
+        if self.position < self.steps.len() {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Moves backward one step, returning whether or not the position
+    /// changed
+    pub fn backward(&mut self) -> bool {
+        if self.position > 0 {
+            self.position -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Moves to the state reached after `position` steps
+    ///
+    /// # Panics
+    ///
+    /// Panics if `position` is greater than the number of recorded steps (see
+    /// [`Self::try_seek`])
+    #[track_caller]
+    pub fn seek(&mut self, position: usize) {
+        InvalidArgument::unwrap(self.try_seek(position))
+    }
+
+    /// Moves to the state reached after `position` steps (see
+    /// [`Self::seek`]), or fails if `position` is greater than the number of
+    /// recorded steps
+    pub fn try_seek(&mut self, position: usize) -> Result<(), InvalidArgument> {
+        InvalidArgument::check(
+            position <= self.steps.len(),
+            "position out of range",
+            || self.position = position,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ResourceChecker, ResourcePolicy,
+        budget::TickBudget,
+        testing::{self, AllEqual, Fill, FillCodec, Fills, Slots, SlotsCodec},
+    };
+
+    /// Rejects expressions with a nine in them
+    struct NoNines;
+
+    impl ResourceChecker for NoNines {
+        type Exp = Slots;
+
+        fn within_budget(&self, e: &Slots) -> bool {
+            !e.contains(&Some(9))
+        }
+    }
+
+    fn controller() -> Controller<TickBudget, Fill> {
+        Controller::new(
+            testing::budget(),
+            Fills { digits: 2 },
+            AllEqual(1),
+            testing::empty(),
+            true,
+        )
+    }
+
+    fn session() -> Controller<TickBudget, Fill> {
+        let mut c = controller();
+        c.decide(Fill(0, 1)).unwrap();
+        c.apply_external_edit(|e| [e[0], Some(0), None]);
+        c.decide(Fill(2, 1)).unwrap();
+        c
+    }
+
+    #[test]
+    fn export_import_round_trip() {
+        let c = session();
+        let exported = c.export_json(&SlotsCodec, &FillCodec);
+
+        let mut d = controller();
+        d.import_json(&exported, &SlotsCodec, &FillCodec).unwrap();
+        assert_eq!(d.working_expression(), &[Some(1), Some(0), Some(1)]);
+        assert_eq!(d.timeline().len(), 3);
+        assert_eq!(d.export_json(&SlotsCodec, &FillCodec), exported);
+
+        d.undo().unwrap();
+        assert_eq!(d.working_expression(), &[Some(1), Some(0), None]);
+    }
+
+    #[test]
+    fn replay_steps_through_states() {
+        let exported = session().export_json(&SlotsCodec, &FillCodec);
+        let replay =
+            Replay::from_json(&exported, &SlotsCodec, &FillCodec).unwrap();
+        assert_eq!(
+            replay.states(),
+            &[
+                testing::empty(),
+                [Some(1), None, None],
+                [Some(1), Some(0), None],
+                [Some(1), Some(0), Some(1)],
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_import_leaves_session_unchanged() {
+        let exported = session().export_json(&SlotsCodec, &FillCodec);
+        let inapplicable = exported.replace("[2,1]", "[0,1]");
+        let over_budget = exported.replace("[2,1]", "[2,9]");
+
+        for (input, error) in [
+            (inapplicable.as_str(), ImportError::Inapplicable(2)),
+            (over_budget.as_str(), ImportError::Inapplicable(2)),
+            ("{\"version\": 99}", ImportError::UnsupportedVersion(99)),
+        ] {
+            let mut d = controller()
+                .with_resource_checker(NoNines, ResourcePolicy::Refuse);
+            d.decide(Fill(1, 1)).unwrap();
+            let before = d.export_json(&SlotsCodec, &FillCodec);
+            assert_eq!(
+                d.import_json(input, &SlotsCodec, &FillCodec),
+                Err(error)
+            );
+            assert_eq!(d.export_json(&SlotsCodec, &FillCodec), before);
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! A typestate interface to [`Controller`] sessions
+//!
+//! With a plain [`Controller`], nothing prevents deciding on a step from a
+//! stale call to [`Controller::provide`] or deciding twice on the same
+//! provision; such misuse only shows up at runtime. This module wraps a
+//! controller in one of two states:
+//!
+//! - [`Providing`], in which steps can be provided (and decisions undone), and
+//! - [`Deciding`], which owns the most recently provided steps and can only
+//!   decide on one of them (or abandon them).
+//!
+//! Each transition consumes the previous state, so the provided steps of a
+//! [`Deciding`] cannot outlive it or be decided on twice.
+
+use crate::{
+    Controller, PbnError, Step, StepProvider, Timer, ValidityChecker,
+    history::HistoryStore,
+};
+
+use alloc::{boxed::Box, vec::Vec};
+
+/// A rejected transition, which returns the state it was attempted from
+#[derive(Debug)]
+pub struct Rejected<X, C> {
+    /// The unchanged state
+    pub state: X,
+    /// The reason the transition was rejected
+    pub error: PbnError<C>,
+}
+
+/// The result of a transition from state `X` to state `Y`
+pub type Transition<X, Y, C> = Result<Y, Rejected<X, C>>;
+
+/// A session that is ready to provide steps
+pub struct Providing<
+    T: Timer,
+    S: Step,
+    P: ?Sized = dyn StepProvider<T, Step = S>,
+    C: ?Sized = dyn ValidityChecker<Exp = <S as Step>::Exp>,
+    H: ?Sized = dyn HistoryStore<<S as Step>::Exp>,
+> {
+    controller: Box<Controller<T, S, P, C, H>>,
+}
+
+/// A session that is waiting for a decision among provided steps
+pub struct Deciding<
+    T: Timer,
+    S: Step,
+    P: ?Sized = dyn StepProvider<T, Step = S>,
+    C: ?Sized = dyn ValidityChecker<Exp = <S as Step>::Exp>,
+    H: ?Sized = dyn HistoryStore<<S as Step>::Exp>,
+> {
+    controller: Box<Controller<T, S, P, C, H>>,
+    steps: Vec<S>,
+}
+
+impl<T, S, P, C, H> Providing<T, S, P, C, H>
+where
+    T: Timer,
+    S: Step,
+    P: StepProvider<T, Step = S> + ?Sized,
+    C: ValidityChecker<Exp = S::Exp> + ?Sized,
+    H: HistoryStore<S::Exp> + ?Sized,
+{
+    /// Wraps a controller
+    pub fn new(controller: Controller<T, S, P, C, H>) -> Self {
+        Self {
+            controller: Box::new(controller),
+        }
+    }
+
+    /// Returns the wrapped controller (for read-only queries)
+    pub fn controller(&self) -> &Controller<T, S, P, C, H> {
+        &self.controller
+    }
+
+    /// Unwraps the controller
+    pub fn into_inner(self) -> Controller<T, S, P, C, H> {
+        *self.controller
+    }
+
+    /// Provides steps (see [`Controller::provide`]) and waits for a decision
+    /// among them
+    pub fn provide(
+        mut self,
+    ) -> Transition<Self, Deciding<T, S, P, C, H>, T::EarlyCutoff> {
+        match self.controller.provide() {
+            Ok(steps) => Ok(Deciding {
+                controller: self.controller,
+                steps,
+            }),
+            Err(error) => Err(Rejected { state: self, error }),
+        }
+    }
+
+    /// Undoes the most recent decision (see [`Controller::undo`])
+    pub fn undo(&mut self) -> Result<(), PbnError<T::EarlyCutoff>> {
+        self.controller.undo()
+    }
+}
+
+impl<T, S, P, C, H> Deciding<T, S, P, C, H>
+where
+    T: Timer,
+    S: Step,
+    P: StepProvider<T, Step = S> + ?Sized,
+    C: ValidityChecker<Exp = S::Exp> + ?Sized,
+    H: HistoryStore<S::Exp> + ?Sized,
+{
+    /// Returns the provided steps
+    pub fn steps(&self) -> &[S] {
+        &self.steps
+    }
+
+    /// Returns the wrapped controller (for read-only queries)
+    pub fn controller(&self) -> &Controller<T, S, P, C, H> {
+        &self.controller
+    }
+
+    /// Decides on the provided step at index `i` (see [`Controller::decide`])
+    ///
+    /// If there is no such step or it does not apply, the decision is
+    /// rejected.
+    pub fn decide(
+        mut self,
+        i: usize,
+    ) -> Transition<Self, Providing<T, S, P, C, H>, T::EarlyCutoff> {
+        let error = match self.steps.get(i) {
+            None => PbnError::InvalidDecision("no such provided step"),
+            Some(step) if !self.controller.applicable(step) => {
+                let e = self.controller.working_expression();
+                PbnError::InapplicableStep(step.why_inapplicable(e))
+            }
+            Some(_) => {
+                let step = self.steps.remove(i);
+                return match self.controller.decide(step) {
+                    Ok(()) => Ok(Providing {
+                        controller: self.controller,
+                    }),
+                    Err(error) => Err(Rejected { state: self, error }),
+                };
+            }
+        };
+        Err(Rejected { state: self, error })
+    }
+
+    /// Abandons the provided steps without deciding on any of them
+    pub fn abandon(self) -> Providing<T, S, P, C, H> {
+        Providing {
+            controller: self.controller,
+        }
+    }
+}